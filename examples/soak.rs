@@ -0,0 +1,368 @@
+//! Multi-threaded stress harness for file-rotator
+//!
+//! Several writer threads share one rotating file and write numbered lines into it, optionally
+//! rate limited, while fault injection threads force rotations and delete archives behind its
+//! back. Once everything's written, the harness reads the whole rotation set back and checks that:
+//!
+//! - there are never more files than configured
+//! - every line is intact
+//! - for every writer, the lines that are still around are exactly its last ones, in order: the
+//!   only lines that may go missing are the oldest ones, deleted by retention (or faults)
+//!
+//! Run it with e.g.
+//!
+//! ```sh
+//! $ cargo run --release --features zstd --example soak -- --writers 8 --lines 100000 \
+//!     --period bytes:65536 --max-files 20 --compression zstd:3 --fault-rotate-ms 5 \
+//!     --fault-delete-ms 50
+//! ```
+//!
+//! Compression algorithms are only available with their cargo features. Pass `--help` for the full
+//! list of options. The exit code is non-zero if any invariant is violated.
+
+use std::collections::HashMap;
+use std::io::{self, prelude::*};
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use file_rotator::{Compression, Naming, RotatingFile, RotationPeriod};
+
+const USAGE: &str = "\
+Usage: soak [OPTIONS]
+
+Options:
+    --dir PATH              Directory to log into (default: a temporary directory)
+    --writers N             Number of writer threads (default: 4)
+    --lines N               Lines written by each writer (default: 10000)
+    --rate N                Lines per second for each writer, 0 meaning unlimited (default: 0)
+    --line-size N           Payload bytes per line (default: 64)
+    --period SPEC           lines:N, bytes:N, interval-ms:N or manual (default: bytes:65536)
+    --max-files N           Maximum number of files (default: 10)
+    --compression SPEC      none, lz4, zstd:LEVEL or xz:PRESET (default: none)
+    --naming SPEC           index, stable, sequence or timestamp (default: index)
+    --fault-rotate-ms N     Force a rotation every N milliseconds (default: never)
+    --fault-delete-ms N     Delete the oldest archive every N milliseconds (default: never)
+";
+
+#[derive(Debug)]
+struct Config {
+    dir: Option<PathBuf>,
+    writers: usize,
+    lines: u64,
+    rate: u64,
+    line_size: usize,
+    period: RotationPeriod,
+    max_files: NonZeroUsize,
+    compression: Compression,
+    naming: Naming,
+    fault_rotate: Option<Duration>,
+    fault_delete: Option<Duration>,
+}
+
+fn parse_args() -> Result<Config, String> {
+    let mut config = Config {
+        dir: None,
+        writers: 4,
+        lines: 10_000,
+        rate: 0,
+        line_size: 64,
+        period: RotationPeriod::Bytes(65536),
+        max_files: NonZeroUsize::new(10).unwrap(),
+        compression: Compression::None,
+        naming: Naming::Index,
+        fault_rotate: None,
+        fault_delete: None,
+    };
+
+    fn num<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+        value
+            .parse()
+            .map_err(|_| format!("invalid value {:?} for {}", value, flag))
+    }
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        if flag == "--help" || flag == "-h" {
+            print!("{}", USAGE);
+            process::exit(0);
+        }
+
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for {}", flag))?;
+        match flag.as_str() {
+            "--dir" => config.dir = Some(value.into()),
+            "--writers" => config.writers = num(&flag, &value)?,
+            "--lines" => config.lines = num(&flag, &value)?,
+            "--rate" => config.rate = num(&flag, &value)?,
+            "--line-size" => config.line_size = num(&flag, &value)?,
+            "--max-files" => config.max_files = num(&flag, &value)?,
+
+            "--period" => {
+                config.period = match value.split_once(':') {
+                    Some(("lines", n)) => RotationPeriod::Lines(num(&flag, n)?),
+                    Some(("bytes", n)) => RotationPeriod::Bytes(num(&flag, n)?),
+                    Some(("interval-ms", n)) => {
+                        RotationPeriod::Interval(Duration::from_millis(num(&flag, n)?))
+                    }
+                    None if value == "manual" => RotationPeriod::Manual,
+                    _ => return Err(format!("invalid rotation period {:?}", value)),
+                }
+            }
+
+            "--compression" => {
+                config.compression = match value.split_once(':') {
+                    #[cfg(feature = "zstd")]
+                    Some(("zstd", level)) => Compression::zstd(num(&flag, level)?),
+                    #[cfg(feature = "xz")]
+                    Some(("xz", preset)) => Compression::Xz {
+                        preset: num(&flag, preset)?,
+                    },
+                    None if value == "none" => Compression::None,
+                    #[cfg(feature = "lz4")]
+                    None if value == "lz4" => Compression::Lz4,
+                    _ => return Err(format!("invalid compression {:?}", value)),
+                }
+            }
+
+            "--naming" => {
+                config.naming = match value.as_str() {
+                    "index" => Naming::Index,
+                    "stable" => Naming::Stable,
+                    "sequence" => Naming::Sequence,
+                    "timestamp" => Naming::Timestamp,
+                    _ => return Err(format!("invalid naming {:?}", value)),
+                }
+            }
+
+            "--fault-rotate-ms" => {
+                config.fault_rotate = Some(Duration::from_millis(num(&flag, &value)?))
+            }
+            "--fault-delete-ms" => {
+                config.fault_delete = Some(Duration::from_millis(num(&flag, &value)?))
+            }
+
+            _ => return Err(format!("unknown option {}\n\n{}", flag, USAGE)),
+        }
+    }
+
+    if config.writers == 0 {
+        return Err("need at least one writer".into());
+    }
+    Ok(config)
+}
+
+fn writer(id: usize, config: &Config, file: &Mutex<RotatingFile>) -> io::Result<()> {
+    let payload = "x".repeat(config.line_size);
+    let start = Instant::now();
+
+    for seq in 0..config.lines {
+        if config.rate != 0 {
+            let due = start + Duration::from_secs_f64(seq as f64 / config.rate as f64);
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                thread::sleep(wait);
+            }
+        }
+
+        let line = format!("{} {} {}\n", id, seq, payload);
+        file.lock().unwrap().write_all(line.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+// Run `fault` every `every` until `done` is set
+fn fault_loop(
+    every: Duration,
+    done: &AtomicBool,
+    mut fault: impl FnMut() -> io::Result<()>,
+) -> io::Result<()> {
+    while !done.load(Ordering::Relaxed) {
+        thread::sleep(every);
+        fault()?;
+    }
+    Ok(())
+}
+
+fn delete_oldest_archive(file: &Mutex<RotatingFile>) -> io::Result<()> {
+    // Hold the lock so that we don't delete files from under a rotation in progress
+    let file = file.lock().unwrap();
+    let files = file.files_matching("*")?;
+    if files.len() > 1 {
+        std::fs::remove_file(files.last().unwrap())?;
+    }
+    Ok(())
+}
+
+fn verify(config: &Config, file: &mut RotatingFile) -> Result<(), String> {
+    let files = file.files_matching("*").map_err(|e| e.to_string())?;
+    if files.len() > config.max_files.get() {
+        return Err(format!(
+            "found {} files, but at most {} should exist",
+            files.len(),
+            config.max_files
+        ));
+    }
+
+    let mut readers = file.read_matching("*").map_err(|e| e.to_string())?;
+    // Oldest first, so that lines come out in order
+    readers.reverse();
+
+    let mut next_seq = HashMap::<usize, u64>::new();
+    let mut lines = 0;
+    for (reader, path) in readers.into_iter().zip(files.iter().rev()) {
+        for line in io::BufReader::new(reader).lines() {
+            let line = line.map_err(|e| format!("{}: {}", path.display(), e))?;
+            let mut parts = line.splitn(3, ' ');
+            let (id, seq, payload) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(id), Some(seq), Some(payload)) => (id, seq, payload),
+                _ => return Err(format!("{}: torn line {:?}", path.display(), line)),
+            };
+            let id: usize = id
+                .parse()
+                .map_err(|_| format!("bad writer in {:?}", line))?;
+            let seq: u64 = seq
+                .parse()
+                .map_err(|_| format!("bad sequence in {:?}", line))?;
+            if payload.len() != config.line_size {
+                return Err(format!("{}: torn line {:?}", path.display(), line));
+            }
+
+            // The first line we see from each writer may come after some deleted ones, but from
+            // then on there can't be any gaps
+            if let Some(&expected) = next_seq.get(&id) {
+                if seq != expected {
+                    return Err(format!(
+                        "{}: writer {} jumped from line {} to line {}",
+                        path.display(),
+                        id,
+                        expected - 1,
+                        seq
+                    ));
+                }
+            }
+            next_seq.insert(id, seq + 1);
+            lines += 1;
+        }
+    }
+
+    for id in 0..config.writers {
+        match next_seq.get(&id) {
+            Some(&next) if next == config.lines => {}
+            Some(&next) => {
+                return Err(format!(
+                    "writer {}'s last line is {}, expected {}",
+                    id,
+                    next - 1,
+                    config.lines - 1
+                ))
+            }
+            // All of this writer's lines might have been deleted already
+            None => {}
+        }
+    }
+
+    // If no file could have been deleted, then nothing can be missing
+    let total = config.writers as u64 * config.lines;
+    if files.len() < config.max_files.get() && config.fault_delete.is_none() && lines != total {
+        return Err(format!(
+            "found {} lines, but {} were written and no file was deleted",
+            lines, total
+        ));
+    }
+
+    println!("ok: {} lines still around in {} files", lines, files.len());
+    Ok(())
+}
+
+fn main() {
+    let config = match parse_args() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(2);
+        }
+    };
+
+    let tmp;
+    let dir = match &config.dir {
+        Some(dir) => dir.clone(),
+        None => {
+            tmp = tempfile::tempdir().expect("could not create temporary directory");
+            tmp.path().to_owned()
+        }
+    };
+    println!("logging into {}", dir.display());
+
+    let file = RotatingFile::new(
+        "soak",
+        dir,
+        config.period,
+        config.max_files,
+        config.compression.clone(),
+    )
+    .with_naming(config.naming.clone());
+    let file = Arc::new(Mutex::new(file));
+    let done = AtomicBool::new(false);
+
+    let start = Instant::now();
+    let result = thread::scope(|scope| {
+        let (done, file) = (&done, &*file);
+        let faults = vec![
+            config.fault_rotate.map(|every| {
+                scope.spawn(move || fault_loop(every, done, || file.lock().unwrap().rotate()))
+            }),
+            config.fault_delete.map(|every| {
+                scope.spawn(move || fault_loop(every, done, || delete_oldest_archive(file)))
+            }),
+        ];
+
+        let writers = (0..config.writers)
+            .map(|id| {
+                let config = &config;
+                scope.spawn(move || writer(id, config, file))
+            })
+            .collect::<Vec<_>>();
+
+        let mut result = Ok(());
+        for handle in writers {
+            result = result.and(handle.join().expect("writer panicked"));
+        }
+        done.store(true, Ordering::Relaxed);
+        for handle in faults.into_iter().flatten() {
+            result = result.and(handle.join().expect("fault injector panicked"));
+        }
+        result
+    });
+    let elapsed = start.elapsed();
+
+    let mut file = Arc::try_unwrap(file)
+        .expect("all threads are done")
+        .into_inner()
+        .unwrap();
+    let result = result
+        .and_then(|()| file.flush())
+        .and_then(|()| file.wait_for_maintenance());
+    if let Err(e) = result {
+        eprintln!("error while writing: {}", e);
+        process::exit(1);
+    }
+
+    let total = config.writers as u64 * config.lines;
+    println!(
+        "wrote {} lines in {:.2?} ({:.0} lines/s)",
+        total,
+        elapsed,
+        total as f64 / elapsed.as_secs_f64()
+    );
+
+    if let Err(e) = verify(&config, &mut file) {
+        eprintln!("invariant violated: {}", e);
+        process::exit(1);
+    }
+}
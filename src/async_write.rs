@@ -0,0 +1,123 @@
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_io::AsyncWrite;
+
+use super::{NonBlocking, RotatingFile, SharedRotatingFile};
+
+/// Blocks the executor: writes, and rotations when it's time to, happen right away on the task's
+/// thread. Use [`NonBlocking`] in async code, see the [crate docs](index.html#cargo-features).
+impl AsyncWrite for RotatingFile {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(self.get_mut().write(buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(self.get_mut().flush())
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+/// Blocks the executor, on top of waiting for the lock, like `impl AsyncWrite for RotatingFile`
+impl AsyncWrite for SharedRotatingFile {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(self.get_mut().write(buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(self.get_mut().flush())
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+/// Blocks the executor, on top of waiting for the lock, like `impl AsyncWrite for RotatingFile`
+impl AsyncWrite for &SharedRotatingFile {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(self.get_mut().write(buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(self.get_mut().flush())
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+/// Never waits on the disk, which makes this the one to use in async code, though it waits on
+/// the background thread when its queue is full with
+/// [`Backpressure::Block`](enum.Backpressure.html#variant.Block)
+impl AsyncWrite for NonBlocking {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(self.get_mut().write(buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(self.get_mut().flush())
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::num::NonZeroUsize;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Waker};
+
+    use futures_io::AsyncWrite;
+
+    use crate::{Compression, RotatingFile, RotationPeriod};
+
+    #[test]
+    fn test_async_write() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut file = RotatingFile::new(
+            "async",
+            directory.path().to_owned(),
+            RotationPeriod::Manual,
+            NonZeroUsize::new(1).unwrap(),
+            Compression::None,
+        )
+        .into_shared();
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(matches!(
+            Pin::new(&mut file).poll_write(&mut cx, b"hello\n"),
+            Poll::Ready(Ok(6))
+        ));
+        assert!(matches!(
+            Pin::new(&mut file).poll_close(&mut cx),
+            Poll::Ready(Ok(()))
+        ));
+
+        let path = file.lock().files_matching("*").unwrap().remove(0);
+        assert_eq!(fs::read_to_string(path).unwrap(), "hello\n");
+    }
+}
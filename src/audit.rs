@@ -0,0 +1,90 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::{permissions, timestamp};
+
+/// Something that happened to one of a rotating file's files, see
+/// [`RotatingFile::with_audit_log`](struct.RotatingFile.html#method.with_audit_log)
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct AuditEvent {
+    /// When it happened
+    pub time: SystemTime,
+    /// What happened
+    pub action: AuditAction,
+    /// The file it happened to: where it ended up if it was rotated out, where it was if it was
+    /// deleted
+    pub path: PathBuf,
+}
+
+/// What happened to a file, and why if it was deleted
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum AuditAction {
+    /// It was rotated out
+    Rotated,
+    /// It was deleted to make room for a new one, there being a maximum number of files
+    Evicted,
+    /// It was deleted once it had been put in a bundle, see
+    /// [`RotatingFile::with_bundles`](struct.RotatingFile.html#method.with_bundles)
+    Bundled,
+    /// It was deleted to free up space once the disk was full, see
+    /// [`DiskFullPolicy::Prune`](enum.DiskFullPolicy.html#variant.Prune)
+    Pruned,
+    /// It was deleted by
+    /// [`RotatingFile::purge`](struct.RotatingFile.html#method.purge)
+    Purged,
+}
+
+impl fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AuditAction::Rotated => "rotated",
+            AuditAction::Evicted => "evicted",
+            AuditAction::Bundled => "bundled",
+            AuditAction::Pruned => "pruned",
+            AuditAction::Purged => "purged",
+        })
+    }
+}
+
+// Each line of the audit log is `TIME ACTION PATH`, TIME being in RFC 3339 format
+fn line(event: &AuditEvent) -> String {
+    format!(
+        "{} {} {}\n",
+        timestamp::rfc3339(event.time),
+        event.action,
+        event.path.display()
+    )
+}
+
+// Add `event` to the end of the audit log at `path`, in one write so that lines never get mixed up
+pub(super) fn append(path: &Path, event: &AuditEvent) -> io::Result<()> {
+    let mut log =
+        permissions::no_follow(fs::OpenOptions::new().create(true).append(true)).open(path)?;
+    log.write_all(line(event).as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{line, AuditAction, AuditEvent};
+
+    #[test]
+    fn test_line() {
+        let event = AuditEvent {
+            time: UNIX_EPOCH + Duration::from_millis(1_714_566_896_789),
+            action: AuditAction::Evicted,
+            path: PathBuf::from("logs/app.3.log"),
+        };
+        assert_eq!(
+            line(&event),
+            "2024-05-01T12:34:56.789Z evicted logs/app.3.log\n"
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, prelude::*};
+use std::path::{Path, PathBuf};
+
+use flate2::{read::GzDecoder, write::GzEncoder};
+
+use super::permissions;
+use super::timestamp::{self, DateTime};
+
+const BLOCK: usize = 512;
+
+// The name of the bundle holding the files last written to on the given day
+pub(super) fn bundle_name(name: &str, day: DateTime) -> String {
+    format!(
+        "{}.{:04}-{:02}-{:02}.tar.gz",
+        name, day.year, day.month, day.day
+    )
+}
+
+// Check if `file_name` is one of our bundles, returning its day (in seconds since epoch) if so
+pub(super) fn parse_bundle_name(name: &str, file_name: &str) -> Option<u64> {
+    let date = file_name
+        .strip_prefix(name)?
+        .strip_prefix('.')?
+        .strip_suffix(".tar.gz")?;
+    let mut parts = date.splitn(3, '-');
+    let mut part = |len| {
+        let part = parts.next()?;
+        if part.len() == len && part.bytes().all(|b| b.is_ascii_digit()) {
+            part.parse().ok()
+        } else {
+            None
+        }
+    };
+    DateTime {
+        year: part(4)?,
+        month: part(2)?,
+        day: part(2)?,
+        hour: 0,
+        minute: 0,
+        second: 0,
+    }
+    .to_unix()
+}
+
+fn octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}", value, width = field.len() - 1);
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+}
+
+// A ustar header for a regular file
+fn header(name: &str, size: u64, mtime: u64) -> io::Result<[u8; BLOCK]> {
+    if name.len() > 100 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{:?} is too long for a tar header", name),
+        ));
+    }
+
+    let mut header = [0; BLOCK];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    octal(&mut header[100..108], 0o644);
+    octal(&mut header[108..116], 0);
+    octal(&mut header[116..124], 0);
+    octal(&mut header[124..136], size);
+    octal(&mut header[136..148], mtime);
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    // The checksum is computed with its own field set to spaces
+    header[148..156].copy_from_slice(b"        ");
+    let checksum = header.iter().map(|&b| u64::from(b)).sum();
+    octal(&mut header[148..155], checksum);
+    header[154] = 0;
+    Ok(header)
+}
+
+fn parse_octal(field: &[u8]) -> io::Result<u64> {
+    let digits = std::str::from_utf8(field)
+        .ok()
+        .map(|field| field.trim_matches(|c: char| c == '\0' || c == ' '))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "bad tar header"))?;
+    u64::from_str_radix(digits, 8)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "bad tar header"))
+}
+
+fn padding(size: u64) -> usize {
+    (BLOCK - (size as usize % BLOCK)) % BLOCK
+}
+
+// Copy the entries of a tar from `src` to `dst`, leaving out the end-of-archive marker, and
+// remembering their names
+fn copy_entries<R: Read, W: Write>(
+    mut src: R,
+    dst: &mut W,
+    names: &mut HashSet<String>,
+) -> io::Result<()> {
+    let mut header = [0; BLOCK];
+    loop {
+        src.read_exact(&mut header)?;
+        if header.iter().all(|&b| b == 0) {
+            return Ok(());
+        }
+
+        let name = header[..100].split(|&b| b == 0).next().unwrap_or_default();
+        names.insert(String::from_utf8_lossy(name).into_owned());
+        let size = parse_octal(&header[124..136])?;
+        dst.write_all(&header)?;
+        let copied = io::copy(&mut (&mut src).take(size + padding(size) as u64), dst)?;
+        if copied != size + padding(size) as u64 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+    }
+}
+
+// Add `files` to the bundle at `path`, creating it if needed. Entries are named by `entry_name`,
+// which is given a number to bump until the name isn't taken yet. The bundle is rewritten to a
+// temporary file first, so that it's never seen half-written.
+pub(super) fn add_to_bundle(
+    path: &Path,
+    files: &[PathBuf],
+    entry_name: impl Fn(&Path, u64) -> String,
+) -> io::Result<()> {
+    let mut tmp = path.to_owned().into_os_string();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    let mut names = HashSet::new();
+    let mut bundle = GzEncoder::new(
+        permissions::create_replacing(&tmp)?,
+        flate2::Compression::default(),
+    );
+    match fs::File::open(path) {
+        Ok(existing) => copy_entries(GzDecoder::new(existing), &mut bundle, &mut names)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    for file in files {
+        let name = (0..)
+            .map(|n| entry_name(file, n))
+            .find(|name| !names.contains(name))
+            .expect("there's always a free name");
+        append_file(&mut bundle, &name, file)?;
+        names.insert(name);
+    }
+    finish(bundle, &tmp, path)
+}
+
+// Write a new bundle at `path` holding `manifest` as `MANIFEST`, followed by each of `files` under
+// the name it comes with
+pub(super) fn export(path: &Path, manifest: &[u8], files: &[(String, PathBuf)]) -> io::Result<()> {
+    let mut tmp = path.to_owned().into_os_string();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    let mut bundle = GzEncoder::new(
+        permissions::create_replacing(&tmp)?,
+        flate2::Compression::default(),
+    );
+    let now = timestamp::unix_secs(std::time::SystemTime::now());
+    bundle.write_all(&header("MANIFEST", manifest.len() as u64, now)?)?;
+    bundle.write_all(manifest)?;
+    bundle.write_all(&[0; BLOCK][..padding(manifest.len() as u64)])?;
+    for (name, file) in files {
+        append_file(&mut bundle, name, file)?;
+    }
+    finish(bundle, &tmp, path)
+}
+
+// Add the file at `file` to the tar being written to `bundle`, as `name`
+fn append_file<W: Write>(bundle: &mut W, name: &str, file: &Path) -> io::Result<()> {
+    let mut src = fs::File::open(file)?;
+    let metadata = src.metadata()?;
+    let mtime = metadata.modified().map_or(0, timestamp::unix_secs);
+
+    bundle.write_all(&header(name, metadata.len(), mtime)?)?;
+    let copied = io::copy(&mut (&mut src).take(metadata.len()), bundle)?;
+    if copied != metadata.len() {
+        return Err(io::Error::other(format!(
+            "{} shrank while being bundled",
+            file.display()
+        )));
+    }
+    bundle.write_all(&[0; BLOCK][..padding(metadata.len())])
+}
+
+// End the tar being written to `tmp`, and move it to `path` once it's on disk
+fn finish(mut bundle: GzEncoder<fs::File>, tmp: &Path, path: &Path) -> io::Result<()> {
+    // Two empty blocks mark the end of the archive
+    bundle.write_all(&[0; 2 * BLOCK])?;
+    bundle.finish()?.sync_all()?;
+    fs::rename(tmp, path)
+}
+
+// Read back a bundle's entries, as (name, contents)
+#[cfg(test)]
+pub(super) fn read_bundle(path: &Path) -> io::Result<Vec<(String, Vec<u8>)>> {
+    let mut src = GzDecoder::new(fs::File::open(path)?);
+    let mut entries = Vec::new();
+    let mut header = [0; BLOCK];
+    loop {
+        src.read_exact(&mut header)?;
+        if header.iter().all(|&b| b == 0) {
+            return Ok(entries);
+        }
+        let name = header[..100].split(|&b| b == 0).next().unwrap_or_default();
+        let size = parse_octal(&header[124..136])?;
+        let mut contents = vec![0; size as usize + padding(size)];
+        src.read_exact(&mut contents)?;
+        contents.truncate(size as usize);
+        entries.push((String::from_utf8_lossy(name).into_owned(), contents));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::fs;
+    use std::path::Path;
+
+    use super::{add_to_bundle, bundle_name, parse_bundle_name, read_bundle};
+    use crate::timestamp::DateTime;
+
+    #[test]
+    fn test_bundle_name() {
+        let day = DateTime::from_unix(1_717_243_200);
+        assert_eq!(bundle_name("app", day), "app.2024-06-01.tar.gz");
+        assert_eq!(
+            parse_bundle_name("app", "app.2024-06-01.tar.gz"),
+            Some(1_717_200_000)
+        );
+        assert_eq!(parse_bundle_name("app", "app.2024-6-01.tar.gz"), None);
+        assert_eq!(parse_bundle_name("app", "other.2024-06-01.tar.gz"), None);
+        assert_eq!(parse_bundle_name("app", "app.2024-06-01.log"), None);
+    }
+
+    #[test]
+    fn test_add_to_bundle() {
+        let directory = tempfile::tempdir().unwrap();
+        let bundle = directory.path().join("app.2024-06-01.tar.gz");
+        let files = (0..3)
+            .map(|i| {
+                let path = directory.path().join(format!("app.{}.log", i));
+                fs::write(&path, vec![b'a' + i; 1000 * usize::from(i)]).unwrap();
+                path
+            })
+            .collect::<Vec<_>>();
+
+        // Bundling the same files twice must give them different names
+        let entry_name =
+            |path: &Path, n| format!("{}.{}", path.file_name().unwrap().to_string_lossy(), n);
+        add_to_bundle(&bundle, &files[..2], entry_name).unwrap();
+        add_to_bundle(&bundle, &files, entry_name).unwrap();
+
+        let entries = read_bundle(&bundle).unwrap();
+        let names = entries
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "app.0.log.0",
+                "app.1.log.0",
+                "app.0.log.1",
+                "app.1.log.1",
+                "app.2.log.0"
+            ]
+        );
+        assert_eq!(names.iter().collect::<HashSet<_>>().len(), 5);
+        for (name, contents) in &entries {
+            let i = name.as_bytes()[4] - b'0';
+            assert_eq!(contents, &vec![b'a' + i; 1000 * usize::from(i)]);
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, prelude::*};
+use std::path::Path;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::{checksum, permissions};
+
+// Each line of the manifest is `CHAIN HASH NAME`, HASH being the SHA-256 of a file's contents as
+// it was rotated out and CHAIN the HMAC-SHA256 of the previous line's CHAIN, HASH and NAME under
+// the key, starting from all zeroes. Changing, removing or reordering lines breaks every CHAIN
+// after them, and there's no writing new ones that follow without the key.
+const GENESIS: [u8; 32] = [0; 32];
+
+// What the chain is keyed with
+#[derive(Clone)]
+pub(super) struct Key(pub(super) Vec<u8>);
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Keys don't belong in logs
+        f.write_str("<redacted>")
+    }
+}
+
+fn link(key: &Key, previous: &[u8; 32], hash: &[u8; 32], name: &str) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(&key.0).expect("HMAC takes keys of any length");
+    mac.update(previous);
+    mac.update(hash);
+    mac.update(name.as_bytes());
+    mac.finalize().into_bytes().into()
+}
+
+fn broken(line: usize, why: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("hash chain broken at line {}: {}", line + 1, why),
+    )
+}
+
+// Read back the manifest at `path`, checking every link, and return the hash of every file in it
+// from oldest to newest
+pub(super) fn read(path: &Path, key: &Key) -> io::Result<Vec<[u8; 32]>> {
+    let manifest = match fs::read_to_string(path) {
+        Ok(manifest) => manifest,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut previous = GENESIS;
+    let mut hashes = Vec::new();
+    for (i, line) in manifest.lines().enumerate() {
+        let mut parts = line.splitn(3, ' ');
+        let (chain, hash, name) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(chain), Some(hash), Some(name)) => (chain, hash, name),
+            _ => return Err(broken(i, "malformed line")),
+        };
+        let chain = checksum::parse_hex(chain).ok_or_else(|| broken(i, "malformed line"))?;
+        let hash = checksum::parse_hex(hash).ok_or_else(|| broken(i, "malformed line"))?;
+        if chain != link(key, &previous, &hash, name) {
+            return Err(broken(i, "line doesn't follow from the previous ones"));
+        }
+        previous = chain;
+        hashes.push(hash);
+    }
+    Ok(hashes)
+}
+
+// Hash what's read from `contents` and add it to the end of the chain at `path` under `name`
+pub(super) fn append<R: Read>(path: &Path, key: &Key, name: &str, contents: R) -> io::Result<()> {
+    let hash = checksum::sha256(contents)?;
+    // Start from the last line as is, checking the whole chain is for `read`
+    let previous = match fs::read_to_string(path) {
+        Ok(manifest) => match manifest.lines().last() {
+            Some(line) => checksum::parse_hex(line.split(' ').next().unwrap_or_default())
+                .ok_or_else(|| broken(manifest.lines().count() - 1, "malformed line"))?,
+            None => GENESIS,
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => GENESIS,
+        Err(e) => return Err(e),
+    };
+
+    let chain = link(key, &previous, &hash, name);
+    let mut manifest =
+        permissions::no_follow(fs::OpenOptions::new().create(true).append(true)).open(path)?;
+    writeln!(
+        manifest,
+        "{} {} {}",
+        checksum::hex(&chain),
+        checksum::hex(&hash),
+        name
+    )?;
+    manifest.sync_data()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{append, link, read, Key, GENESIS};
+
+    #[test]
+    fn test_chain() {
+        let directory = tempfile::tempdir().unwrap();
+        let manifest = directory.path().join("app.chain");
+        let key = Key(b"secret".to_vec());
+        assert!(read(&manifest, &key).unwrap().is_empty());
+
+        let contents = ["first", "second", "third"];
+        for (i, contents) in contents.iter().enumerate() {
+            append(
+                &manifest,
+                &key,
+                &format!("app.{}.log", i),
+                contents.as_bytes(),
+            )
+            .unwrap();
+        }
+        let hashes = read(&manifest, &key).unwrap();
+        assert_eq!(hashes.len(), 3);
+        assert_eq!(
+            hashes[0],
+            crate::checksum::sha256(&b"first"[..]).unwrap(),
+            "hashes are of the contents"
+        );
+
+        // Removing or changing any line must be noticed
+        let lines = fs::read_to_string(&manifest).unwrap();
+        let lines = lines.lines().collect::<Vec<_>>();
+        for skipped in 0..2 {
+            let mut tampered = lines.clone();
+            tampered.remove(skipped);
+            fs::write(&manifest, tampered.join("\n")).unwrap();
+            assert!(read(&manifest, &key).is_err());
+        }
+        let renamed = lines.join("\n").replace("app.1.log", "app.5.log");
+        fs::write(&manifest, renamed).unwrap();
+        assert!(read(&manifest, &key).is_err());
+
+        // Nor can it be rewritten from scratch without the key
+        fs::write(&manifest, lines.join("\n")).unwrap();
+        assert!(read(&manifest, &key).is_ok());
+        assert!(read(&manifest, &Key(b"guess".to_vec())).is_err());
+        let hash = crate::checksum::sha256(&b"forged"[..]).unwrap();
+        let forged = link(&Key(Vec::new()), &GENESIS, &hash, "app.0.log");
+        let line = format!(
+            "{} {} app.0.log",
+            crate::checksum::hex(&forged),
+            crate::checksum::hex(&hash)
+        );
+        fs::write(&manifest, line).unwrap();
+        assert!(read(&manifest, &key).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+#[cfg(feature = "checksum")]
+use std::fs;
+#[cfg(feature = "checksum")]
+use std::io::Write;
+use std::io::{self, Read};
+#[cfg(feature = "checksum")]
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+#[cfg(feature = "checksum")]
+use super::permissions;
+
+pub(super) struct HashingReader<R> {
+    inner: R,
+    hasher: Option<Sha256>,
+}
+
+impl<R> HashingReader<R> {
+    pub(super) fn new(inner: R, enabled: bool) -> Self {
+        Self {
+            inner,
+            hasher: if enabled { Some(Sha256::new()) } else { None },
+        }
+    }
+
+    // The SHA-256 of everything read so far, if hashing was enabled
+    pub(super) fn finish(self) -> Option<[u8; 32]> {
+        self.hasher.map(|hasher| hasher.finalize().into())
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..read]);
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(feature = "checksum")]
+pub(super) fn sha256<R: Read>(reader: R) -> io::Result<[u8; 32]> {
+    let mut reader = HashingReader::new(reader, true);
+    io::copy(&mut reader, &mut io::sink())?;
+    Ok(reader.finish().expect("hashing is enabled"))
+}
+
+pub(super) fn hex(digest: &[u8; 32]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// The other way around from `hex`
+pub(super) fn parse_hex(hex: &str) -> Option<[u8; 32]> {
+    let mut digest = [0; 32];
+    if hex.len() != 64 {
+        return None;
+    }
+    for (byte, pair) in digest.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(digest)
+}
+
+#[cfg(feature = "checksum")]
+fn sha256_hex<R: Read>(reader: R) -> io::Result<String> {
+    Ok(hex(&sha256(reader)?))
+}
+
+// Where the checksum of the file at `path` goes
+#[cfg(feature = "checksum")]
+fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = path.to_owned().into_os_string();
+    sidecar.push(".sha256");
+    PathBuf::from(sidecar)
+}
+
+// Lines are in the format `sha256sum` outputs and `sha256sum -c` checks, which only names the file
+// itself so that the directory can be moved around
+#[cfg(feature = "checksum")]
+fn write_sidecar_line(path: &Path, hash: &str) -> io::Result<()> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    permissions::create_replacing(&sidecar_path(path))?
+        .write_all(format!("{}  {}\n", hash, file_name).as_bytes())
+}
+
+// Hash the file at `path` into its sidecar
+#[cfg(feature = "checksum")]
+pub(super) fn write_sidecar(path: &Path) -> io::Result<()> {
+    let hash = sha256_hex(fs::File::open(path)?)?;
+    write_sidecar_line(path, &hash)
+}
+
+// Move the sidecar of a file that has been renamed from `from` to `to`, if it has one
+#[cfg(feature = "checksum")]
+pub(super) fn move_sidecar(from: &Path, to: &Path) -> io::Result<()> {
+    let line = match fs::read_to_string(sidecar_path(from)) {
+        Ok(line) => line,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let hash = line.split_whitespace().next().unwrap_or_default();
+    write_sidecar_line(to, hash)?;
+    fs::remove_file(sidecar_path(from))
+}
+
+// Remove the sidecar of a file that's gone, if it has one
+#[cfg(feature = "checksum")]
+pub(super) fn remove_sidecar(path: &Path) -> io::Result<()> {
+    match fs::remove_file(sidecar_path(path)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(all(test, feature = "checksum"))]
+mod tests {
+    use super::sha256_hex;
+
+    #[test]
+    fn test_sha256() {
+        let cases = [
+            (
+                &b""[..],
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            ),
+            (
+                &b"abc"[..],
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            (
+                &b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"[..],
+                "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+            ),
+        ];
+        for &(data, hash) in &cases {
+            assert_eq!(sha256_hex(data).unwrap(), hash);
+        }
+
+        let million = vec![b'a'; 1_000_000];
+        assert_eq!(
+            sha256_hex(&million[..]).unwrap(),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+    }
+}
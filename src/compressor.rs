@@ -0,0 +1,105 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// A user-provided compression format, to be used with
+/// [`Compression::Custom`](enum.Compression.html#variant.Custom)
+///
+/// # Examples
+///
+/// A "compressor" which just reverses the file, for no reason whatsoever:
+///
+/// ```rust
+/// # use std::{fs, io::{self, Read}, path::Path};
+/// # use file_rotator::Compressor;
+/// #[derive(Debug)]
+/// struct Reverse;
+///
+/// impl Compressor for Reverse {
+///     fn suffix(&self) -> &str {
+///         "rev"
+///     }
+///
+///     fn compress(&self, src: &Path, dst: &Path) -> io::Result<()> {
+///         let mut contents = fs::read(src)?;
+///         contents.reverse();
+///         fs::write(dst, contents)
+///     }
+///
+///     fn decompress(&self, mut src: fs::File) -> io::Result<Box<dyn Read + Send>> {
+///         let mut contents = Vec::new();
+///         src.read_to_end(&mut contents)?;
+///         contents.reverse();
+///         Ok(Box::new(io::Cursor::new(contents)))
+///     }
+/// }
+/// ```
+pub trait Compressor: fmt::Debug + Send + Sync {
+    /// The suffix compressed files get after their name, without a leading dot
+    ///
+    /// This is also how compressed files are recognized when scanning the directory, so it must
+    /// not change between runs, nor clash with any of the built-in ones (`zstd`, `lz4` and `xz`).
+    fn suffix(&self) -> &str;
+
+    /// Compress the uncompressed file at `src` into a new file at `dst`
+    ///
+    /// `src` must be left alone: the rotating file takes care of removing it once this succeeds,
+    /// and of moving `dst` into place.
+    fn compress(&self, src: &Path, dst: &Path) -> io::Result<()>;
+
+    /// Open a file compressed by [`Compressor::compress`] for reading, yielding the original bytes
+    ///
+    /// This is needed for the reading APIs such as
+    /// [`RotatingFile::read_matching`](struct.RotatingFile.html#method.read_matching), and to
+    /// recompress files when switching to another compression. The default implementation
+    /// returns an error of kind [`io::ErrorKind::Unsupported`].
+    fn decompress(&self, src: fs::File) -> io::Result<Box<dyn Read + Send>> {
+        drop(src);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{} files can't be decompressed", self.suffix()),
+        ))
+    }
+}
+
+// The suffixes of the built-in formats, which are always recognized
+#[cfg(not(all(feature = "zstd", feature = "lz4", feature = "xz")))]
+pub(super) const BUILTIN_SUFFIXES: [&str; 3] = ["zstd", "lz4", "xz"];
+
+// Stands in for a built-in format whose feature isn't enabled, so that files in it are still part
+// of the rotation set even though they can't be read. The features are named after the suffixes.
+#[cfg(not(all(feature = "zstd", feature = "lz4", feature = "xz")))]
+#[derive(Debug)]
+pub(super) struct Disabled {
+    pub(super) suffix: &'static str,
+}
+
+#[cfg(not(all(feature = "zstd", feature = "lz4", feature = "xz")))]
+impl Disabled {
+    fn error(&self) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "{} files need file-rotator's `{}` feature",
+                self.suffix, self.suffix
+            ),
+        )
+    }
+}
+
+#[cfg(not(all(feature = "zstd", feature = "lz4", feature = "xz")))]
+impl Compressor for Disabled {
+    fn suffix(&self) -> &str {
+        self.suffix
+    }
+
+    fn compress(&self, _src: &Path, _dst: &Path) -> io::Result<()> {
+        Err(self.error())
+    }
+
+    fn decompress(&self, src: fs::File) -> io::Result<Box<dyn Read + Send>> {
+        drop(src);
+        Err(self.error())
+    }
+}
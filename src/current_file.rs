@@ -0,0 +1,136 @@
+#[cfg(feature = "zstd")]
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+
+#[cfg(feature = "zstd")]
+use super::ZstdOptions;
+use super::{ArchiveSettings, Compression};
+
+// The file being written to, which is compressed as it's written with live compression
+#[derive(Debug)]
+pub(super) enum CurrentFile {
+    Plain(fs::File),
+    #[cfg(feature = "zstd")]
+    Zstd(ZstdFile),
+}
+
+impl CurrentFile {
+    // Wrap a freshly created file so that it's written with `compression`, which is either
+    // `Compression::None` or something that can be written live. It becomes an archive as is,
+    // so it's compressed like one.
+    #[cfg_attr(not(feature = "zstd"), allow(unused_variables))]
+    pub(super) fn new(
+        file: fs::File,
+        compression: &Compression,
+        settings: &ArchiveSettings,
+    ) -> Self {
+        match *compression {
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { level } => CurrentFile::Zstd(ZstdFile {
+                file,
+                level,
+                options: settings.zstd,
+                frame: None,
+            }),
+            _ => CurrentFile::Plain(file),
+        }
+    }
+
+    pub(super) fn file(&mut self) -> io::Result<&fs::File> {
+        match self {
+            CurrentFile::Plain(file) => Ok(file),
+            #[cfg(feature = "zstd")]
+            CurrentFile::Zstd(file) => {
+                file.finish_frame()?;
+                Ok(&file.file)
+            }
+        }
+    }
+
+    // The file itself, leaving any unfinished frame alone
+    pub(super) fn handle(&self) -> &fs::File {
+        match self {
+            CurrentFile::Plain(file) => file,
+            #[cfg(feature = "zstd")]
+            CurrentFile::Zstd(file) => &file.file,
+        }
+    }
+
+    pub(super) fn sync_data(&mut self) -> io::Result<()> {
+        self.file()?.sync_data()
+    }
+
+    pub(super) fn sync_all(&mut self) -> io::Result<()> {
+        self.file()?.sync_all()
+    }
+}
+
+impl Write for CurrentFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            CurrentFile::Plain(file) => file.write(buf),
+            #[cfg(feature = "zstd")]
+            CurrentFile::Zstd(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            CurrentFile::Plain(file) => file.flush(),
+            #[cfg(feature = "zstd")]
+            CurrentFile::Zstd(file) => file.finish_frame(),
+        }
+    }
+}
+
+// A file written as a series of zstd frames, a new one being started after every flush so that
+// everything that's been flushed can be decompressed even if we never get to finish the file
+#[cfg(feature = "zstd")]
+pub(super) struct ZstdFile {
+    file: fs::File,
+    level: i32,
+    options: ZstdOptions,
+    frame: Option<zstd::Encoder<'static, fs::File>>,
+}
+
+#[cfg(feature = "zstd")]
+impl ZstdFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let frame = match &mut self.frame {
+            Some(frame) => frame,
+            None => {
+                let encoder = self.options.encoder(self.file.try_clone()?, self.level)?;
+                self.frame.insert(encoder)
+            }
+        };
+        frame.write(buf)
+    }
+
+    fn finish_frame(&mut self) -> io::Result<()> {
+        match self.frame.take() {
+            Some(frame) => frame.finish().map(drop),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl Drop for ZstdFile {
+    fn drop(&mut self) {
+        // Rotation finishes the frame itself, this is for when we're dropped with data in flight
+        let _ = self.finish_frame();
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl fmt::Debug for ZstdFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ZstdFile")
+            .field("file", &self.file)
+            .field("level", &self.level)
+            .field("options", &self.options)
+            .field("in_frame", &self.frame.is_some())
+            .finish()
+    }
+}
@@ -0,0 +1,107 @@
+use std::mem;
+
+// Collapses runs of identical lines into the first one and a line saying how many times it was
+// repeated, like syslog does. Lines are held back until they're complete, so that they can be
+// compared.
+#[derive(Debug, Default)]
+pub(super) struct Dedup {
+    pub(super) enabled: bool,
+    last: Option<Vec<u8>>,
+    partial: Vec<u8>,
+    repeats: usize,
+}
+
+impl Dedup {
+    fn summarize(&mut self, out: &mut Vec<u8>) {
+        if self.repeats > 0 {
+            out.extend_from_slice(
+                format!("last message repeated {} times\n", self.repeats).as_bytes(),
+            );
+            self.repeats = 0;
+        }
+    }
+
+    // Take in `buf`, returning what should be written now
+    pub(super) fn filter(&mut self, buf: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut partial = mem::take(&mut self.partial);
+        partial.extend_from_slice(buf);
+
+        let mut start = 0;
+        while let Some(end) = partial[start..].iter().position(|&b| b == b'\n') {
+            let line = &partial[start..=start + end];
+            if self.last.as_deref() == Some(line) {
+                self.repeats += 1;
+            } else {
+                self.summarize(&mut out);
+                out.extend_from_slice(line);
+                self.last = Some(line.to_vec());
+            }
+            start += end + 1;
+        }
+
+        partial.drain(..start);
+        self.partial = partial;
+        out
+    }
+
+    // Give up on the line being held back until it's complete, if there's one, returning it along
+    // with the summary of the run before it
+    pub(super) fn flush_partial(&mut self) -> Vec<u8> {
+        if self.partial.is_empty() {
+            return Vec::new();
+        }
+        self.flush()
+    }
+
+    // Give up on everything that's being held back, returning it
+    pub(super) fn flush(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.summarize(&mut out);
+        if !self.partial.is_empty() {
+            // Whatever comes next is the rest of this line, so it can't be a repeat
+            out.append(&mut self.partial);
+            self.last = None;
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Dedup;
+
+    #[test]
+    fn test_dedup() {
+        let mut dedup = Dedup::default();
+        assert_eq!(dedup.filter(b"a\na\n"), b"a\n");
+        assert_eq!(dedup.filter(b"a"), b"");
+        assert_eq!(
+            dedup.filter(b"\nb\nb\na\n"),
+            &b"last message repeated 2 times\nb\nlast message repeated 1 times\na\n"[..]
+        );
+        assert_eq!(dedup.flush(), b"");
+    }
+
+    #[test]
+    fn test_flush() {
+        let mut dedup = Dedup::default();
+        assert_eq!(dedup.filter(b"a\na\na"), b"a\n");
+        assert_eq!(dedup.flush(), &b"last message repeated 1 times\na"[..]);
+        // The line that was cut short isn't a repeat of anything
+        assert_eq!(dedup.filter(b"\na\n"), b"\na\n");
+    }
+
+    #[test]
+    fn test_flush_partial() {
+        let mut dedup = Dedup::default();
+        assert_eq!(dedup.filter(b"a\na\n"), b"a\n");
+        // Runs are kept going if there's no line being held back
+        assert_eq!(dedup.flush_partial(), b"");
+        assert_eq!(dedup.filter(b"a\nb"), b"");
+        assert_eq!(
+            dedup.flush_partial(),
+            &b"last message repeated 2 times\nb"[..]
+        );
+    }
+}
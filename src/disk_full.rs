@@ -0,0 +1,204 @@
+use std::io;
+
+/// What to do with writes once the disk is full, see
+/// [`RotatingFile::with_disk_full_policy`](struct.RotatingFile.html#method.with_disk_full_policy)
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum DiskFullPolicy {
+    /// Fail the write, like any other error
+    #[default]
+    Fail,
+
+    /// Pretend the write went through, counting the bytes that got lost
+    Drop,
+
+    /// Keep up to the given number of bytes in memory, writing them out before anything else once
+    /// there's room again. Writes fail once that's full.
+    Buffer(usize),
+
+    /// Delete the oldest files until the write goes through, never deleting the current one
+    Prune,
+}
+
+// Where writes go, as far as handling a full disk is concerned
+pub(super) trait Target {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>;
+
+    // Delete the oldest file, returning whether there was one
+    fn prune(&mut self) -> io::Result<bool>;
+}
+
+fn is_full(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded
+    )
+}
+
+#[derive(Debug, Default)]
+pub(super) struct DiskFull {
+    pub(super) policy: DiskFullPolicy,
+    buffer: Vec<u8>,
+    dropped: u64,
+}
+
+impl DiskFull {
+    pub(super) fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    // Write `buf` to `target`, following the policy if the disk is full
+    pub(super) fn write<T: Target>(&mut self, target: &mut T, buf: &[u8]) -> io::Result<usize> {
+        // Whatever's been kept in memory goes first, so that writes stay in order
+        while !self.buffer.is_empty() {
+            match target.write(&self.buffer) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(written) => drop(self.buffer.drain(..written)),
+                Err(e) if is_full(&e) => return self.fall_back(target, buf, e),
+                Err(e) => return Err(e),
+            }
+        }
+
+        match target.write(buf) {
+            Err(e) if is_full(&e) => self.fall_back(target, buf, e),
+            result => result,
+        }
+    }
+
+    fn fall_back<T: Target>(
+        &mut self,
+        target: &mut T,
+        buf: &[u8],
+        e: io::Error,
+    ) -> io::Result<usize> {
+        match self.policy {
+            DiskFullPolicy::Fail => Err(e),
+
+            DiskFullPolicy::Drop => {
+                self.dropped += buf.len() as u64;
+                Ok(buf.len())
+            }
+
+            DiskFullPolicy::Buffer(capacity) => {
+                let taken = capacity.saturating_sub(self.buffer.len()).min(buf.len());
+                if taken == 0 && !buf.is_empty() {
+                    return Err(e);
+                }
+                self.buffer.extend_from_slice(&buf[..taken]);
+                Ok(taken)
+            }
+
+            DiskFullPolicy::Prune => loop {
+                if !target.prune()? {
+                    return Err(e);
+                }
+                match target.write(buf) {
+                    Err(e) if is_full(&e) => continue,
+                    result => return result,
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::{DiskFull, DiskFullPolicy, Target};
+
+    // A disk with room for `room` bytes, `files` of them being taken up by one old file each
+    struct Disk {
+        room: usize,
+        files: usize,
+        written: Vec<u8>,
+    }
+
+    impl Target for Disk {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let free = self.room - self.files - self.written.len();
+            if free == 0 && !buf.is_empty() {
+                return Err(io::ErrorKind::StorageFull.into());
+            }
+            let written = free.min(buf.len());
+            self.written.extend_from_slice(&buf[..written]);
+            Ok(written)
+        }
+
+        fn prune(&mut self) -> io::Result<bool> {
+            if self.files == 0 {
+                return Ok(false);
+            }
+            self.files -= 1;
+            Ok(true)
+        }
+    }
+
+    fn write_all(disk_full: &mut DiskFull, disk: &mut Disk, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            let written = disk_full.write(disk, buf)?;
+            buf = &buf[written..];
+        }
+        Ok(())
+    }
+
+    fn full_disk() -> Disk {
+        Disk {
+            room: 6,
+            files: 2,
+            written: b"abcd".to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_fail() {
+        let mut disk_full = DiskFull::default();
+        let e = disk_full.write(&mut full_disk(), b"efgh").unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::StorageFull);
+    }
+
+    #[test]
+    fn test_drop() {
+        let mut disk_full = DiskFull {
+            policy: DiskFullPolicy::Drop,
+            ..DiskFull::default()
+        };
+        let mut disk = full_disk();
+        write_all(&mut disk_full, &mut disk, b"efgh").unwrap();
+        write_all(&mut disk_full, &mut disk, b"ij").unwrap();
+        assert_eq!(disk_full.dropped(), 6);
+        assert_eq!(disk.written, b"abcd");
+    }
+
+    #[test]
+    fn test_buffer() {
+        let mut disk_full = DiskFull {
+            policy: DiskFullPolicy::Buffer(3),
+            ..DiskFull::default()
+        };
+        let mut disk = full_disk();
+        write_all(&mut disk_full, &mut disk, b"ef").unwrap();
+        assert_eq!(disk_full.write(&mut disk, b"gh").unwrap(), 1);
+        assert!(write_all(&mut disk_full, &mut disk, b"h").is_err());
+
+        // Once there's room again, buffered writes go first
+        disk.room = 10;
+        write_all(&mut disk_full, &mut disk, b"h").unwrap();
+        assert_eq!(disk.written, b"abcdefgh");
+        assert_eq!(disk_full.dropped(), 0);
+    }
+
+    #[test]
+    fn test_prune() {
+        let mut disk_full = DiskFull {
+            policy: DiskFullPolicy::Prune,
+            ..DiskFull::default()
+        };
+        let mut disk = full_disk();
+        write_all(&mut disk_full, &mut disk, b"ef").unwrap();
+        assert_eq!(disk.files, 0);
+        assert_eq!(disk.written, b"abcdef");
+        let e = write_all(&mut disk_full, &mut disk, b"g").unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::StorageFull);
+    }
+}
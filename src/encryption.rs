@@ -0,0 +1,261 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, prelude::*};
+use std::path::{Path, PathBuf};
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::stream::{DecryptorBE32, EncryptorBE32};
+use aes_gcm::aead::{KeyInit, OsRng};
+use aes_gcm::Aes256Gcm;
+
+use super::{permissions, run_compressor, ArchiveSettings, Compression, Compressor};
+
+// Encrypted files start with this, followed by the nonce prefix of the stream
+const MAGIC: &[u8; 8] = b"FRAESGCM";
+const NONCE_LEN: usize = 7;
+
+// Plaintext is encrypted in chunks of this size, each followed by its tag. Only the last chunk is
+// shorter (possibly empty), so that a file can't be cut short at a chunk boundary unnoticed.
+const CHUNK: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+
+/// How archives are encrypted, see
+/// [`RotatingFile::with_encryption`](struct.RotatingFile.html#method.with_encryption)
+#[derive(Clone, Copy)]
+#[non_exhaustive]
+pub enum Encryption {
+    /// AES-256-GCM with the given key, in 64 KiB chunks so that archives can be decrypted as
+    /// they're read
+    Aes256Gcm {
+        /// The key, which must be kept somewhere safe as archives can't be read without it
+        key: [u8; 32],
+    },
+}
+
+impl fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            // Keys don't belong in logs
+            Encryption::Aes256Gcm { .. } => f
+                .debug_struct("Aes256Gcm")
+                .field("key", &"<redacted>")
+                .finish(),
+        }
+    }
+}
+
+impl Encryption {
+    fn cipher(&self) -> Aes256Gcm {
+        match self {
+            Encryption::Aes256Gcm { key } => Aes256Gcm::new(key.into()),
+        }
+    }
+}
+
+fn crypto_error() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "can't decrypt archive, it's corrupted or the key is wrong",
+    )
+}
+
+// Read up to `len` bytes into `buf`, stopping short only at the end of `reader`
+fn fill<R: Read>(reader: &mut R, buf: &mut Vec<u8>, len: usize) -> io::Result<()> {
+    buf.clear();
+    reader.take(len as u64).read_to_end(buf).map(drop)
+}
+
+pub(super) fn encrypt<R: Read, W: Write>(
+    mut src: R,
+    mut dst: W,
+    encryption: &Encryption,
+) -> io::Result<W> {
+    let mut nonce = [0; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    dst.write_all(MAGIC)?;
+    dst.write_all(&nonce)?;
+
+    let mut encryptor = EncryptorBE32::from_aead(encryption.cipher(), (&nonce).into());
+    let mut chunk = Vec::with_capacity(CHUNK);
+    loop {
+        fill(&mut src, &mut chunk, CHUNK)?;
+        if chunk.len() < CHUNK {
+            let encrypted = encryptor
+                .encrypt_last(chunk.as_slice())
+                .map_err(|_| io::Error::other("can't encrypt archive"))?;
+            dst.write_all(&encrypted)?;
+            return Ok(dst);
+        }
+        let encrypted = encryptor
+            .encrypt_next(chunk.as_slice())
+            .map_err(|_| io::Error::other("can't encrypt archive"))?;
+        dst.write_all(&encrypted)?;
+    }
+}
+
+// Decrypts a file written by `encrypt` as it's read
+pub(super) struct Decrypting<R> {
+    src: R,
+    // `None` once the last chunk has been decrypted
+    decryptor: Option<DecryptorBE32<Aes256Gcm>>,
+    chunk: Vec<u8>,
+    plain: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> Decrypting<R> {
+    pub(super) fn new(mut src: R, encryption: &Encryption) -> io::Result<Self> {
+        let mut header = [0; MAGIC.len() + NONCE_LEN];
+        src.read_exact(&mut header).map_err(|_| crypto_error())?;
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(crypto_error());
+        }
+        let nonce = &header[MAGIC.len()..];
+        Ok(Self {
+            src,
+            decryptor: Some(DecryptorBE32::from_aead(encryption.cipher(), nonce.into())),
+            chunk: Vec::with_capacity(CHUNK + TAG_LEN),
+            plain: Vec::new(),
+            pos: 0,
+        })
+    }
+}
+
+impl<R: Read> Read for Decrypting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.plain.len() {
+            let decryptor = match &mut self.decryptor {
+                Some(decryptor) => decryptor,
+                None => return Ok(0),
+            };
+            fill(&mut self.src, &mut self.chunk, CHUNK + TAG_LEN)?;
+            self.plain = if self.chunk.len() == CHUNK + TAG_LEN {
+                decryptor.decrypt_next(self.chunk.as_slice())
+            } else {
+                let decryptor = self.decryptor.take().expect("checked above");
+                decryptor.decrypt_last(self.chunk.as_slice())
+            }
+            .map_err(|_| crypto_error())?;
+            self.pos = 0;
+        }
+
+        let read = buf.len().min(self.plain.len() - self.pos);
+        buf[..read].copy_from_slice(&self.plain[self.pos..self.pos + read]);
+        self.pos += read;
+        Ok(read)
+    }
+}
+
+// Compresses files with another compression then encrypts them, as set up by
+// `RotatingFile::with_encryption`
+#[derive(Debug)]
+pub(super) struct Encrypted {
+    inner: Compression,
+    encryption: Encryption,
+    suffix: String,
+    // What the inner compression is done with
+    settings: ArchiveSettings,
+}
+
+impl Encrypted {
+    pub(super) fn new(
+        inner: Compression,
+        encryption: Encryption,
+        settings: &ArchiveSettings,
+    ) -> Self {
+        let suffix = match inner.suffix() {
+            Some(suffix) => format!("{}.enc", suffix),
+            None => "enc".to_owned(),
+        };
+        Self {
+            inner,
+            encryption,
+            suffix,
+            settings: *settings,
+        }
+    }
+}
+
+impl Compressor for Encrypted {
+    fn suffix(&self) -> &str {
+        &self.suffix
+    }
+
+    fn compress(&self, src: &Path, dst: &Path) -> io::Result<()> {
+        if let Compression::None = self.inner {
+            let dst = permissions::create_replacing(dst)?;
+            return encrypt(fs::File::open(src)?, dst, &self.encryption)?.sync_all();
+        }
+
+        let mut compressed = dst.to_owned().into_os_string();
+        compressed.push(".inner");
+        let compressed = PathBuf::from(compressed);
+        let level = match self.inner {
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { level, .. } => level,
+            #[cfg(feature = "xz")]
+            Compression::Xz { preset } => preset as i32,
+            _ => 0,
+        };
+        let result = match &self.inner {
+            Compression::Custom(compressor) => compressor.compress(src, &compressed),
+            Compression::External { command, args, .. } => {
+                run_compressor(command, args, &mut fs::File::open(src)?, &compressed)
+            }
+            inner => inner.encode(
+                &mut fs::File::open(src)?,
+                permissions::create_replacing(&compressed)?,
+                level,
+                &self.settings,
+            ),
+        }
+        .and_then(|()| {
+            let dst = permissions::create_replacing(dst)?;
+            encrypt(fs::File::open(&compressed)?, dst, &self.encryption)?.sync_all()
+        });
+        let _ = fs::remove_file(&compressed);
+        result
+    }
+
+    fn decompress(&self, src: fs::File) -> io::Result<Box<dyn Read + Send>> {
+        let decrypted = Decrypting::new(src, &self.encryption)?;
+        self.inner.decoder(decrypted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read};
+
+    use super::{encrypt, Decrypting, Encryption, CHUNK};
+
+    fn decrypt(encrypted: &[u8], encryption: &Encryption) -> io::Result<Vec<u8>> {
+        let mut decrypted = Vec::new();
+        Decrypting::new(encrypted, encryption)?.read_to_end(&mut decrypted)?;
+        Ok(decrypted)
+    }
+
+    #[test]
+    fn test_encryption() {
+        let encryption = Encryption::Aes256Gcm { key: [7; 32] };
+        for &len in &[0, 1, CHUNK - 1, CHUNK, CHUNK + 1, 2 * CHUNK] {
+            let data = (0..len).map(|i| i as u8).collect::<Vec<_>>();
+            let encrypted = encrypt(&data[..], Vec::new(), &encryption).unwrap();
+            assert_eq!(decrypt(&encrypted, &encryption).unwrap(), data);
+
+            // Tampering with the file, cutting it short or using the wrong key must all fail
+            let mut tampered = encrypted.clone();
+            let last = tampered.len() - 1;
+            tampered[last] ^= 1;
+            assert!(decrypt(&tampered, &encryption).is_err());
+            let chunks = len / CHUNK;
+            for &cut in &[encrypted.len() - 1, 15 + chunks * (CHUNK + 16)] {
+                if cut < encrypted.len() {
+                    assert!(decrypt(&encrypted[..cut], &encryption).is_err());
+                }
+            }
+            let wrong = Encryption::Aes256Gcm { key: [8; 32] };
+            assert!(decrypt(&encrypted, &wrong).is_err());
+        }
+    }
+}
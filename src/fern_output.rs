@@ -0,0 +1,104 @@
+use std::io::{self, Write};
+use std::mem;
+
+use super::{RotatingFile, SharedRotatingFile};
+
+// What fern writes to. Fern formats every record with as many writes as it takes and then
+// flushes, so a record is only written once it's complete, and kept whole with
+// `RotatingFile::write_record`. Flushing the file itself after every record would make it sync
+// to disk every time with `Durability::Flush`, so that's left to its flush policy.
+#[derive(Debug)]
+struct FernWriter {
+    file: SharedRotatingFile,
+    record: Vec<u8>,
+}
+
+impl Write for FernWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.record.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.record.is_empty() {
+            return Ok(());
+        }
+        let record = mem::take(&mut self.record);
+        self.file.lock().write_record(&record)
+    }
+}
+
+impl Drop for FernWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+        let _ = self.file.lock().flush();
+    }
+}
+
+/// Chain to a rotating file with [`fern::Dispatch::chain`], which can still be written to
+/// through other clones
+///
+/// Every record is written in one piece with [`RotatingFile::write_record`], and the file is
+/// flushed when its [`FlushPolicy`](enum.FlushPolicy.html) says so rather than after every
+/// record.
+impl From<SharedRotatingFile> for fern::Output {
+    fn from(file: SharedRotatingFile) -> Self {
+        let writer: Box<dyn Write + Send> = Box::new(FernWriter {
+            file,
+            record: Vec::new(),
+        });
+        writer.into()
+    }
+}
+
+/// Chain to a rotating file with [`fern::Dispatch::chain`], see
+/// [the impl for `SharedRotatingFile`](#impl-From<SharedRotatingFile>-for-Output)
+impl From<RotatingFile> for fern::Output {
+    fn from(file: RotatingFile) -> Self {
+        file.into_shared().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::num::NonZeroUsize;
+
+    use crate::{Compression, RotatingFile, RotationPeriod};
+
+    #[test]
+    fn test_fern() {
+        let directory = tempfile::tempdir().unwrap();
+        let file = RotatingFile::new(
+            "fern",
+            directory.path().to_owned(),
+            RotationPeriod::Lines(1),
+            NonZeroUsize::new(2).unwrap(),
+            Compression::None,
+        )
+        .into_shared();
+        let (_, logger) = fern::Dispatch::new()
+            .format(|out, message, record| {
+                out.finish(format_args!("{} {}", record.level(), message))
+            })
+            .chain(file.clone())
+            .into_log();
+        for message in &["a", "b"] {
+            logger.log(
+                &log::Record::builder()
+                    .level(log::Level::Info)
+                    .args(format_args!("{}", message))
+                    .build(),
+            );
+        }
+
+        let contents = file
+            .lock()
+            .files_matching("*")
+            .unwrap()
+            .iter()
+            .map(|path| fs::read_to_string(path).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(contents, ["INFO b\n", "INFO a\n"]);
+    }
+}
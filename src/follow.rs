@@ -0,0 +1,76 @@
+use std::collections::VecDeque;
+use std::mem;
+use std::sync::mpsc;
+
+/// The lines written to a rotating file from now on, as they're written, see
+/// [`RotatingFile::follow`](struct.RotatingFile.html#method.follow)
+///
+/// Iterating blocks until the next line is complete, and ends once the rotating file is dropped,
+/// with whatever was written after its last line terminator if there's anything. Lines come
+/// without their line terminator.
+#[derive(Debug)]
+pub struct Follow {
+    receiver: mpsc::Receiver<Vec<u8>>,
+    lines: VecDeque<Vec<u8>>,
+    partial: Vec<u8>,
+}
+
+impl Follow {
+    pub(super) fn new(receiver: mpsc::Receiver<Vec<u8>>) -> Self {
+        Self {
+            receiver,
+            lines: VecDeque::new(),
+            partial: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, buf: &[u8]) {
+        for piece in buf.split_inclusive(|&b| b == b'\n') {
+            match piece.strip_suffix(b"\n") {
+                Some(end) => {
+                    self.partial.extend_from_slice(end);
+                    self.lines.push_back(mem::take(&mut self.partial));
+                }
+                None => self.partial.extend_from_slice(piece),
+            }
+        }
+    }
+}
+
+impl Iterator for Follow {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(line) = self.lines.pop_front() {
+                return Some(line);
+            }
+            match self.receiver.recv() {
+                Ok(buf) => self.push(&buf),
+                Err(mpsc::RecvError) if self.partial.is_empty() => return None,
+                Err(mpsc::RecvError) => return Some(mem::take(&mut self.partial)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::Follow;
+
+    #[test]
+    fn test_follow() {
+        let (sender, receiver) = mpsc::channel();
+        let mut follow = Follow::new(receiver);
+        sender.send(b"a\nb".to_vec()).unwrap();
+        sender.send(b"c\n\nd".to_vec()).unwrap();
+        assert_eq!(follow.next().unwrap(), b"a");
+        assert_eq!(follow.next().unwrap(), b"bc");
+        assert_eq!(follow.next().unwrap(), b"");
+        drop(sender);
+        assert_eq!(follow.next().unwrap(), b"d");
+        assert_eq!(follow.next(), None);
+    }
+}
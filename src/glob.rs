@@ -0,0 +1,147 @@
+use std::io;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+    Literal(char),
+    AnyChar,
+    AnySequence,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+/// A shell-style glob pattern, matched against whole file names
+///
+/// Supports `*` (any sequence of characters), `?` (any single character) and character classes
+/// like `[abc]`, `[a-z]` or `[!0-9]`. Use `[*]` and friends to match the special characters
+/// themselves.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) struct Glob {
+    tokens: Vec<Token>,
+}
+
+impl Glob {
+    pub(super) fn new(pattern: &str) -> io::Result<Self> {
+        let mut tokens = Vec::new();
+        let mut chars = pattern.chars();
+
+        while let Some(c) = chars.next() {
+            tokens.push(match c {
+                '*' => Token::AnySequence,
+                '?' => Token::AnyChar,
+                '[' => {
+                    let negated = chars.as_str().starts_with('!');
+                    if negated {
+                        chars.next();
+                    }
+
+                    let mut ranges = Vec::new();
+                    // A `]` right at the start is part of the class rather than its end
+                    let mut first = true;
+                    loop {
+                        let start = match chars.next() {
+                            Some(']') if !first => break,
+                            Some(c) => c,
+                            None => {
+                                return Err(io::Error::new(
+                                    io::ErrorKind::InvalidInput,
+                                    format!("unclosed character class in {:?}", pattern),
+                                ))
+                            }
+                        };
+                        first = false;
+
+                        let rest = chars.as_str();
+                        let end = match rest.strip_prefix('-') {
+                            Some(after) if !after.starts_with(']') && !after.is_empty() => {
+                                chars.next();
+                                chars.next().expect("checked above")
+                            }
+                            _ => start,
+                        };
+                        ranges.push((start, end));
+                    }
+
+                    Token::Class { negated, ranges }
+                }
+                c => Token::Literal(c),
+            });
+        }
+
+        Ok(Self { tokens })
+    }
+
+    pub(super) fn matches(&self, s: &str) -> bool {
+        let chars = s.chars().collect::<Vec<_>>();
+        Self::matches_from(&self.tokens, &chars)
+    }
+
+    fn matches_from(tokens: &[Token], s: &[char]) -> bool {
+        match tokens.split_first() {
+            None => s.is_empty(),
+
+            Some((Token::AnySequence, rest)) => {
+                (0..=s.len()).any(|skip| Self::matches_from(rest, &s[skip..]))
+            }
+
+            Some((token, rest)) => match s.split_first() {
+                Some((&c, s)) => {
+                    let matched = match token {
+                        Token::Literal(literal) => c == *literal,
+                        Token::AnyChar => true,
+                        Token::Class { negated, ranges } => {
+                            ranges
+                                .iter()
+                                .any(|&(start, end)| (start..=end).contains(&c))
+                                != *negated
+                        }
+                        Token::AnySequence => unreachable!(),
+                    };
+                    matched && Self::matches_from(rest, s)
+                }
+                None => false,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Glob;
+
+    #[test]
+    fn test_matches() {
+        let cases = [
+            ("*", "anything", true),
+            ("*", "", true),
+            ("app.*.log", "app.0.log", true),
+            ("app.*.log", "app.0.log.zstd", false),
+            ("app.2024-05-01*", "app.2024-05-01T12-00-00.log", true),
+            ("app.2024-05-01*", "app.2024-05-02T12-00-00.log", false),
+            ("app.?.log", "app.10.log", false),
+            ("app.[0-4].log", "app.3.log", true),
+            ("app.[!0-4].log", "app.3.log", false),
+            ("app.[!0-4].log", "app.7.log", true),
+            ("[]]", "]", true),
+            ("[a-]", "-", true),
+            ("[*]", "*", true),
+            ("[*]", "a", false),
+        ];
+
+        for &(pattern, s, expected) in &cases {
+            assert_eq!(
+                Glob::new(pattern).unwrap().matches(s),
+                expected,
+                "{:?} against {:?}",
+                pattern,
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn test_unclosed_class() {
+        assert!(Glob::new("app.[0-9.log").is_err());
+    }
+}
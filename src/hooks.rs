@@ -0,0 +1,87 @@
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+type Hook = Box<dyn FnMut(&Path) -> Vec<u8> + Send>;
+
+// Gives the bytes to write at the start or the end of the file at the given path
+pub(super) struct FileHook(Hook);
+
+impl FileHook {
+    pub(super) fn new<F>(hook: F) -> Self
+    where
+        F: FnMut(&Path) -> Vec<u8> + Send + 'static,
+    {
+        Self(Box::new(hook))
+    }
+
+    pub(super) fn call(&mut self, path: &Path) -> Vec<u8> {
+        (self.0)(path)
+    }
+}
+
+impl fmt::Debug for FileHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("FileHook")
+    }
+}
+
+// Called with every event of some kind
+pub(super) struct Callback<T: ?Sized>(Box<dyn FnMut(&T) + Send>);
+
+impl<T: ?Sized> Callback<T> {
+    pub(super) fn new<F>(callback: F) -> Self
+    where
+        F: FnMut(&T) + Send + 'static,
+    {
+        Self(Box::new(callback))
+    }
+
+    pub(super) fn call(&mut self, event: &T) {
+        (self.0)(event)
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for Callback<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Callback")
+    }
+}
+
+// Gets a copy of everything written, ignoring its errors so that they never get in the way of the
+// rotating file itself
+pub(super) struct Tee(Box<dyn Write + Send>);
+
+impl Tee {
+    pub(super) fn new<W>(writer: W) -> Self
+    where
+        W: Write + Send + 'static,
+    {
+        Self(Box::new(writer))
+    }
+
+    pub(super) fn write(&mut self, buf: &[u8]) {
+        let _ = self.0.write_all(buf);
+    }
+
+    pub(super) fn flush(&mut self) {
+        let _ = self.0.flush();
+    }
+}
+
+impl fmt::Debug for Tee {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Tee")
+    }
+}
+
+/// A file having been rotated out, see
+/// [`RotatingFile::with_on_rotate`](struct.RotatingFile.html#method.with_on_rotate)
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct RotationEvent {
+    /// Where the file that was rotated out is now, if there was one
+    pub rotated_out: Option<PathBuf>,
+    /// The new current file
+    pub current: PathBuf,
+}
@@ -0,0 +1,25 @@
+// The machine's hostname, for the `{hostname}` placeholder. Falls back to `localhost` if it
+// can't be found out, since that's still better than failing to log at all.
+pub(super) fn hostname() -> String {
+    imp().unwrap_or_else(|| "localhost".to_owned())
+}
+
+#[cfg(unix)]
+fn imp() -> Option<String> {
+    let mut buf = vec![0u8; 256];
+    // SAFETY: the buffer is valid for writes of its whole length
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+        return None;
+    }
+    // POSIX doesn't guarantee NUL-termination on truncation, so look for it ourselves
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    buf.truncate(len);
+    String::from_utf8(buf).ok().filter(|name| !name.is_empty())
+}
+
+#[cfg(not(unix))]
+fn imp() -> Option<String> {
+    std::env::var("COMPUTERNAME")
+        .ok()
+        .filter(|name| !name.is_empty())
+}
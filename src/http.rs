@@ -0,0 +1,210 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use flate2::{read::GzEncoder, Compression as GzCompression};
+
+use super::shipping;
+use super::snapshot::Archived;
+use super::stats::Counters;
+use super::RotatingFile;
+
+/// Sends every file that's rotated out to an HTTP endpoint from a background thread, for
+/// centralizing logs without running an agent, see [`spawn`](#method.spawn)
+///
+/// Each file is `POST`ed on its own with `Content-Encoding: gzip`, compressed on the way if it
+/// isn't a `.gz` already, and its name in `Content-Disposition`. Anything but a successful
+/// response is retried.
+///
+/// Files are sent once they're done being compressed, from a hard link to them in the hidden
+/// `.NAME.snapshots` directory next to them that later rotations don't move on or delete, and
+/// which is removed once they're sent. Files that are rotated out to the same name every time,
+/// e.g. with [`Naming::Index`](enum.Naming.html#variant.Index), are all sent under that name.
+#[derive(Clone)]
+pub struct HttpShipper {
+    url: String,
+    headers: Vec<(String, String)>,
+    retries: usize,
+    retry_delay: Duration,
+}
+
+impl fmt::Debug for HttpShipper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Headers are left out since they're likely to hold credentials
+        f.debug_struct("HttpShipper")
+            .field("url", &self.url)
+            .field("retries", &self.retries)
+            .field("retry_delay", &self.retry_delay)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HttpShipper {
+    /// Send files to `url`, which can be `http` or `https`
+    pub fn new<S>(url: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            url: url.into(),
+            headers: Vec::new(),
+            retries: 5,
+            retry_delay: Duration::from_secs(1),
+        }
+    }
+
+    /// Add a header to every request, like `Authorization: Bearer ...`
+    pub fn with_header<N, V>(mut self, name: N, value: V) -> Self
+    where
+        N: Into<String>,
+        V: Into<String>,
+    {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Try again up to `retries` times when sending a file fails, waiting `delay` before the first
+    /// retry and twice as long before every one after that, 5 times starting at a second by
+    /// default
+    pub fn with_retries(mut self, retries: usize, delay: Duration) -> Self {
+        self.retries = retries;
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Start sending whatever `file` rotates out from now on, from a background thread
+    ///
+    /// The thread stops once `file` is dropped and everything it rotated out has been sent. Files
+    /// that couldn't be sent show up in the rotating file's [`Stats`](struct.Stats.html).
+    pub fn spawn(self, file: &mut RotatingFile) -> io::Result<thread::JoinHandle<()>> {
+        let archives = file.subscribe_archived();
+        let stats = Arc::clone(&file.stats);
+        thread::Builder::new()
+            .name("file-rotator shipper".into())
+            .spawn(move || self.work(&archives, &stats))
+    }
+
+    fn work(&self, archives: &mpsc::Receiver<Arc<Archived>>, stats: &Counters) {
+        let agent = ureq::AgentBuilder::new().timeout(shipping::TIMEOUT).build();
+        for archived in archives {
+            // Errors end up in the statistics, there's nobody else to give them to
+            let _ = stats.record(shipping::retrying(self.retries, self.retry_delay, || {
+                self.send(&agent, &archived)
+            }));
+        }
+    }
+
+    fn send(&self, agent: &ureq::Agent, archived: &Archived) -> io::Result<()> {
+        let name = archived.name();
+        let path = &archived.snapshot;
+        let body = if path.extension().is_some_and(|extension| extension == "gz") {
+            fs::read(path)?
+        } else {
+            let mut body = Vec::new();
+            GzEncoder::new(fs::File::open(path)?, GzCompression::default())
+                .read_to_end(&mut body)?;
+            body
+        };
+
+        let request = self.headers.iter().fold(
+            agent
+                .post(&self.url)
+                .set("content-type", "application/octet-stream")
+                .set("content-encoding", "gzip")
+                .set(
+                    "content-disposition",
+                    &format!("attachment; filename=\"{}\"", name.replace('"', "\\\"")),
+                ),
+            |request, (name, value)| request.set(name, value),
+        );
+        request
+            .send_bytes(&body)
+            .map(drop)
+            .map_err(|e| shipping::to_io_error(&format!("sending {}", archived.path.display()), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::num::NonZeroUsize;
+    use std::thread;
+    use std::time::Duration;
+
+    use flate2::read::GzDecoder;
+
+    use super::HttpShipper;
+    use crate::{Compression, RotatingFile, RotationPeriod};
+
+    #[test]
+    fn test_ship() {
+        // Turns the first request down, to see that it's retried
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/logs", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for status in &["503 Service Unavailable", "200 OK"] {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut head = String::new();
+                while !head.ends_with("\r\n\r\n") {
+                    reader.read_line(&mut head).unwrap();
+                }
+                let len = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Content-Length: "))
+                    .unwrap()
+                    .parse()
+                    .unwrap();
+                let mut body = vec![0; len];
+                reader.read_exact(&mut body).unwrap();
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+                requests.push((head, body));
+            }
+            requests
+        });
+
+        let directory = tempfile::tempdir().unwrap();
+        let mut file = RotatingFile::new(
+            "ship",
+            directory.path().to_owned(),
+            RotationPeriod::Manual,
+            NonZeroUsize::new(2).unwrap(),
+            Compression::None,
+        );
+        let shipper = HttpShipper::new(url)
+            .with_header("authorization", "Bearer hunter2")
+            .with_retries(1, Duration::from_millis(1))
+            .spawn(&mut file)
+            .unwrap();
+        file.write_all(b"hello\n").unwrap();
+        file.rotate().unwrap();
+        drop(file);
+        shipper.join().unwrap();
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 2);
+        let (head, body) = &requests[1];
+        assert!(head.starts_with("POST /logs HTTP/1.1\r\n"));
+        assert!(head.contains("\r\nauthorization: Bearer hunter2\r\n"));
+        assert!(head.contains("\r\ncontent-encoding: gzip\r\n"));
+        assert!(head.contains("\r\ncontent-disposition: attachment; filename=\"ship.1.log\"\r\n"));
+        let mut contents = String::new();
+        GzDecoder::new(&body[..])
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "hello\n");
+        assert!(directory.path().join("ship.1.log").exists());
+        let snapshots = directory.path().join(".ship.snapshots");
+        assert_eq!(std::fs::read_dir(snapshots).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,134 @@
+use std::io::{self, Write};
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+
+const SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Sends every line written to it to systemd-journald with its native protocol, for mirroring a
+/// rotating file to the journal with
+/// [`RotatingFile::with_tee`](struct.RotatingFile.html#method.with_tee)
+///
+/// Lines are sent as they're completed, and whatever is left of an incomplete line is sent when
+/// flushing or dropping this. Every entry gets `MESSAGE`, `PRIORITY` and `SYSLOG_IDENTIFIER`
+/// fields, so `journalctl -t IDENTIFIER` shows them. Lines too long for a datagram, usually
+/// around 200KiB, fail to be sent.
+///
+/// ```no_run
+/// # use std::num::NonZeroUsize;
+/// # use file_rotator::{Compression, Journald, RotatingFile, RotationPeriod};
+/// let file = RotatingFile::new(
+///     "app",
+///     "logs",
+///     RotationPeriod::Bytes(10 * 1024 * 1024),
+///     NonZeroUsize::new(5).unwrap(),
+///     Compression::None,
+/// )
+/// .with_tee(Journald::new("app")?);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Journald {
+    socket: UnixDatagram,
+    identifier: String,
+    priority: u8,
+    partial: Vec<u8>,
+}
+
+impl Journald {
+    /// Connect to the journal, sending entries with the given `SYSLOG_IDENTIFIER`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if journald's socket can't be connected to, e.g. because it's not running.
+    pub fn new<Identifier>(identifier: Identifier) -> io::Result<Self>
+    where
+        Identifier: Into<String>,
+    {
+        Self::connect(Path::new(SOCKET), identifier.into())
+    }
+
+    fn connect(socket: &Path, identifier: String) -> io::Result<Self> {
+        let datagram = UnixDatagram::unbound()?;
+        datagram.connect(socket)?;
+        Ok(Self {
+            socket: datagram,
+            identifier,
+            priority: 6,
+            partial: Vec::new(),
+        })
+    }
+
+    /// Send entries with the given syslog level, from 0 (`emerg`) to 7 (`debug`), instead of 6
+    /// (`info`)
+    pub fn with_priority(mut self, priority: u8) -> Self {
+        self.priority = priority.min(7);
+        self
+    }
+
+    fn send(&self, line: &[u8]) -> io::Result<()> {
+        let mut entry = format!(
+            "PRIORITY={}\nSYSLOG_IDENTIFIER={}\nMESSAGE=",
+            self.priority, self.identifier
+        )
+        .into_bytes();
+        entry.extend_from_slice(line);
+        entry.push(b'\n');
+        self.socket.send(&entry).map(drop)
+    }
+}
+
+impl Write for Journald {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.partial.extend_from_slice(buf);
+        let mut start = 0;
+        let mut sent = Ok(());
+        while let Some(end) = self.partial[start..].iter().position(|&b| b == b'\n') {
+            sent = sent.and(self.send(&self.partial[start..start + end]));
+            start += end + 1;
+        }
+        self.partial.drain(..start);
+        sent.map(|()| buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.partial.is_empty() {
+            return Ok(());
+        }
+        let sent = self.send(&self.partial);
+        self.partial.clear();
+        sent
+    }
+}
+
+impl Drop for Journald {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::os::unix::net::UnixDatagram;
+
+    use super::Journald;
+
+    #[test]
+    fn test_journald() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("socket");
+        let journal = UnixDatagram::bind(&path).unwrap();
+        let mut journald = Journald::connect(&path, "test".to_owned())
+            .unwrap()
+            .with_priority(3);
+
+        journald.write_all(b"a\nb").unwrap();
+        journald.flush().unwrap();
+        let mut buf = [0; 128];
+        for message in &["a", "b"] {
+            let len = journal.recv(&mut buf).unwrap();
+            let expected = format!("PRIORITY=3\nSYSLOG_IDENTIFIER=test\nMESSAGE={}\n", message);
+            assert_eq!(&buf[..len], expected.as_bytes());
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::fmt;
+use std::io::Write;
+
+use log::{Level, Log, Metadata, Record};
+
+use super::SharedRotatingFile;
+
+type Format = Box<dyn Fn(&Record) -> String + Send + Sync>;
+
+/// A logger that writes records of different levels to different rotating files, each with
+/// rotation and retention settings of its own
+///
+/// Records go to the file set for their level with [`with_level`](#method.with_level), and to
+/// the default one otherwise. Every record is written with
+/// [`RotatingFile::write_record`](struct.RotatingFile.html#method.write_record), formatted as
+/// `LEVEL target: message` unless [`with_format`](#method.with_format) says otherwise.
+///
+/// ```no_run
+/// # use std::num::NonZeroUsize;
+/// # use file_rotator::{Compression, LevelSplit, RotatingFile, RotationPeriod};
+/// let file = |name, max_index| {
+///     RotatingFile::new(
+///         name,
+///         "logs",
+///         RotationPeriod::Bytes(10 * 1024 * 1024),
+///         NonZeroUsize::new(max_index).unwrap(),
+///         Compression::None,
+///     )
+/// };
+/// let logger =
+///     LevelSplit::new(file("app.info", 5)).with_level(log::Level::Error, file("app.error", 50));
+/// log::set_boxed_logger(Box::new(logger)).unwrap();
+/// log::set_max_level(log::LevelFilter::Info);
+/// ```
+pub struct LevelSplit {
+    default: SharedRotatingFile,
+    files: Vec<(Level, SharedRotatingFile)>,
+    format: Format,
+}
+
+impl LevelSplit {
+    /// Write records to the given rotating file, unless another one is set for their level
+    pub fn new<File>(default: File) -> Self
+    where
+        File: Into<SharedRotatingFile>,
+    {
+        Self {
+            default: default.into(),
+            files: Vec::new(),
+            format: Box::new(|record| {
+                format!(
+                    "{} {}: {}\n",
+                    record.level(),
+                    record.target(),
+                    record.args()
+                )
+            }),
+        }
+    }
+
+    /// Write records of the given level to the given rotating file
+    ///
+    /// Clones of the same [`SharedRotatingFile`] can be given for many levels, so that they share
+    /// a file.
+    pub fn with_level<File>(mut self, level: Level, file: File) -> Self
+    where
+        File: Into<SharedRotatingFile>,
+    {
+        self.files.retain(|&(other, _)| other != level);
+        self.files.push((level, file.into()));
+        self
+    }
+
+    /// Format records with the given function, which should end them with a line terminator
+    pub fn with_format<F>(mut self, format: F) -> Self
+    where
+        F: Fn(&Record) -> String + Send + Sync + 'static,
+    {
+        self.format = Box::new(format);
+        self
+    }
+
+    /// The rotating file that records of the given level are written to
+    pub fn file(&self, level: Level) -> &SharedRotatingFile {
+        self.files
+            .iter()
+            .find(|&&(other, _)| other == level)
+            .map_or(&self.default, |(_, file)| file)
+    }
+
+    fn all_files(&self) -> impl Iterator<Item = &SharedRotatingFile> {
+        std::iter::once(&self.default).chain(self.files.iter().map(|(_, file)| file))
+    }
+}
+
+impl Log for LevelSplit {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        // Formatting before locking the file, in case formatting logs something itself
+        let formatted = (self.format)(record);
+        // There's nowhere to report it
+        let _ = self
+            .file(record.level())
+            .lock()
+            .write_record(formatted.as_bytes());
+    }
+
+    fn flush(&self) {
+        for mut file in self.all_files() {
+            let _ = file.flush();
+        }
+    }
+}
+
+impl fmt::Debug for LevelSplit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LevelSplit")
+            .field("default", &self.default)
+            .field("files", &self.files)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::num::NonZeroUsize;
+    use std::path::Path;
+
+    use log::{Level, Log};
+
+    use super::LevelSplit;
+    use crate::{Compression, RotatingFile, RotationPeriod, SharedRotatingFile};
+
+    fn file(directory: &Path, name: &'static str) -> SharedRotatingFile {
+        RotatingFile::new(
+            name,
+            directory.to_owned(),
+            RotationPeriod::Manual,
+            NonZeroUsize::new(1).unwrap(),
+            Compression::None,
+        )
+        .into_shared()
+    }
+
+    fn contents(file: &SharedRotatingFile) -> String {
+        let path = file.lock().files_matching("*").unwrap().remove(0);
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn test_level_split() {
+        let directory = tempfile::tempdir().unwrap();
+        let info = file(directory.path(), "info");
+        let error = file(directory.path(), "error");
+        let logger = LevelSplit::new(info.clone())
+            .with_level(Level::Warn, error.clone())
+            .with_level(Level::Error, error.clone());
+        for &(level, message) in &[(Level::Info, "a"), (Level::Error, "b"), (Level::Warn, "c")] {
+            logger.log(
+                &log::Record::builder()
+                    .level(level)
+                    .target("app")
+                    .args(format_args!("{}", message))
+                    .build(),
+            );
+        }
+        logger.flush();
+
+        assert_eq!(contents(&info), "INFO app: a\n");
+        assert_eq!(contents(&error), "ERROR app: b\nWARN app: c\n");
+    }
+}
@@ -27,6 +27,56 @@
 //!     Compression::None,
 //! );
 //! ```
+//!
+//! # Cargo features
+//!
+//! None of the compression algorithms are built by default, so that using [`Compression::None`]
+//! doesn't pull in any compression library:
+//!
+//! - `zstd`: [`Compression::Zstd`]
+//! - `lz4`: [`Compression::Lz4`]
+//! - `xz`: [`Compression::Xz`]
+//!
+//! Files compressed with an algorithm whose feature isn't enabled are still recognized, so that
+//! they get renamed and deleted like any other, but they can't be read nor recompressed.
+//!
+//! Other features are:
+//!
+//! - `bundle`: bundle old archives into one `.tar.gz` per day, see
+//!   `RotatingFile::with_bundles`, or export everything as one with
+//!   `RotatingFile::export_bundle`
+//! - `checksum`: SHA-256 checksum sidecars, hash chains and checking archives after compressing
+//!   them, see `RotatingFile::with_checksums`, `RotatingFile::with_hash_chain` and
+//!   `RotatingFile::with_compression_verification`
+//! - `encryption`: encrypt archives with AES-256-GCM, see `RotatingFile::with_encryption`
+//! - `fern`: chain fern dispatchers to rotating files, which become a `fern::Output`
+//! - `futures`: implement `futures::io::AsyncWrite` for [`RotatingFile`] and the ways of sharing
+//!   it, for async-std, smol and other runtimes. There's no tokio `AsyncWrite`, go through
+//!   `tokio_util::compat` for that.
+//!
+//!   **These block the executor**: writing, and rotating when it's time to (which renames,
+//!   compresses and deletes files), happen right away on the task's thread, as if the write was
+//!   a regular one, and `poll_write` never returns `Poll::Pending`. In an async program, write
+//!   through the [`NonBlocking`] handle from `RotatingFile::non_blocking`, which only queues
+//!   things up for a background thread, or move the writes over to the runtime's blocking
+//!   threads (e.g. `spawn_blocking`).
+//! - `http`: send files as they're rotated out to an HTTP endpoint, see `HttpShipper`
+//! - `journald`: mirror what's written to systemd-journald, on unix, see `Journald`
+//! - `log`: a logger writing records to different rotating files depending on their level, see
+//!   `LevelSplit`
+//! - `log4rs`: a log4rs appender, see `Log4rsAppender`
+//! - `manifest`: keep a manifest of every archive, see `RotatingFile::with_manifest`
+//! - `metrics`: report bytes written, files deleted and how long rotations take through the
+//!   `metrics` facade, as `file_rotator_bytes_written`, `file_rotator_files_deleted` and
+//!   `file_rotator_rotation_duration_seconds`, labelled with the name of the file
+//! - `parse`: parse durations, sizes and [`RotationPeriod`]s from human-friendly strings like
+//!   `"15m"` or `"500MB"`, for configuration files and command lines (see `parse_duration` and
+//!   `parse_size`)
+//! - `s3`: upload files as they're rotated out to an S3-compatible bucket, see `S3Uploader`
+//! - `signals`: flush registered files before the process is ended by `SIGINT` or `SIGTERM`,
+//!   see `flush_on_signal`
+//! - `tracing`: put rotations, compression and the deletion of old files in tracing spans, with
+//!   events giving file sizes and how long they took
 
 #![warn(
     missing_docs,
@@ -35,11 +85,14 @@
 )]
 
 use std::borrow::Cow;
+use std::ffi::OsString;
 use std::fs;
 use std::io::{self, prelude::*};
 use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::process;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, SystemTime};
 
 /// A specifier for how often we should rotate files
 #[derive(Clone, Copy, Debug, Ord, PartialOrd, Eq, PartialEq)]
@@ -73,18 +126,368 @@ pub enum RotationPeriod {
 mod rotation_tracker;
 use rotation_tracker::RotationTracker;
 
+mod timestamp;
+
+mod naming;
+pub use naming::{FileKey, IndexDirection, Naming, NamingScheme};
+
+mod template;
+pub use template::Template;
+
+mod glob;
+use glob::Glob;
+
+mod permissions;
+pub use permissions::ArchivePermissions;
+
+mod maintenance;
+use maintenance::{ArchiveSettings, Job, Maintenance};
+
+mod snapshot;
+use snapshot::{Archived, Pending};
+
+mod hostname;
+
+#[cfg(any(feature = "checksum", feature = "manifest"))]
+mod checksum;
+
+#[cfg(feature = "checksum")]
+mod chain;
+
+#[cfg(feature = "manifest")]
+mod manifest;
+#[cfg(feature = "manifest")]
+use manifest::Manifest;
+
+mod compressor;
+pub use compressor::Compressor;
+
+mod locking;
+
+mod current_file;
+use current_file::CurrentFile;
+
+#[cfg(feature = "zstd")]
+mod zstd_options;
+#[cfg(feature = "zstd")]
+pub use zstd_options::ZstdOptions;
+
+#[cfg(feature = "zstd")]
+mod seekable;
+
+#[cfg(feature = "encryption")]
+mod encryption;
+#[cfg(feature = "encryption")]
+pub use encryption::Encryption;
+
+#[cfg(feature = "bundle")]
+mod bundle;
+
+mod spool;
+use spool::Spool;
+pub use spool::SpoolEvent;
+
+#[cfg(unix)]
+mod syslog;
+#[cfg(unix)]
+pub use syslog::Syslog;
+
+#[cfg(all(unix, feature = "journald"))]
+mod journald;
+#[cfg(all(unix, feature = "journald"))]
+pub use journald::Journald;
+
+mod hooks;
+pub use hooks::RotationEvent;
+use hooks::{Callback, FileHook, Tee};
+
+mod post_rotate;
+pub use post_rotate::PostRotate;
+
+mod audit;
+pub use audit::{AuditAction, AuditEvent};
+
+mod stats;
+use stats::Counters;
+pub use stats::{DiskUsage, Stats};
+
+mod disk_full;
+use disk_full::DiskFull;
+pub use disk_full::DiskFullPolicy;
+
+mod line_length;
+use line_length::LineLength;
+pub use line_length::LongLines;
+
+mod line_timestamps;
+pub use line_timestamps::TimestampFormat;
+use line_timestamps::Timestamps;
+
+mod transform;
+use transform::Transform;
+
+mod dedup;
+use dedup::Dedup;
+
+mod rate_limit;
+pub use rate_limit::RateLimit;
+use rate_limit::RateLimiter;
+
+mod shared;
+pub use shared::SharedRotatingFile;
+
+mod registry;
+pub use registry::flush_all;
+
+mod reader;
+pub use reader::LogReader;
+
+mod follow;
+pub use follow::Follow;
+
+mod search;
+pub use search::{Match, Search};
+
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "http")]
+pub use http::HttpShipper;
+
+#[cfg(feature = "s3")]
+mod s3;
+#[cfg(feature = "s3")]
+pub use s3::S3Uploader;
+
+#[cfg(any(feature = "http", feature = "s3"))]
+mod shipping;
+
+#[cfg(feature = "signals")]
+mod signals;
+#[cfg(feature = "signals")]
+pub use signals::flush_on_signal;
+
+mod panic_hook;
+
+mod non_blocking;
+pub use non_blocking::{Backpressure, NonBlocking, NonBlockingOptions, WorkerGuard};
+
+#[cfg(feature = "futures")]
+mod async_write;
+
+#[cfg(feature = "fern")]
+mod fern_output;
+
+#[cfg(feature = "log")]
+mod level_split;
+#[cfg(feature = "log")]
+pub use level_split::LevelSplit;
+
+#[cfg(feature = "log4rs")]
+mod log4rs_appender;
+#[cfg(feature = "log4rs")]
+pub use log4rs_appender::Log4rsAppender;
+
+mod thread_buffered;
+pub use thread_buffered::{MergeGuard, ThreadBuffered};
+
+#[cfg(feature = "parse")]
+mod parse;
+#[cfg(feature = "parse")]
+pub use parse::{parse_duration, parse_size};
+
+// A file in our directory which is part of our rotation set
+#[derive(Debug)]
+struct LogFile {
+    path: PathBuf,
+    key: FileKey,
+    // How the file is compressed, as told by its suffix
+    compression: Compression,
+}
+
+// What a rotation did besides creating a new file
+#[derive(Debug, Default)]
+struct Rotation {
+    // Compression left to the background maintenance
+    jobs: Vec<Job>,
+    // Where the previous current file ended up
+    rotated_out: Option<PathBuf>,
+    // Where it's going to end up once the background maintenance has compressed it
+    compressing: Option<PathBuf>,
+    // Files deleted to make room
+    evicted: Vec<PathBuf>,
+    // Files deleted once they were put in a bundle
+    bundled: Vec<PathBuf>,
+    // Where files were moved from and to, counting where the background maintenance is going to
+    // put them
+    #[cfg(feature = "manifest")]
+    moved: Vec<(PathBuf, PathBuf)>,
+}
+
+// Pipe `src` through `command` into `dst`, failing if it doesn't exit successfully
+fn run_compressor(
+    command: &OsString,
+    args: &[OsString],
+    src: &mut impl Read,
+    dst: &Path,
+) -> io::Result<()> {
+    let mut child = process::Command::new(command)
+        .args(args)
+        .stdin(process::Stdio::piped())
+        .stdout(permissions::create_replacing(dst)?)
+        .spawn()?;
+    let copied = io::copy(src, &mut child.stdin.take().expect("stdin is piped"));
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "{} exited with {}",
+            command.to_string_lossy(),
+            status
+        )));
+    }
+    copied.map(drop)
+}
+
+// Make sure that what's been created in or renamed into the directory of `path` would still be
+// there after a crash, which on Unix takes syncing the directory itself. Directories can't be
+// synced elsewhere.
+#[cfg_attr(not(unix), allow(unused_variables))]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        fs::File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
+
+// Flush the current file, also syncing it to disk if `durability` says so
+fn flush_current(file: &mut CurrentFile, durability: Durability) -> io::Result<()> {
+    file.flush()?;
+    if durability == Durability::Flush {
+        file.sync_all()?;
+    }
+    Ok(())
+}
+
+// Overwrite the contents of the file at `path` with zeroes, making sure they've hit the disk
+fn overwrite_file(path: &Path) -> io::Result<()> {
+    let mut file = permissions::no_follow(fs::OpenOptions::new().write(true)).open(path)?;
+    let zeroes = [0; 64 * 1024];
+    let mut left = file.metadata()?.len();
+    while left > 0 {
+        let chunk = left.min(zeroes.len() as u64) as usize;
+        file.write_all(&zeroes[..chunk])?;
+        left -= chunk as u64;
+    }
+    file.sync_all()
+}
+
+impl LogFile {
+    // Open the file for reading, decompressing it if needed
+    fn open(&self) -> io::Result<Box<dyn Read + Send>> {
+        let file = fs::File::open(&self.path)?;
+        match &self.compression {
+            // A file compressed live is empty until something has been written to it
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { .. } if file.metadata()?.len() == 0 => Ok(Box::new(io::empty())),
+            Compression::Custom(compressor) => compressor.decompress(file),
+            compression => compression.decoder(file),
+        }
+    }
+}
+
 /// As per the name, a rotating file
 ///
 /// Handles being a fake file which will automagicaly rotate as bytes are written into it
+///
+/// This can't be cloned like a [`fs::File`], since every copy would keep track of rotating on its
+/// own. To hand it to more than one owner, turn it into a [`SharedRotatingFile`] with
+/// [`RotatingFile::into_shared`], or into a [`NonBlocking`] with [`RotatingFile::non_blocking`]:
+/// their clones all write to the same rotating file.
 #[derive(Debug)]
 pub struct RotatingFile {
     name: Cow<'static, str>,
     directory: PathBuf,
     rotation_tracker: RotationTracker,
+    // Counts what's been written since the last flush, following a `FlushPolicy`
+    flush_tracker: RotationTracker,
+    line_boundaries: bool,
+    exact_size: bool,
+    locking: bool,
+    reopen_on_move: bool,
+    copy_truncate: bool,
+    // Held for as long as this is around, see `RotatingFile::lock_exclusively`
+    exclusive_lock: Option<fs::File>,
+    // Whether to keep writing to the newest file instead of starting a new one, which is only done
+    // for the first file we open
+    resume: bool,
+    // Whether the last byte written to the current file wasn't a line terminator
+    mid_line: bool,
     max_index: usize,
 
     compression: Compression,
-    current_file: Option<fs::File>,
+    naming: Box<dyn NamingScheme>,
+    index_direction: IndexDirection,
+    date_directories: Option<Template>,
+    extension: Cow<'static, str>,
+    latest_link: bool,
+    archive_permissions: ArchivePermissions,
+    // Permissions and ownership for the files and directories we create
+    file_permissions: ArchivePermissions,
+    directory_permissions: ArchivePermissions,
+    create_directory: bool,
+    share_mode: Option<u32>,
+    retention_held: bool,
+    compression_tiers: Vec<CompressionTier>,
+    compression_timing: CompressionTiming,
+    background_compression: bool,
+    #[cfg(feature = "zstd")]
+    live_compression: bool,
+    #[cfg(feature = "zstd")]
+    zstd_options: ZstdOptions,
+    // The compression and encryption given to `with_encryption`, to redo it with later settings
+    #[cfg(feature = "encryption")]
+    encryption: Option<(Compression, Encryption)>,
+    #[cfg(feature = "checksum")]
+    checksums: bool,
+    #[cfg(feature = "checksum")]
+    verify_compression: bool,
+    #[cfg(feature = "checksum")]
+    hash_chain: Option<chain::Key>,
+    #[cfg(feature = "manifest")]
+    manifest: Option<Manifest>,
+    secure_deletion: bool,
+    hardened: bool,
+    durability: Durability,
+    #[cfg(feature = "bundle")]
+    bundle_after: Option<usize>,
+    maintenance: Maintenance,
+    spool: Spool,
+    disk_full: DiskFull,
+    rate_limit: RateLimiter,
+    transform: Transform,
+    line_length: LineLength,
+    dedup: Dedup,
+    timestamps: Timestamps,
+    tee: Option<Tee>,
+    followers: Vec<mpsc::Sender<Vec<u8>>>,
+    // How many times in a row the current file may be reopened after becoming unusable, and how
+    // many times it has been
+    reopen_limit: usize,
+    reopen_attempts: usize,
+    header: Option<FileHook>,
+    footer: Option<FileHook>,
+    on_rotate: Option<Callback<RotationEvent>>,
+    subscribers: Vec<mpsc::Sender<RotationEvent>>,
+    // Those who want the files rotated out once they're done, and how many have been handed out
+    archive_subscribers: snapshot::Subscribers,
+    snapshots: u64,
+    stats: Arc<Counters>,
+    on_evict: Option<Callback<Path>>,
+    audit_log: bool,
+    on_audit: Option<Callback<AuditEvent>>,
+    current_file: Option<CurrentFile>,
+    current_path: Option<PathBuf>,
+    current_offset: u64,
 }
 
 /// What compression algorithm should be used?
@@ -92,256 +495,6606 @@ pub struct RotatingFile {
 /// The current log file (`NAME.0.log`) is always written uncompressed; once its time to rotate
 /// out, compression will be applied. Depending on compression type, an extra extension might be
 /// added.
-#[derive(Clone, Copy, Debug)]
+///
+/// Each algorithm is only available with its cargo feature, see the [crate docs](index.html).
+#[derive(Clone, Debug)]
+#[non_exhaustive]
 pub enum Compression {
     /// No compression, just bytes to disk.
     None,
-    /// Zstd compression.
+    /// Zstd compression, with the `zstd` feature.
+    #[cfg(feature = "zstd")]
     Zstd {
         /// What level of compression should be used? As per the zstd crate's docs, zero means default.
         level: i32,
     },
+    /// LZ4 compression, using the LZ4 frame format, with the `lz4` feature.
+    ///
+    /// This compresses a lot faster than zstd at the price of bigger archives, which is a good
+    /// trade-off when rotation speed matters most. There are no levels, so
+    /// [`RotatingFile::with_compression_tiers`] has no effect with it.
+    #[cfg(feature = "lz4")]
+    Lz4,
+    /// XZ compression, for long-term archival where the compression ratio matters most, with the
+    /// `xz` feature.
+    ///
+    /// Archives are regular `.xz` files which standard `xz` tooling can read. This is a lot slower
+    /// than the other algorithms, so consider [`CompressionTiming`] to keep it off the rotation
+    /// itself.
+    #[cfg(feature = "xz")]
+    Xz {
+        /// The compression preset, from 0 (fastest) to 9 (best compression), 6 being `xz`'s
+        /// default.
+        preset: u32,
+    },
+    /// A user-provided compression format, see [`Compressor`].
+    Custom(Arc<dyn Compressor>),
+    /// Pipe files through an external program, e.g. `pigz` or `zstd --long=31`
+    ///
+    /// The program is given the file on its standard input and must write the compressed file to
+    /// its standard output. If it can't be started or exits unsuccessfully, the original file is
+    /// kept as is and rotation fails. Files compressed this way can't be decompressed again, so
+    /// they can't be read through [`RotatingFile::read_matching`] nor recompressed when switching
+    /// to another compression.
+    External {
+        /// The program to run, looked up in `PATH` if it's not a path itself
+        command: OsString,
+        /// Arguments to pass to it
+        args: Vec<OsString>,
+        /// The suffix compressed files get, e.g. `gz` for `pigz`
+        suffix: Cow<'static, str>,
+    },
 }
 
-impl RotatingFile {
-    /// Create a new rotating file with the given base name, in the given directory, rotating every
-    /// given period and with a max of a given number of files
-    pub fn new<Name, Directory>(
-        name: Name,
-        directory: Directory,
-        rotate_every: RotationPeriod,
-        max_files: NonZeroUsize,
-        compression: Compression,
-    ) -> Self
-    where
-        Name: Into<Cow<'static, str>>,
-        Directory: Into<PathBuf>,
-    {
-        Self {
-            name: name.into(),
-            directory: directory.into(),
-            rotation_tracker: RotationTracker::from(rotate_every),
-            max_index: max_files.get() - 1,
-            compression,
-            current_file: None,
-        }
-    }
+/// An algorithm-independent compression level, see [`Compression::with_level`]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CompressionLevel {
+    /// Compress as fast as possible
+    Fastest,
+    /// The algorithm's own default, a good balance between speed and ratio
+    #[default]
+    Default,
+    /// Compress as much as possible, without resorting to settings that need a lot of memory to
+    /// decompress
+    Best,
+}
 
-    fn should_rotate(&self) -> bool {
-        // If we have no current file, it's probably best if we make one :p
-        self.current_file.is_none() || self.rotation_tracker.should_rotate()
+impl CompressionLevel {
+    /// The equivalent zstd level
+    pub fn zstd(self) -> i32 {
+        match self {
+            CompressionLevel::Fastest => 1,
+            CompressionLevel::Default => 3,
+            CompressionLevel::Best => 19,
+        }
     }
 
-    // Paths are split into three parts: NAME.INDEX.EXTENSION
-    // NAME is user-defined and unimportant to us, while EXTENSION must be either log or log.zstd
-    fn logfile_index<P: AsRef<Path>>(&self, path: P) -> Option<usize> {
-        let mut parts = path.as_ref().file_name()?.to_str()?.split('.');
-        match parts.next_back() {
-            Some("zstd") => {
-                if parts.next_back() != Some("log") {
-                    return None;
-                }
-            }
-            Some("log") => {}
-            Some(..) | None => return None,
+    /// The equivalent xz preset
+    pub fn xz(self) -> u32 {
+        match self {
+            CompressionLevel::Fastest => 0,
+            CompressionLevel::Default => 6,
+            CompressionLevel::Best => 9,
         }
-        parts.next_back()?.parse().ok()
     }
+}
 
-    // Increment a log file's index component by one by moving it, compressing if necessary
-    fn increment_index(&self, index: usize) -> io::Result<()> {
-        let path = self.make_filepath(index);
-        let dst = self.make_filepath(index + 1);
-        debug_assert!(!dst.exists());
-        // If we're rotating out the current log file, we must compress it. Otherwise, everything's
-        // already compressed and we can just rotate
-        match self.compression {
-            Compression::Zstd { level } if index == 0 => {
-                zstd::stream::copy_encode(fs::File::open(&path)?, fs::File::create(dst)?, level)?;
-                fs::remove_file(&path)?;
-                Ok(())
-            }
+/// A way of telling apart processes which log to the same directory with the same name, see
+/// [`RotatingFile::with_instance`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Instance {
+    /// The current process' ID
+    Pid,
+    /// A caller-provided identifier, e.g. a worker number or a container name
+    Id(Cow<'static, str>),
+}
+
+/// Where a [`RotatingFile`] is at, as returned by [`RotatingFile::flush_with_info`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FlushInfo {
+    /// The path of the current file
+    pub path: PathBuf,
+    /// How many bytes of the current file are durably on disk, compressed ones with live
+    /// compression
+    pub offset: u64,
+}
+
+/// When files get compressed after being rotated out, see
+/// [`RotatingFile::with_compression_timing`]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum CompressionTiming {
+    /// Compress each file right as it gets rotated out, as part of the rotation
+    #[default]
+    Immediately,
+
+    /// Compress each file on the rotation after the one that rotated it out, in the background
+    ///
+    /// This takes compression off the rotation itself, and keeps the most recently rotated file
+    /// readable as is.
+    NextRotation,
+
+    /// Compress each file once it's been rotated out the given number of times, in the background
+    /// unless that's 1 (which is the same as [`CompressionTiming::Immediately`])
+    AfterRotations(usize),
+}
 
-            Compression::None | Compression::Zstd { .. } => fs::rename(path, dst),
+impl CompressionTiming {
+    // How many times a file must have been rotated out to get compressed
+    fn min_age(self) -> usize {
+        match self {
+            CompressionTiming::Immediately => 1,
+            CompressionTiming::NextRotation => 2,
+            CompressionTiming::AfterRotations(rotations) => rotations.max(1),
         }
     }
+}
 
-    fn make_filepath(&self, index: usize) -> PathBuf {
-        self.directory.join(format!(
-            "{}.{}.{}",
-            self.name,
-            index,
-            match self.compression {
-                Compression::Zstd { .. } if index != 0 => "log.zstd",
-                Compression::None | Compression::Zstd { .. } => "log",
-            }
-        ))
-    }
+/// How often writes get flushed automatically, see [`RotatingFile::with_flush_policy`]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum FlushPolicy {
+    /// Only flush when asked to
+    #[default]
+    Never,
 
-    fn create_file(&self) -> io::Result<fs::File> {
-        // Let's survey the directory and find out what's the biggest index in there
-        let max_found_index = itertools::process_results(fs::read_dir(&self.directory)?, |dir| {
-            dir.into_iter()
-                .filter_map(|entry| self.logfile_index(entry.path()))
-                .max()
-        })?;
+    /// Flush once at least N bytes have been written since the last flush
+    Bytes(usize),
 
-        // If we've found any logs, let's make sure we stay under `self.max_index` files.
-        if let Some(mut max_found_index) = max_found_index {
-            // First, let's check if we have the maximum amount of logs available (or maybe even more!)
-            if max_found_index >= self.max_index {
-                // If so, let's remove all of the ones >=self.max_index so that we can make room for one more
-                (self.max_index..=max_found_index)
-                    .try_for_each(|index| fs::remove_file(self.make_filepath(index)))?;
-
-                // We'll need to update our `max_found_index` to avoid trying to
-                // move stuff that isn't there, but we'll use a saturating
-                // subtraction to handle the case where self.max_index == 0
-                // (only one logfile ever)
-                max_found_index = self.max_index.saturating_sub(1);
-            }
-
-            // If we've got a non-zero max index, we've got files to shuffle around!
-            if self.max_index != 0 {
-                // Increment all the remaining log files' indices so that we have
-                // room for a new one with index 0. Make sure that we do this in reverse order so
-                // we don't trample anything!
-                (0..=max_found_index)
-                    .rev()
-                    .try_for_each(|index| self.increment_index(index))?;
-            }
+    /// Flush once at least N line terminator bytes (0x0a, b'\n') have been written since the last
+    /// flush
+    Lines(usize),
+
+    /// Flush on the first write once the given amount of time has passed since the last flush
+    Interval(Duration),
+}
+
+/// How hard to try to make sure files survive a crash or power loss, see
+/// [`RotatingFile::with_durability`]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Durability {
+    /// Leave writing things out to the OS whenever it sees fit
+    #[default]
+    None,
+
+    /// Sync files to disk before they're rotated out and as they're archived, and directories
+    /// after files are created or renamed in them
+    Rotation,
+
+    /// Like [`Durability::Rotation`], also syncing the current file on every flush
+    Flush,
+}
+
+/// A step of a tiered compression policy, see [`RotatingFile::with_compression_tiers`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CompressionTier {
+    /// How many times a file must have been rotated out for this tier to apply, the file that
+    /// was just rotated out being at 1
+    pub after: usize,
+    /// The zstd compression level to use for files in this tier
+    pub level: i32,
+}
+
+impl Compression {
+    /// The suffix files compressed this way get after their name, if any
+    pub fn suffix(&self) -> Option<&str> {
+        match self {
+            Compression::None => None,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { .. } => Some("zstd"),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => Some("lz4"),
+            #[cfg(feature = "xz")]
+            Compression::Xz { .. } => Some("xz"),
+            Compression::Custom(compressor) => Some(compressor.suffix()),
+            Compression::External { suffix, .. } => Some(suffix),
         }
+    }
 
-        // Make sure we pass `create_new` so that nobody tries to be sneaky and
-        // place a file under us
-        fs::OpenOptions::new()
-            .create_new(true)
-            .write(true)
-            .open(self.make_filepath(0))
+    // Decompress what's read from `reader`, which only works with the built-in algorithms as
+    // custom compressors decompress files
+    fn decoder<R: Read + Send + 'static>(&self, reader: R) -> io::Result<Box<dyn Read + Send>> {
+        match self {
+            Compression::None => Ok(Box::new(reader)),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { .. } => {
+                let mut decoder = zstd::Decoder::new(reader)?;
+                // Allow archives compressed with any window log
+                decoder.window_log_max(31)?;
+                Ok(Box::new(decoder))
+            }
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => Ok(Box::new(lz4_flex::frame::FrameDecoder::new(reader))),
+            #[cfg(feature = "xz")]
+            Compression::Xz { .. } => Ok(Box::new(xz2::read::XzDecoder::new_multi_decoder(reader))),
+            Compression::Custom(..) | Compression::External { .. } => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "{} files can't be decompressed",
+                    self.suffix().unwrap_or_default()
+                ),
+            )),
+        }
     }
 
-    fn current_file(&mut self) -> io::Result<&mut fs::File> {
-        if self.should_rotate() {
-            self.rotate()?;
+    // Tell how a file we found is compressed from its suffix, given how we're configured to
+    // compress files. Parameters other than the format don't matter for decompression, so they're
+    // left at their defaults.
+    fn from_suffix(suffix: &str, configured: &Self) -> Option<Self> {
+        match suffix {
+            _ if configured.suffix() == Some(suffix) => Some(configured.clone()),
+            #[cfg(feature = "zstd")]
+            "zstd" => Some(Self::zstd(0)),
+            #[cfg(feature = "lz4")]
+            "lz4" => Some(Self::Lz4),
+            #[cfg(feature = "xz")]
+            "xz" => Some(Self::Xz { preset: 6 }),
+            // Built-in formats whose feature isn't enabled still need to be recognized, so that
+            // their files are kept track of
+            #[cfg(not(all(feature = "zstd", feature = "lz4", feature = "xz")))]
+            _ => compressor::BUILTIN_SUFFIXES
+                .iter()
+                .find(|&&builtin| builtin == suffix)
+                .map(|&suffix| Self::Custom(Arc::new(compressor::Disabled { suffix }))),
+            #[cfg(all(feature = "zstd", feature = "lz4", feature = "xz"))]
+            _ => None,
         }
+    }
 
-        Ok(self
-            .current_file
-            .as_mut()
-            .expect("should've been created before"))
+    // Whether files compressed this way and `other`'s way are in the same format
+    fn same_format(&self, other: &Self) -> bool {
+        self.suffix() == other.suffix()
     }
 
-    /// Manually rotate the file out
-    ///
-    /// This is the only way that a file whose `rotation_period` is [`RotationPeriod::Manual`] can rotate.
+    /// Use the given level, mapped to whatever it means for the algorithm
     ///
-    /// # Errors
+    /// This does nothing for algorithms without levels, such as [`Compression::Lz4`] and
+    /// [`Compression::Custom`].
     ///
-    /// Returns an error if one is encountered during creation of the new logfile.
+    /// # Examples
     ///
-    /// [`RotationPeriod::Manual`]: enum.RotationPeriod.html#variant.Manual
-    pub fn rotate(&mut self) -> io::Result<()> {
-        self.current_file = Some(self.create_file()?);
-        self.rotation_tracker.reset();
-        Ok(())
+    /// ```rust
+    /// # #[cfg(feature = "zstd")] {
+    /// # use file_rotator::{Compression, CompressionLevel};
+    /// let compression = Compression::zstd(0).with_level(CompressionLevel::Best);
+    /// assert!(matches!(compression, Compression::Zstd { level: 19, .. }));
+    /// # }
+    /// ```
+    #[cfg_attr(not(any(feature = "zstd", feature = "xz")), allow(unused_variables))]
+    pub fn with_level(mut self, level: CompressionLevel) -> Self {
+        match &mut self {
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { level: zstd, .. } => *zstd = level.zstd(),
+            #[cfg(feature = "xz")]
+            Compression::Xz { preset } => *preset = level.xz(),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => {}
+            Compression::None | Compression::Custom(..) | Compression::External { .. } => {}
+        }
+        self
     }
-}
 
-impl Write for RotatingFile {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        let written = self.current_file()?.write(buf)?;
-        self.rotation_tracker.wrote(&buf[..written]);
-        Ok(written)
+    /// Zstd compression at the given level, the other parameters being in [`ZstdOptions`]
+    #[cfg(feature = "zstd")]
+    pub fn zstd(level: i32) -> Self {
+        Self::Zstd { level }
     }
 
-    fn flush(&mut self) -> io::Result<()> {
-        self.current_file()?.flush()
-    }
-}
+    // Compress everything read from `src` into `dst` with one of the built-in algorithms
+    #[cfg_attr(not(feature = "zstd"), allow(unused_variables))]
+    fn encode<R: Read>(
+        &self,
+        src: &mut R,
+        mut dst: fs::File,
+        level: i32,
+        settings: &ArchiveSettings,
+    ) -> io::Result<()> {
+        match self {
+            Compression::None => io::copy(src, &mut dst).map(drop),
 
-#[cfg(test)]
-mod tests {
-    use std::fs;
-    use std::num::NonZeroUsize;
-    use std::path::Path;
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { .. } => match settings.zstd.seekable_frame_size {
+                Some(frame_size) => {
+                    let mut encoder =
+                        seekable::SeekableEncoder::new(dst, settings.zstd, level, frame_size);
+                    io::copy(src, &mut encoder)?;
+                    encoder.finish().map(drop)
+                }
+                None => {
+                    let mut encoder = settings.zstd.encoder(dst, level)?;
+                    io::copy(src, &mut encoder)?;
+                    encoder.finish().map(drop)
+                }
+            },
 
-    use proptest::prelude::*;
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => {
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(dst);
+                io::copy(src, &mut encoder)?;
+                encoder.finish().map(drop).map_err(io::Error::from)
+            }
 
-    use super::{RotatingFile, RotationPeriod};
+            #[cfg(feature = "xz")]
+            Compression::Xz { .. } => {
+                let mut encoder = xz2::write::XzEncoder::new(dst, level as u32);
+                io::copy(src, &mut encoder)?;
+                encoder.finish().map(drop)
+            }
 
-    #[track_caller]
-    fn assert_contains_files<P: AsRef<Path>>(
-        directory: P,
-        num: usize,
-    ) -> Result<(), TestCaseError> {
-        let p = directory.as_ref();
-        prop_assert_eq!(
-            fs::read_dir(p).unwrap().count(),
-            num,
-            "Directory {:?} did not contain {} file(s) (at {})",
-            p,
-            num,
-            std::panic::Location::caller()
-        );
-        Ok(())
+            Compression::Custom(..) | Compression::External { .. } => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "not a built-in compression algorithm",
+            )),
+        }
     }
 
-    proptest! {
-        #![proptest_config(ProptestConfig {
-            cases: 15,
-            ..ProptestConfig::default()
-        })]
+    // Compress `src` (compressed with `src_compression`) into `dst` at the given level,
+    // decompressing `src` first if it's compressed already. Data is written to a temporary file
+    // first so that `dst` doesn't show up under its final name until it's complete and has the
+    // right permissions, and gets a checksum sidecar if asked to. With verification on, it's also
+    // decompressed again and checked against `src` before `src` is removed.
+    fn compress_file(
+        &self,
+        src: &Path,
+        src_compression: Compression,
+        dst: &Path,
+        level: i32,
+        settings: &ArchiveSettings,
+    ) -> io::Result<()> {
+        #[cfg(feature = "tracing")]
+        let _span =
+            tracing::info_span!("compress", src = %src.display(), dst = %dst.display()).entered();
+        #[cfg(feature = "tracing")]
+        let (started, src_size) = (
+            std::time::Instant::now(),
+            fs::metadata(src).map(|metadata| metadata.len()).ok(),
+        );
 
-        #[test]
-        fn test_max_files(name in "[a-zA-Z_-]+", n in 1..25usize) {
-            let directory = tempfile::tempdir().unwrap();
+        let mut tmp = dst.to_owned().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        // Custom compressors create `tmp` themselves, so don't let them write through a leftover
+        permissions::remove_if_exists(&tmp)?;
 
-            let mut file = RotatingFile::new(
-                name,
-                directory.path().to_owned(),
-                RotationPeriod::Manual,
-                NonZeroUsize::new(n).unwrap(),
-                crate::Compression::None
-            );
+        let src_file = LogFile {
+            path: src.to_owned(),
+            key: FileKey::default(),
+            compression: src_compression.clone(),
+        }
+        .open()?;
+        #[cfg(feature = "checksum")]
+        let mut src_file = checksum::HashingReader::new(src_file, settings.verify);
+        #[cfg(not(feature = "checksum"))]
+        let mut src_file = src_file;
 
-            assert_contains_files(&directory, 0)?;
-            for i in 0..n {
-                file.rotate().unwrap();
-                assert_contains_files(&directory, i+1)?;
+        match self {
+            // Custom compressors work on plain files, so decompress to one first if needed
+            Compression::Custom(compressor) if src_compression.suffix().is_none() => {
+                #[cfg(feature = "checksum")]
+                if settings.verify {
+                    io::copy(&mut src_file, &mut io::sink())?;
+                }
+                compressor.compress(src, &tmp)?;
             }
 
-            for _ in 0..n {
-                assert_contains_files(&directory, n)?;
-                file.rotate().unwrap();
+            Compression::External { command, args, .. } => {
+                if let Err(e) = run_compressor(command, args, &mut src_file, &tmp) {
+                    // Don't leave a partial archive behind
+                    let _ = fs::remove_file(&tmp);
+                    return Err(e);
+                }
             }
-        }
 
-        #[test]
-        fn test_roundtrip_uncompressed(name in "[a-zA-Z_-]+", data: Vec<u8>) {
-            use std::io::prelude::*;
+            Compression::Custom(compressor) => {
+                let mut plain = tmp.clone().into_os_string();
+                plain.push(".plain");
+                let plain = PathBuf::from(plain);
+                io::copy(&mut src_file, &mut permissions::create_replacing(&plain)?)?;
+                let result = compressor.compress(&plain, &tmp);
+                fs::remove_file(&plain)?;
+                result?;
+            }
 
-            let directory = tempfile::tempdir().unwrap();
-            let mut file = RotatingFile::new(
-                name,
-                directory.path().to_owned(),
-                RotationPeriod::Manual,
-                NonZeroUsize::new(10).unwrap(),
-                crate::Compression::None
-            );
-            file.write_all(&data).unwrap();
-            file.rotate().unwrap();
-            file.write_all(&data).unwrap();
-            drop(file);
+            _ => self.encode(
+                &mut src_file,
+                permissions::create_replacing(&tmp)?,
+                level,
+                settings,
+            )?,
+        }
 
-            for entry in fs::read_dir(&directory).unwrap().map(Result::unwrap) {
-                let path = entry.path();
-                let read = fs::read(path).unwrap();
-                prop_assert_eq!(&read, &data);
+        #[cfg(feature = "checksum")]
+        let expected = src_file.finish();
+        #[cfg(not(feature = "checksum"))]
+        let expected: Option<[u8; 32]> = None;
+        if expected.is_some() || settings.durable {
+            fs::File::open(&tmp)?.sync_all()?;
+        }
+        #[cfg(feature = "checksum")]
+        if let Some(expected) = expected {
+            if let Err(e) = self.verify_file(&tmp, expected) {
+                fs::remove_file(&tmp)?;
+                return Err(e);
+            }
+        }
+
+        settings.permissions.apply(&tmp)?;
+        fs::rename(&tmp, dst)?;
+        if settings.durable {
+            sync_parent(dst)?;
+        }
+        if src != dst {
+            fs::remove_file(src)?;
+        }
+        #[cfg(feature = "checksum")]
+        if settings.checksums {
+            if src != dst {
+                checksum::remove_sidecar(src)?;
+            }
+            checksum::write_sidecar(dst)?;
+        }
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            src_size = ?src_size,
+            dst_size = ?fs::metadata(dst).map(|metadata| metadata.len()).ok(),
+            elapsed = ?started.elapsed(),
+            "compressed",
+        );
+        Ok(())
+    }
+
+    // Check that `path`, compressed with `self`, decompresses to bytes with the given SHA-256
+    #[cfg(feature = "checksum")]
+    fn verify_file(&self, path: &Path, expected: [u8; 32]) -> io::Result<()> {
+        let decompressed = LogFile {
+            path: path.to_owned(),
+            key: FileKey::default(),
+            compression: self.clone(),
+        }
+        .open()
+        .and_then(checksum::sha256);
+        match decompressed {
+            Ok(actual) if actual == expected => Ok(()),
+            Ok(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} doesn't decompress back to what was compressed",
+                    path.display()
+                ),
+            )),
+            // Not every custom compressor can decompress
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl RotatingFile {
+    /// Create a new rotating file with the given base name, in the given directory, rotating every
+    /// given period and with a max of a given number of files
+    ///
+    /// Any `{hostname}` in the name is replaced by the machine's hostname, so that files from
+    /// different machines don't collide on shared storage.
+    pub fn new<Name, Directory>(
+        name: Name,
+        directory: Directory,
+        rotate_every: RotationPeriod,
+        max_files: NonZeroUsize,
+        compression: Compression,
+    ) -> Self
+    where
+        Name: Into<Cow<'static, str>>,
+        Directory: Into<PathBuf>,
+    {
+        let mut name = name.into();
+        if name.contains("{hostname}") {
+            name = Cow::Owned(name.replace("{hostname}", &hostname::hostname()));
+        }
+        let stats = Arc::new(Counters::new(&name));
+
+        Self {
+            name,
+            directory: directory.into(),
+            rotation_tracker: RotationTracker::from(rotate_every),
+            flush_tracker: RotationTracker::from(FlushPolicy::Never),
+            line_boundaries: false,
+            exact_size: false,
+            locking: false,
+            reopen_on_move: false,
+            copy_truncate: false,
+            exclusive_lock: None,
+            resume: false,
+            mid_line: false,
+            max_index: max_files.get() - 1,
+            compression,
+            naming: Box::new(Naming::default()),
+            index_direction: IndexDirection::default(),
+            date_directories: None,
+            extension: Cow::Borrowed("log"),
+            latest_link: false,
+            archive_permissions: ArchivePermissions::default(),
+            file_permissions: ArchivePermissions::default(),
+            directory_permissions: ArchivePermissions::default(),
+            create_directory: false,
+            share_mode: None,
+            retention_held: false,
+            compression_tiers: Vec::new(),
+            compression_timing: CompressionTiming::default(),
+            background_compression: false,
+            #[cfg(feature = "zstd")]
+            live_compression: false,
+            #[cfg(feature = "zstd")]
+            zstd_options: ZstdOptions::default(),
+            #[cfg(feature = "encryption")]
+            encryption: None,
+            #[cfg(feature = "checksum")]
+            checksums: false,
+            #[cfg(feature = "checksum")]
+            verify_compression: false,
+            #[cfg(feature = "checksum")]
+            hash_chain: None,
+            #[cfg(feature = "manifest")]
+            manifest: None,
+            secure_deletion: false,
+            hardened: false,
+            durability: Durability::None,
+            #[cfg(feature = "bundle")]
+            bundle_after: None,
+            maintenance: Maintenance::default(),
+            spool: Spool::default(),
+            disk_full: DiskFull::default(),
+            rate_limit: RateLimiter::default(),
+            transform: Transform::default(),
+            line_length: LineLength::default(),
+            dedup: Dedup::default(),
+            timestamps: Timestamps::default(),
+            tee: None,
+            followers: Vec::new(),
+            reopen_limit: 0,
+            reopen_attempts: 0,
+            header: None,
+            footer: None,
+            on_rotate: None,
+            subscribers: Vec::new(),
+            archive_subscribers: snapshot::Subscribers::default(),
+            snapshots: 0,
+            stats,
+            on_evict: None,
+            audit_log: false,
+            on_audit: None,
+            current_file: None,
+            current_path: None,
+            current_offset: 0,
+        }
+    }
+
+    /// Like [`RotatingFile::new`], but make sure that the name is safe to use in a file name
+    ///
+    /// The name ends up in the middle of paths, so a name like `"../../etc/foo"` could make files
+    /// end up outside of the directory. This rejects names that are empty, `.` or `..`, contain
+    /// path separators (`/` and `\`) or NUL bytes, or are (or start with) names that are reserved
+    /// on Windows such as `CON`, `NUL` or `COM1`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`io::ErrorKind::InvalidInput`] if the name isn't safe.
+    pub fn try_new<Name, Directory>(
+        name: Name,
+        directory: Directory,
+        rotate_every: RotationPeriod,
+        max_files: NonZeroUsize,
+        compression: Compression,
+    ) -> io::Result<Self>
+    where
+        Name: Into<Cow<'static, str>>,
+        Directory: Into<PathBuf>,
+    {
+        let file = Self::new(name, directory, rotate_every, max_files, compression);
+        validate_name(&file.name)?;
+        Ok(file)
+    }
+
+    /// Choose how the log files should be named
+    ///
+    /// This takes either one of the built-in schemes in [`Naming`], a [`Template`] or any other
+    /// [`NamingScheme`], boxed or not.
+    pub fn with_naming<Scheme>(mut self, naming: Scheme) -> Self
+    where
+        Scheme: NamingScheme + 'static,
+    {
+        self.naming = Box::new(naming);
+        self
+    }
+
+    /// Choose whether index zero is the newest or the oldest file, for shifting naming schemes
+    ///
+    /// With [`IndexDirection::OldestFirst`], files are numbered append-style: `NAME.0.log` is the
+    /// oldest file, and every rotation creates a new current file with the next index. Once the
+    /// maximum number of files is reached, the oldest ones get deleted and the rest get shifted
+    /// down so that numbering starts at zero again. Reading APIs such as
+    /// [`RotatingFile::files_matching`] still list files newest first.
+    ///
+    /// This has no effect on non-shifting schemes, which don't have indices, and isn't a good fit
+    /// for [`Naming::Stable`], whose un-indexed `NAME.log` would end up being the oldest file.
+    pub fn with_index_direction(mut self, index_direction: IndexDirection) -> Self {
+        self.index_direction = index_direction;
+        self
+    }
+
+    /// Put files into date subdirectories of the directory, named after the given template
+    ///
+    /// For example, with `Template::parse("{%Y}/{%m}/{%d}")` a file created on June 1st 2024 goes
+    /// into `DIRECTORY/2024/06/01/`, which keeps directories small when retention spans months.
+    /// Directories are created as needed, retention looks at files across all of them, and
+    /// directories left empty by deleting old files are removed. Files stay in the directory
+    /// they were created in even when they get renamed by rotation.
+    ///
+    /// Files directly inside the directory are still recognized, so turning this on for an
+    /// existing directory is fine. The date used is the UTC date at which each file is created.
+    pub fn with_date_directories(mut self, layout: Template) -> Self {
+        self.date_directories = Some(layout);
+        self
+    }
+
+    /// Use the given extension instead of `log`, e.g. `jsonl` for `NAME.0.jsonl`
+    ///
+    /// A leading dot is ignored, so `".jsonl"` works too. Compressed files still get their
+    /// compression suffix after it (`NAME.1.jsonl.zstd`). This has no effect with
+    /// [`Naming::Template`], whose template already includes the extension.
+    pub fn with_extension<Extension>(mut self, extension: Extension) -> Self
+    where
+        Extension: Into<Cow<'static, str>>,
+    {
+        self.extension = match extension.into() {
+            Cow::Borrowed(extension) => Cow::Borrowed(extension.trim_start_matches('.')),
+            Cow::Owned(extension) => Cow::Owned(extension.trim_start_matches('.').to_owned()),
+        };
+        self
+    }
+
+    /// Give this process its own rotating series by appending an instance identifier to the name
+    ///
+    /// With [`Instance::Pid`] and a name of `app`, process 1234 writes to `app-1234.0.log` and so
+    /// on, so several instances of the same program can share a directory without fighting over
+    /// the same files. Each instance only ever sees and rotates its own files.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`io::ErrorKind::InvalidInput`] if the name with the identifier
+    /// appended isn't safe to use in a file name, as checked by [`RotatingFile::try_new`].
+    pub fn with_instance(mut self, instance: Instance) -> io::Result<Self> {
+        let id = match instance {
+            Instance::Pid => Cow::Owned(std::process::id().to_string()),
+            Instance::Id(id) => id,
+        };
+        let name = format!("{}-{}", self.name, id);
+        validate_name(&name)?;
+        self.name = Cow::Owned(name);
+        self.stats.renamed(&self.name);
+        Ok(self)
+    }
+
+    /// Maintain a `NAME.latest.log` link which always points to the current file
+    ///
+    /// The link is a symlink on Unix and a hard link elsewhere, and gets atomically replaced on
+    /// every rotation so that tailing tools don't need to know about the naming scheme.
+    pub fn with_latest_link(mut self, latest_link: bool) -> Self {
+        self.latest_link = latest_link;
+        self
+    }
+
+    /// Give files the given permissions and ownership as they get rotated out, see
+    /// [`ArchivePermissions`]
+    pub fn with_archive_permissions(mut self, archive_permissions: ArchivePermissions) -> Self {
+        self.archive_permissions = archive_permissions;
+        self
+    }
+
+    /// Create log files with the given permission bits, e.g. `0o600`, instead of whatever the
+    /// process' umask allows
+    ///
+    /// Compressed archives get them too, unless [`ArchivePermissions::mode`] says otherwise. This
+    /// is only supported on Unix, and does nothing elsewhere.
+    pub fn with_file_mode(mut self, mode: u32) -> Self {
+        self.file_permissions.mode = Some(mode);
+        self
+    }
+
+    /// Create directories (see [`RotatingFile::with_create_directory`] and
+    /// [`RotatingFile::with_date_directories`]) with the given permission bits, e.g. `0o750`,
+    /// instead of whatever the process' umask allows
+    ///
+    /// This is only supported on Unix, and does nothing elsewhere.
+    pub fn with_directory_mode(mut self, mode: u32) -> Self {
+        self.directory_permissions.mode = Some(mode);
+        self
+    }
+
+    /// Create the directory, along with any missing parents, if it's missing when rotating
+    ///
+    /// Directories created this way get the permissions set with
+    /// [`RotatingFile::with_directory_mode`] and [`RotatingFile::with_owner`]; the ones that exist
+    /// already are left alone.
+    pub fn with_create_directory(mut self, create_directory: bool) -> Self {
+        self.create_directory = create_directory;
+        self
+    }
+
+    /// Open the current file with the given Windows share mode, a combination of the
+    /// `FILE_SHARE_READ` (1), `FILE_SHARE_WRITE` (2) and `FILE_SHARE_DELETE` (4) flags
+    ///
+    /// This decides what other programs may do with the current file while it's open, such as
+    /// reading it or deleting it. The default is the standard library's, which is all three.
+    /// Access control lists aren't set here: files inherit those of their directory.
+    #[cfg(windows)]
+    pub fn with_share_mode(mut self, share_mode: u32) -> Self {
+        self.share_mode = Some(share_mode);
+        self
+    }
+
+    /// Give the files and directories we create the given owning user and group IDs, leaving out
+    /// whichever is `None`
+    ///
+    /// This is for daemons which start out as root and then drop privileges, so that their log
+    /// files belong to the user they run as. Archives get the same ownership, unless
+    /// [`ArchivePermissions`] says otherwise. This is only supported on Unix, and does nothing
+    /// elsewhere.
+    pub fn with_owner(mut self, uid: Option<u32>, gid: Option<u32>) -> Self {
+        for permissions in [&mut self.file_permissions, &mut self.directory_permissions] {
+            permissions.uid = uid;
+            permissions.gid = gid;
+        }
+        self
+    }
+
+    /// Compress files differently depending on how old they are
+    ///
+    /// Each file gets the level of the last tier whose `after` it has reached, so that e.g.
+    /// `[CompressionTier { after: 3, level: 3 }, CompressionTier { after: 10, level: 19 }]`
+    /// leaves the two most recently rotated files uncompressed (so they're quick to grep),
+    /// compresses the next ones at level 3 and recompresses them at level 19 once they're even
+    /// older. The file that was just rotated out is compressed right away if the first tier
+    /// applies to it; everything else is done by a background thread, see
+    /// [`RotatingFile::wait_for_maintenance`].
+    ///
+    /// Tiers only override the compression level: they do nothing with anything but
+    /// [`Compression::Zstd`], which all other parameters come from.
+    pub fn with_compression_tiers<Tiers>(mut self, tiers: Tiers) -> Self
+    where
+        Tiers: IntoIterator<Item = CompressionTier>,
+    {
+        self.compression_tiers = tiers.into_iter().collect();
+        self.compression_tiers.sort_by_key(|tier| tier.after);
+        self
+    }
+
+    /// Choose when files get compressed after being rotated out, see [`CompressionTiming`]
+    ///
+    /// Only the file that was just rotated out is ever compressed as part of the rotation, so
+    /// delaying compression moves its cost to the background maintenance (see
+    /// [`RotatingFile::wait_for_maintenance`]). With [`RotatingFile::with_compression_tiers`],
+    /// files are only compressed once both the timing and a tier say so.
+    ///
+    /// # Examples
+    ///
+    /// Keeping the two most recently rotated out files (`NAME.1.log` and `NAME.2.log`) plain so
+    /// that they can still be grepped, and compressing everything older:
+    ///
+    /// ```rust
+    /// # #[cfg(feature = "zstd")] {
+    /// # use std::num::NonZeroUsize;
+    /// # use file_rotator::{Compression, CompressionTiming, RotatingFile, RotationPeriod};
+    /// let file = RotatingFile::new(
+    ///     "app",
+    ///     "/logs",
+    ///     RotationPeriod::Bytes(1 << 20),
+    ///     NonZeroUsize::new(10).unwrap(),
+    ///     Compression::zstd(0),
+    /// )
+    /// .with_compression_timing(CompressionTiming::AfterRotations(3));
+    /// # }
+    /// ```
+    pub fn with_compression_timing(mut self, timing: CompressionTiming) -> Self {
+        self.compression_timing = timing;
+        self
+    }
+
+    /// Compress the file that was just rotated out on a background thread
+    ///
+    /// Normally, that file is compressed as part of the rotation, which can block the write that
+    /// triggered it for a long time if the file is big. With this, rotation only renames files and
+    /// the file gets compressed (and its original removed) asynchronously, in the same background
+    /// maintenance as [`RotatingFile::with_compression_tiers`] uses, see
+    /// [`RotatingFile::wait_for_maintenance`]. Until then, it stays around uncompressed.
+    pub fn with_background_compression(mut self, background_compression: bool) -> Self {
+        self.background_compression = background_compression;
+        self
+    }
+
+    /// Write the current file already compressed, instead of compressing it when it's rotated out
+    ///
+    /// This is for very high-volume logs, where writing everything twice (once as is, and once
+    /// compressed) is too much disk I/O: the current file becomes `NAME.0.log.zstd` and is
+    /// compressed at the configured level as it's written. Every flush ends a zstd frame, so that
+    /// everything up to the last flush can be decompressed even if the process crashes; flushing
+    /// too often hurts the compression ratio, though.
+    ///
+    /// This only has an effect with [`Compression::Zstd`]. [`RotatingFile::with_compression_timing`]
+    /// doesn't apply to the current file, which is compressed no matter what.
+    #[cfg(feature = "zstd")]
+    pub fn with_live_compression(mut self, live_compression: bool) -> Self {
+        self.live_compression = live_compression;
+        self
+    }
+
+    // How the current file is written, which is uncompressed unless live compression is on
+    fn current_compression(&self) -> Compression {
+        match &self.compression {
+            #[cfg(feature = "zstd")]
+            compression @ Compression::Zstd { .. } if self.live_compression => compression.clone(),
+            _ => Compression::None,
+        }
+    }
+
+    /// Tune zstd beyond its level, e.g. for a bigger window or archives in the seekable format
+    ///
+    /// These apply to everything compressed with [`Compression::Zstd`], archives as well as the
+    /// current file with [`RotatingFile::with_live_compression`], and to archives encrypted with
+    /// `RotatingFile::with_encryption` whichever of the two is called first.
+    ///
+    /// ```rust
+    /// # #[cfg(feature = "zstd")] {
+    /// # use std::num::NonZeroUsize;
+    /// # use file_rotator::{Compression, RotatingFile, RotationPeriod, ZstdOptions};
+    /// let file = RotatingFile::new(
+    ///     "app",
+    ///     "/logs",
+    ///     RotationPeriod::Bytes(1 << 20),
+    ///     NonZeroUsize::new(10).unwrap(),
+    ///     Compression::zstd(19),
+    /// )
+    /// .with_zstd_options(
+    ///     ZstdOptions::default()
+    ///         .with_long_distance_matching(true)
+    ///         .with_window_log(Some(27)),
+    /// );
+    /// # }
+    /// ```
+    #[cfg(feature = "zstd")]
+    pub fn with_zstd_options(mut self, zstd_options: ZstdOptions) -> Self {
+        self.zstd_options = zstd_options;
+        #[cfg(feature = "encryption")]
+        self.encrypt();
+        self
+    }
+
+    /// Write a checksum sidecar next to each archive, so that archives can be checked after being
+    /// shipped somewhere else
+    ///
+    /// Once a file has been rotated out (and compressed, if it's going to be), its SHA-256 is
+    /// written to `NAME.1.log.zstd.sha256` in the format that `sha256sum -c` checks. Sidecars
+    /// follow their archive around as it's renamed, recompressed and eventually deleted; they
+    /// don't count towards the maximum number of files.
+    #[cfg(feature = "checksum")]
+    pub fn with_checksums(mut self, checksums: bool) -> Self {
+        self.checksums = checksums;
+        self
+    }
+
+    /// Keep a tamper-evident hash chain of every file that gets rotated out, keyed with `key`
+    ///
+    /// On every rotation, the SHA-256 of the file being rotated out is appended to `NAME.chain`
+    /// together with an HMAC-SHA256 of itself and the previous entry under `key`, so that changing
+    /// or removing any entry breaks every one after it. [`RotatingFile::verify_hash_chain`] then
+    /// checks both the chain and the archives still around, which detects modified and deleted
+    /// archives.
+    ///
+    /// This only holds up against someone who doesn't have the key: with it, they can write a
+    /// whole new chain to go with the archives they changed. Keep it somewhere else than the
+    /// files, such as a secret store that the process gets it from when it starts.
+    #[cfg(feature = "checksum")]
+    pub fn with_hash_chain<K>(mut self, key: K) -> Self
+    where
+        K: Into<Vec<u8>>,
+    {
+        self.hash_chain = Some(chain::Key(key.into()));
+        self
+    }
+
+    /// Keep track of every file that gets rotated out in `NAME.manifest.json`, off by default
+    ///
+    /// For each archive, the manifest records where it is relative to the directory, when it was
+    /// created and rotated out (in RFC 3339 format), its size and SHA-256 before compression and
+    /// its size once compressed:
+    ///
+    /// ```json
+    /// {
+    ///   "name": "app",
+    ///   "current_created": "2024-05-01T12:34:56.789Z",
+    ///   "archives": [
+    ///     {
+    ///       "file": "app.1.log.gz",
+    ///       "created": "2024-05-01T00:00:00.000Z",
+    ///       "rotated": "2024-05-01T12:34:56.789Z",
+    ///       "original_size": 1024,
+    ///       "compressed_size": 256,
+    ///       "sha256": "..."
+    ///     }
+    ///   ]
+    /// }
+    /// ```
+    ///
+    /// It's rewritten atomically on every rotation, which also means reading the file being
+    /// rotated out once more to hash it. [`RotatingFile::files_between`] goes by it rather than by
+    /// modification times, and [`RotatingFile::verify_manifest`] checks the archives against it.
+    /// Both fail with an error of kind [`io::ErrorKind::InvalidData`] if the manifest can't be
+    /// parsed, while rotating moves it aside to `NAME.manifest.json.corrupt` and starts over.
+    #[cfg(feature = "manifest")]
+    pub fn with_manifest(mut self, manifest: bool) -> Self {
+        self.manifest = if manifest {
+            Some(Manifest::default())
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Overwrite files with zeroes before deleting them once there's no room for them anymore
+    ///
+    /// This is a best effort for deployments with data destruction requirements: filesystems
+    /// which don't write in place (copy-on-write filesystems, SSDs' wear levelling, snapshots...)
+    /// may still keep the old contents around.
+    pub fn with_secure_deletion(mut self, secure_deletion: bool) -> Self {
+        self.secure_deletion = secure_deletion;
+        self
+    }
+
+    /// Sync files and directories to disk as they're written, so that rotated files can't
+    /// vanish or end up empty after a power loss
+    ///
+    /// This costs a lot more disk activity, which is why it's off by default. Flushes, whether
+    /// they're asked for or follow the [`FlushPolicy`], only sync with [`Durability::Flush`].
+    /// Directories are only synced on Unix, as they can't be elsewhere.
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// Guard against symlink attacks, for directories that other users can write to
+    ///
+    /// Temporary files are always replaced rather than written through, and the current file
+    /// is always created from scratch. Hardening also makes rotation fail with an error of kind
+    /// [`io::ErrorKind::PermissionDenied`] if the directory (or a date directory) is a symlink,
+    /// ignores symlinks among the files so that they never get renamed, compressed or overwritten,
+    /// and refuses to follow them when opening files where supported.
+    pub fn with_hardening(mut self, hardened: bool) -> Self {
+        self.hardened = hardened;
+        self
+    }
+
+    /// Check every archive by decompressing it again before deleting the file it was made from
+    ///
+    /// Compression always goes through a temporary file which is only renamed into place once
+    /// it's complete. With verification on, that file is also synced to disk and decompressed
+    /// again, and if that doesn't give back exactly the original bytes (same length and SHA-256)
+    /// it's thrown away, the original is kept as is and rotation fails with an error of kind
+    /// [`io::ErrorKind::InvalidData`]. Custom compressors that can't decompress aren't verified.
+    #[cfg(feature = "checksum")]
+    pub fn with_compression_verification(mut self, verify: bool) -> Self {
+        self.verify_compression = verify;
+        self
+    }
+
+    /// Encrypt files as they're rotated out, after compressing them with the compression this was
+    /// created with
+    ///
+    /// Encrypted archives get an extra `.enc` suffix, e.g. `NAME.1.log.zstd.enc`, and are still
+    /// readable through [`RotatingFile::read_matching`] given the same key. This takes over the
+    /// compression, so that [`RotatingFile::with_compression_tiers`] and
+    /// `RotatingFile::with_live_compression` don't apply anymore; [`CompressionTiming`] does,
+    /// and files are kept unencrypted until it says they should be compressed. Files encrypted
+    /// with another compression or none at all aren't recognized as ours anymore.
+    #[cfg(feature = "encryption")]
+    pub fn with_encryption(mut self, encryption: Encryption) -> Self {
+        let inner = std::mem::replace(&mut self.compression, Compression::None);
+        self.encryption = Some((inner, encryption));
+        self.encrypt();
+        self
+    }
+
+    // Take over the compression with encryption, if it's on, compressing with the settings as
+    // they are now
+    #[cfg(feature = "encryption")]
+    fn encrypt(&mut self) {
+        if let Some((inner, encryption)) = &self.encryption {
+            let encrypted =
+                encryption::Encrypted::new(inner.clone(), *encryption, &self.archive_settings());
+            self.compression = Compression::Custom(Arc::new(encrypted));
+        }
+    }
+
+    /// Bundle files that have been rotated out at least `after` times into one `.tar.gz` per day
+    ///
+    /// This cuts down on the number of files kept around and makes copying old logs elsewhere
+    /// easier. On every rotation, files old enough are moved into `NAME.YYYY-MM-DD.tar.gz`
+    /// depending on the UTC day they were last written to, the bundle being created if needed.
+    /// Inside bundles, files are named after that time like with [`Naming::Timestamp`], so that
+    /// they don't clash.
+    ///
+    /// Bundles count towards the maximum number of files, the oldest ones being deleted first,
+    /// so `after` must be smaller than that for anything to get bundled. Bundles aren't part of
+    /// what [`RotatingFile::files_matching`] and [`RotatingFile::read_matching`] return.
+    #[cfg(feature = "bundle")]
+    pub fn with_bundles(mut self, after: usize) -> Self {
+        self.bundle_after = Some(after.max(1));
+        self
+    }
+
+    /// Keep up to `capacity` bytes of writes in memory while the directory is missing
+    ///
+    /// This is meant for brief outages like a volume being unmounted and remounted: instead of
+    /// every write failing, writes get buffered, and once the directory is back a new current file
+    /// is created and everything is written to it. Writes only fail once the spool is full.
+    /// A capacity of zero (the default) turns spooling off.
+    ///
+    /// The directory going missing is noticed when a new file needs to be created or a write
+    /// fails: until then, writes keep going to the current file, wherever it may be.
+    ///
+    /// See [`RotatingFile::with_spool_events`] to find out when this happens.
+    pub fn with_spool(mut self, capacity: usize) -> Self {
+        self.spool.capacity = capacity;
+        self
+    }
+
+    /// Call the given function whenever spooling starts or stops, see [`RotatingFile::with_spool`]
+    pub fn with_spool_events<F>(mut self, on_event: F) -> Self
+    where
+        F: FnMut(&SpoolEvent) + Send + 'static,
+    {
+        self.spool.on_event = Some(Box::new(on_event));
+        self
+    }
+
+    /// Write to the given fallback, such as `Syslog` on unix, rather than failing while the directory
+    /// is missing or refuses to be written to
+    ///
+    /// This takes the place of the spool (see [`RotatingFile::with_spool`]), and starts and stops
+    /// just like it, except that it also starts when the directory is there but writing to it is
+    /// denied or the file system is read-only. Whatever was written to the fallback stays there:
+    /// once the directory can be written to again, which is checked at most once a second, a new
+    /// current file is created and writes go to it again.
+    pub fn with_fallback<W>(mut self, fallback: W) -> Self
+    where
+        W: Write + Send + 'static,
+    {
+        self.spool.fallback = Some(Box::new(fallback));
+        self
+    }
+
+    /// Start every new file with what the given function returns, e.g. a banner with the
+    /// program's version, the hostname and the time
+    ///
+    /// The function is given the path of the new file. What it returns is written right after the
+    /// file is created (but not to a file picked up again with [`RotatingFile::with_resume`]) and
+    /// counts towards the rotation period like any other write.
+    pub fn with_header<F>(mut self, header: F) -> Self
+    where
+        F: FnMut(&Path) -> Vec<u8> + Send + 'static,
+    {
+        self.header = Some(FileHook::new(header));
+        self
+    }
+
+    /// End every file with what the given function returns, written right before the file is
+    /// rotated out
+    ///
+    /// The function is given the path of the file as it is before rotating it out. The current
+    /// file doesn't get a footer if it's never rotated out, e.g. because the program exits.
+    pub fn with_footer<F>(mut self, footer: F) -> Self
+    where
+        F: FnMut(&Path) -> Vec<u8> + Send + 'static,
+    {
+        self.footer = Some(FileHook::new(footer));
+        self
+    }
+
+    /// Call the given function after every rotation, with where the file that was rotated out
+    /// ended up and the new current file
+    ///
+    /// This is called once the rotation is done, so the file that was rotated out has already been
+    /// compressed, unless it's left to the background (see
+    /// [`RotatingFile::with_background_compression`] and [`CompressionTiming`]), in which case
+    /// it's given as it is before being compressed.
+    pub fn with_on_rotate<F>(mut self, on_rotate: F) -> Self
+    where
+        F: FnMut(&RotationEvent) + Send + 'static,
+    {
+        self.on_rotate = Some(Callback::new(on_rotate));
+        self
+    }
+
+    /// Get every future rotation sent to the returned channel, for reacting to rotations from
+    /// another thread instead of from a callback run as part of writing
+    ///
+    /// Events are the same as with [`RotatingFile::with_on_rotate`]. The channel is unbounded,
+    /// so rotation never waits for the receiving end; dropping the receiver unsubscribes.
+    pub fn subscribe(&mut self) -> mpsc::Receiver<RotationEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    // Like `subscribe`, but for getting files once they're finished being compressed, through a
+    // snapshot of them that the following rotations can't move, for the background threads that
+    // do something with them
+    fn subscribe_archived(&mut self) -> mpsc::Receiver<Arc<Archived>> {
+        let (sender, receiver) = mpsc::channel();
+        self.archive_subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(sender);
+        receiver
+    }
+
+    /// Get every line written from now on, as it's written, for live consoles and the like
+    ///
+    /// Lines are what's written through [`io::Write`] and [`RotatingFile::write_record`], like
+    /// with [`RotatingFile::with_tee`]. There's no limit on how much is kept for a follower that
+    /// falls behind; dropping the [`Follow`] stops following.
+    pub fn follow(&mut self) -> Follow {
+        let (sender, receiver) = mpsc::channel();
+        self.followers.push(sender);
+        Follow::new(receiver)
+    }
+
+    /// Call the given function with the path of every file deleted to make room for new ones,
+    /// including bundles (see `RotatingFile::with_bundles`)
+    pub fn with_on_evict<F>(mut self, on_evict: F) -> Self
+    where
+        F: FnMut(&Path) + Send + 'static,
+    {
+        self.on_evict = Some(Callback::new(on_evict));
+        self
+    }
+
+    /// Keep an append-only record of every file rotated out and deleted, along with when and why,
+    /// in `NAME.audit`, off by default
+    ///
+    /// Each line is `TIME ACTION PATH`, TIME being in RFC 3339 format and ACTION one of the
+    /// [`AuditAction`]s in lowercase, like `2024-05-01T12:34:56.789Z evicted /logs/app.7.log`.
+    /// The audit log is only ever appended to; in particular, [`RotatingFile::purge`] leaves it
+    /// alone. Failing to append to it fails whatever did what's being recorded, after the fact.
+    pub fn with_audit_log(mut self, audit_log: bool) -> Self {
+        self.audit_log = audit_log;
+        self
+    }
+
+    /// Call the given function with everything that would go in the audit log (see
+    /// [`RotatingFile::with_audit_log`]), whether there's one or not
+    pub fn with_on_audit<F>(mut self, on_audit: F) -> Self
+    where
+        F: FnMut(&AuditEvent) + Send + 'static,
+    {
+        self.on_audit = Some(Callback::new(on_audit));
+        self
+    }
+
+    /// Decide what happens to writes once the disk (or the user's quota) is full, instead of
+    /// having every one of them fail
+    ///
+    /// This applies to writes through [`io::Write`]; see [`RotatingFile::dropped_bytes`] for how
+    /// much got lost with [`DiskFullPolicy::Drop`].
+    pub fn with_disk_full_policy(mut self, policy: DiskFullPolicy) -> Self {
+        self.disk_full.policy = policy;
+        self
+    }
+
+    /// Throw away whatever's written beyond the given limit, so that a runaway loop can't fill up
+    /// the disk
+    ///
+    /// Writes through [`io::Write`] are cut short at the limit, pretending the rest went through,
+    /// while records from [`RotatingFile::write_record`] are kept or dropped as a whole. See
+    /// [`RotatingFile::rate_limited`] for how much got lost.
+    pub fn with_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.rate_limit.set_limit(limit);
+        self
+    }
+
+    /// Whether to write a line like `rate limit exceeded, 3 lines dropped` at the start of the
+    /// first write after the rate limit was hit, off by default
+    pub fn with_rate_limit_summary(mut self, summarize: bool) -> Self {
+        self.rate_limit.summarize = summarize;
+        self
+    }
+
+    /// Run every line through `transform` before it's written, e.g. to scrub secrets
+    ///
+    /// `transform` is given each line without its line terminator and returns what to write
+    /// instead, which is counted towards the rotation period. Like with
+    /// [`RotatingFile::with_repeat_suppression`], lines written through [`io::Write`] are held back
+    /// until they're complete or [`Write::flush`] is called, which gives `transform` what's there
+    /// of the line. Records from [`RotatingFile::write_record`] are never held back.
+    pub fn with_transform<F>(mut self, transform: F) -> Self
+    where
+        F: FnMut(&[u8]) -> Cow<'_, [u8]> + Send + 'static,
+    {
+        self.transform.set(transform);
+        self
+    }
+
+    /// Keep lines to at most `max` bytes, not counting their line terminator, so that a single
+    /// huge line can't blow through the rotation period
+    ///
+    /// This comes right after [`RotatingFile::with_transform`]. With [`LongLines::Truncate`], the
+    /// `...` written in place of what's thrown away comes on top of `max`.
+    pub fn with_max_line_length(mut self, max: NonZeroUsize, long_lines: LongLines) -> Self {
+        self.line_length.max = Some((max, long_lines));
+        self
+    }
+
+    /// Replace runs of identical lines with the first one and a line like `last message repeated 3
+    /// times`, off by default
+    ///
+    /// Lines are held back until they're complete, and runs until they end, so that they can be
+    /// compared: [`Write::flush`] writes out whatever's held back, which means dropping this
+    /// without flushing it loses it. Records from [`RotatingFile::write_record`] are whole even if
+    /// they don't end a line, so only runs are held back for them. This counts as a write for the
+    /// rate limit, if there's one.
+    pub fn with_repeat_suppression(mut self, enabled: bool) -> Self {
+        self.dedup.enabled = enabled;
+        self
+    }
+
+    /// Start every line with the time it started being written in the given format, followed by a
+    /// space, for producers that don't do it themselves
+    ///
+    /// This comes after [`RotatingFile::with_transform`] and
+    /// [`RotatingFile::with_repeat_suppression`], so timestamps are never transformed or compared.
+    pub fn with_timestamps(mut self, format: TimestampFormat) -> Self {
+        self.timestamps.format = Some(format);
+        self
+    }
+
+    /// Also write everything that's written to this to `tee`, e.g. [`io::stderr`]
+    ///
+    /// `tee` gets what's written through [`io::Write`] and [`RotatingFile::write_record`] as it's
+    /// written to the file, after transforms and timestamps, but not headers and footers nor
+    /// what's dropped by [`RotatingFile::with_rate_limit`]. Errors from it are ignored, so that they never get
+    /// in the way of writing to the file.
+    pub fn with_tee<W>(mut self, tee: W) -> Self
+    where
+        W: Write + Send + 'static,
+    {
+        self.tee = Some(Tee::new(tee));
+        self
+    }
+
+    /// Reopen the current file when writing to it fails because its handle isn't usable anymore,
+    /// up to `limit` times in a row, retrying the write each time
+    ///
+    /// This is for file handles broken from the outside, e.g. an invalid file descriptor
+    /// (`EBADF`) after another tool closed it, or a stale NFS handle. The file is opened again at
+    /// the same path, or replaced by a new one if it's gone. A limit of zero (the default) turns
+    /// this off.
+    pub fn with_reopen(mut self, limit: usize) -> Self {
+        self.reopen_limit = limit;
+        self
+    }
+
+    /// How many bytes have been thrown away because the disk was full, see
+    /// [`RotatingFile::with_disk_full_policy`]
+    pub fn dropped_bytes(&self) -> u64 {
+        self.disk_full.dropped()
+    }
+
+    /// How many bytes or lines, whichever the limit is in, have been thrown away because of the
+    /// rate limit, see [`RotatingFile::with_rate_limit`]
+    pub fn rate_limited(&self) -> u64 {
+        self.rate_limit.dropped()
+    }
+
+    /// Keep writing to the newest file when starting out instead of rotating it out, for picking
+    /// up where the previous run of the program left off
+    ///
+    /// The file is only reused if it's compressed like the current file would be (see
+    /// `RotatingFile::with_live_compression`), and what's in it counts towards
+    /// [`RotationPeriod::Bytes`] and [`RotationPeriod::Lines`], so that it's rotated out once the
+    /// rotation period says so. [`RotationPeriod::Interval`] still starts counting from when this
+    /// was created.
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Flush the current file automatically as it's written to, following the given policy
+    ///
+    /// Flushing is checked for after every write, so that no more than the policy allows can get
+    /// lost if the process crashes. This matters with live compression (see
+    /// `RotatingFile::with_live_compression`), where data only hits the file once its frame is
+    /// finished.
+    pub fn with_flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.flush_tracker = RotationTracker::from(policy);
+        self
+    }
+
+    /// Only rotate between lines, so that lines aren't split across two files
+    ///
+    /// Once the rotation period says it's time to rotate, rotation is put off until a line
+    /// terminator (0x0a, b'\n') has been written: writes in the middle of a line only go up to
+    /// the end of it, and the file rotates right after. A line that never ends keeps the file
+    /// from rotating, except through [`RotatingFile::rotate`].
+    pub fn with_line_boundaries(mut self, line_boundaries: bool) -> Self {
+        self.line_boundaries = line_boundaries;
+        self
+    }
+
+    /// With [`RotationPeriod::Bytes`], split writes that would take the current file over the
+    /// period, rotating in between, so that files are never bigger than the period
+    ///
+    /// Only writes through [`io::Write`] are split: records from [`RotatingFile::write_record`]
+    /// are still written whole. This is ignored with [`RotatingFile::with_line_boundaries`], which
+    /// never splits lines.
+    pub fn with_exact_size(mut self, exact_size: bool) -> Self {
+        self.exact_size = exact_size;
+        self
+    }
+
+    /// Cooperate with other processes writing to the same files through advisory locks, off by
+    /// default
+    ///
+    /// Rotating takes an exclusive lock on `NAME.rotate.lock` in the directory, so that only one
+    /// process rotates at a time, and finding that another process already rotated the current
+    /// file out means switching to the new one instead of rotating again. Writes take a lock on
+    /// the current file, so that they don't interleave. Every process should use the same settings
+    /// and [`RotatingFile::with_resume`], and a naming scheme where the current file always has the
+    /// same name, like the default one.
+    ///
+    /// Locks are `flock` on unix and `LockFileEx` on Windows. Telling that another process
+    /// rotated only works on unix.
+    pub fn with_locking(mut self, locking: bool) -> Self {
+        self.locking = locking;
+        self
+    }
+
+    /// Notice when something else, such as logrotate, moves or deletes the current file, and
+    /// carry on in a new file at its path instead of writing to the moved one, off by default
+    ///
+    /// This is checked before every write, which takes a call to [`fs::metadata`]. If there's
+    /// already a file at the path, like the one logrotate's `create` makes, it's appended to;
+    /// otherwise a new one is created, starting with the header if there is one
+    /// (see [`RotatingFile::with_header`]). Either way this doesn't count as rotating. Noticing
+    /// that the file was moved, rather than deleted, only works on unix.
+    pub fn with_reopen_on_move(mut self, reopen_on_move: bool) -> Self {
+        self.reopen_on_move = reopen_on_move;
+        self
+    }
+
+    /// Rotate by copying the current file to where it'd be moved to and then truncating it, so
+    /// that it's never closed, off by default
+    ///
+    /// This is for programs such as `tail -f` or fluent-bit that keep the current file open, and
+    /// would otherwise go on reading the one that was rotated out. Like logrotate's
+    /// `copytruncate`, this takes a copy of the whole file on every rotation, and whatever is
+    /// read from the file while it's being rotated might be read again from the copy. The file
+    /// keeps its path only with the default [`Naming::Index`] and
+    /// [`IndexDirection::NewestFirst`], since other schemes give every new file a new name.
+    pub fn with_copy_truncate(mut self, copy_truncate: bool) -> Self {
+        self.copy_truncate = copy_truncate;
+        self
+    }
+
+    /// Take an exclusive lock on `NAME.lock` in the directory right away, holding it until this
+    /// is dropped, so that a second process writing to the same files fails instead of quietly
+    /// getting in the way
+    ///
+    /// Unlike [`RotatingFile::with_locking`], this is for making sure nobody else is there.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`io::ErrorKind::WouldBlock`] if the lock is already taken, or
+    /// any error encountered while creating the lock file (or the directory, see
+    /// [`RotatingFile::with_create_directory`]).
+    pub fn lock_exclusively(mut self) -> io::Result<Self> {
+        if self.create_directory {
+            permissions::create_dir_all(&self.directory, &self.directory_permissions)?;
+        }
+        let path = self.directory.join(format!("{}.lock", self.name));
+        self.exclusive_lock = Some(locking::try_lock(&path)?);
+        Ok(self)
+    }
+
+    fn should_rotate(&self) -> bool {
+        // If we have no current file, it's probably best if we make one :p
+        self.current_file.is_none()
+            || (self.rotation_tracker.should_rotate() && !(self.line_boundaries && self.mid_line))
+    }
+
+    // Paths are split into two parts: NAME and an optional compression suffix such as .zstd, with
+    // NAME having to be recognized by our naming scheme. The configured suffix may have more than
+    // one part, such as .zstd.enc.
+    fn logfile_key<P: AsRef<Path>>(&self, path: P) -> Option<(FileKey, Compression)> {
+        let file_name = path.as_ref().file_name()?.to_str()?;
+        let configured = self.compression.suffix().and_then(|suffix| {
+            let file_name = file_name.strip_suffix(suffix)?.strip_suffix('.')?;
+            Some((file_name, self.compression.clone()))
+        });
+        let (file_name, compression) = configured
+            .or_else(|| {
+                let (file_name, suffix) = file_name.rsplit_once('.')?;
+                Some((
+                    file_name,
+                    Compression::from_suffix(suffix, &self.compression)?,
+                ))
+            })
+            .unwrap_or((file_name, Compression::None));
+        let key = self.naming.parse(&self.name, &self.extension, file_name)?;
+        Some((key, compression))
+    }
+
+    #[cfg(test)]
+    fn logfile_index<P: AsRef<Path>>(&self, path: P) -> Option<usize> {
+        if self.naming.shifts() {
+            Some(self.logfile_key(path)?.0.major as usize)
+        } else {
+            None
+        }
+    }
+
+    // Collect our files in `directory`, descending `depth` levels of date directories
+    fn scan_directory(
+        &self,
+        directory: &Path,
+        depth: usize,
+        files: &mut Vec<LogFile>,
+    ) -> io::Result<()> {
+        for entry in fs::read_dir(directory)? {
+            let entry = entry?;
+            let path = entry.path();
+            if self.hardened && entry.file_type()?.is_symlink() {
+                continue;
+            }
+            if depth > 0 && entry.file_type()?.is_dir() {
+                self.scan_directory(&path, depth - 1, files)?;
+            } else if let Some((key, compression)) = self.logfile_key(&path) {
+                files.push(LogFile {
+                    path,
+                    key,
+                    compression,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // All of our files, newest first
+    fn log_files(&self) -> io::Result<Vec<LogFile>> {
+        let depth = self.date_directories.as_ref().map_or(0, |layout| {
+            Path::new(&layout.render(&self.name, (0, 0)))
+                .components()
+                .count()
+        });
+        let mut files = Vec::new();
+        self.scan_directory(&self.directory, depth, &mut files)?;
+
+        // Shifting schemes normally have newer files with smaller indices, while the other schemes
+        // have newer files with bigger keys
+        if self.naming.shifts() && self.index_direction == IndexDirection::NewestFirst {
+            files.sort_unstable_by_key(|file| file.key);
+        } else {
+            files.sort_unstable_by_key(|file| std::cmp::Reverse(file.key));
+        }
+        Ok(files)
+    }
+
+    fn log_files_matching(&self, pattern: &str) -> io::Result<Vec<LogFile>> {
+        let glob = Glob::new(pattern)?;
+        let mut files = self.log_files()?;
+        files.retain(|file| {
+            file.path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| glob.matches(name))
+        });
+        Ok(files)
+    }
+
+    /// List the files in the rotation set whose name matches the given glob pattern, newest first
+    ///
+    /// The pattern is matched against file names only (not whole paths) and supports `*`, `?`
+    /// and character classes such as `[0-9]` or `[!a-z]`. Only files that belong to this rotating
+    /// file are considered, so for example `"app.2024-05-01*"` gets all of the files created on
+    /// May 1st when using [`Naming::Timestamp`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory can't be read, or one of kind
+    /// [`io::ErrorKind::InvalidInput`] if the pattern is malformed.
+    pub fn files_matching(&self, pattern: &str) -> io::Result<Vec<PathBuf>> {
+        Ok(self
+            .log_files_matching(pattern)?
+            .into_iter()
+            .map(|file| file.path)
+            .collect())
+    }
+
+    /// List the files in the rotation set holding anything written between `start` and `end`,
+    /// newest first
+    ///
+    /// When each line was written isn't recorded, so every file is taken to go from when it was
+    /// created to when it was rotated out, as recorded in the manifest if there's one (see
+    /// [`RotatingFile::with_manifest`]). Otherwise, every file is taken to go from when the file
+    /// before it was last modified, which is about when it was rotated out, to when it was last
+    /// modified itself. The oldest file goes back to when it was created, or to the unix epoch if
+    /// the platform doesn't know.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory or the manifest can't be read, or if the modification
+    /// time of one of the files can't be had.
+    pub fn files_between(&self, start: SystemTime, end: SystemTime) -> io::Result<Vec<PathBuf>> {
+        #[cfg(feature = "manifest")]
+        let read;
+        #[cfg(feature = "manifest")]
+        let manifest = match &self.manifest {
+            Some(manifest) if manifest.is_loaded() => Some(manifest),
+            Some(_) => {
+                read = Manifest::read(&self.manifest_path())?;
+                Some(&read)
+            }
+            None => None,
+        };
+        let mut files = Vec::new();
+        let mut previous_end = None;
+        for file in self.log_files()?.into_iter().rev() {
+            let metadata = fs::metadata(&file.path)?;
+            #[cfg(feature = "manifest")]
+            let (rotated, created) = {
+                let entry =
+                    manifest.and_then(|manifest| manifest.entry(&self.relative_name(&file.path)));
+                (
+                    entry.map(|entry| entry.rotated),
+                    entry.and_then(|entry| entry.created),
+                )
+            };
+            #[cfg(not(feature = "manifest"))]
+            let (rotated, created) = (None, None);
+            let file_end = match rotated {
+                Some(rotated) => rotated,
+                None => metadata.modified()?,
+            };
+            let file_start = created
+                .or(previous_end)
+                .or_else(|| metadata.created().ok())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            if file_start <= end && start <= file_end {
+                files.push(file.path);
+            }
+            previous_end = Some(file_end);
+        }
+        files.reverse();
+        Ok(files)
+    }
+
+    /// Like [`RotatingFile::files_matching`], but open each file for reading
+    ///
+    /// Compressed files are decompressed transparently, so every reader yields the original
+    /// bytes.
+    ///
+    /// # Errors
+    ///
+    /// See [`RotatingFile::files_matching`]; also returns an error if any of the files can't be
+    /// opened.
+    pub fn read_matching(&self, pattern: &str) -> io::Result<Vec<Box<dyn Read + Send>>> {
+        self.log_files_matching(pattern)?
+            .iter()
+            .map(LogFile::open)
+            .collect()
+    }
+
+    /// Get the last `lines` lines written, oldest first, going through as many files as it takes,
+    /// newest first, decompressing them transparently
+    ///
+    /// Lines come without their line terminator. The current file's last line is included even
+    /// if it isn't complete yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory can't be read, or if any of the files needed can't be
+    /// read.
+    pub fn tail(&self, lines: usize) -> io::Result<Vec<Vec<u8>>> {
+        // Grown as lines come in, since there might be far fewer than asked for
+        let mut tail = std::collections::VecDeque::new();
+        for file in self.log_files()? {
+            let wanted = lines - tail.len();
+            if wanted == 0 {
+                break;
+            }
+            let mut last = std::collections::VecDeque::new();
+            for line in io::BufReader::new(file.open()?).split(b'\n') {
+                if last.len() == wanted {
+                    last.pop_front();
+                }
+                last.push_back(line?);
+            }
+            last.into_iter()
+                .rev()
+                .for_each(|line| tail.push_front(line));
+        }
+        Ok(tail.into())
+    }
+
+    /// Put the current file and the files that have been rotated out in one `.tar.gz` at `dest`,
+    /// e.g. to attach them to a bug report, leaving out those last modified longer than `max_age`
+    /// ago if given
+    ///
+    /// Files are put in as they are, compressed or not, under their path relative to the
+    /// directory. They come after a `MANIFEST` with a line for each of them, oldest first, like
+    /// `MODIFIED SIZE NAME`, MODIFIED being in RFC 3339 format. The current file is flushed
+    /// first; `dest` only shows up once it's complete.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if flushing fails, if the directory or any of the files can't be read, or
+    /// if `dest` can't be written to.
+    #[cfg(feature = "bundle")]
+    pub fn export_bundle(&mut self, dest: &Path, max_age: Option<Duration>) -> io::Result<()> {
+        self.flush()?;
+        let now = SystemTime::now();
+        let mut manifest = String::new();
+        let mut files = Vec::new();
+        for file in self.log_files()?.into_iter().rev() {
+            let metadata = fs::metadata(&file.path)?;
+            let modified = metadata.modified()?;
+            let age = now.duration_since(modified).unwrap_or_default();
+            if max_age.is_some_and(|max_age| age > max_age) {
+                continue;
+            }
+            let name = self.relative_name(&file.path);
+            manifest.push_str(&format!(
+                "{} {} {}\n",
+                timestamp::rfc3339(modified),
+                metadata.len(),
+                name
+            ));
+            files.push((name, file.path));
+        }
+        bundle::export(dest, manifest.as_bytes(), &files)
+    }
+
+    /// Read all of the files in the rotation set as one, from the oldest to the newest (the
+    /// current file), decompressing them transparently
+    ///
+    /// Every file is opened right away, so that rotations happening while reading don't get in the
+    /// way. The current file is read as far as it has made it to disk by the time it is reached.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory can't be read, or if any of the files can't be opened.
+    pub fn reader(&self) -> io::Result<LogReader> {
+        let files = self
+            .log_files()?
+            .iter()
+            .rev()
+            .map(LogFile::open)
+            .collect::<io::Result<_>>()?;
+        Ok(LogReader::new(files))
+    }
+
+    /// Find the lines containing `pattern` in all of the files in the rotation set, from the oldest
+    /// to the newest (the current file), decompressing them transparently
+    ///
+    /// Matching lines are found as they're iterated over, so that searching through a lot of
+    /// files doesn't mean holding everything found in memory. Every file is opened right away,
+    /// like with [`RotatingFile::reader`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory can't be read, or if any of the files can't be opened.
+    pub fn search<Pattern>(&self, pattern: Pattern) -> io::Result<Search>
+    where
+        Pattern: AsRef<[u8]>,
+    {
+        let files = self
+            .log_files()?
+            .into_iter()
+            .rev()
+            .map(|file| Ok((file.path.clone(), file.open()?)))
+            .collect::<io::Result<_>>()?;
+        Ok(Search::new(pattern.as_ref().to_vec(), files))
+    }
+
+    // Where `path` is relative to the directory, with `/` between components whatever the platform
+    #[cfg(any(feature = "bundle", feature = "manifest"))]
+    fn relative_name(&self, path: &Path) -> String {
+        path.strip_prefix(&self.directory)
+            .unwrap_or(path)
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    // The compression level a file that has been rotated out `age` times should have, if any
+    fn compression_level(&self, age: usize) -> Option<i32> {
+        match self.compression {
+            Compression::None => None,
+            _ if age < self.compression_timing.min_age() => None,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => Some(0),
+            #[cfg(feature = "xz")]
+            Compression::Xz { preset } => Some(preset as i32),
+            Compression::Custom(..) | Compression::External { .. } => Some(0),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { level, .. } if self.compression_tiers.is_empty() => Some(level),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { .. } => self
+                .compression_tiers
+                .iter()
+                .rev()
+                .find(|tier| tier.after <= age)
+                .map(|tier| tier.level),
+        }
+    }
+
+    // Move a file that's just been rotated out once more to `key`, compressing it and adjusting
+    // its permissions as needed. Only the current file gets compressed right away (unless we're
+    // asked not to), anything else is left to the background maintenance.
+    fn archive(
+        &self,
+        file: &LogFile,
+        key: FileKey,
+        age: usize,
+        rotation: &mut Rotation,
+    ) -> io::Result<()> {
+        let level = self.compression_level(age);
+        let compressed = file.compression.suffix().is_some();
+        // The current file's level is the configured one if it's been compressed live
+        let previous_level = match self.current_compression() {
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { level, .. } if age == 1 => Some(level),
+            _ => self.compression_level(age - 1),
+        };
+        // A file that's already compressed just like it should be can be moved as is
+        let up_to_date = compressed
+            && file.compression.same_format(&self.compression)
+            && previous_level == level;
+
+        let path = match level {
+            Some(_) if up_to_date => {
+                let dst = self.archived_filepath(file, key, &self.compression);
+                self.rename_log_file(&file.path, &dst)?;
+                // Only files compressed live get here right as they're rotated out
+                #[cfg(feature = "checksum")]
+                if self.checksums && age == 1 {
+                    checksum::write_sidecar(&dst)?;
+                }
+                dst
+            }
+
+            Some(level) if age == 1 && !compressed && !self.background_compression => {
+                let dst = self.archived_filepath(file, key, &self.compression);
+                let started = std::time::Instant::now();
+                let compressed = self.compression.compress_file(
+                    &file.path,
+                    Compression::None,
+                    &dst,
+                    level,
+                    &self.archive_settings(),
+                );
+                self.stats.compressed(started.elapsed());
+                compressed?;
+                dst
+            }
+
+            Some(level) => {
+                let src = self.archived_filepath(file, key, &file.compression);
+                if src != file.path {
+                    self.rename_log_file(&file.path, &src)?;
+                }
+                let dst = self.archived_filepath(file, key, &self.compression);
+                #[cfg(feature = "manifest")]
+                rotation.moved.push((file.path.clone(), dst.clone()));
+                // Subscribers get to see the file before it's compressed
+                if age == 1 {
+                    rotation.rotated_out = Some(src.clone());
+                    rotation.compressing = Some(dst.clone());
+                }
+                rotation.jobs.push(Job {
+                    src,
+                    src_compression: file.compression.clone(),
+                    dst,
+                    level,
+                });
+                return Ok(());
+            }
+
+            None => {
+                if age == 1 {
+                    self.archive_permissions.apply(&file.path)?;
+                }
+                let dst = self.archived_filepath(file, key, &file.compression);
+                if dst != file.path {
+                    self.rename_log_file(&file.path, &dst)?;
+                }
+                #[cfg(feature = "checksum")]
+                if self.checksums && age == 1 {
+                    checksum::write_sidecar(&dst)?;
+                }
+                dst
+            }
+        };
+
+        #[cfg(feature = "manifest")]
+        if path != file.path {
+            rotation.moved.push((file.path.clone(), path.clone()));
+        }
+        if age == 1 {
+            rotation.rotated_out = Some(path);
+        }
+        Ok(())
+    }
+
+    fn archive_settings(&self) -> ArchiveSettings {
+        ArchiveSettings {
+            permissions: ArchivePermissions {
+                mode: self.archive_permissions.mode.or(self.file_permissions.mode),
+                uid: self.archive_permissions.uid.or(self.file_permissions.uid),
+                gid: self.archive_permissions.gid.or(self.file_permissions.gid),
+            },
+            #[cfg(feature = "checksum")]
+            checksums: self.checksums,
+            #[cfg(feature = "checksum")]
+            verify: self.verify_compression,
+            durable: self.durability != Durability::None,
+            #[cfg(feature = "zstd")]
+            zstd: self.zstd_options,
+        }
+    }
+
+    // Rename one of our files, taking its checksum sidecar along
+    fn rename_log_file(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)?;
+        if self.durability != Durability::None {
+            sync_parent(to)?;
+        }
+        #[cfg(feature = "checksum")]
+        if self.checksums && from != to {
+            checksum::move_sidecar(from, to)?;
+        }
+        Ok(())
+    }
+
+    fn keyed_file_name(&self, key: FileKey, compression: &Compression) -> String {
+        file_name(&*self.naming, &self.name, &self.extension, key, compression)
+    }
+
+    // Where an existing file should be moved to, which is always next to where it is now so that
+    // files stay in the date directory they were created in
+    fn archived_filepath(
+        &self,
+        file: &LogFile,
+        key: FileKey,
+        compression: &Compression,
+    ) -> PathBuf {
+        file.path
+            .with_file_name(self.keyed_file_name(key, compression))
+    }
+
+    // Where a brand new file should be created, making its date directory if needed
+    fn new_filepath(&self, key: FileKey) -> io::Result<PathBuf> {
+        let directory = match &self.date_directories {
+            Some(layout) => {
+                let secs = timestamp::unix_secs(SystemTime::now());
+                let directory = self.directory.join(layout.render(&self.name, (secs, 0)));
+                permissions::create_dir_all(&directory, &self.directory_permissions)?;
+                if self.hardened {
+                    directory
+                        .ancestors()
+                        .take_while(|ancestor| *ancestor != self.directory)
+                        .try_for_each(permissions::check_not_symlink)?;
+                }
+                directory
+            }
+            None => self.directory.clone(),
+        };
+        Ok(directory.join(self.keyed_file_name(key, &self.current_compression())))
+    }
+
+    // Move the files that have been rotated out at least `after` times into their day's bundle,
+    // then delete the bundles there's no room for anymore
+    #[cfg(feature = "bundle")]
+    fn bundle_old_files(&self, after: usize, rotation: &mut Rotation) -> io::Result<()> {
+        let mut files = self.log_files()?;
+        // The newest file is the current one, which is about to be rotated out once more
+        let old = files.split_off(after.min(files.len()));
+
+        let mut days = std::collections::BTreeMap::<u64, Vec<PathBuf>>::new();
+        for file in old.iter().rev() {
+            let modified = fs::metadata(&file.path)?.modified()?;
+            let day = timestamp::unix_secs(modified) / (24 * 60 * 60) * (24 * 60 * 60);
+            days.entry(day).or_default().push(file.path.clone());
+        }
+        for (&day, paths) in &days {
+            let bundle = self.directory.join(bundle::bundle_name(
+                &self.name,
+                timestamp::DateTime::from_unix(day),
+            ));
+            bundle::add_to_bundle(&bundle, paths, |path, sequence| {
+                let modified = fs::metadata(path)
+                    .and_then(|metadata| metadata.modified())
+                    .map_or(0, timestamp::unix_secs);
+                let compression = old
+                    .iter()
+                    .find(|file| file.path == path)
+                    .map_or(Compression::None, |file| file.compression.clone());
+                let key = FileKey {
+                    major: modified,
+                    minor: sequence,
+                };
+                file_name(
+                    &Naming::Timestamp,
+                    &self.name,
+                    &self.extension,
+                    key,
+                    &compression,
+                )
+            })?;
+            paths
+                .iter()
+                .try_for_each(|path| self.remove_log_file(path))?;
+            rotation.bundled.extend(paths.iter().cloned());
+        }
+
+        if self.retention_held {
+            return Ok(());
+        }
+
+        // Bundles are older than every file that's left, so they go first, leaving room for the
+        // files we're keeping and the one that's about to be created
+        let mut bundles = self.bundles()?;
+        bundles.sort_unstable_by_key(|&(day, _)| std::cmp::Reverse(day));
+        let room = self
+            .max_index
+            .saturating_sub(files.len().min(self.max_index));
+        for (_, path) in bundles.into_iter().skip(room) {
+            self.delete_file(&path)?;
+            rotation.evicted.push(path);
+        }
+        Ok(())
+    }
+
+    // Our bundles, along with their day in seconds since epoch
+    #[cfg(feature = "bundle")]
+    fn bundles(&self) -> io::Result<Vec<(u64, PathBuf)>> {
+        let mut bundles = Vec::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            let day = path
+                .file_name()
+                .and_then(|file_name| file_name.to_str())
+                .and_then(|file_name| bundle::parse_bundle_name(&self.name, file_name));
+            if let Some(day) = day {
+                bundles.push((day, path));
+            }
+        }
+        Ok(bundles)
+    }
+
+    // Delete a file, overwriting it first with secure deletion
+    fn delete_file(&self, path: &Path) -> io::Result<()> {
+        // Whatever a symlink points to isn't ours to overwrite
+        if self.secure_deletion && !fs::symlink_metadata(path)?.file_type().is_symlink() {
+            overwrite_file(path)?;
+        }
+        fs::remove_file(path)
+    }
+
+    // Delete one of our files to make room for a new one
+    fn evict(&self, path: PathBuf, rotation: &mut Rotation) -> io::Result<()> {
+        self.remove_log_file(&path)?;
+        rotation.evicted.push(path);
+        Ok(())
+    }
+
+    // Delete one of our files, along with the date directories it leaves empty
+    fn remove_log_file(&self, path: &Path) -> io::Result<()> {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(path = %path.display(), "deleting");
+        self.delete_file(path)?;
+        #[cfg(feature = "checksum")]
+        if self.checksums {
+            checksum::remove_sidecar(path)?;
+        }
+        if self.date_directories.is_some() {
+            for directory in path.ancestors().skip(1) {
+                if directory == self.directory || fs::remove_dir(directory).is_err() {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn create_file(&self, rotation: &mut Rotation) -> io::Result<(CurrentFile, PathBuf)> {
+        let path = self.prepare_file(rotation)?;
+
+        // Make sure we pass `create_new` so that nobody tries to be sneaky and
+        // place a file under us
+        let file = permissions::create_new_file(&path, &self.file_permissions, self.share_mode)?;
+        Ok((
+            CurrentFile::new(file, &self.current_compression(), &self.archive_settings()),
+            path,
+        ))
+    }
+
+    // Make room for a new current file, returning the path it should be created at
+    fn prepare_file(&self, rotation: &mut Rotation) -> io::Result<PathBuf> {
+        if !self.naming.shifts() {
+            self.prepare_keyed_file(rotation)
+        } else if self.index_direction == IndexDirection::OldestFirst {
+            self.prepare_appended_file(rotation)
+        } else {
+            self.prepare_indexed_file(rotation)
+        }
+    }
+
+    // Make room for a new file that won't ever get renamed, returning the path it should be
+    // created at
+    fn prepare_keyed_file(&self, rotation: &mut Rotation) -> io::Result<PathBuf> {
+        let mut files = self.log_files()?;
+        // Remember this before deleting anything, so that keys keep growing even if we only keep
+        // one file around
+        let newest = files.first().map(|file| file.key);
+
+        // Keep at most `self.max_index` files so that there's room for one more
+        if !self.retention_held {
+            files
+                .drain(self.max_index.min(files.len())..)
+                .try_for_each(|file| self.evict(file.path, rotation))?;
+        }
+
+        // Everything that's left is getting one rotation older, the newest file being the one
+        // we're rotating out
+        for (age, file) in files.iter().enumerate() {
+            self.archive(file, file.key, age + 1, rotation)?;
+        }
+
+        let key = self.naming.next_key(newest, SystemTime::now());
+        self.new_filepath(key)
+    }
+
+    // Shift all the indexed files up by one, returning the path the new index 0 file should be
+    // created at
+    fn prepare_indexed_file(&self, rotation: &mut Rotation) -> io::Result<PathBuf> {
+        let mut files = self.log_files()?;
+
+        // Let's make sure we stay under `self.max_index` files, unless retention is on hold, in
+        // which case everything just gets shifted. To make room for one more, we remove all of
+        // the ones >=self.max_index.
+        if !self.retention_held {
+            let keep = files
+                .iter()
+                .take_while(|file| (file.key.major as usize) < self.max_index)
+                .count();
+            files
+                .drain(keep..)
+                .try_for_each(|file| self.evict(file.path, rotation))?;
+        }
+
+        // Increment all the remaining log files' indices so that we have room for a new one with
+        // index 0. Make sure that we do this in reverse order so we don't trample anything!
+        for file in files.iter().rev() {
+            let index = file.key.major as usize + 1;
+            self.archive(file, FileKey::index(index), index, rotation)?;
+        }
+
+        self.new_filepath(FileKey::index(0))
+    }
+
+    // Shift all the indexed files down so that the oldest one has index 0, returning the path the
+    // new file should be created at, right after the newest one
+    fn prepare_appended_file(&self, rotation: &mut Rotation) -> io::Result<PathBuf> {
+        let mut files = self.log_files()?;
+
+        // Keep at most `self.max_index` files so that there's room for one more
+        if !self.retention_held {
+            files
+                .drain(self.max_index.min(files.len())..)
+                .try_for_each(|file| self.evict(file.path, rotation))?;
+        }
+
+        // Close any gaps left by deleted files, starting from the oldest so that every file moves
+        // into a spot that's already been vacated
+        let count = files.len();
+        for (age, file) in files.iter().enumerate().rev() {
+            let index = count - 1 - age;
+            self.archive(file, FileKey::index(index), age + 1, rotation)?;
+        }
+
+        self.new_filepath(FileKey::index(count))
+    }
+
+    // Open the newest file to keep writing to it, see `RotatingFile::with_resume`
+    fn resume_newest_file(&mut self) -> io::Result<()> {
+        let compression = self.current_compression();
+        let newest = match self.log_files()?.into_iter().next() {
+            Some(file) if file.compression.suffix() == compression.suffix() => file,
+            _ => return Ok(()),
+        };
+
+        // What's already there counts as written
+        let mut contents = newest.open()?;
+        let mut chunk = vec![0; 64 * 1024];
+        loop {
+            match contents.read(&mut chunk)? {
+                0 => break,
+                read => self.track(&chunk[..read]),
+            }
+        }
+        self.flush_tracker.reset();
+
+        let file = self.open_appending(&newest.path)?;
+        self.current_file = Some(CurrentFile::new(
+            file,
+            &compression,
+            &self.archive_settings(),
+        ));
+        if self.latest_link {
+            self.update_latest_link(&newest.path)?;
+        }
+        self.current_path = Some(newest.path);
+        Ok(())
+    }
+
+    fn current_file(&mut self) -> io::Result<&mut CurrentFile> {
+        if self.resume && self.current_file.is_none() {
+            self.resume = false;
+            self.resume_newest_file()?;
+        }
+        if self.reopen_on_move {
+            self.reopen_if_moved()?;
+        }
+        if self.should_rotate() {
+            self.rotate_file()?;
+        }
+
+        Ok(self
+            .current_file
+            .as_mut()
+            .expect("should've been created before"))
+    }
+
+    /// Manually rotate the file out
+    ///
+    /// This is the only way that a file whose `rotation_period` is [`RotationPeriod::Manual`] can rotate.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one is encountered during creation of the new logfile. If spooling is
+    /// on (see [`RotatingFile::with_spool`]) and the directory is missing, this starts spooling
+    /// instead, the new file being created once the directory is back.
+    ///
+    /// [`RotationPeriod::Manual`]: enum.RotationPeriod.html#variant.Manual
+    pub fn rotate(&mut self) -> io::Result<()> {
+        let rotated = match self.rotate_file() {
+            Err(e) if self.should_spool(&e) => {
+                self.start_spooling();
+                Ok(())
+            }
+            result => result,
+        };
+        self.stats.record(rotated)
+    }
+
+    fn rotate_file(&mut self) -> io::Result<()> {
+        let started = std::time::Instant::now();
+        #[cfg(feature = "tracing")]
+        let _span =
+            tracing::info_span!("rotate", file = %self.name, size = self.current_offset).entered();
+        self.resume = false;
+        self.maintenance.wait()?;
+        if let (Some(footer), Some(file), Some(path)) =
+            (&mut self.footer, &mut self.current_file, &self.current_path)
+        {
+            file.write_all(&footer.call(path))?;
+        }
+        if self.create_directory {
+            permissions::create_dir_all(&self.directory, &self.directory_permissions)?;
+        }
+        if self.hardened {
+            permissions::check_not_symlink(&self.directory)?;
+        }
+        let _lock = if self.locking {
+            let lock = locking::lock(&self.directory.join(format!("{}.rotate.lock", self.name)))?;
+            if self.rotated_elsewhere()? {
+                return self.follow_rotation();
+            }
+            Some(lock)
+        } else {
+            None
+        };
+        // Files compressed live are only complete once their last frame is finished
+        if let Some(file) = &mut self.current_file {
+            file.flush()?;
+            if self.durability != Durability::None {
+                file.sync_all()?;
+            }
+        }
+        let stash = self.stash_current_file()?;
+        #[cfg(feature = "checksum")]
+        if let Some(key) = &self.hash_chain {
+            // The newest file is the one that's about to be rotated out
+            if let Some(file) = self.log_files()?.first() {
+                let name = file.path.file_name().unwrap_or_default().to_string_lossy();
+                chain::append(&self.chain_path(), key, &name, file.open()?)?;
+            }
+        }
+        #[cfg(feature = "manifest")]
+        let archived = self.manifest_entry()?;
+        let mut rotation = Rotation::default();
+        #[cfg(feature = "bundle")]
+        if let Some(after) = self.bundle_after {
+            self.bundle_old_files(after, &mut rotation)?;
+        }
+        let (file, path) = match stash {
+            // The copy has taken the current file's place and been rotated out, so the current
+            // file can carry on from scratch wherever the new one goes
+            Some(stash) => {
+                let path = self.prepare_file(&mut rotation)?;
+                fs::rename(&stash, &path)?;
+                let file = self.current_file.take().expect("was just stashed");
+                file.handle().set_len(0)?;
+                (file, path)
+            }
+            None => self.create_file(&mut rotation)?,
+        };
+        if self.durability != Durability::None {
+            sync_parent(&path)?;
+        }
+        #[cfg(feature = "manifest")]
+        let pending = rotation
+            .jobs
+            .iter()
+            .map(|job| job.dst.clone())
+            .collect::<Vec<_>>();
+        let snapshot = self.pending_archive(&rotation);
+        self.maintenance.spawn(
+            rotation.jobs,
+            self.compression.clone(),
+            self.archive_settings(),
+            Arc::clone(&self.stats),
+            snapshot,
+        )?;
+        #[cfg(feature = "manifest")]
+        self.update_manifest(archived, &rotation.moved, &pending)?;
+        self.current_file = Some(file);
+        if self.latest_link {
+            self.update_latest_link(&path)?;
+        }
+        self.current_path = Some(path);
+        self.current_offset = 0;
+        self.mid_line = false;
+        self.rotation_tracker.reset();
+        self.flush_tracker.reset();
+
+        self.write_header()?;
+
+        self.stats.rotated(started.elapsed());
+        self.stats.deleted(rotation.evicted.len());
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            rotated_out = ?rotation.rotated_out,
+            evicted = rotation.evicted.len(),
+            elapsed = ?started.elapsed(),
+            "rotated",
+        );
+        if let Some(on_evict) = &mut self.on_evict {
+            rotation.evicted.iter().for_each(|path| on_evict.call(path));
+        }
+        // In the order they happened in
+        for path in &rotation.bundled {
+            self.audit(AuditAction::Bundled, path)?;
+        }
+        for path in &rotation.evicted {
+            self.audit(AuditAction::Evicted, path)?;
+        }
+        if let Some(path) = &rotation.rotated_out {
+            self.audit(AuditAction::Rotated, path)?;
+        }
+        if let Some(current) = &self.current_path {
+            let event = RotationEvent {
+                rotated_out: rotation.rotated_out,
+                current: current.clone(),
+            };
+            if let Some(on_rotate) = &mut self.on_rotate {
+                on_rotate.call(&event);
+            }
+            // Receivers that have been dropped don't need to hear about it anymore
+            self.subscribers
+                .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+        }
+        Ok(())
+    }
+
+    // What to hand to `subscribe_archived`'s subscribers once the file that was just rotated out
+    // is done, if anyone's listening
+    fn pending_archive(&mut self, rotation: &Rotation) -> Option<Pending> {
+        let rotated_out = rotation.rotated_out.as_ref()?;
+        let subscribers = Arc::clone(&self.archive_subscribers);
+        if subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_empty()
+        {
+            return None;
+        }
+        // Unique even across restarts, since it may be what the file is uploaded as
+        let now = SystemTime::now();
+        self.snapshots += 1;
+        let snapshot = format!(
+            "{}.{:03}.{}.{}",
+            timestamp::DateTime::from_unix(timestamp::unix_secs(now)),
+            timestamp::unix_millis(now) % 1000,
+            self.snapshots,
+            rotated_out
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy(),
+        );
+        Some(Pending {
+            path: rotation
+                .compressing
+                .clone()
+                .unwrap_or_else(|| rotated_out.clone()),
+            fallback: rotation.compressing.as_ref().map(|_| rotated_out.clone()),
+            snapshot: self.snapshot_directory().join(snapshot),
+            subscribers,
+        })
+    }
+
+    // Hidden, so that the snapshots are never taken for log files
+    fn snapshot_directory(&self) -> PathBuf {
+        self.directory.join(format!(".{}.snapshots", self.name))
+    }
+
+    // Put a copy of the current file in its place, moving the file itself aside so that it can be
+    // kept open, see `RotatingFile::with_copy_truncate`. Returns where it was moved to.
+    fn stash_current_file(&self) -> io::Result<Option<PathBuf>> {
+        let path = match (&self.current_file, &self.current_path) {
+            (Some(_), Some(path)) if self.copy_truncate => path,
+            _ => return Ok(None),
+        };
+        // Hidden, so that they're never taken for log files
+        let copy = path.with_file_name(format!(".{}.copy", self.name));
+        let stash = path.with_file_name(format!(".{}.stash", self.name));
+        fs::copy(path, &copy)?;
+        fs::rename(path, &stash)?;
+        fs::rename(&copy, path)?;
+        Ok(Some(stash))
+    }
+
+    // Whether another process has rotated the current file out from under us
+    fn rotated_elsewhere(&mut self) -> io::Result<bool> {
+        let (file, path) = match (&mut self.current_file, &self.current_path) {
+            (Some(file), Some(path)) => (file, path),
+            _ => return Ok(false),
+        };
+        let ours = file.handle().metadata()?;
+        match fs::metadata(path) {
+            Ok(current) => Ok(!locking::same_file(&ours, &current)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Switch to the file another process rotated in
+    fn follow_rotation(&mut self) -> io::Result<()> {
+        if let Some(file) = &mut self.current_file {
+            file.flush()?;
+        }
+        self.reopen_current_file()?;
+        self.current_offset = match &self.current_file {
+            Some(file) => file.handle().metadata()?.len(),
+            None => 0,
+        };
+        self.mid_line = false;
+        self.rotation_tracker.reset();
+        self.flush_tracker.reset();
+        Ok(())
+    }
+
+    // Carry on at the current file's path if it's been moved away, see
+    // `RotatingFile::with_reopen_on_move`
+    fn reopen_if_moved(&mut self) -> io::Result<()> {
+        let (file, path) = match (&self.current_file, &self.current_path) {
+            (Some(file), Some(path)) => (file, path.clone()),
+            _ => return Ok(()),
+        };
+        match fs::metadata(&path) {
+            Ok(current) if locking::same_file(&file.handle().metadata()?, &current) => Ok(()),
+            Ok(_) => self.follow_rotation(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                self.follow_rotation()?;
+                let file =
+                    permissions::create_new_file(&path, &self.file_permissions, self.share_mode)?;
+                self.current_file = Some(CurrentFile::new(
+                    file,
+                    &self.current_compression(),
+                    &self.archive_settings(),
+                ));
+                self.write_header()
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if let (Some(header), Some(path)) = (&mut self.header, &self.current_path) {
+            let header = header.call(path);
+            self.current_file
+                .as_mut()
+                .expect("was just created")
+                .write_all(&header)?;
+            self.wrote(&header);
+        }
+        Ok(())
+    }
+
+    fn latest_link_path(&self) -> PathBuf {
+        self.directory
+            .join(format!("{}.latest.{}", self.name, self.extension))
+    }
+
+    // Point the latest link at the given file by creating a temporary link and renaming it over
+    // the old one, so that there's never a moment where it's missing
+    fn update_latest_link(&self, target: &Path) -> io::Result<()> {
+        let link = self.latest_link_path();
+        let tmp = self
+            .directory
+            .join(format!("{}.latest.{}.tmp", self.name, self.extension));
+
+        permissions::remove_if_exists(&tmp)?;
+
+        // Relative symlinks keep working if the whole directory gets moved around
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(target.strip_prefix(&self.directory).unwrap_or(target), &tmp)?;
+        #[cfg(not(unix))]
+        fs::hard_link(target, &tmp)?;
+
+        fs::rename(tmp, link)
+    }
+
+    // Whether writes should go to the spool after running into `e`, which is the case if the
+    // directory is gone, or can't be written to with a fallback
+    fn should_spool(&self, e: &io::Error) -> bool {
+        self.spool.falls_back_on(e)
+            || (self.spool.is_enabled()
+                && matches!(fs::metadata(&self.directory), Err(e) if e.kind() == io::ErrorKind::NotFound))
+    }
+
+    fn start_spooling(&mut self) {
+        self.current_file = None;
+        self.current_path = None;
+        self.spool.start(self.directory.clone());
+    }
+
+    // Write out the spool if the directory is back, returning whether it was
+    fn resume_from_spool(&mut self) -> io::Result<bool> {
+        if !self.directory.is_dir() || !self.spool.may_retry() {
+            return Ok(false);
+        }
+
+        if self.current_file.is_none() {
+            match self.rotate_file() {
+                Err(e) if self.spool.falls_back_on(&e) => {
+                    self.spool.refused();
+                    return Ok(false);
+                }
+                result => result?,
+            }
+        }
+        let file = self
+            .current_file
+            .as_mut()
+            .expect("should've been created before");
+        file.write_all(self.spool.buffered())?;
+        self.rotation_tracker.wrote(self.spool.buffered());
+        self.stats.wrote(self.spool.buffered());
+        self.current_offset += self.spool.buffered().len() as u64;
+        if let Some(&last) = self.spool.buffered().last() {
+            self.mid_line = last != b'\n';
+        }
+        self.spool.finish();
+        Ok(true)
+    }
+
+    // Open the current file again after its handle broke, see `RotatingFile::with_reopen`
+    fn reopen_current_file(&mut self) -> io::Result<()> {
+        self.current_file = None;
+        // If it's gone, the next write will create a new one like on the first write
+        if let Some(path) = self.current_path.as_ref().filter(|path| path.exists()) {
+            let file = self.open_appending(path)?;
+            self.current_file = Some(CurrentFile::new(
+                file,
+                &self.current_compression(),
+                &self.archive_settings(),
+            ));
+        }
+        Ok(())
+    }
+
+    // Open one of our files to carry on writing to it, refusing to follow a symlink that's been
+    // put in its place if hardened
+    fn open_appending(&self, path: &Path) -> io::Result<fs::File> {
+        let mut options = fs::OpenOptions::new();
+        options.append(true);
+        if self.hardened {
+            permissions::no_follow(&mut options);
+        }
+        options.open(path)
+    }
+
+    // Keep track of what's been written to the current file
+    fn wrote(&mut self, buf: &[u8]) {
+        self.stats.wrote(buf);
+        self.track(buf);
+    }
+
+    // Count what's in the current file towards rotating and flushing it
+    fn track(&mut self, buf: &[u8]) {
+        self.rotation_tracker.wrote(buf);
+        self.flush_tracker.wrote(buf);
+        self.current_offset += buf.len() as u64;
+        if let Some(&last) = buf.last() {
+            self.mid_line = last != b'\n';
+        }
+    }
+
+    // Flush the current file if the flush policy says it's time to
+    fn auto_flush(&mut self) -> io::Result<()> {
+        if self.flush_tracker.should_rotate() {
+            if let Some(file) = &mut self.current_file {
+                flush_current(file, self.durability)?;
+            }
+            self.flush_tracker.reset();
+        }
+        Ok(())
+    }
+
+    /// Write a whole record to a single file
+    ///
+    /// This rotates first if it's due, then writes all of `record` to the current file, so that
+    /// records never get split across two files even if they take the file over its rotation
+    /// period. With spooling on (see [`RotatingFile::with_spool`]) and the directory missing,
+    /// the record is either spooled whole or not at all.
+    ///
+    /// With [`RotatingFile::with_repeat_suppression`], a record that repeats the one before it is
+    /// only counted: it's in the file as part of the `last message repeated N times` line, which
+    /// is written once a different record comes in or on [`Write::flush`]. Until then, it's lost
+    /// if the process crashes.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error encountered while rotating or writing, or if the spool doesn't have room
+    /// for the record.
+    pub fn write_record(&mut self, record: &[u8]) -> io::Result<()> {
+        let written = self.write_transformed_record(record);
+        self.stats.record(written)
+    }
+
+    /// Write a single line as a record, adding the line terminator (0x0a, b'\n') if it's missing
+    ///
+    /// Like [`RotatingFile::write_record`], the line never gets split across two files; it also
+    /// counts as exactly one line for [`RotationPeriod::Lines`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`io::ErrorKind::InvalidInput`] if `line` has a line terminator
+    /// anywhere but at its end, as well as anything [`RotatingFile::write_record`] can return.
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let record = match line.find('\n') {
+            Some(end) if end + 1 == line.len() => Cow::Borrowed(line),
+            Some(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "lines can't have line terminators in the middle",
+                ))
+            }
+            None => Cow::Owned(format!("{}\n", line)),
+        };
+        self.write_record(record.as_bytes())
+    }
+
+    fn write_transformed_record(&mut self, record: &[u8]) -> io::Result<()> {
+        let mut record = self.transform.apply(record);
+        if self.transform.is_enabled() {
+            // Records are written whole, even without a line terminator at the end
+            let rest = self.transform.flush();
+            record.to_mut().extend_from_slice(&rest);
+        }
+        if self.line_length.is_enabled() {
+            record = Cow::Owned(self.line_length.apply(&record).into_owned());
+        }
+        let record = if self.dedup.enabled {
+            let mut filtered = self.dedup.filter(&record);
+            // Records are whole even if they don't end a line, so they're never held back to be
+            // joined with the next one
+            filtered.extend(self.dedup.flush_partial());
+            if filtered.is_empty() {
+                return Ok(());
+            }
+            Cow::Owned(filtered)
+        } else {
+            record
+        };
+
+        let record = self.timestamps.apply(&record);
+        self.write_limited_record(&record)
+    }
+
+    fn write_limited_record(&mut self, record: &[u8]) -> io::Result<()> {
+        let allowed = self.rate_limit.allowed(record);
+        self.write_rate_limit_summary()?;
+        if allowed < record.len() {
+            self.rate_limit.discard(record);
+            return Ok(());
+        }
+        self.write_whole_record(record)?;
+        self.rate_limit.wrote(record);
+        self.copy_out(record);
+        Ok(())
+    }
+
+    fn write_rate_limit_summary(&mut self) -> io::Result<()> {
+        match self.rate_limit.take_summary() {
+            Some(summary) => self.write_whole_record(&summary),
+            None => Ok(()),
+        }
+    }
+
+    fn write_whole_record(&mut self, record: &[u8]) -> io::Result<()> {
+        if self.spool.is_active() && !self.resume_from_spool()? {
+            return self.spool_record(record);
+        }
+
+        let locking = self.locking;
+        let written = self.current_file().and_then(|file| {
+            if locking {
+                locking::locked(file, |file| file.write_all(record))
+            } else {
+                file.write_all(record)
+            }
+        });
+        match written {
+            Ok(()) => {
+                self.wrote(record);
+                self.auto_flush()
+            }
+            Err(e) if self.should_spool(&e) => {
+                self.start_spooling();
+                self.spool_record(record)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Delete every file of this rotating file, the current one included, and start over as if
+    /// nothing had been written yet, e.g. for a "clear logs" button
+    ///
+    /// Only what this recognizes as its own is deleted: the current file and the ones rotated
+    /// out, along with their checksums, bundles, the hash chain, the manifest and the latest link.
+    /// Anything else in the directory is left alone. The next write creates a new current file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory can't be read or if any of the files can't be deleted,
+    /// in which case some of them may be gone already.
+    pub fn purge(&mut self) -> io::Result<()> {
+        let purged = self.purge_files();
+        self.stats.record(purged)
+    }
+
+    fn purge_files(&mut self) -> io::Result<()> {
+        self.maintenance.wait()?;
+        self.current_file = None;
+        self.current_path = None;
+        self.current_offset = 0;
+        self.mid_line = false;
+        self.resume = false;
+        self.rotation_tracker.reset();
+        self.flush_tracker.reset();
+
+        let mut deleted = 0;
+        for file in self.log_files()? {
+            self.remove_log_file(&file.path)?;
+            deleted += 1;
+            self.audit(AuditAction::Purged, &file.path)?;
+        }
+        #[cfg(feature = "bundle")]
+        for (_, path) in self.bundles()? {
+            self.delete_file(&path)?;
+            deleted += 1;
+            self.audit(AuditAction::Purged, &path)?;
+        }
+        self.stats.deleted(deleted);
+
+        #[cfg(feature = "checksum")]
+        permissions::remove_if_exists(&self.chain_path())?;
+        #[cfg(feature = "manifest")]
+        if let Some(manifest) = &mut self.manifest {
+            manifest.clear();
+            permissions::remove_if_exists(&self.manifest_path())?;
+        }
+        if self.latest_link {
+            permissions::remove_if_exists(&self.latest_link_path())?;
+        }
+        Ok(())
+    }
+
+    /// What this has been up to since it was created, such as how much has been written and how
+    /// many files have been deleted
+    ///
+    /// Counters are updated atomically as things happen, background compression included.
+    pub fn stats(&self) -> Stats {
+        self.stats.snapshot()
+    }
+
+    /// Where the current file is, if one has been created yet, e.g. to say where logs are going or
+    /// to attach them to a crash report
+    ///
+    /// Files are only created once something is written, or when rotating. The path changes with
+    /// every rotation, unless the naming scheme keeps the current file under the same name.
+    pub fn current_path(&self) -> Option<&Path> {
+        self.current_path.as_deref()
+    }
+
+    /// How much room the current file and the files rotated out take up, bundles included, e.g.
+    /// to show "Logs: 1.3 GiB across 12 files" or to decide what to keep
+    ///
+    /// Sizes are what's on disk right now, so what's still buffered isn't counted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory can't be read, or if the metadata of one of the files
+    /// can't be had.
+    pub fn disk_usage(&self) -> io::Result<DiskUsage> {
+        #[cfg_attr(not(feature = "bundle"), allow(unused_mut))]
+        let mut paths = self
+            .log_files()?
+            .into_iter()
+            .map(|file| file.path)
+            .collect::<Vec<_>>();
+        #[cfg(feature = "bundle")]
+        {
+            let mut bundles = self.bundles()?;
+            bundles.sort_unstable_by_key(|&(day, _)| std::cmp::Reverse(day));
+            paths.extend(bundles.into_iter().map(|(_, path)| path));
+        }
+
+        let now = SystemTime::now();
+        let mut usage = DiskUsage::default();
+        for path in paths {
+            let metadata = fs::metadata(&path)?;
+            let age = now.duration_since(metadata.modified()?).unwrap_or_default();
+            usage.total_bytes += metadata.len();
+            usage.oldest_age = usage.oldest_age.max(Some(age));
+            usage.files.push((path, metadata.len()));
+        }
+        Ok(usage)
+    }
+
+    /// Share this between owners, see [`SharedRotatingFile`]
+    pub fn into_shared(self) -> SharedRotatingFile {
+        SharedRotatingFile::new(self)
+    }
+
+    /// Hand this over to a background thread, returning a handle that writes to it without ever
+    /// waiting on the disk and a guard that keeps the thread going
+    ///
+    /// Up to 128,000 writes are queued up waiting for the thread, see
+    /// [`non_blocking_with`](#method.non_blocking_with).
+    pub fn non_blocking(self) -> io::Result<(NonBlocking, WorkerGuard)> {
+        self.non_blocking_with(NonBlockingOptions::default())
+    }
+
+    /// Like [`non_blocking`](#method.non_blocking), with the given options
+    pub fn non_blocking_with(
+        self,
+        options: NonBlockingOptions,
+    ) -> io::Result<(NonBlocking, WorkerGuard)> {
+        non_blocking::spawn(self, options)
+    }
+
+    /// Hand this over to a background thread which merges the writes of many threads into it
+    /// every `interval`, returning a handle that every thread should have a clone of and a guard
+    /// that keeps the thread going
+    ///
+    /// This is for when so many threads are writing at once that they'd spend their time waiting
+    /// on each other with a [`SharedRotatingFile`]: every clone of the handle only locks a buffer
+    /// of its own, and writes still show up in the order they were made in, see
+    /// [`ThreadBuffered`]. Writes made less than `interval` ago are lost if the process doesn't
+    /// get to drop the guard.
+    pub fn thread_buffered(self, interval: Duration) -> io::Result<(ThreadBuffered, MergeGuard)> {
+        thread_buffered::spawn(self, interval)
+    }
+
+    fn spool_record(&mut self, record: &[u8]) -> io::Result<()> {
+        if !self.spool.push_all(record)? {
+            return Err(io::Error::other(format!(
+                "{} is missing and the spool doesn't have room for the record",
+                self.directory.display()
+            )));
+        }
+        Ok(())
+    }
+
+    fn write_spooled(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let taken = self.spool.push(buf)?;
+        if taken == 0 && !buf.is_empty() {
+            return Err(io::Error::other(format!(
+                "{} is missing and the spool is full",
+                self.directory.display()
+            )));
+        }
+        Ok(taken)
+    }
+
+    /// Flush and sync the current file to disk, returning its path and how much of it is on disk
+    ///
+    /// This is meant for integrations that ship log files somewhere else and need to record
+    /// checkpoints like "shipped up to offset X of file Y": everything before the returned offset
+    /// is durably written and will never change. Unlike [`Write::flush`], this never rotates, so
+    /// the offset always refers to data that was actually written to the returned path.
+    ///
+    /// The offset is the size of the file on disk, so it can be seeked to. With
+    /// [`RotatingFile::with_live_compression`] that's compressed bytes, ending with a complete
+    /// zstd frame, not how much was written.
+    ///
+    /// Returns `None` if nothing has been written yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file couldn't be flushed or synced.
+    pub fn flush_with_info(&mut self) -> io::Result<Option<FlushInfo>> {
+        let (file, path) = match (&mut self.current_file, &self.current_path) {
+            (Some(file), Some(path)) => (file, path),
+            _ => return Ok(None),
+        };
+        file.flush()?;
+        file.sync_data()?;
+        Ok(Some(FlushInfo {
+            path: path.clone(),
+            offset: file.handle().metadata()?.len(),
+        }))
+    }
+
+    /// Compress the files that have already been rotated out but aren't compressed as configured
+    ///
+    /// This is meant to be called at startup, for example after switching from
+    /// [`Compression::None`] to zstd: files that were rotated out before then are compressed (or
+    /// recompressed, if they're in another format) as they would have been if the current
+    /// configuration had been used all along. The newest file, which the next rotation takes care
+    /// of, is left alone.
+    ///
+    /// The work is done by the background maintenance, see
+    /// [`RotatingFile::wait_for_maintenance`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory can't be read or the previous background maintenance
+    /// failed.
+    pub fn compress_archives(&mut self) -> io::Result<()> {
+        self.maintenance.wait()?;
+
+        let mut jobs = Vec::new();
+        for (age, file) in self.log_files()?.into_iter().enumerate().skip(1) {
+            let level = match self.compression_level(age) {
+                Some(level) => level,
+                None => continue,
+            };
+            if file.compression.suffix().is_some()
+                && file.compression.same_format(&self.compression)
+            {
+                continue;
+            }
+
+            jobs.push(Job {
+                dst: self.archived_filepath(&file, file.key, &self.compression),
+                src: file.path,
+                src_compression: file.compression,
+                level,
+            });
+        }
+
+        self.maintenance.spawn(
+            jobs,
+            self.compression.clone(),
+            self.archive_settings(),
+            Arc::clone(&self.stats),
+            None,
+        )
+    }
+
+    fn audit_path(&self) -> PathBuf {
+        self.directory.join(format!("{}.audit", self.name))
+    }
+
+    // Let the audit log and whoever's listening know that `action` just happened to `path`
+    fn audit(&mut self, action: AuditAction, path: &Path) -> io::Result<()> {
+        let event = AuditEvent {
+            time: SystemTime::now(),
+            action,
+            path: path.to_owned(),
+        };
+        if let Some(on_audit) = &mut self.on_audit {
+            on_audit.call(&event);
+        }
+        if self.audit_log {
+            audit::append(&self.audit_path(), &event)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "manifest")]
+    fn manifest_path(&self) -> PathBuf {
+        self.directory.join(format!("{}.manifest.json", self.name))
+    }
+
+    // What goes in the manifest for the file that's about to be rotated out, if there's a manifest
+    // and such a file
+    #[cfg(feature = "manifest")]
+    fn manifest_entry(&mut self) -> io::Result<Option<manifest::Entry>> {
+        let path = self.manifest_path();
+        let current_created = match &mut self.manifest {
+            Some(manifest) => {
+                manifest.load(&path)?;
+                manifest.current_created
+            }
+            None => return Ok(None),
+        };
+        // The newest file is the one that's about to be rotated out
+        let file = match self.log_files()?.into_iter().next() {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+        let (original_size, sha256) = manifest::digest(file.open()?)?;
+        Ok(Some(manifest::Entry {
+            file: self.relative_name(&file.path),
+            created: current_created.or_else(|| fs::metadata(&file.path).ok()?.created().ok()),
+            rotated: SystemTime::now(),
+            original_size,
+            sha256,
+            compressed_size: None,
+        }))
+    }
+
+    // Bring the manifest up to date with a rotation that rotated out `archived` and moved files
+    // around as told by `moved`, with the background maintenance yet to create `pending`
+    #[cfg(feature = "manifest")]
+    fn update_manifest(
+        &mut self,
+        archived: Option<manifest::Entry>,
+        moved: &[(PathBuf, PathBuf)],
+        pending: &[PathBuf],
+    ) -> io::Result<()> {
+        if self.manifest.is_none() {
+            return Ok(());
+        }
+        let moves = moved
+            .iter()
+            .map(|(from, to)| (self.relative_name(from), self.relative_name(to)))
+            .collect::<Vec<_>>();
+        // What's still around, along with its size if it's compressed
+        let mut existing = self
+            .log_files()?
+            .into_iter()
+            .map(|file| {
+                let size = match file.compression.suffix() {
+                    Some(_) => fs::metadata(&file.path).ok().map(|metadata| metadata.len()),
+                    None => None,
+                };
+                (self.relative_name(&file.path), size)
+            })
+            .collect::<Vec<_>>();
+        existing.extend(pending.iter().map(|path| (self.relative_name(path), None)));
+
+        let path = self.manifest_path();
+        let manifest = self.manifest.as_mut().expect("there's a manifest");
+        manifest.entries.extend(archived);
+        manifest.moved(&moves);
+        manifest
+            .entries
+            .retain(|entry| existing.iter().any(|(file, _)| *file == entry.file));
+        for entry in &mut manifest.entries {
+            if let Some((_, Some(size))) = existing.iter().find(|(file, _)| *file == entry.file) {
+                entry.compressed_size = Some(*size);
+            }
+        }
+        manifest.current_created = Some(SystemTime::now());
+        manifest.save(&path, &self.name)
+    }
+
+    /// Check the archives against the manifest kept with [`RotatingFile::with_manifest`]
+    ///
+    /// This waits for the background maintenance to be done, then makes sure that every archive
+    /// in the manifest is still around and decompresses to what was rotated out. Archives that
+    /// aren't in the manifest, such as those from before it was turned on, aren't checked.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`io::ErrorKind::InvalidData`] describing the first problem found,
+    /// or whatever error reading the manifest or the archives gave.
+    #[cfg(feature = "manifest")]
+    pub fn verify_manifest(&mut self) -> io::Result<()> {
+        self.maintenance.wait()?;
+        let manifest = Manifest::read(&self.manifest_path())?;
+        let files = self.log_files()?;
+        for entry in &manifest.entries {
+            let path = self.directory.join(&entry.file);
+            let file = files.iter().find(|file| file.path == path).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} is missing", path.display()),
+                )
+            })?;
+            if manifest::digest(file.open()?)? != (entry.original_size, entry.sha256) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} doesn't match the manifest", path.display()),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "checksum")]
+    fn chain_path(&self) -> PathBuf {
+        self.directory.join(format!("{}.chain", self.name))
+    }
+
+    /// Check the hash chain kept with [`RotatingFile::with_hash_chain`] against the archives
+    ///
+    /// This makes sure that the chain itself is intact, that every archive still around
+    /// decompresses to what was rotated out and that none of those that should still be around
+    /// are missing (bundled files aren't checked).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`io::ErrorKind::InvalidData`] describing the first problem found,
+    /// of kind [`io::ErrorKind::InvalidInput`] if there's no hash chain key to check it with, or
+    /// whatever error reading the chain or the archives gave.
+    #[cfg(feature = "checksum")]
+    pub fn verify_hash_chain(&self) -> io::Result<()> {
+        let key = self.hash_chain.as_ref().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "there's no hash chain key")
+        })?;
+        let hashes = chain::read(&self.chain_path(), key)?;
+        let files = self.log_files()?;
+        // The newest file hasn't been rotated out yet, so it's not in the chain
+        let archived = files.get(1..).unwrap_or_default();
+        if archived.len() > hashes.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "there are archives missing from the hash chain",
+            ));
+        }
+
+        for (file, hash) in archived.iter().zip(hashes.iter().rev()) {
+            if checksum::sha256(file.open()?)? != *hash {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} doesn't match the hash chain", file.path.display()),
+                ));
+            }
+        }
+
+        let kept = if self.retention_held {
+            hashes.len()
+        } else {
+            hashes.len().min(self.max_index)
+        };
+        #[cfg(feature = "bundle")]
+        let kept = if self.bundle_after.is_some() {
+            archived.len()
+        } else {
+            kept
+        };
+        if archived.len() < kept {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} archives have been deleted", kept - archived.len()),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Wait for the background maintenance started by the last rotation to finish
+    ///
+    /// Rotation hands (re)compression of older files to a background thread when using
+    /// [`RotatingFile::with_compression_tiers`]. The next rotation waits for it anyway, but this
+    /// is useful before shutting down or inspecting the directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error the background maintenance encountered.
+    pub fn wait_for_maintenance(&mut self) -> io::Result<()> {
+        self.maintenance.wait()
+    }
+
+    /// Stop deleting old files, regardless of the configured maximum number of files
+    ///
+    /// This is meant to preserve evidence during an incident: rotation keeps happening as usual,
+    /// but files that would normally be deleted are kept around (with index-based naming, they
+    /// just keep getting shifted to bigger indices). Keep in mind that this means disk usage is
+    /// no longer bounded until [`RotatingFile::release_retention_hold`] is called.
+    pub fn retention_hold(&mut self) {
+        self.retention_held = true;
+    }
+
+    /// Go back to deleting old files as configured
+    ///
+    /// Files that were kept because of [`RotatingFile::retention_hold`] get deleted on the next
+    /// rotation.
+    pub fn release_retention_hold(&mut self) {
+        self.retention_held = false;
+    }
+
+    /// Check if retention is currently on hold, see [`RotatingFile::retention_hold`]
+    pub fn is_retention_held(&self) -> bool {
+        self.retention_held
+    }
+
+    /// Check that logging actually works in the current environment
+    ///
+    /// This is meant to be called once at startup, so that problems with permissions, disk space
+    /// and the like are found right away instead of when the first log line is lost. It checks
+    /// that files can be created and renamed inside the directory, then writes a canary line to
+    /// the current file (rotating first if needed, just like a regular write would), syncs it to
+    /// disk and reads it back. With [`RotatingFile::with_locking`], the current file is locked
+    /// while the canary is written, like with any other write.
+    ///
+    /// The canary line (`file-rotator self-test <NANOS>`) stays in the log file.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error encountered, or an error of kind [`io::ErrorKind::InvalidData`]
+    /// if the canary line couldn't be read back.
+    pub fn self_test(&mut self) -> io::Result<()> {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+
+        // Rotation needs to create, rename and remove files, which we can check for without
+        // touching any of the actual log files
+        let probe = self
+            .directory
+            .join(format!("{}.self-test.{}", self.name, nanos));
+        let renamed_probe = self
+            .directory
+            .join(format!("{}.self-test.{}.renamed", self.name, nanos));
+        fs::OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .open(&probe)?;
+        fs::rename(&probe, &renamed_probe)?;
+        fs::remove_file(&renamed_probe)?;
+
+        let canary = format!("file-rotator self-test {}\n", nanos);
+        let locking = self.locking;
+        let file = self.current_file()?;
+        let write = |file: &mut CurrentFile| {
+            file.write_all(canary.as_bytes())?;
+            file.sync_all()
+        };
+        // Locks are taken like a regular write would, to find file systems that don't have them
+        if locking {
+            locking::locked(file, write)?;
+        } else {
+            write(file)?;
+        }
+        self.wrote(canary.as_bytes());
+
+        let path = self
+            .current_path
+            .as_ref()
+            .expect("should've been created before");
+        let read = match self.current_compression() {
+            Compression::None => {
+                let mut file = fs::File::open(path)?;
+                file.seek(io::SeekFrom::End(-(canary.len() as i64)))?;
+                let mut read = vec![0; canary.len()];
+                file.read_exact(&mut read)?;
+                read
+            }
+            // Compressed streams can't be read from the end
+            compression => {
+                let mut read = Vec::new();
+                LogFile {
+                    path: path.clone(),
+                    key: FileKey::default(),
+                    compression,
+                }
+                .open()?
+                .read_to_end(&mut read)?;
+                read
+            }
+        };
+        if !read.ends_with(canary.as_bytes()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "could not read back self-test canary from {}",
+                    path.display()
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+// Check that a name can't escape the directory or clash with special files, see
+// `RotatingFile::try_new`
+fn validate_name(name: &str) -> io::Result<()> {
+    const RESERVED: &[&str] = &[
+        "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+        "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+    ];
+
+    let invalid = |reason| {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid name {:?}: {}", name, reason),
+        ))
+    };
+
+    if name.is_empty() || name == "." || name == ".." {
+        return invalid("not a file name");
+    }
+    if name.contains(['/', '\\']) {
+        return invalid("contains a path separator");
+    }
+    if name.contains('\0') {
+        return invalid("contains a NUL byte");
+    }
+
+    // Windows ignores extensions when checking for reserved names, so `NUL.0.log` is out too
+    let stem = name.split('.').next().unwrap_or(name);
+    if RESERVED
+        .iter()
+        .any(|reserved| stem.trim_end().eq_ignore_ascii_case(reserved))
+    {
+        return invalid("reserved on Windows");
+    }
+
+    Ok(())
+}
+
+/// Compute the name of a log file, exactly as a [`RotatingFile`] would
+///
+/// This lets external tooling (uploaders, dashboards, ...) predict file names without
+/// duplicating the logic. `name`, `extension` and `naming` are what the rotating file was
+/// configured with (`log` being the default extension), `key` is the file's position in the
+/// rotation set and `compression` is what the file has been compressed with, if it has been
+/// rotated out yet.
+///
+/// # Examples
+///
+/// ```rust
+/// # use file_rotator::{file_name, Compression, FileKey, Naming};
+/// let key = FileKey::index(3);
+/// assert_eq!(file_name(&Naming::Index, "app", "log", key, &Compression::None), "app.3.log");
+/// # #[cfg(feature = "zstd")]
+/// assert_eq!(
+///     file_name(&Naming::Index, "app", "log", key, &Compression::zstd(0)),
+///     "app.3.log.zstd"
+/// );
+///
+/// let key = FileKey { major: 1_717_243_200, minor: 0 };
+/// assert_eq!(
+///     file_name(&Naming::Timestamp, "app", "jsonl", key, &Compression::None),
+///     "app.2024-06-01T12-00-00.jsonl"
+/// );
+/// ```
+pub fn file_name<Scheme>(
+    naming: &Scheme,
+    name: &str,
+    extension: &str,
+    key: FileKey,
+    compression: &Compression,
+) -> String
+where
+    Scheme: NamingScheme + ?Sized,
+{
+    let mut file_name = naming.file_name(name, extension, key);
+    if let Some(suffix) = compression.suffix() {
+        file_name.push('.');
+        file_name.push_str(suffix);
+    }
+    file_name
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.write_buf(buf);
+        self.stats.record(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(tee) = &mut self.tee {
+            tee.flush();
+        }
+        let flushed = self.flush_buf();
+        self.stats.record(flushed)
+    }
+}
+
+// What's behind `Write`, without keeping track of errors
+impl RotatingFile {
+    fn write_buf(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.has_line_filters() {
+            let (written, kept) = self.write_rate_limited(buf)?;
+            self.copy_out(&buf[..kept]);
+            return Ok(written);
+        }
+
+        let lines = self.transform.apply(buf);
+        self.write_lines(&lines)?;
+        Ok(buf.len())
+    }
+
+    // Give what's being written to the tee and to whoever's following
+    fn copy_out(&mut self, buf: &[u8]) {
+        if let Some(tee) = &mut self.tee {
+            tee.write(buf);
+        }
+        if !buf.is_empty() {
+            // Followers that have been dropped don't need to hear about it anymore
+            self.followers
+                .retain(|follower| follower.send(buf.to_vec()).is_ok());
+        }
+    }
+
+    fn has_line_filters(&self) -> bool {
+        self.transform.is_enabled()
+            || self.line_length.is_enabled()
+            || self.dedup.enabled
+            || self.timestamps.is_enabled()
+    }
+
+    // Write lines that have been through the transform
+    fn write_lines(&mut self, lines: &[u8]) -> io::Result<()> {
+        let lines = self.line_length.apply(lines);
+        if !self.dedup.enabled {
+            return self.write_filtered(&lines);
+        }
+        let filtered = self.dedup.filter(&lines);
+        self.write_filtered(&filtered)
+    }
+
+    // Write lines that have been through repeat suppression
+    fn write_filtered(&mut self, lines: &[u8]) -> io::Result<()> {
+        let lines = self.timestamps.apply(lines);
+        self.write_all_rate_limited(&lines)
+    }
+
+    fn write_all_rate_limited(&mut self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write_rate_limited(buf) {
+                Ok((0, _)) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok((written, kept)) => {
+                    self.copy_out(&buf[..kept]);
+                    buf = &buf[written..];
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    // Returns how much of `buf` is done with, and how much of that was kept rather than dropped
+    // by the rate limit, which is always the start of it
+    fn write_rate_limited(&mut self, buf: &[u8]) -> io::Result<(usize, usize)> {
+        let allowed = self.rate_limit.allowed(buf);
+        self.write_rate_limit_summary()?;
+        if allowed == 0 && !buf.is_empty() {
+            self.rate_limit.discard(buf);
+            return Ok((buf.len(), 0));
+        }
+
+        let written = self.write_unlimited(&buf[..allowed])?;
+        self.rate_limit.wrote(&buf[..written]);
+        if written == allowed && allowed < buf.len() {
+            self.rate_limit.discard(&buf[allowed..]);
+            return Ok((buf.len(), written));
+        }
+        Ok((written, written))
+    }
+
+    fn write_unlimited(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.spool.is_active() && !self.resume_from_spool()? {
+            return self.write_spooled(buf);
+        }
+
+        // If we're only waiting for the end of the line to rotate, don't write past it
+        let buf = match buf.iter().position(|&b| b == b'\n') {
+            Some(end) if self.mid_line && self.rotation_tracker.should_rotate() => &buf[..=end],
+            _ => buf,
+        };
+        // Nor past the end of the file, if it's meant to be exactly as big as the period
+        let buf = match self.rotation_tracker.bytes_left() {
+            Some(left) if self.exact_size && !self.line_boundaries => &buf[..left.min(buf.len())],
+            _ => buf,
+        };
+
+        let mut disk_full = std::mem::take(&mut self.disk_full);
+        let written = disk_full.write(self, buf);
+        self.disk_full = disk_full;
+        match written {
+            Ok(written) => {
+                self.auto_flush()?;
+                Ok(written)
+            }
+            Err(e) if self.should_spool(&e) => {
+                self.start_spooling();
+                self.write_spooled(buf)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn flush_buf(&mut self) -> io::Result<()> {
+        let held_back = self.transform.flush();
+        self.write_lines(&held_back)?;
+        let held_back = self.dedup.flush();
+        self.write_filtered(&held_back)?;
+
+        if self.spool.is_active() && !self.resume_from_spool()? {
+            return self.spool.flush();
+        }
+
+        let durability = self.durability;
+        match self
+            .current_file()
+            .and_then(|file| flush_current(file, durability))
+        {
+            Ok(()) => {
+                self.flush_tracker.reset();
+                Ok(())
+            }
+            Err(e) if self.should_spool(&e) => {
+                self.start_spooling();
+                Ok(())
+            }
+            result => result,
+        }
+    }
+}
+
+// Whether `e` means the file handle itself is broken, rather than what's behind it
+fn is_broken_handle(e: &io::Error) -> bool {
+    #[cfg(unix)]
+    if e.raw_os_error() == Some(libc::EBADF) {
+        return true;
+    }
+    // ERROR_INVALID_HANDLE
+    #[cfg(windows)]
+    if e.raw_os_error() == Some(6) {
+        return true;
+    }
+    e.kind() == io::ErrorKind::StaleNetworkFileHandle
+}
+
+impl disk_full::Target for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let locking = self.locking;
+        let written = loop {
+            let file = self.current_file()?;
+            let written = if locking {
+                locking::locked(file, |file| file.write(buf))
+            } else {
+                file.write(buf)
+            };
+            match written {
+                Err(e) if is_broken_handle(&e) && self.reopen_attempts < self.reopen_limit => {
+                    self.reopen_attempts += 1;
+                    self.reopen_current_file()?;
+                }
+                result => break result?,
+            }
+        };
+        self.reopen_attempts = 0;
+        self.wrote(&buf[..written]);
+        Ok(written)
+    }
+
+    fn prune(&mut self) -> io::Result<bool> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("prune", file = %self.name).entered();
+        self.maintenance.wait()?;
+        let oldest = self
+            .log_files()?
+            .into_iter()
+            .rev()
+            .find(|file| Some(&file.path) != self.current_path.as_ref());
+        match oldest {
+            Some(file) => {
+                self.remove_log_file(&file.path)?;
+                self.stats.deleted(1);
+                if let Some(on_evict) = &mut self.on_evict {
+                    on_evict.call(&file.path);
+                }
+                self.audit(AuditAction::Pruned, &file.path)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::num::NonZeroUsize;
+    use std::path::Path;
+
+    use proptest::prelude::*;
+
+    use super::{
+        Compressor, Durability, FlushInfo, Instance, Naming, RotatingFile, RotationPeriod,
+        SpoolEvent, Template,
+    };
+    // Only the tests which need compression use these
+    #[cfg(feature = "zstd")]
+    use super::{
+        ArchivePermissions, CompressionTier, CompressionTiming, FileKey, FlushPolicy,
+        IndexDirection, NamingScheme,
+    };
+
+    #[track_caller]
+    fn assert_contains_files<P: AsRef<Path>>(
+        directory: P,
+        num: usize,
+    ) -> Result<(), TestCaseError> {
+        let p = directory.as_ref();
+        prop_assert_eq!(
+            fs::read_dir(p).unwrap().count(),
+            num,
+            "Directory {:?} did not contain {} file(s) (at {})",
+            p,
+            num,
+            std::panic::Location::caller()
+        );
+        Ok(())
+    }
+
+    // Stores files backwards, for no reason whatsoever
+    #[derive(Debug)]
+    struct Reverse;
+
+    impl Compressor for Reverse {
+        fn suffix(&self) -> &str {
+            "rev"
+        }
+
+        fn compress(&self, src: &Path, dst: &Path) -> std::io::Result<()> {
+            let mut contents = fs::read(src)?;
+            contents.reverse();
+            fs::write(dst, contents)
+        }
+
+        fn decompress(&self, mut src: fs::File) -> std::io::Result<Box<dyn std::io::Read + Send>> {
+            use std::io::Read;
+
+            let mut contents = Vec::new();
+            src.read_to_end(&mut contents)?;
+            contents.reverse();
+            Ok(Box::new(std::io::Cursor::new(contents)))
+        }
+    }
+
+    // Stores files with an extra byte at the end, which it forgets to strip when decompressing
+    #[cfg(feature = "checksum")]
+    #[derive(Debug)]
+    struct Lossy;
+
+    #[cfg(feature = "checksum")]
+    impl Compressor for Lossy {
+        fn suffix(&self) -> &str {
+            "lossy"
+        }
+
+        fn compress(&self, src: &Path, dst: &Path) -> std::io::Result<()> {
+            let mut contents = fs::read(src)?;
+            contents.push(b'!');
+            fs::write(dst, contents)
+        }
+
+        fn decompress(&self, src: fs::File) -> std::io::Result<Box<dyn std::io::Read + Send>> {
+            Ok(Box::new(src))
+        }
+    }
+
+    // Names files NAME-SEQUENCE.EXTENSION, with the sequence in hex, for no reason whatsoever
+    #[cfg(feature = "zstd")]
+    #[derive(Debug)]
+    struct HexNaming;
+
+    #[cfg(feature = "zstd")]
+    impl NamingScheme for HexNaming {
+        fn shifts(&self) -> bool {
+            false
+        }
+
+        fn file_name(&self, name: &str, extension: &str, key: FileKey) -> String {
+            format!("{}-{:x}.{}", name, key.major, extension)
+        }
+
+        fn parse(&self, name: &str, extension: &str, file_name: &str) -> Option<FileKey> {
+            let key = file_name
+                .strip_prefix(name)?
+                .strip_prefix('-')?
+                .strip_suffix(extension)?
+                .strip_suffix('.')?;
+            Some(FileKey {
+                major: u64::from_str_radix(key, 16).ok()?,
+                minor: 0,
+            })
+        }
+
+        fn next_key(&self, newest: Option<FileKey>, _now: std::time::SystemTime) -> FileKey {
+            FileKey {
+                major: newest.map_or(0, |key| key.major + 1),
+                minor: 0,
+            }
+        }
+    }
+
+    #[test]
+    fn test_validate_name() {
+        for name in ["app", "my-app.v2", "console", "com10", "{hostname}", "a..b"] {
+            assert!(super::validate_name(name).is_ok(), "{:?}", name);
+        }
+
+        for name in [
+            "",
+            ".",
+            "..",
+            "../../etc/foo",
+            "a/b",
+            "a\\b",
+            "a\0b",
+            "CON",
+            "nul",
+            "Com1",
+            "aux.app",
+            "LPT9 ",
+        ] {
+            let e = super::validate_name(name).unwrap_err();
+            assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput, "{:?}", name);
+        }
+
+        assert!(RotatingFile::try_new(
+            "../app",
+            "logs",
+            RotationPeriod::Manual,
+            NonZeroUsize::new(1).unwrap(),
+            crate::Compression::None
+        )
+        .is_err());
+
+        // The instance identifier ends up in the name too
+        for id in ["../../etc", "a/b", "a\\b"] {
+            let e = RotatingFile::try_new(
+                "app",
+                "logs",
+                RotationPeriod::Manual,
+                NonZeroUsize::new(1).unwrap(),
+                crate::Compression::None,
+            )
+            .unwrap()
+            .with_instance(crate::Instance::Id(id.into()))
+            .unwrap_err();
+            assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput, "{:?}", id);
+        }
+    }
+
+    #[cfg(all(unix, feature = "signals"))]
+    #[test]
+    fn test_flush_on_signal() {
+        use std::io::prelude::*;
+        use std::os::unix::process::ExitStatusExt;
+        use std::process;
+
+        const DIRECTORY: &str = "FILE_ROTATOR_TEST_SIGNAL_DIRECTORY";
+
+        // Killing ourselves would take the other tests down too, so it's done in another process
+        if let Some(directory) = std::env::var_os(DIRECTORY) {
+            let file = RotatingFile::new(
+                "signal",
+                std::path::PathBuf::from(directory),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(1).unwrap(),
+                crate::Compression::None,
+            )
+            // Holds on to the line until it's flushed
+            .with_transform(|line| std::borrow::Cow::Borrowed(line))
+            .into_shared();
+            file.register();
+            (&file).write_all(b"last words").unwrap();
+            crate::flush_on_signal().unwrap();
+            // SAFETY: signalling ourselves has no preconditions
+            unsafe { libc::raise(libc::SIGTERM) };
+            loop {
+                std::thread::park();
+            }
+        }
+
+        let directory = tempfile::tempdir().unwrap();
+        let status = process::Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "tests::test_flush_on_signal",
+                "--test-threads",
+                "1",
+            ])
+            .env(DIRECTORY, directory.path())
+            .stdout(process::Stdio::null())
+            .status()
+            .unwrap();
+        assert_eq!(status.signal(), Some(libc::SIGTERM));
+        let files = fs::read_dir(directory.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        assert_eq!(files.len(), 1, "{:?}", files);
+        assert_eq!(fs::read_to_string(&files[0]).unwrap(), "last words");
+    }
+
+    #[test]
+    fn test_log_panics() {
+        use std::io::prelude::*;
+        use std::panic;
+        use std::process;
+
+        const DIRECTORY: &str = "FILE_ROTATOR_TEST_PANIC_DIRECTORY";
+
+        // Panic hooks are global, so this is done in another process to keep it away from the
+        // other tests
+        if let Some(directory) = std::env::var_os(DIRECTORY) {
+            let file = RotatingFile::new(
+                "panic",
+                std::path::PathBuf::from(directory),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(3).unwrap(),
+                crate::Compression::None,
+            )
+            .into_shared();
+            (&file).write_all(b"before\n").unwrap();
+            file.log_panics(true);
+            let _ = panic::catch_unwind(|| panic!("boom"));
+            (&file).write_all(b"after\n").unwrap();
+            (&file).flush().unwrap();
+            return;
+        }
+
+        let directory = tempfile::tempdir().unwrap();
+        let status = process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "tests::test_log_panics", "--test-threads", "1"])
+            .env(DIRECTORY, directory.path())
+            .stdout(process::Stdio::null())
+            .stderr(process::Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let file = RotatingFile::new(
+            "panic",
+            directory.path().to_owned(),
+            RotationPeriod::Manual,
+            NonZeroUsize::new(3).unwrap(),
+            crate::Compression::None,
+        );
+        let contents = file
+            .files_matching("*")
+            .unwrap()
+            .iter()
+            .map(|path| fs::read_to_string(path).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(contents.len(), 3, "{:?}", contents);
+        assert_eq!(contents[0], "after\n");
+        assert!(contents[1].starts_with("thread 'tests::test_log_panics' panicked at "));
+        assert!(contents[1].contains("boom\nstack backtrace:\n"));
+        assert_eq!(contents[2], "before\n");
+    }
+
+    #[test]
+    fn test_files_between() {
+        use std::io::prelude::*;
+        use std::time::{Duration, SystemTime};
+
+        let directory = tempfile::tempdir().unwrap();
+        let mut file = RotatingFile::new(
+            "between",
+            directory.path().to_owned(),
+            RotationPeriod::Manual,
+            NonZeroUsize::new(3).unwrap(),
+            crate::Compression::None,
+        );
+        for contents in [&b"a\n"[..], b"b\n"] {
+            file.write_all(contents).unwrap();
+            file.rotate().unwrap();
+        }
+        file.write_all(b"c\n").unwrap();
+
+        // Later than the files were created, so that the oldest one starts before all of this
+        let base = SystemTime::now() + Duration::from_secs(1000);
+        let t = |secs: u64| base + Duration::from_secs(secs);
+        let files = file.files_matching("*").unwrap();
+        for (path, secs) in files.iter().zip([30, 20, 10]) {
+            fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(t(secs))
+                .unwrap();
+        }
+        // Newest first
+        assert_eq!(file.files_between(t(0), t(5)).unwrap(), files[2..]);
+        assert_eq!(file.files_between(t(15), t(18)).unwrap(), files[1..2]);
+        assert_eq!(file.files_between(t(18), t(25)).unwrap(), files[..2]);
+        assert!(file.files_between(t(31), t(40)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_purge() {
+        use std::io::prelude::*;
+
+        let directory = tempfile::tempdir().unwrap();
+        let file = RotatingFile::new(
+            "purge",
+            directory.path().to_owned(),
+            RotationPeriod::Manual,
+            NonZeroUsize::new(3).unwrap(),
+            crate::Compression::None,
+        )
+        .with_latest_link(true);
+        #[cfg(feature = "checksum")]
+        let file = file.with_hash_chain("secret");
+        #[cfg(feature = "manifest")]
+        let file = file.with_manifest(true);
+        let mut file = file;
+        for _ in 0..3 {
+            file.write_all(b"a\n").unwrap();
+            file.rotate().unwrap();
+        }
+        // Not ours, despite looking a lot like it
+        for other in ["purge.notes", "purge.x.log", "other.0.log"] {
+            fs::write(directory.path().join(other), "").unwrap();
+        }
+
+        let deleted = file.stats().files_deleted;
+        file.purge().unwrap();
+        assert_eq!(file.current_path(), None);
+        assert_eq!(file.stats().files_deleted, deleted + 3);
+        let mut left = fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        left.sort_unstable();
+        assert_eq!(left, ["other.0.log", "purge.notes", "purge.x.log"]);
+
+        file.write_all(b"b\n").unwrap();
+        assert_eq!(file.tail(2).unwrap(), [b"b"]);
+    }
+
+    #[cfg(feature = "manifest")]
+    #[test]
+    fn test_manifest() {
+        use std::io::prelude::*;
+        use std::time::{Duration, SystemTime};
+
+        let directory = tempfile::tempdir().unwrap();
+        let rotating_file = || {
+            RotatingFile::new(
+                "manifest",
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(3).unwrap(),
+                crate::Compression::None,
+            )
+            .with_manifest(true)
+        };
+        // Times in the manifest only go down to the millisecond
+        let started = SystemTime::now() - Duration::from_secs(1);
+        let mut file = rotating_file();
+        for contents in ["a\n", "bb\n", "ccc\n"] {
+            file.write_all(contents.as_bytes()).unwrap();
+            file.rotate().unwrap();
+        }
+        // Carries on where the previous one left off
+        drop(file);
+        let mut file = rotating_file();
+        file.write_all(b"dddd\n").unwrap();
+        file.rotate().unwrap();
+        file.write_all(b"eeeee\n").unwrap();
+
+        let manifest = crate::manifest::Manifest::read(&file.manifest_path()).unwrap();
+        let files = file.files_matching("*").unwrap();
+        assert_eq!(files.len(), 3);
+        // The oldest ones were deleted to make room, and starting over rotated out the empty file
+        // the previous one had left behind
+        assert_eq!(
+            manifest
+                .entries
+                .iter()
+                .map(|entry| (directory.path().join(&entry.file), entry.original_size))
+                .collect::<Vec<_>>(),
+            [(files[2].clone(), 0), (files[1].clone(), 5)]
+        );
+        for entry in &manifest.entries {
+            let contents = fs::read(directory.path().join(&entry.file)).unwrap();
+            assert_eq!(
+                entry.sha256,
+                crate::manifest::digest(&contents[..]).unwrap().1
+            );
+            assert!(started <= entry.created.unwrap() && entry.created.unwrap() <= entry.rotated);
+            assert_eq!(entry.compressed_size, None);
+        }
+        assert!(manifest.entries[0].rotated <= manifest.entries[1].created.unwrap());
+        file.verify_manifest().unwrap();
+
+        // Goes by the manifest rather than by modification times
+        let later = SystemTime::now() + Duration::from_secs(1000);
+        for path in &files[1..] {
+            fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(later)
+                .unwrap();
+        }
+        assert_eq!(
+            file.files_between(later, later).unwrap(),
+            Vec::<std::path::PathBuf>::new()
+        );
+        let rotated = manifest.entries[0].rotated;
+        assert!(file
+            .files_between(SystemTime::UNIX_EPOCH, rotated)
+            .unwrap()
+            .contains(&files[2]));
+
+        fs::write(&files[2], "tampered\n").unwrap();
+        let kind = file.verify_manifest().unwrap_err().kind();
+        assert_eq!(kind, std::io::ErrorKind::InvalidData);
+        fs::remove_file(&files[2]).unwrap();
+        let kind = file.verify_manifest().unwrap_err().kind();
+        assert_eq!(kind, std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_audit() {
+        use std::io::prelude::*;
+        use std::sync::{Arc, Mutex};
+
+        use crate::AuditAction;
+
+        let directory = tempfile::tempdir().unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut file = RotatingFile::new(
+            "audit",
+            directory.path().to_owned(),
+            RotationPeriod::Manual,
+            NonZeroUsize::new(2).unwrap(),
+            crate::Compression::None,
+        )
+        .with_audit_log(true)
+        .with_on_audit({
+            let events = Arc::clone(&events);
+            move |event| events.lock().unwrap().push(event.clone())
+        });
+        for _ in 0..2 {
+            file.write_all(b"a\n").unwrap();
+            file.rotate().unwrap();
+        }
+        let files = file.files_matching("*").unwrap();
+        file.purge().unwrap();
+
+        let events = events.lock().unwrap();
+        assert_eq!(
+            events.iter().map(|event| event.action).collect::<Vec<_>>(),
+            [
+                AuditAction::Rotated,
+                AuditAction::Evicted,
+                AuditAction::Rotated,
+                AuditAction::Purged,
+                AuditAction::Purged,
+            ]
+        );
+        assert_eq!(events[1].path, events[0].path);
+        assert_eq!(
+            events[3..]
+                .iter()
+                .map(|event| &event.path)
+                .collect::<Vec<_>>(),
+            files.iter().collect::<Vec<_>>()
+        );
+
+        // Purging leaves the audit log alone
+        let log = fs::read_to_string(directory.path().join("audit.audit")).unwrap();
+        assert_eq!(
+            log.lines().collect::<Vec<_>>(),
+            events
+                .iter()
+                .map(|event| format!(
+                    "{} {} {}",
+                    crate::timestamp::rfc3339(event.time),
+                    event.action,
+                    event.path.display()
+                ))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_disk_usage() {
+        use std::io::prelude::*;
+
+        let directory = tempfile::tempdir().unwrap();
+        let mut file = RotatingFile::new(
+            "usage",
+            directory.path().to_owned(),
+            RotationPeriod::Manual,
+            NonZeroUsize::new(3).unwrap(),
+            crate::Compression::None,
+        );
+        assert_eq!(file.disk_usage().unwrap(), crate::DiskUsage::default());
+
+        file.write_all(b"abc\n").unwrap();
+        file.rotate().unwrap();
+        file.write_all(b"de\n").unwrap();
+        fs::write(directory.path().join("other.log"), "not ours").unwrap();
+
+        let usage = file.disk_usage().unwrap();
+        assert_eq!(usage.total_bytes, 7);
+        assert_eq!(usage.file_count(), 2);
+        let files = file.files_matching("*").unwrap();
+        assert_eq!(usage.files, [(files[0].clone(), 3), (files[1].clone(), 4)]);
+        assert!(usage.oldest_age.is_some());
+    }
+
+    #[test]
+    fn test_current_path() {
+        use std::io::prelude::*;
+
+        let directory = tempfile::tempdir().unwrap();
+        let mut file = RotatingFile::new(
+            "current",
+            directory.path().to_owned(),
+            RotationPeriod::Manual,
+            NonZeroUsize::new(2).unwrap(),
+            crate::Compression::None,
+        )
+        .with_naming(Naming::Timestamp);
+        assert_eq!(file.current_path(), None);
+
+        file.write_all(b"first\n").unwrap();
+        let first = file.current_path().unwrap().to_owned();
+        assert_eq!(fs::read_to_string(&first).unwrap(), "first\n");
+        file.rotate().unwrap();
+        file.write_all(b"second\n").unwrap();
+        let second = file.current_path().unwrap();
+        assert_ne!(second, first);
+        assert_eq!(fs::read_to_string(second).unwrap(), "second\n");
+    }
+
+    #[test]
+    fn test_timestamp_year_zero() {
+        use std::io::prelude::*;
+
+        let directory = tempfile::tempdir().unwrap();
+        let stray = directory.path().join("zero.0000-01-01T00-00-00.log");
+        fs::write(&stray, "stray\n").unwrap();
+        let mut file = RotatingFile::new(
+            "zero",
+            directory.path().to_owned(),
+            RotationPeriod::Manual,
+            NonZeroUsize::new(1).unwrap(),
+            crate::Compression::None,
+        )
+        .with_naming(Naming::Timestamp);
+        file.write_all(b"hello\n").unwrap();
+        file.rotate().unwrap();
+        file.write_all(b"hello\n").unwrap();
+        file.rotate().unwrap();
+
+        // Not one of ours, so it's neither rotated nor deleted
+        assert!(file.logfile_key(stray.clone()).is_none());
+        assert_eq!(fs::read_to_string(&stray).unwrap(), "stray\n");
+    }
+
+    #[test]
+    fn test_search() {
+        use std::io::prelude::*;
+
+        let directory = tempfile::tempdir().unwrap();
+        let mut file = RotatingFile::new(
+            "search",
+            directory.path().to_owned(),
+            RotationPeriod::Manual,
+            NonZeroUsize::new(3).unwrap(),
+            crate::Compression::None,
+        );
+        file.write_all(b"error: one\nfine\n").unwrap();
+        file.rotate().unwrap();
+        file.write_all(b"fine\nerror: two").unwrap();
+
+        let files = file.files_matching("*").unwrap();
+        let found = file
+            .search("error")
+            .unwrap()
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            found,
+            [
+                crate::Match {
+                    path: files[1].clone(),
+                    offset: 0,
+                    line: b"error: one".to_vec(),
+                },
+                crate::Match {
+                    path: files[0].clone(),
+                    offset: 5,
+                    line: b"error: two".to_vec(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tail_and_follow() {
+        use std::io::prelude::*;
+
+        let directory = tempfile::tempdir().unwrap();
+        let mut file = RotatingFile::new(
+            "tail",
+            directory.path().to_owned(),
+            RotationPeriod::Manual,
+            NonZeroUsize::new(3).unwrap(),
+            crate::Compression::None,
+        );
+        assert!(file.tail(2).unwrap().is_empty());
+        file.write_all(b"a\nb\n").unwrap();
+        file.rotate().unwrap();
+        file.write_all(b"c\n").unwrap();
+        assert_eq!(file.tail(2).unwrap(), [b"b", b"c"]);
+        assert_eq!(file.tail(5).unwrap(), [b"a", b"b", b"c"]);
+        assert!(file.tail(0).unwrap().is_empty());
+        assert_eq!(file.tail(usize::MAX).unwrap(), [b"a", b"b", b"c"]);
+
+        let follow = file.follow();
+        file.write_all(b"d\ne").unwrap();
+        file.rotate().unwrap();
+        file.write_record(b"f\n").unwrap();
+        drop(file);
+        assert_eq!(follow.collect::<Vec<_>>(), [&b"d"[..], b"ef"]);
+    }
+
+    #[test]
+    fn test_reader() {
+        use std::io::prelude::*;
+
+        let directory = tempfile::tempdir().unwrap();
+        let mut file = RotatingFile::new(
+            "reader",
+            directory.path().to_owned(),
+            RotationPeriod::Manual,
+            NonZeroUsize::new(4).unwrap(),
+            crate::Compression::None,
+        );
+        for line in ["first", "second", "third"] {
+            writeln!(file, "{}", line).unwrap();
+            file.rotate().unwrap();
+        }
+        // The current file comes last
+        file.write_all(b"fourth\n").unwrap();
+
+        let lines = file
+            .reader()
+            .unwrap()
+            .lines()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(lines, ["first", "second", "third", "fourth"]);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_tracing() {
+        use std::io::prelude::*;
+        use std::sync::{Arc, Mutex};
+
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        // Remembers the names of spans and the messages of events
+        #[derive(Clone, Default)]
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+
+        struct Message<'a>(&'a mut String);
+
+        impl Visit for Message<'_> {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                if field.name() == "message" {
+                    *self.0 = format!("{:?}", value);
+                }
+            }
+        }
+
+        impl Subscriber for Recorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let mut recorded = self.0.lock().unwrap();
+                recorded.push(format!("span {}", span.metadata().name()));
+                Id::from_u64(recorded.len() as u64)
+            }
+
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                let mut message = String::new();
+                event.record(&mut Message(&mut message));
+                self.0.lock().unwrap().push(message);
+            }
+
+            fn enter(&self, _: &Id) {}
+
+            fn exit(&self, _: &Id) {}
+        }
+
+        let directory = tempfile::tempdir().unwrap();
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let mut file = RotatingFile::new(
+                "tracing",
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(2).unwrap(),
+                crate::Compression::None,
+            );
+            for _ in 0..2 {
+                file.write_all(b"hello\n").unwrap();
+                file.rotate().unwrap();
+            }
+        });
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            [
+                // The first file being created
+                "span rotate",
+                "rotated",
+                "span rotate",
+                "rotated",
+                "span rotate",
+                "deleting",
+                "rotated",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_template_without_sequence() {
+        use std::io::prelude::*;
+
+        let directory = tempfile::tempdir().unwrap();
+        let mut file = RotatingFile::new(
+            "dated",
+            directory.path().to_owned(),
+            RotationPeriod::Manual,
+            NonZeroUsize::new(5).unwrap(),
+            crate::Compression::None,
+        )
+        .with_naming(Template::parse("{name}.{%Y-%m-%d}.log").unwrap());
+        // Several times within the same day, barring the test running across midnight
+        for contents in &["a\n", "b\n", "c\n"] {
+            file.write_all(contents.as_bytes()).unwrap();
+            file.rotate().unwrap();
+        }
+
+        let files = file.log_files().unwrap();
+        assert_eq!(files.len(), 4);
+        let contents = files
+            .iter()
+            .map(|file| fs::read_to_string(&file.path).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(contents, ["", "c\n", "b\n", "a\n"]);
+        let suffixes = files
+            .iter()
+            .filter(|file| file.key.major == files[0].key.major)
+            .map(|file| file.key.minor)
+            .collect::<Vec<_>>();
+        assert!(suffixes.windows(2).all(|pair| pair[0] > pair[1]));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_flush_with_info_live_compression() {
+        use std::io::prelude::*;
+
+        let directory = tempfile::tempdir().unwrap();
+        let mut file = RotatingFile::new(
+            "live",
+            directory.path().to_owned(),
+            RotationPeriod::Manual,
+            NonZeroUsize::new(2).unwrap(),
+            crate::Compression::zstd(0),
+        )
+        .with_live_compression(true);
+        file.write_all(&b"hello\n".repeat(100)).unwrap();
+        let first = file.flush_with_info().unwrap().unwrap();
+        file.write_all(b"world\n").unwrap();
+        let second = file.flush_with_info().unwrap().unwrap();
+
+        // Offsets are into the compressed file, each at the end of a frame
+        assert!(first.offset < 600);
+        let compressed = fs::read(&second.path).unwrap();
+        assert_eq!(compressed.len() as u64, second.offset);
+        let decoded = zstd::decode_all(&compressed[..first.offset as usize]).unwrap();
+        assert_eq!(decoded, b"hello\n".repeat(100));
+        let decoded = zstd::decode_all(&compressed[first.offset as usize..]).unwrap();
+        assert_eq!(decoded, b"world\n");
+    }
+
+    #[test]
+    fn test_record_repeat_suppression() {
+        use std::io::prelude::*;
+
+        let directory = tempfile::tempdir().unwrap();
+        let mut file = RotatingFile::new(
+            "records",
+            directory.path().to_owned(),
+            RotationPeriod::Manual,
+            NonZeroUsize::new(1).unwrap(),
+            crate::Compression::None,
+        )
+        .with_repeat_suppression(true);
+        let contents = |file: &RotatingFile| fs::read_to_string(file.current_path().unwrap());
+
+        // Records that don't end a line are written right away, not joined with the next one
+        file.write_record(b"{}").unwrap();
+        assert_eq!(contents(&file).unwrap(), "{}");
+        file.write_record(b"\na\n").unwrap();
+        file.write_record(b"a\n").unwrap();
+        assert_eq!(contents(&file).unwrap(), "{}\na\n");
+        // Repeats only show up once the run is over
+        file.flush().unwrap();
+        assert_eq!(
+            contents(&file).unwrap(),
+            "{}\na\nlast message repeated 1 times\n"
+        );
+    }
+
+    #[cfg(all(feature = "zstd", feature = "encryption"))]
+    #[test]
+    fn test_encryption_zstd_options() {
+        use std::io::prelude::*;
+
+        let directory = tempfile::tempdir().unwrap();
+        let key = crate::Encryption::Aes256Gcm { key: [1; 32] };
+        let mut file = RotatingFile::new(
+            "encrypted",
+            directory.path().to_owned(),
+            RotationPeriod::Manual,
+            NonZeroUsize::new(2).unwrap(),
+            crate::Compression::zstd(0),
+        )
+        .with_encryption(key)
+        .with_zstd_options(crate::ZstdOptions::default().with_seekable_frame_size(Some(16)));
+        file.write_all(&b"hello\n".repeat(10)).unwrap();
+        file.rotate().unwrap();
+
+        // Set afterwards, but used all the same
+        let path = directory.path().join("encrypted.1.log.zstd.enc");
+        let mut compressed = Vec::new();
+        crate::encryption::Decrypting::new(fs::File::open(path).unwrap(), &key)
+            .unwrap()
+            .read_to_end(&mut compressed)
+            .unwrap();
+        assert_eq!(
+            compressed[compressed.len() - 4..],
+            0x8F92_EAB1_u32.to_le_bytes()
+        );
+        assert_eq!(
+            zstd::decode_all(&compressed[..]).unwrap(),
+            b"hello\n".repeat(10)
+        );
+    }
+
+    #[test]
+    fn test_self_test_locking() {
+        use std::io::prelude::*;
+
+        let directory = tempfile::tempdir().unwrap();
+        let mut file = RotatingFile::new(
+            "locked",
+            directory.path().to_owned(),
+            RotationPeriod::Manual,
+            NonZeroUsize::new(2).unwrap(),
+            crate::Compression::None,
+        )
+        .with_locking(true);
+        file.write_all(b"hello\n").unwrap();
+        file.self_test().unwrap();
+
+        // The lock taken for the canary is given back
+        let path = file.current_path.clone().unwrap();
+        crate::locking::try_lock(&path).unwrap();
+        let read = fs::read_to_string(&path).unwrap();
+        assert!(read.starts_with("hello\nfile-rotator self-test "));
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig {
+            cases: 15,
+            ..ProptestConfig::default()
+        })]
+
+        #[test]
+        fn test_max_files(name in "[a-zA-Z_-]+", n in 1..25usize) {
+            let directory = tempfile::tempdir().unwrap();
+
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n).unwrap(),
+                crate::Compression::None
+            );
+
+            assert_contains_files(&directory, 0)?;
+            for i in 0..n {
+                file.rotate().unwrap();
+                assert_contains_files(&directory, i+1)?;
+            }
+
+            for _ in 0..n {
+                assert_contains_files(&directory, n)?;
+                file.rotate().unwrap();
+            }
+        }
+
+        #[cfg(feature = "zstd")]
+        #[test]
+        fn test_max_files_timestamped(name in "[a-zA-Z_-]+", n in 1..25usize) {
+            let directory = tempfile::tempdir().unwrap();
+
+            let mut file = RotatingFile::new(
+                name.clone(),
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n).unwrap(),
+                crate::Compression::zstd(0)
+            ).with_naming(Naming::Timestamp);
+
+            for i in 0..2 * n {
+                file.rotate().unwrap();
+                assert_contains_files(&directory, (i + 1).min(n))?;
+            }
+
+            let mut names = fs::read_dir(&directory)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect::<Vec<_>>();
+            names.sort();
+            let current = names.iter().filter(|name| name.ends_with(".log")).collect::<Vec<_>>();
+            prop_assert_eq!(current.len(), 1);
+            let prefix = format!("{}.", name);
+            for file_name in &names {
+                prop_assert!(file_name.starts_with(&prefix));
+                prop_assert!(file.logfile_key(directory.path().join(file_name)).is_some());
+            }
+        }
+
+        #[test]
+        fn test_max_files_templated(name in "[a-zA-Z_-]+", n in 1..25usize) {
+            let directory = tempfile::tempdir().unwrap();
+
+            let template = Template::parse("{name}.{%Y-%m-%d}.{seq}.txt").unwrap();
+            let mut file = RotatingFile::new(
+                name.clone(),
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n).unwrap(),
+                crate::Compression::None
+            ).with_naming(template);
+
+            for i in 0..2 * n {
+                file.rotate().unwrap();
+                assert_contains_files(&directory, (i + 1).min(n))?;
+            }
+
+            for entry in fs::read_dir(&directory).unwrap() {
+                let path = entry.unwrap().path();
+                prop_assert!(file.logfile_key(&path).is_some());
+                prop_assert_eq!(path.extension().unwrap().to_string_lossy(), "txt");
+            }
+        }
+
+        #[cfg(feature = "zstd")]
+        #[test]
+        fn test_extension(name in "[a-zA-Z_-]+", extension in "\\.?[a-z]{1,6}", n in 1..10usize) {
+            let directory = tempfile::tempdir().unwrap();
+
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n).unwrap(),
+                crate::Compression::zstd(0)
+            ).with_extension(extension.clone());
+
+            for _ in 0..2 * n {
+                file.rotate().unwrap();
+            }
+            assert_contains_files(&directory, n)?;
+
+            let extension = extension.trim_start_matches('.');
+            for entry in fs::read_dir(&directory).unwrap() {
+                let path = entry.unwrap().path();
+                let index = file.logfile_index(&path).unwrap();
+                let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+                let suffix = if index == 0 {
+                    format!(".0.{}", extension)
+                } else {
+                    format!(".{}.zstd", extension)
+                };
+                prop_assert!(file_name.ends_with(&suffix));
+            }
+        }
+
+        #[cfg(feature = "zstd")]
+        #[test]
+        fn test_read_matching(name in "[a-zA-Z_-]+", n in 1..10usize, data: Vec<u8>) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            fs::write(directory.path().join("unrelated.0.log"), b"nope").unwrap();
+
+            let mut file = RotatingFile::new(
+                name.clone(),
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n + 1).unwrap(),
+                crate::Compression::zstd(0)
+            );
+            for _ in 0..=n {
+                file.rotate().unwrap();
+                file.write_all(&data).unwrap();
+            }
+            file.flush().unwrap();
+
+            let all = file.files_matching("*").unwrap();
+            prop_assert_eq!(all.len(), n + 1);
+            for (index, path) in all.iter().enumerate() {
+                prop_assert_eq!(file.logfile_index(path), Some(index));
+            }
+
+            let pattern = format!("{}.[0-9].log", name);
+            prop_assert_eq!(file.files_matching(&pattern).unwrap().len(), 1);
+
+            for mut reader in file.read_matching("*").unwrap() {
+                let mut read = Vec::new();
+                reader.read_to_end(&mut read).unwrap();
+                prop_assert_eq!(&read, &data);
+            }
+        }
+
+        #[cfg(feature = "zstd")]
+        #[test]
+        fn test_stable_naming(name in "[a-zA-Z_-]+", n in 1..10usize) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name.clone(),
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n).unwrap(),
+                crate::Compression::zstd(0)
+            ).with_naming(Naming::Stable);
+
+            for i in 0..2 * n {
+                file.rotate().unwrap();
+                write!(file, "{}", i).unwrap();
+                file.flush().unwrap();
+                assert_contains_files(&directory, (i + 1).min(n))?;
+
+                let current = directory.path().join(format!("{}.log", name));
+                prop_assert_eq!(fs::read_to_string(&current).unwrap(), i.to_string());
+            }
+
+            for (index, path) in file.files_matching("*").unwrap().iter().enumerate() {
+                prop_assert_eq!(file.logfile_index(path), Some(index));
+            }
+        }
+
+        #[test]
+        fn test_sequence_naming(name in "[a-zA-Z_-]+", n in 1..10usize) {
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name.clone(),
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n).unwrap(),
+                crate::Compression::None
+            ).with_naming(Naming::Sequence);
+
+            for i in 0..2 * n {
+                file.rotate().unwrap();
+                assert_contains_files(&directory, (i + 1).min(n))?;
+            }
+
+            // Only the biggest numbers are left, and nothing was ever renamed
+            let expected = (n..2 * n)
+                .rev()
+                .map(|seq| directory.path().join(format!("{}.{}.log", name, seq)))
+                .collect::<Vec<_>>();
+            prop_assert_eq!(file.files_matching("*").unwrap(), expected);
+        }
+
+        #[test]
+        fn test_padded_index(name in "[a-zA-Z_-]+", n in 3..10usize) {
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name.clone(),
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n).unwrap(),
+                crate::Compression::None
+            );
+            file.rotate().unwrap();
+            file.rotate().unwrap();
+            drop(file);
+
+            // Switching over renames the existing unpadded files
+            let mut file = RotatingFile::new(
+                name.clone(),
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n).unwrap(),
+                crate::Compression::None
+            ).with_naming(Naming::PaddedIndex(3));
+            file.rotate().unwrap();
+            assert_contains_files(&directory, 3)?;
+
+            for index in 0..3 {
+                let path = directory.path().join(format!("{}.{:03}.log", name, index));
+                prop_assert!(path.exists());
+                prop_assert_eq!(file.logfile_index(&path), Some(index));
+            }
+        }
+
+        #[cfg(feature = "zstd")]
+        #[test]
+        fn test_oldest_first(name in "[a-zA-Z_-]+", n in 1..10usize, compressed: bool) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name.clone(),
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n).unwrap(),
+                if compressed { crate::Compression::zstd(0) } else { crate::Compression::None }
+            ).with_index_direction(IndexDirection::OldestFirst);
+
+            for i in 0..2 * n {
+                file.rotate().unwrap();
+                write!(file, "{}", i).unwrap();
+                file.flush().unwrap();
+                let count = (i + 1).min(n);
+                assert_contains_files(&directory, count)?;
+
+                let current = directory.path().join(format!("{}.{}.log", name, count - 1));
+                prop_assert_eq!(fs::read_to_string(&current).unwrap(), i.to_string());
+            }
+
+            // Files are still listed newest first
+            let files = file.files_matching("*").unwrap();
+            let readers = file.read_matching("*").unwrap();
+            for (age, (path, mut reader)) in files.iter().zip(readers).enumerate() {
+                prop_assert_eq!(file.logfile_index(path), Some(n - 1 - age));
+                let mut contents = String::new();
+                reader.read_to_string(&mut contents).unwrap();
+                prop_assert_eq!(contents, (2 * n - 1 - age).to_string());
+            }
+        }
+
+        #[cfg(feature = "zstd")]
+        #[test]
+        fn test_date_directories(name in "[a-zA-Z_-]+", n in 1..10usize, shifting: bool) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            // A file from before date directories were turned on, which still counts
+            let old = if shifting {
+                format!("{}.0.log", name)
+            } else {
+                format!("{}.2000-01-01T00-00-00.log", name)
+            };
+            fs::write(directory.path().join(old), "old").unwrap();
+
+            let mut file = RotatingFile::new(
+                name.clone(),
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n).unwrap(),
+                crate::Compression::zstd(0)
+            )
+            .with_naming(if shifting { Naming::Index } else { Naming::Timestamp })
+            .with_date_directories(Template::parse("{%Y}/{%m}/{%d}").unwrap())
+            .with_latest_link(true);
+
+            for i in 0..2 * n {
+                file.rotate().unwrap();
+                write!(file, "{}", i).unwrap();
+                file.flush().unwrap();
+                prop_assert_eq!(file.files_matching("*").unwrap().len(), (i + 2).min(n));
+            }
+
+            let files = file.files_matching("*").unwrap();
+            for path in &files {
+                let date = path.parent().unwrap().strip_prefix(directory.path()).unwrap();
+                prop_assert_eq!(date.components().count(), 3);
+            }
+
+            let latest = directory.path().join(format!("{}.latest.log", name));
+            let expected = (2 * n - 1).to_string();
+            prop_assert_eq!(fs::read_to_string(latest).unwrap(), expected);
+        }
+
+        #[test]
+        fn test_latest_link(name in "[a-zA-Z_-]+", n in 1..10usize) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name.clone(),
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n).unwrap(),
+                crate::Compression::None
+            ).with_naming(Naming::Timestamp).with_latest_link(true);
+
+            let link = directory.path().join(format!("{}.latest.log", name));
+            for i in 0..2 * n {
+                file.rotate().unwrap();
+                write!(file, "{}", i).unwrap();
+                file.flush().unwrap();
+                assert_contains_files(&directory, (i + 1).min(n) + 1)?;
+                prop_assert_eq!(fs::read_to_string(&link).unwrap(), i.to_string());
+            }
+        }
+
+        #[cfg(unix)]
+        #[cfg(feature = "zstd")]
+        #[test]
+        fn test_archive_permissions(name in "[a-zA-Z_-]+", n in 2..10usize, compressed: bool) {
+            use std::os::unix::fs::PermissionsExt;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n).unwrap(),
+                if compressed { crate::Compression::zstd(0) } else { crate::Compression::None }
+            ).with_archive_permissions(ArchivePermissions {
+                mode: Some(0o640),
+                ..ArchivePermissions::default()
+            });
+
+            for _ in 0..n {
+                file.rotate().unwrap();
+            }
+            assert_contains_files(&directory, n)?;
+
+            for path in file.files_matching("*").unwrap() {
+                let mode = fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+                if file.logfile_index(&path) == Some(0) {
+                    prop_assert_ne!(mode, 0o640);
+                } else {
+                    prop_assert_eq!(mode, 0o640);
+                }
+            }
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn test_file_mode(name in "[a-zA-Z_-]+", n in 2..10usize, compressed: bool) {
+            use std::os::unix::fs::PermissionsExt;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n).unwrap(),
+                if compressed {
+                    crate::Compression::Custom(std::sync::Arc::new(Reverse))
+                } else {
+                    crate::Compression::None
+                }
+            )
+            .with_date_directories(Template::parse("{%Y}/{%m}").unwrap())
+            // Modes the usual umask wouldn't allow
+            .with_file_mode(0o666)
+            .with_directory_mode(0o777);
+
+            for _ in 0..n {
+                file.rotate().unwrap();
+            }
+
+            let files = file.files_matching("*").unwrap();
+            prop_assert_eq!(files.len(), n);
+            for path in files {
+                prop_assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o666);
+                let month = path.parent().unwrap();
+                for directory in [month, month.parent().unwrap()] {
+                    let mode = fs::metadata(directory).unwrap().permissions().mode() & 0o777;
+                    prop_assert_eq!(mode, 0o777);
+                }
+            }
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn test_create_directory(name in "[a-zA-Z_-]+", create: bool) {
+            use std::os::unix::fs::PermissionsExt;
+
+            let directory = tempfile::tempdir().unwrap();
+            let logs = directory.path().join("var").join("log");
+            let mut file = RotatingFile::new(
+                name,
+                &logs,
+                RotationPeriod::Manual,
+                NonZeroUsize::new(5).unwrap(),
+                crate::Compression::None
+            )
+            // A mode the usual umask wouldn't allow
+            .with_directory_mode(0o777)
+            .with_create_directory(create);
+
+            prop_assert_eq!(file.rotate().is_ok(), create);
+            if create {
+                assert_contains_files(&logs, 1)?;
+                for directory in [&logs, logs.parent().unwrap()] {
+                    let mode = fs::metadata(directory).unwrap().permissions().mode() & 0o777;
+                    prop_assert_eq!(mode, 0o777);
+                }
+            }
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn test_owner(name in "[a-zA-Z_-]+", n in 2..10usize, compressed: bool) {
+            use std::os::unix::fs::MetadataExt;
+
+            let directory = tempfile::tempdir().unwrap();
+            // Only root can give files away, anybody else can only test with themselves
+            let (uid, gid) = if unsafe { libc::geteuid() } == 0 {
+                (12345, 23456)
+            } else {
+                let metadata = fs::metadata(&directory).unwrap();
+                (metadata.uid(), metadata.gid())
+            };
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n).unwrap(),
+                if compressed {
+                    crate::Compression::Custom(std::sync::Arc::new(Reverse))
+                } else {
+                    crate::Compression::None
+                }
+            )
+            .with_date_directories(Template::parse("{%Y}").unwrap())
+            .with_owner(Some(uid), Some(gid));
+
+            for _ in 0..n {
+                file.rotate().unwrap();
+            }
+
+            let files = file.files_matching("*").unwrap();
+            prop_assert_eq!(files.len(), n);
+            for path in files {
+                for path in [&path, path.parent().unwrap()] {
+                    let metadata = fs::metadata(path).unwrap();
+                    prop_assert_eq!((metadata.uid(), metadata.gid()), (uid, gid));
+                }
+            }
+        }
+
+        #[cfg(feature = "zstd")]
+        #[test]
+        fn test_custom_naming(name in "[a-zA-Z_]+", n in 1..25usize) {
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name.clone(),
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n).unwrap(),
+                crate::Compression::zstd(0)
+            ).with_naming(Box::new(HexNaming) as Box<dyn NamingScheme>);
+
+            for _ in 0..2 * n {
+                file.rotate().unwrap();
+            }
+            assert_contains_files(&directory, n)?;
+
+            let expected = format!("{}-{:x}.log", name, 2 * n - 1);
+            let files = file.files_matching("*").unwrap();
+            prop_assert_eq!(files[0].file_name().unwrap().to_str().unwrap(), &expected);
+            for path in &files[1..] {
+                prop_assert_eq!(path.extension().unwrap().to_str().unwrap(), "zstd");
+            }
+        }
+
+        #[cfg(feature = "zstd")]
+        #[test]
+        fn test_retention_hold(name in "[a-zA-Z_-]+", n in 1..10usize, shifting: bool) {
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n).unwrap(),
+                crate::Compression::zstd(0)
+            ).with_naming(if shifting { Naming::Index } else { Naming::Timestamp });
+
+            for _ in 0..n {
+                file.rotate().unwrap();
+            }
+            assert_contains_files(&directory, n)?;
+
+            file.retention_hold();
+            for i in 0..n {
+                file.rotate().unwrap();
+                assert_contains_files(&directory, n + i + 1)?;
+            }
+
+            file.release_retention_hold();
+            file.rotate().unwrap();
+            assert_contains_files(&directory, n)?;
+        }
+
+        #[cfg(feature = "zstd")]
+        #[test]
+        fn test_compression_tiers(name in "[a-zA-Z_-]+", shifting: bool, data: Vec<u8>) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(8).unwrap(),
+                crate::Compression::zstd(1)
+            )
+            .with_naming(if shifting { Naming::Index } else { Naming::Timestamp })
+            .with_compression_tiers(vec![
+                CompressionTier { after: 5, level: 19 },
+                CompressionTier { after: 3, level: 3 },
+            ]);
+
+            for _ in 0..10 {
+                file.rotate().unwrap();
+                file.write_all(&data).unwrap();
+                file.flush().unwrap();
+            }
+            file.wait_for_maintenance().unwrap();
+            assert_contains_files(&directory, 8)?;
+
+            for (age, path) in file.files_matching("*").unwrap().iter().enumerate() {
+                let compressed = path.extension().unwrap() == "zstd";
+                prop_assert_eq!(compressed, age >= 3);
+            }
+
+            for mut reader in file.read_matching("*").unwrap() {
+                let mut read = Vec::new();
+                reader.read_to_end(&mut read).unwrap();
+                prop_assert_eq!(&read, &data);
+            }
+        }
+
+        #[cfg(feature = "zstd")]
+        #[test]
+        fn test_compression_timing(name in "[a-zA-Z_-]+", shifting: bool, after in 1..5usize, data: Vec<u8>) {
+            use std::io::prelude::*;
+
+            for (timing, min_age) in [
+                (CompressionTiming::Immediately, 1),
+                (CompressionTiming::NextRotation, 2),
+                (CompressionTiming::AfterRotations(after), after),
+            ] {
+                let directory = tempfile::tempdir().unwrap();
+                let mut file = RotatingFile::new(
+                    name.clone(),
+                    directory.path().to_owned(),
+                    RotationPeriod::Manual,
+                    NonZeroUsize::new(6).unwrap(),
+                    crate::Compression::zstd(1)
+                )
+                .with_naming(if shifting { Naming::Index } else { Naming::Timestamp })
+                .with_compression_timing(timing);
+
+                for _ in 0..8 {
+                    file.rotate().unwrap();
+                    file.write_all(&data).unwrap();
+                    file.flush().unwrap();
+                }
+                file.wait_for_maintenance().unwrap();
+                assert_contains_files(&directory, 6)?;
+
+                for (age, path) in file.files_matching("*").unwrap().iter().enumerate() {
+                    let compressed = path.extension().unwrap() == "zstd";
+                    prop_assert_eq!(compressed, age >= min_age);
+                }
+
+                for mut reader in file.read_matching("*").unwrap() {
+                    let mut read = Vec::new();
+                    reader.read_to_end(&mut read).unwrap();
+                    prop_assert_eq!(&read, &data);
+                }
+            }
+        }
+
+        #[test]
+        fn test_instances(name in "[a-zA-Z_]+", n in 1..10usize) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let make = |instance| {
+                RotatingFile::new(
+                    name.clone(),
+                    directory.path().to_owned(),
+                    RotationPeriod::Manual,
+                    NonZeroUsize::new(n).unwrap(),
+                    crate::Compression::None
+                ).with_instance(instance).unwrap()
+            };
+            let mut first = make(Instance::Pid);
+            let mut second = make(Instance::Id("second".into()));
+
+            for _ in 0..2 * n {
+                first.rotate().unwrap();
+                second.rotate().unwrap();
+                first.write_all(b"first").unwrap();
+                second.write_all(b"second").unwrap();
+            }
+            assert_contains_files(&directory, 2 * n)?;
+
+            let pid_name = format!("{}-{}.0.log", name, std::process::id());
+            let second_name = format!("{}-second.0.log", name);
+            prop_assert_eq!(fs::read(directory.path().join(pid_name)).unwrap(), b"first");
+            prop_assert_eq!(fs::read(directory.path().join(second_name)).unwrap(), b"second");
+        }
+
+        #[test]
+        fn test_hostname(name in "[a-zA-Z_]+") {
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                format!("{}-{{hostname}}", name),
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(1).unwrap(),
+                crate::Compression::None
+            );
+            file.rotate().unwrap();
+
+            let expected = format!("{}-{}.0.log", name, crate::hostname::hostname());
+            prop_assert!(directory.path().join(expected).exists());
+        }
+
+        #[test]
+        fn test_flush_with_info(name in "[a-zA-Z_-]+", chunks: Vec<Vec<u8>>) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Bytes(64),
+                NonZeroUsize::new(3).unwrap(),
+                crate::Compression::None
+            );
+            prop_assert_eq!(file.flush_with_info().unwrap(), None);
+
+            for chunk in &chunks {
+                file.write_all(chunk).unwrap();
+                if let Some(FlushInfo { path, offset }) = file.flush_with_info().unwrap() {
+                    prop_assert_eq!(fs::metadata(path).unwrap().len(), offset);
+                }
+            }
+        }
+
+        #[test]
+        fn test_spool(name in "[a-zA-Z_-]+", before: Vec<u8>, during in proptest::collection::vec(any::<u8>(), 1..64)) {
+            use std::io::prelude::*;
+            use std::sync::{Arc, Mutex};
+
+            let parent = tempfile::tempdir().unwrap();
+            let directory = parent.path().join("logs");
+            fs::create_dir(&directory).unwrap();
+            let events = Arc::new(Mutex::new(Vec::new()));
+            let mut file = RotatingFile::new(
+                name,
+                directory.clone(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(3).unwrap(),
+                crate::Compression::None
+            ).with_spool(during.len()).with_spool_events({
+                let events = Arc::clone(&events);
+                move |event| events.lock().unwrap().push(event.clone())
+            });
+
+            file.write_all(&before).unwrap();
+            file.flush().unwrap();
+            fs::remove_dir_all(&directory).unwrap();
+            // Rotating gets postponed, and writing keeps working until the spool is full
+            file.rotate().unwrap();
+            file.write_all(&during).unwrap();
+            file.flush().unwrap();
+            prop_assert!(file.write_all(b"more").is_err());
+
+            fs::create_dir(&directory).unwrap();
+            file.flush().unwrap();
+            let current = file.files_matching("*").unwrap();
+            prop_assert_eq!(current.len(), 1);
+            prop_assert_eq!(fs::read(&current[0]).unwrap(), during.clone());
+
+            let events = events.lock().unwrap();
+            prop_assert_eq!(events.len(), 2);
+            prop_assert_eq!(&events[0], &SpoolEvent::Started { directory });
+            let replayed = matches!(events[1], SpoolEvent::Replayed { bytes, .. } if bytes == during.len());
+            prop_assert!(replayed);
+        }
+
+        #[test]
+        fn test_self_test(name in "[a-zA-Z_-]+", data: Vec<u8>) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(2).unwrap(),
+                crate::Compression::None
+            );
+            file.write_all(&data).unwrap();
+            file.self_test().unwrap();
+            assert_contains_files(&directory, 1)?;
+
+            let read = fs::read(file.current_path.as_ref().unwrap()).unwrap();
+            prop_assert!(read.starts_with(&data));
+            prop_assert!(read[data.len()..].starts_with(b"file-rotator self-test "));
+        }
+
+        #[test]
+        fn test_roundtrip_uncompressed(name in "[a-zA-Z_-]+", data: Vec<u8>) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(10).unwrap(),
+                crate::Compression::None
+            );
+            file.write_all(&data).unwrap();
+            file.rotate().unwrap();
+            file.write_all(&data).unwrap();
+            drop(file);
+
+            for entry in fs::read_dir(&directory).unwrap().map(Result::unwrap) {
+                let path = entry.path();
+                let read = fs::read(path).unwrap();
+                prop_assert_eq!(&read, &data);
+            }
+        }
+
+        #[cfg(feature = "zstd")]
+        #[test]
+        fn test_roundtrip_zstd(
+            name in "[a-zA-Z_-]+",
+            n in 1..25usize,
+            level in 0..21,
+            long_distance_matching: bool,
+            checksum: bool,
+            window_log in proptest::option::of(10..=27u32),
+            seekable_frame_size in proptest::option::of(1..4096u32),
+            data: Vec<u8>,
+        ) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n * 10).unwrap(),
+                crate::Compression::Zstd { level }
+            )
+            .with_zstd_options(
+                crate::ZstdOptions::default()
+                    .with_long_distance_matching(long_distance_matching)
+                    .with_checksum(checksum)
+                    .with_window_log(window_log)
+                    .with_seekable_frame_size(seekable_frame_size),
+            );
+            file.write_all(&data).unwrap();
+            // `write_all` doesn't write at all if `data` is empty, so make sure the file gets created
+            file.flush().unwrap();
+            for i in 0..n {
+                assert_contains_files(&directory, i + 1)?;
+                file.rotate().unwrap();
+                file.write_all(&data).unwrap();
+            }
+            assert_contains_files(&directory, n + 1)?;
+            drop(file);
+
+            for entry in fs::read_dir(&directory).unwrap().map(Result::unwrap) {
+                let path = entry.path();
+                let read = fs::read(&path).unwrap();
+                if path.file_stem().unwrap().to_string_lossy().ends_with(".0") {
+                    prop_assert_eq!(path.extension().unwrap().to_string_lossy(), "log");
+                    prop_assert_eq!(&read, &data);
+                } else {
+                    prop_assert_eq!(path.extension().unwrap().to_string_lossy(), "zstd");
+                    let read = zstd::decode_all(std::io::Cursor::new(read)).unwrap();
+                    prop_assert_eq!(&read, &data);
+                }
+            }
+        }
+
+        #[cfg(feature = "zstd")]
+        #[test]
+        fn test_live_compression(name in "[a-zA-Z_-]+", shifting: bool, chunks: Vec<Vec<u8>>) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(10).unwrap(),
+                crate::Compression::zstd(0)
+            )
+            .with_naming(if shifting { Naming::Index } else { Naming::Timestamp })
+            .with_live_compression(true);
+
+            // Everything that's been flushed can be read back, even though the file isn't done
+            let mut written = Vec::new();
+            file.flush().unwrap();
+            for chunk in &chunks {
+                file.write_all(chunk).unwrap();
+                file.flush().unwrap();
+                written.extend_from_slice(chunk);
+
+                let mut read = Vec::new();
+                file.read_matching("*").unwrap().remove(0).read_to_end(&mut read).unwrap();
+                prop_assert_eq!(&read, &written);
+            }
+
+            file.rotate().unwrap();
+            file.self_test().unwrap();
+            file.wait_for_maintenance().unwrap();
+            assert_contains_files(&directory, 2)?;
+
+            let files = file.files_matching("*").unwrap();
+            prop_assert!(files.iter().all(|path| path.extension().unwrap() == "zstd"));
+            let mut read = Vec::new();
+            file.read_matching("*").unwrap().remove(1).read_to_end(&mut read).unwrap();
+            prop_assert_eq!(&read, &written);
+        }
+
+        #[cfg(feature = "zstd")]
+        #[test]
+        fn test_flush_policy(name in "[a-zA-Z_-]+", by_lines: bool, lines in proptest::collection::vec("[a-z]{0,32}\n", 1..16)) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(10).unwrap(),
+                crate::Compression::zstd(0)
+            )
+            .with_live_compression(true)
+            .with_flush_policy(if by_lines { FlushPolicy::Lines(1) } else { FlushPolicy::Bytes(1) });
+
+            // Every line can be read back without flushing by hand
+            let mut written = Vec::new();
+            for line in &lines {
+                file.write_all(line.as_bytes()).unwrap();
+                written.extend_from_slice(line.as_bytes());
+
+                let mut read = Vec::new();
+                file.read_matching("*").unwrap().remove(0).read_to_end(&mut read).unwrap();
+                prop_assert_eq!(&read, &written);
+            }
+        }
+
+        #[cfg(feature = "lz4")]
+        #[test]
+        fn test_roundtrip_lz4(name in "[a-zA-Z_-]+", n in 1..10usize, data: Vec<u8>) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n + 1).unwrap(),
+                crate::Compression::Lz4
+            );
+            file.write_all(&data).unwrap();
+            file.flush().unwrap();
+            for _ in 0..n {
+                file.rotate().unwrap();
+                file.write_all(&data).unwrap();
+                file.flush().unwrap();
+            }
+            assert_contains_files(&directory, n + 1)?;
+
+            for (age, path) in file.files_matching("*").unwrap().iter().enumerate() {
+                let read = fs::read(path).unwrap();
+                if age == 0 {
+                    prop_assert_eq!(&read, &data);
+                } else {
+                    prop_assert_eq!(path.extension().unwrap().to_string_lossy(), "lz4");
+                    let mut decoded = Vec::new();
+                    lz4_flex::frame::FrameDecoder::new(&read[..]).read_to_end(&mut decoded).unwrap();
+                    prop_assert_eq!(&decoded, &data);
+                }
+            }
+        }
+
+        #[cfg(feature = "xz")]
+        #[test]
+        fn test_roundtrip_xz(name in "[a-zA-Z_-]+", preset in 0..=9u32, data: Vec<u8>) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(3).unwrap(),
+                crate::Compression::Xz { preset }
+            );
+            for _ in 0..3 {
+                file.rotate().unwrap();
+                file.write_all(&data).unwrap();
+                file.flush().unwrap();
+            }
+            file.wait_for_maintenance().unwrap();
+            assert_contains_files(&directory, 3)?;
+
+            for (age, path) in file.files_matching("*").unwrap().iter().enumerate() {
+                let read = fs::read(path).unwrap();
+                if age == 0 {
+                    prop_assert_eq!(&read, &data);
+                } else {
+                    prop_assert_eq!(path.extension().unwrap().to_string_lossy(), "xz");
+                    let mut decoded = Vec::new();
+                    xz2::read::XzDecoder::new(&read[..]).read_to_end(&mut decoded).unwrap();
+                    prop_assert_eq!(&decoded, &data);
+                }
+            }
+        }
+
+        #[test]
+        fn test_custom_compression(name in "[a-zA-Z_-]+", shifting: bool, data: Vec<u8>) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            // Start out with another compression, so that switching over gets tested too
+            #[cfg(feature = "zstd")]
+            let first = crate::Compression::zstd(0);
+            #[cfg(not(feature = "zstd"))]
+            let first = crate::Compression::None;
+            for compression in [first, crate::Compression::Custom(std::sync::Arc::new(Reverse))] {
+                let mut file = RotatingFile::new(
+                    name.clone(),
+                    directory.path().to_owned(),
+                    RotationPeriod::Manual,
+                    NonZeroUsize::new(10).unwrap(),
+                    compression
+                ).with_naming(if shifting { Naming::Index } else { Naming::Timestamp });
+                for _ in 0..3 {
+                    file.rotate().unwrap();
+                    file.write_all(&data).unwrap();
+                    file.flush().unwrap();
+                }
+                file.wait_for_maintenance().unwrap();
+            }
+            assert_contains_files(&directory, 6)?;
+
+            let file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(10).unwrap(),
+                crate::Compression::Custom(std::sync::Arc::new(Reverse))
+            ).with_naming(if shifting { Naming::Index } else { Naming::Timestamp });
+            let files = file.files_matching("*").unwrap();
+            for (age, (path, mut reader)) in files.iter().zip(file.read_matching("*").unwrap()).enumerate() {
+                let expected = if age == 0 { "log" } else { "rev" };
+                prop_assert_eq!(path.extension().unwrap().to_string_lossy(), expected);
+
+                let mut reversed = data.clone();
+                reversed.reverse();
+                if age != 0 {
+                    prop_assert_eq!(&fs::read(path).unwrap(), &reversed);
+                }
+                let mut read = Vec::new();
+                reader.read_to_end(&mut read).unwrap();
+                prop_assert_eq!(&read, &data);
+            }
+        }
+
+        #[cfg(feature = "zstd")]
+        #[test]
+        fn test_background_compression(name in "[a-zA-Z_-]+", shifting: bool, data: Vec<u8>) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(4).unwrap(),
+                crate::Compression::zstd(0)
+            )
+            .with_naming(if shifting { Naming::Index } else { Naming::Timestamp })
+            .with_background_compression(true);
+
+            for _ in 0..6 {
+                file.rotate().unwrap();
+                file.write_all(&data).unwrap();
+                file.flush().unwrap();
+            }
+            file.wait_for_maintenance().unwrap();
+            assert_contains_files(&directory, 4)?;
+
+            for (age, path) in file.files_matching("*").unwrap().iter().enumerate() {
+                let compressed = path.extension().unwrap() == "zstd";
+                prop_assert_eq!(compressed, age >= 1);
+            }
+            for mut reader in file.read_matching("*").unwrap() {
+                let mut read = Vec::new();
+                reader.read_to_end(&mut read).unwrap();
+                prop_assert_eq!(&read, &data);
+            }
+        }
+
+        #[cfg(feature = "zstd")]
+        #[test]
+        fn test_compress_archives(name in "[a-zA-Z_-]+", shifting: bool, data: Vec<u8>) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let naming = if shifting { Naming::Index } else { Naming::Timestamp };
+            let mut file = RotatingFile::new(
+                name.clone(),
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(10).unwrap(),
+                crate::Compression::None
+            ).with_naming(naming.clone());
+            for _ in 0..5 {
+                file.rotate().unwrap();
+                file.write_all(&data).unwrap();
+                file.flush().unwrap();
+            }
+            drop(file);
+
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(10).unwrap(),
+                crate::Compression::zstd(0)
+            ).with_naming(naming);
+            file.compress_archives().unwrap();
+            file.wait_for_maintenance().unwrap();
+            assert_contains_files(&directory, 5)?;
+
+            let files = file.files_matching("*").unwrap();
+            for (age, (path, mut reader)) in files.iter().zip(file.read_matching("*").unwrap()).enumerate() {
+                let compressed = path.extension().unwrap() == "zstd";
+                prop_assert_eq!(compressed, age >= 1);
+                let mut read = Vec::new();
+                reader.read_to_end(&mut read).unwrap();
+                prop_assert_eq!(&read, &data);
+            }
+        }
+
+        #[cfg(not(feature = "zstd"))]
+        #[test]
+        fn test_disabled_compression(name in "[a-zA-Z_-]+", data: Vec<u8>) {
+            let directory = tempfile::tempdir().unwrap();
+            for i in 1..3 {
+                fs::write(directory.path().join(format!("{}.{}.log.zstd", name, i)), &data).unwrap();
+            }
+
+            let mut file = RotatingFile::new(
+                name.clone(),
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(3).unwrap(),
+                crate::Compression::None
+            );
+            prop_assert_eq!(file.files_matching("*").unwrap().len(), 2);
+            let kind = file.read_matching("*").err().map(|e| e.kind());
+            prop_assert_eq!(kind, Some(std::io::ErrorKind::Unsupported));
+
+            // They're still subject to retention like any other file
+            for _ in 0..3 {
+                file.rotate().unwrap();
+            }
+            assert_contains_files(&directory, 3)?;
+            let zstd = file.files_matching("*.zstd").unwrap();
+            prop_assert!(zstd.is_empty());
+        }
+
+        #[cfg(feature = "checksum")]
+        #[test]
+        fn test_checksums(name in "[a-zA-Z_-]+", n in 1..10usize, shifting: bool, compressed: bool, data: Vec<u8>) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n).unwrap(),
+                if compressed {
+                    crate::Compression::Custom(std::sync::Arc::new(Reverse))
+                } else {
+                    crate::Compression::None
+                }
+            )
+            .with_naming(if shifting { Naming::Index } else { Naming::Timestamp })
+            .with_checksums(true);
+            for _ in 0..n + 2 {
+                file.rotate().unwrap();
+                file.write_all(&data).unwrap();
+            }
+
+            // Every archive has exactly one sidecar, which names it
+            let files = file.files_matching("*").unwrap();
+            prop_assert_eq!(files.len(), n);
+            assert_contains_files(&directory, 2 * n - 1)?;
+            let mut hashes = Vec::new();
+            for (age, path) in files.iter().enumerate() {
+                let mut sidecar = path.clone().into_os_string();
+                sidecar.push(".sha256");
+                let sidecar = fs::read_to_string(sidecar);
+                if age == 0 {
+                    prop_assert!(sidecar.is_err());
+                    continue;
+                }
+
+                let sidecar = sidecar.unwrap();
+                let expected = format!("  {}\n", path.file_name().unwrap().to_string_lossy());
+                prop_assert!(sidecar.ends_with(&expected));
+                let hash = sidecar.trim_end_matches(&expected).to_owned();
+                prop_assert_eq!(hash.len(), 64);
+                hashes.push(hash);
+            }
+            // All archives have the same contents
+            hashes.dedup();
+            prop_assert!(hashes.len() <= 1);
+        }
+
+        #[cfg(feature = "bundle")]
+        #[test]
+        fn test_export_bundle(name in "[a-zA-Z_-]+", data in proptest::collection::vec(any::<u8>(), 1..1024)) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name.clone(),
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(4).unwrap(),
+                crate::Compression::None
+            );
+            for _ in 0..3 {
+                file.write_all(&data).unwrap();
+                file.rotate().unwrap();
+            }
+            file.write_all(&data).unwrap();
+            let bundle = directory.path().join("export.tar.gz");
+            file.export_bundle(&bundle, None).unwrap();
+
+            let entries = crate::bundle::read_bundle(&bundle).unwrap();
+            prop_assert_eq!(entries.len(), 5);
+            prop_assert_eq!(&entries[0].0, "MANIFEST");
+            let manifest = String::from_utf8(entries[0].1.clone()).unwrap();
+            let listed = manifest
+                .lines()
+                .map(|line| line.splitn(3, ' ').nth(2).unwrap())
+                .collect::<Vec<_>>();
+            let names = entries[1..].iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+            prop_assert_eq!(&listed, &names);
+            prop_assert_eq!(names.last().copied(), Some(&*format!("{}.0.log", name)));
+            for (_, contents) in &entries[1..] {
+                prop_assert_eq!(contents, &data);
+            }
+
+            // Everything is older than no time at all
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            file.export_bundle(&bundle, Some(std::time::Duration::ZERO)).unwrap();
+            prop_assert_eq!(crate::bundle::read_bundle(&bundle).unwrap().len(), 1);
+        }
+
+        #[cfg(feature = "bundle")]
+        #[test]
+        fn test_bundles(name in "[a-zA-Z_-]+", shifting: bool, data: Vec<u8>) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            // Old bundles are the first to go once there's no room left
+            for day in ["2000-01-01", "2000-01-02"] {
+                fs::write(directory.path().join(format!("{}.{}.tar.gz", name, day)), "").unwrap();
+            }
+
+            let mut file = RotatingFile::new(
+                name.clone(),
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(5).unwrap(),
+                crate::Compression::None
+            )
+            .with_naming(if shifting { Naming::Index } else { Naming::Timestamp })
+            .with_bundles(3);
+            for _ in 0..8 {
+                file.rotate().unwrap();
+                file.write_all(&data).unwrap();
+            }
+
+            // The current file and the three most recently rotated out ones are left as they are
+            prop_assert_eq!(file.files_matching("*").unwrap().len(), 4);
+            assert_contains_files(&directory, 5)?;
+
+            let bundles = fs::read_dir(&directory)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .filter(|path| path.to_string_lossy().ends_with(".tar.gz"))
+                .collect::<Vec<_>>();
+            prop_assert_eq!(bundles.len(), 1);
+            let entries = crate::bundle::read_bundle(&bundles[0]).unwrap();
+            prop_assert_eq!(entries.len(), 4);
+            let names = entries.iter().map(|(name, _)| name).collect::<std::collections::HashSet<_>>();
+            prop_assert_eq!(names.len(), 4);
+            for (entry_name, contents) in &entries {
+                prop_assert!(entry_name.starts_with(&name));
+                prop_assert_eq!(contents, &data);
+            }
+        }
+
+        #[cfg(feature = "checksum")]
+        #[test]
+        fn test_compression_verification(name in "[a-zA-Z_-]+", lossy: bool, data in proptest::collection::vec(any::<u8>(), 1..1024)) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let compressor: std::sync::Arc<dyn Compressor> = if lossy {
+                std::sync::Arc::new(Lossy)
+            } else {
+                std::sync::Arc::new(Reverse)
+            };
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(10).unwrap(),
+                crate::Compression::Custom(compressor)
+            ).with_compression_verification(true);
+            file.write_all(&data).unwrap();
+            let rotated = file.rotate();
+
+            if lossy {
+                // The bad archive is thrown away and the original kept as is
+                prop_assert_eq!(rotated.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+                assert_contains_files(&directory, 1)?;
+                let path = fs::read_dir(&directory).unwrap().next().unwrap().unwrap().path();
+                prop_assert_eq!(path.extension().unwrap(), "log");
+                prop_assert_eq!(fs::read(&path).unwrap(), data);
+            } else {
+                rotated.unwrap();
+                assert_contains_files(&directory, 2)?;
+                let path = &file.files_matching("*").unwrap()[1];
+                prop_assert_eq!(path.extension().unwrap(), "rev");
+                let mut reader = file.read_matching("*").unwrap().remove(1);
+                let mut contents = Vec::new();
+                reader.read_to_end(&mut contents).unwrap();
+                prop_assert_eq!(contents, data);
+            }
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn test_external_compression(name in "[a-zA-Z_-]+", failing: bool, data in proptest::collection::vec(any::<u8>(), 1..1024)) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let script = if failing { "cat; exit 1" } else { "cat; echo done" };
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(10).unwrap(),
+                crate::Compression::External {
+                    command: "sh".into(),
+                    args: vec!["-c".into(), script.into()],
+                    suffix: "ext".into(),
+                }
+            );
+            file.write_all(&data).unwrap();
+            let rotated = file.rotate();
+
+            let path = if failing {
+                // The original is kept as is, without a partial archive next to it
+                prop_assert!(rotated.is_err());
+                assert_contains_files(&directory, 1)?;
+                fs::read_dir(&directory).unwrap().next().unwrap().unwrap().path()
+            } else {
+                rotated.unwrap();
+                assert_contains_files(&directory, 2)?;
+                file.files_matching("*").unwrap().remove(1)
+            };
+            let mut expected = data;
+            if failing {
+                prop_assert_eq!(path.extension().unwrap(), "log");
+            } else {
+                prop_assert_eq!(path.extension().unwrap(), "ext");
+                expected.extend_from_slice(b"done\n");
+            }
+            prop_assert_eq!(fs::read(&path).unwrap(), expected);
+        }
+
+        #[cfg(feature = "encryption")]
+        #[test]
+        fn test_encryption(name in "[a-zA-Z_-]+", compressed: bool, n in 1..5usize, data in proptest::collection::vec(any::<u8>(), 16..1024)) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            #[cfg(feature = "zstd")]
+            let compression = if compressed { crate::Compression::zstd(0) } else { crate::Compression::None };
+            #[cfg(not(feature = "zstd"))]
+            let compression = crate::Compression::None;
+            let suffix = if compression.suffix().is_some() { "zstd.enc" } else { "enc" };
+            let open = |key| {
+                RotatingFile::new(
+                    name.clone(),
+                    directory.path().to_owned(),
+                    RotationPeriod::Manual,
+                    NonZeroUsize::new(10).unwrap(),
+                    compression.clone()
+                ).with_encryption(crate::Encryption::Aes256Gcm { key: [key; 32] })
+            };
+
+            let mut file = open(1);
+            for _ in 0..n {
+                file.rotate().unwrap();
+                file.write_all(&data).unwrap();
+            }
+            file.rotate().unwrap();
+            assert_contains_files(&directory, n + 1)?;
+
+            let paths = file.files_matching("*").unwrap();
+            for (age, (path, mut reader)) in paths.iter().zip(file.read_matching("*").unwrap()).enumerate() {
+                let mut contents = Vec::new();
+                reader.read_to_end(&mut contents).unwrap();
+                if age == 0 {
+                    prop_assert!(contents.is_empty());
+                    continue;
+                }
+                let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+                prop_assert!(file_name.ends_with(suffix));
+                prop_assert_eq!(&contents, &data);
+                let raw = fs::read(path).unwrap();
+                prop_assert!(!raw.windows(data.len()).any(|window| window == &data[..]));
+            }
+
+            // Archives can't be read without the right key
+            let other = open(2);
+            prop_assert_eq!(other.files_matching("*").unwrap(), paths);
+            let mut contents = Vec::new();
+            let read = other.read_matching("*").unwrap().remove(1).read_to_end(&mut contents);
+            prop_assert_eq!(read.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        }
+
+        #[cfg(feature = "checksum")]
+        #[test]
+        fn test_hash_chain(name in "[a-zA-Z_-]+", n in 1..10usize, shifting: bool, compressed: bool) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let open = |key: &str| RotatingFile::new(
+                name.clone(),
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(5).unwrap(),
+                if compressed {
+                    crate::Compression::Custom(std::sync::Arc::new(Reverse))
+                } else {
+                    crate::Compression::None
+                }
+            )
+            .with_naming(if shifting { Naming::Index } else { Naming::Timestamp })
+            .with_hash_chain(key);
+            let mut file = open("secret");
+            for i in 0..=n {
+                file.rotate().unwrap();
+                write!(file, "file {}", i).unwrap();
+                file.verify_hash_chain().unwrap();
+            }
+
+            // Only the key the chain was written with checks out
+            let kind = open("guess").verify_hash_chain().unwrap_err().kind();
+            prop_assert_eq!(kind, std::io::ErrorKind::InvalidData);
+
+            // Archives can be neither changed nor deleted without it being noticed
+            let archive = file.files_matching("*").unwrap().remove(1);
+            let contents = fs::read(&archive).unwrap();
+            fs::write(&archive, b"something else").unwrap();
+            let kind = file.verify_hash_chain().unwrap_err().kind();
+            prop_assert_eq!(kind, std::io::ErrorKind::InvalidData);
+            fs::write(&archive, contents).unwrap();
+            file.verify_hash_chain().unwrap();
+            fs::remove_file(&archive).unwrap();
+            let kind = file.verify_hash_chain().unwrap_err().kind();
+            prop_assert_eq!(kind, std::io::ErrorKind::InvalidData);
+        }
+
+        #[cfg(feature = "manifest")]
+        #[test]
+        fn test_manifest_archives(name in "[a-zA-Z_-]+", n in 1..10usize, shifting: bool, compressed: bool) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(5).unwrap(),
+                if compressed {
+                    crate::Compression::Custom(std::sync::Arc::new(Reverse))
+                } else {
+                    crate::Compression::None
+                }
+            )
+            .with_naming(if shifting { Naming::Index } else { Naming::Timestamp })
+            .with_manifest(true);
+            for i in 0..=n {
+                file.rotate().unwrap();
+                write!(file, "file {}", i).unwrap();
+            }
+            file.verify_manifest().unwrap();
+
+            // Every archive follows its file around as it's renamed and compressed
+            let manifest = crate::manifest::Manifest::read(&file.manifest_path()).unwrap();
+            let files = file.files_matching("*").unwrap();
+            prop_assert_eq!(
+                manifest
+                    .entries
+                    .iter()
+                    .rev()
+                    .map(|entry| directory.path().join(&entry.file))
+                    .collect::<Vec<_>>(),
+                &files[1..]
+            );
+            for (entry, path) in manifest.entries.iter().rev().zip(&files[1..]) {
+                prop_assert_eq!(entry.original_size, 6);
+                let size = fs::metadata(path).unwrap().len();
+                prop_assert_eq!(entry.compressed_size, if compressed { Some(size) } else { None });
+            }
+        }
+
+        #[test]
+        fn test_secure_deletion(name in "[a-zA-Z_-]+", n in 1..5usize, data in proptest::collection::vec(1..=255u8, 1..4096)) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n).unwrap(),
+                crate::Compression::None
+            )
+            .with_naming(Naming::Timestamp)
+            .with_secure_deletion(true);
+            file.rotate().unwrap();
+            file.write_all(&data).unwrap();
+            file.flush().unwrap();
+
+            // A hard link shares its contents with the file, which outlives it being deleted
+            let current = file.files_matching("*").unwrap().remove(0);
+            let link = directory.path().join("link");
+            fs::hard_link(&current, &link).unwrap();
+            for _ in 0..n {
+                file.rotate().unwrap();
+            }
+            prop_assert!(!current.exists());
+            prop_assert_eq!(fs::read(&link).unwrap(), vec![0; data.len()]);
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn test_hardening(name in "[a-zA-Z_-]+", hardened: bool, data in proptest::collection::vec(any::<u8>(), 1..1024)) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let outside = directory.path().join("outside");
+            fs::write(&outside, b"not a log").unwrap();
+            let logs = directory.path().join("logs");
+            fs::create_dir(&logs).unwrap();
+
+            // A symlink where a temporary file goes must be replaced, not written through
+            let mut file = RotatingFile::new(
+                name.clone(),
+                &logs,
+                RotationPeriod::Manual,
+                NonZeroUsize::new(5).unwrap(),
+                crate::Compression::Custom(std::sync::Arc::new(Reverse)),
+            )
+            .with_hardening(hardened);
+            file.write_all(&data).unwrap();
+            let tmp = logs.join(format!("{}.1.log.rev.tmp", name));
+            std::os::unix::fs::symlink(&outside, &tmp).unwrap();
+            file.rotate().unwrap();
+            prop_assert_eq!(fs::read(&outside).unwrap(), b"not a log");
+            prop_assert!(!tmp.exists());
+
+            // Planted log files are only left alone with hardening
+            let planted = logs.join(format!("{}.1.log", name));
+            std::os::unix::fs::symlink(&outside, &planted).unwrap();
+            file.rotate().unwrap();
+            prop_assert_eq!(fs::read(&outside).unwrap(), b"not a log");
+            prop_assert_eq!(fs::symlink_metadata(&planted).is_ok(), hardened);
+            // The other file below goes through the same ones, which might still be compressing
+            file.wait_for_maintenance().unwrap();
+
+            let link = directory.path().join("link");
+            std::os::unix::fs::symlink(&logs, &link).unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                &link,
+                RotationPeriod::Manual,
+                NonZeroUsize::new(5).unwrap(),
+                crate::Compression::None,
+            )
+            .with_hardening(hardened);
+            match file.rotate() {
+                Err(e) => prop_assert!(hardened && e.kind() == std::io::ErrorKind::PermissionDenied),
+                Ok(()) => prop_assert!(!hardened),
+            }
+        }
+
+        #[test]
+        fn test_durability(name in "[a-zA-Z_-]+", n in 1..5usize, on_flush: bool, background: bool, data in proptest::collection::vec(any::<u8>(), 1..1024)) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n + 1).unwrap(),
+                crate::Compression::Custom(std::sync::Arc::new(Reverse))
+            )
+            .with_background_compression(background)
+            .with_durability(if on_flush { Durability::Flush } else { Durability::Rotation });
+
+            for _ in 0..n {
+                file.write_all(&data).unwrap();
+                file.flush().unwrap();
+                file.rotate().unwrap();
+            }
+            file.wait_for_maintenance().unwrap();
+            assert_contains_files(&directory, n + 1)?;
+            let archives = file.read_matching("*.rev").unwrap();
+            prop_assert_eq!(archives.len(), n);
+            for mut reader in archives {
+                let mut read = Vec::new();
+                reader.read_to_end(&mut read).unwrap();
+                prop_assert_eq!(&read, &data);
+            }
+        }
+
+        #[test]
+        fn test_resume(name in "[a-zA-Z_-]+", resume: bool, before in "[a-z]{1,9}\n", after in "[a-z]{1,9}\n") {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let open = || RotatingFile::new(
+                name.clone(),
+                directory.path().to_owned(),
+                // Just enough for what's there before not to trigger rotation on its own
+                RotationPeriod::Bytes(before.len() + 1),
+                NonZeroUsize::new(5).unwrap(),
+                crate::Compression::None
+            )
+            .with_resume(resume);
+
+            open().write_all(before.as_bytes()).unwrap();
+            let mut file = open();
+            file.write_all(after.as_bytes()).unwrap();
+            if resume {
+                assert_contains_files(&directory, 1)?;
+                let newest = file.files_matching("*").unwrap().remove(0);
+                prop_assert_eq!(fs::read_to_string(newest).unwrap(), before + &after);
+
+                // What was there before counts towards the rotation period
+                file.write_all(b"x").unwrap();
+                assert_contains_files(&directory, 2)?;
+            } else {
+                assert_contains_files(&directory, 2)?;
+            }
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn test_reopen(name in "[a-zA-Z_-]+", limit in 0..3usize, breaks in 1..3usize, data in proptest::collection::vec(any::<u8>(), 1..1024)) {
+            use std::io::prelude::*;
+            use std::os::unix::io::AsRawFd;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(5).unwrap(),
+                crate::Compression::None
+            )
+            .with_reopen(limit);
+            file.write_all(&data).unwrap();
+
+            // Point the current file's descriptor at something read-only, which writes then fail
+            // on with EBADF, just like when it's been closed from under us
+            let read_only = fs::File::open("/dev/null").unwrap();
+            for _ in 0..breaks {
+                let fd = file.current_file.as_mut().unwrap().file().unwrap().as_raw_fd();
+                let duped = unsafe { libc::dup2(read_only.as_raw_fd(), fd) };
+                prop_assert_eq!(duped, fd);
+                let written = file.write_all(&data);
+                prop_assert_eq!(written.is_ok(), limit > 0);
+                if written.is_err() {
+                    return Ok(());
+                }
+            }
+
+            assert_contains_files(&directory, 1)?;
+            let contents = fs::read(file.files_matching("*").unwrap().remove(0)).unwrap();
+            prop_assert_eq!(contents, data.repeat(breaks + 1));
+        }
+
+        #[test]
+        fn test_header_footer(name in "[a-zA-Z_-]+", n in 1..5usize, data in proptest::collection::vec(any::<u8>(), 1..1024)) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let header = |path: &Path| format!("start of {}\n", path.file_name().unwrap().to_string_lossy()).into_bytes();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n + 1).unwrap(),
+                crate::Compression::None
+            )
+            .with_naming(Naming::Sequence)
+            .with_header(header)
+            .with_footer(|_| b"end\n".to_vec());
+
+            for _ in 0..n {
+                file.write_all(&data).unwrap();
+                file.rotate().unwrap();
+            }
+
+            let files = file.files_matching("*").unwrap();
+            prop_assert_eq!(files.len(), n + 1);
+            for (i, path) in files.iter().enumerate() {
+                let mut expected = header(path);
+                if i > 0 {
+                    expected.extend_from_slice(&data);
+                    expected.extend_from_slice(b"end\n");
+                }
+                prop_assert_eq!(fs::read(path).unwrap(), expected);
+            }
+        }
+
+        #[test]
+        fn test_rotation_events(name in "[a-zA-Z_-]+", n in 2..5usize, rotations in 1..10usize, data in proptest::collection::vec(any::<u8>(), 1..1024)) {
+            use std::io::prelude::*;
+            use std::sync::{Arc, Mutex};
+
+            let directory = tempfile::tempdir().unwrap();
+            let rotated = Arc::new(Mutex::new(Vec::new()));
+            let evicted = Arc::new(Mutex::new(Vec::new()));
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n).unwrap(),
+                crate::Compression::Custom(Arc::new(Reverse))
+            )
+            .with_naming(Naming::Sequence)
+            .with_on_rotate({
+                let rotated = Arc::clone(&rotated);
+                move |event| rotated.lock().unwrap().push(event.clone())
+            })
+            .with_on_evict({
+                let evicted = Arc::clone(&evicted);
+                move |path| evicted.lock().unwrap().push(path.to_owned())
+            });
+
+            for _ in 0..rotations {
+                file.rotate().unwrap();
+                file.write_all(&data).unwrap();
+            }
+
+            let rotated = rotated.lock().unwrap();
+            prop_assert_eq!(rotated.len(), rotations);
+            prop_assert!(rotated[0].rotated_out.is_none());
+            for (i, event) in rotated.iter().enumerate().skip(1) {
+                let rotated_out = event.rotated_out.as_ref().unwrap();
+                prop_assert_eq!(rotated_out.extension().unwrap(), "rev");
+                prop_assert_eq!(rotated_out.with_extension(""), rotated[i - 1].current.clone());
+            }
+            let evicted = evicted.lock().unwrap();
+            prop_assert_eq!(evicted.len(), rotations.saturating_sub(n));
+            prop_assert!(evicted.iter().all(|path| !path.exists()));
+        }
+
+        #[test]
+        fn test_subscribe(name in "[a-zA-Z_-]+", rotations in 1..10usize) {
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(5).unwrap(),
+                crate::Compression::None
+            );
+            let kept = file.subscribe();
+            drop(file.subscribe());
+
+            let handle = std::thread::spawn(move || kept.iter().collect::<Vec<_>>());
+            for _ in 0..rotations {
+                file.rotate().unwrap();
+            }
+            prop_assert_eq!(file.subscribers.len(), 1);
+            let current = file.files_matching("*").unwrap().remove(0);
+            drop(file);
+
+            let events = handle.join().unwrap();
+            prop_assert_eq!(events.len(), rotations);
+            prop_assert_eq!(&events[rotations - 1].current, &current);
+        }
+
+        #[test]
+        fn test_stats(name in "[a-zA-Z_-]+", n in 1..5usize, rotations in 1..10usize, lines in proptest::collection::vec("[a-z]{0,32}\n", 0..8)) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let logs = directory.path().join("logs");
+            fs::create_dir(&logs).unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                &logs,
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n).unwrap(),
+                crate::Compression::Custom(std::sync::Arc::new(Reverse))
+            );
+            let data = lines.concat();
+            for _ in 0..rotations {
+                file.rotate().unwrap();
+                file.write_all(data.as_bytes()).unwrap();
+            }
+
+            let stats = file.stats();
+            prop_assert_eq!(stats.bytes_written, (rotations * data.len()) as u64);
+            prop_assert_eq!(stats.lines_written, (rotations * lines.len()) as u64);
+            prop_assert_eq!(stats.rotations, rotations as u64);
+            prop_assert_eq!(stats.files_deleted, rotations.saturating_sub(n) as u64);
+            prop_assert!(stats.last_error.is_none());
+
+            fs::remove_dir_all(&logs).unwrap();
+            prop_assert!(file.rotate().is_err());
+            prop_assert!(file.stats().last_error.is_some());
+        }
+
+        #[test]
+        fn test_non_blocking(name in "[a-zA-Z_-]+", threads in 1..4usize, lines in proptest::collection::vec("[a-z]{0,32}\n", 0..16)) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(1).unwrap(),
+                crate::Compression::None,
+            );
+            let (writer, guard) = file.non_blocking().unwrap();
+            let handles = (0..threads)
+                .map(|_| {
+                    let mut writer = writer.clone();
+                    let lines = lines.clone();
+                    std::thread::spawn(move || {
+                        for line in lines {
+                            writer.write_all(line.as_bytes()).unwrap();
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+            drop(guard);
+
+            let mut contents = String::new();
+            for entry in fs::read_dir(directory.path()).unwrap() {
+                contents.push_str(&fs::read_to_string(entry.unwrap().path()).unwrap());
+            }
+            let mut expected = (0..threads).flat_map(|_| lines.iter().map(String::as_str)).collect::<Vec<_>>();
+            expected.sort();
+            let mut written = contents.split_inclusive('\n').collect::<Vec<_>>();
+            written.sort();
+            prop_assert_eq!(written, expected);
+            prop_assert_eq!(writer.stats().lines_written, (threads * lines.len()) as u64);
+
+            // Once the guard is gone, there's nothing left to write to
+            let mut writer = writer;
+            prop_assert!(writer.write_all(b"late\n").is_err());
+        }
+
+        #[test]
+        fn test_non_blocking_dropped(name in "[a-zA-Z_-]+", extra in 1..8usize) {
+            use std::io::prelude::*;
+
+            // Hold up the background thread in the header until everything's been written
+            let directory = tempfile::tempdir().unwrap();
+            let (entered, has_entered) = std::sync::mpsc::channel();
+            let (release, released) = std::sync::mpsc::channel::<()>();
+            let file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(1).unwrap(),
+                crate::Compression::None,
+            )
+            .with_header(move |_| {
+                entered.send(()).unwrap();
+                let _ = released.recv();
+                Vec::new()
+            });
+            let options = crate::NonBlockingOptions::default()
+                .with_capacity(1)
+                .with_dropped_report(true);
+            let (mut writer, guard) = file.non_blocking_with(options).unwrap();
+
+            writer.write_all(b"first\n").unwrap();
+            has_entered.recv().unwrap();
+            writer.write_all(b"second\n").unwrap();
+            for _ in 0..extra {
+                writer.write_all(b"lost\n").unwrap();
+            }
+            prop_assert_eq!(writer.dropped(), extra as u64);
+            drop(release);
+            drop(guard);
+
+            let path = fs::read_dir(directory.path()).unwrap().next().unwrap().unwrap().path();
+            let expected = format!("first\nsecond\n{} messages dropped\n", extra);
+            prop_assert_eq!(fs::read_to_string(path).unwrap(), expected);
+        }
+
+        #[test]
+        fn test_backpressure(name in "[a-zA-Z_-]+", capacity in 1..4usize, extra in 1..8usize) {
+            use std::io::prelude::*;
+            use crate::Backpressure;
+
+            // Hold up the background thread in the header, after it's taken the first write
+            let blocked = |backpressure| {
+                let directory = tempfile::tempdir().unwrap();
+                let (entered, has_entered) = std::sync::mpsc::channel();
+                let (release, released) = std::sync::mpsc::channel::<()>();
+                let file = RotatingFile::new(
+                    name.clone(),
+                    directory.path().to_owned(),
+                    RotationPeriod::Manual,
+                    NonZeroUsize::new(1).unwrap(),
+                    crate::Compression::None,
+                )
+                .with_header(move |_| {
+                    entered.send(()).unwrap();
+                    let _ = released.recv();
+                    Vec::new()
+                });
+                let options = crate::NonBlockingOptions::default()
+                    .with_capacity(capacity)
+                    .with_byte_capacity(capacity * 2)
+                    .with_backpressure(backpressure);
+                let (mut writer, guard) = file.non_blocking_with(options).unwrap();
+                writer.write_all(b"-\n").unwrap();
+                has_entered.recv().unwrap();
+                (directory, writer, guard, release)
+            };
+            let contents = |directory: &tempfile::TempDir| {
+                let path = fs::read_dir(directory.path()).unwrap().next().unwrap().unwrap().path();
+                fs::read_to_string(path).unwrap()
+            };
+            let lines = (0..capacity + extra).map(|i| format!("{}\n", i % 10)).collect::<Vec<_>>();
+
+            let (directory, mut writer, guard, release) = blocked(Backpressure::DropNewest);
+            for line in &lines {
+                writer.write_all(line.as_bytes()).unwrap();
+            }
+            prop_assert_eq!(writer.dropped(), extra as u64);
+            drop((release, guard));
+            prop_assert_eq!(contents(&directory), format!("-\n{}", lines[..capacity].concat()));
+
+            let (directory, mut writer, guard, release) = blocked(Backpressure::DropOldest);
+            for line in &lines {
+                writer.write_all(line.as_bytes()).unwrap();
+            }
+            prop_assert_eq!(writer.dropped(), extra as u64);
+            drop((release, guard));
+            prop_assert_eq!(contents(&directory), format!("-\n{}", lines[extra..].concat()));
+
+            let (directory, mut writer, guard, release) = blocked(Backpressure::Error);
+            for line in &lines[..capacity] {
+                writer.write_all(line.as_bytes()).unwrap();
+            }
+            let e = writer.write_all(b"x\n").unwrap_err();
+            prop_assert_eq!(e.kind(), std::io::ErrorKind::WouldBlock);
+            drop((release, guard));
+            prop_assert_eq!(contents(&directory), format!("-\n{}", lines[..capacity].concat()));
+
+            let (directory, writer, guard, release) = blocked(Backpressure::Block);
+            let writing = {
+                let lines = lines.clone();
+                let mut writer = writer.clone();
+                std::thread::spawn(move || {
+                    for line in lines {
+                        writer.write_all(line.as_bytes()).unwrap();
+                    }
+                })
+            };
+            drop(release);
+            writing.join().unwrap();
+            drop(guard);
+            prop_assert_eq!(writer.dropped(), 0);
+            prop_assert_eq!(contents(&directory), format!("-\n{}", lines.concat()));
+        }
+
+        #[test]
+        fn test_rate_limit(name in "[a-zA-Z_-]+", limit in 0..8usize, lines in proptest::collection::vec("[a-z]{0,32}\n", 0..16)) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(1).unwrap(),
+                crate::Compression::None,
+            )
+            .with_rate_limit(crate::RateLimit::Lines { limit, per: std::time::Duration::from_secs(3600) });
+            for (i, line) in lines.iter().enumerate() {
+                if i % 2 == 0 {
+                    file.write_all(line.as_bytes()).unwrap();
+                } else {
+                    file.write_record(line.as_bytes()).unwrap();
+                }
+            }
+            file.flush().unwrap();
+
+            let kept = limit.min(lines.len());
+            prop_assert_eq!(file.rate_limited(), (lines.len() - kept) as u64);
+            let written = fs::read_dir(directory.path())
+                .unwrap()
+                .map(|entry| fs::read_to_string(entry.unwrap().path()).unwrap())
+                .collect::<String>();
+            prop_assert_eq!(written, lines[..kept].concat());
+        }
+
+        #[test]
+        fn test_repeat_suppression(name in "[a-zA-Z_-]+", lines in proptest::collection::vec("[ab]\n", 0..32)) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(1).unwrap(),
+                crate::Compression::None,
+            )
+            .with_repeat_suppression(true);
+            let data = lines.concat();
+            for chunk in data.as_bytes().chunks(3) {
+                file.write_all(chunk).unwrap();
+            }
+            file.flush().unwrap();
+
+            let mut expected = String::new();
+            let mut i = 0;
+            while i < lines.len() {
+                let run = lines[i..].iter().take_while(|&line| line == &lines[i]).count();
+                expected.push_str(&lines[i]);
+                if run > 1 {
+                    expected.push_str(&format!("last message repeated {} times\n", run - 1));
+                }
+                i += run;
+            }
+            let written = fs::read_dir(directory.path())
+                .unwrap()
+                .map(|entry| fs::read_to_string(entry.unwrap().path()).unwrap())
+                .collect::<String>();
+            prop_assert_eq!(written, expected);
+        }
+
+        #[test]
+        fn test_tee(name in "[a-zA-Z_-]+", n in 1..4usize, data in proptest::collection::vec(any::<u8>(), 0..1024)) {
+            use std::io::prelude::*;
+            use std::sync::{Arc, Mutex};
+
+            struct Shared(Arc<Mutex<Vec<u8>>>);
+
+            impl Write for Shared {
+                fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                    self.0.lock().unwrap().write(buf)
+                }
+
+                fn flush(&mut self) -> std::io::Result<()> {
+                    Ok(())
+                }
+            }
+
+            let directory = tempfile::tempdir().unwrap();
+            let teed = Arc::new(Mutex::new(Vec::new()));
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(n + 1).unwrap(),
+                crate::Compression::None,
+            )
+            .with_header(|_| b"header\n".to_vec())
+            .with_tee(Shared(Arc::clone(&teed)));
+            for _ in 0..n {
+                file.write_all(&data).unwrap();
+                file.write_record(&data).unwrap();
+                file.rotate().unwrap();
+            }
+
+            let teed = teed.lock().unwrap();
+            prop_assert_eq!(teed.len(), 2 * n * data.len());
+            prop_assert!(teed.chunks(data.len().max(1)).all(|chunk| chunk == &data[..]));
+        }
+
+        #[test]
+        fn test_tee_rate_limit(
+            name in "[a-zA-Z_-]+",
+            limit in 0..64usize,
+            lines in proptest::collection::vec("[a-z]{0,16}\n", 0..16),
+            timestamps: bool,
+        ) {
+            use std::io::prelude::*;
+            use std::sync::{Arc, Mutex};
+
+            struct Shared(Arc<Mutex<Vec<u8>>>);
+
+            impl Write for Shared {
+                fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                    self.0.lock().unwrap().write(buf)
+                }
+
+                fn flush(&mut self) -> std::io::Result<()> {
+                    Ok(())
+                }
+            }
+
+            let directory = tempfile::tempdir().unwrap();
+            let teed = Arc::new(Mutex::new(Vec::new()));
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(1).unwrap(),
+                crate::Compression::None,
+            )
+            .with_rate_limit(crate::RateLimit::Bytes { limit, per: std::time::Duration::from_secs(3600) })
+            .with_tee(Shared(Arc::clone(&teed)));
+            // Timestamps go through the line filters, which write differently
+            if timestamps {
+                file = file.with_timestamps(crate::TimestampFormat::Rfc3339);
+            }
+            for (i, line) in lines.iter().enumerate() {
+                if i % 2 == 0 {
+                    file.write_all(line.as_bytes()).unwrap();
+                } else {
+                    file.write_record(line.as_bytes()).unwrap();
+                }
+            }
+            file.flush().unwrap();
+
+            let written = fs::read_dir(directory.path())
+                .unwrap()
+                .flat_map(|entry| fs::read(entry.unwrap().path()).unwrap())
+                .collect::<Vec<u8>>();
+            prop_assert!(written.len() <= limit);
+            prop_assert_eq!(&*teed.lock().unwrap(), &written);
+        }
+
+        #[test]
+        fn test_transform(name in "[a-zA-Z_-]+", period in 1..64usize, lines in proptest::collection::vec("[a-z]{0,8}( secret)?\n", 0..16)) {
+            use std::borrow::Cow;
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Bytes(period),
+                NonZeroUsize::new(64).unwrap(),
+                crate::Compression::None,
+            )
+            .with_naming(Naming::Sequence)
+            .with_transform(|line| match line.strip_suffix(b" secret") {
+                Some(rest) => Cow::Owned([rest, b" [redacted]"].concat()),
+                None => Cow::Borrowed(line),
+            });
+            let data = lines.concat();
+            for chunk in data.as_bytes().chunks(5) {
+                file.write_all(chunk).unwrap();
             }
+            file.flush().unwrap();
+
+            let written = file.files_matching("*")
+                .unwrap()
+                .iter()
+                .rev()
+                .map(|path| fs::read_to_string(path).unwrap())
+                .collect::<String>();
+            prop_assert_eq!(written, data.replace(" secret", " [redacted]"));
+            prop_assert_eq!(file.stats().bytes_written, data.replace(" secret", " [redacted]").len() as u64);
         }
 
         #[test]
-        fn test_roundtrip_zstd(name in "[a-zA-Z_-]+", n in 1..25usize, level in 0..21, data: Vec<u8>) {
+        fn test_timestamps(name in "[a-zA-Z_-]+", lines in proptest::collection::vec("[a-z]{0,8}\n", 0..16)) {
             use std::io::prelude::*;
 
             let directory = tempfile::tempdir().unwrap();
@@ -349,30 +7102,623 @@ mod tests {
                 name,
                 directory.path().to_owned(),
                 RotationPeriod::Manual,
-                NonZeroUsize::new(n * 10).unwrap(),
-                crate::Compression::Zstd { level }
-            );
-            file.write_all(&data).unwrap();
-            for i in 0..n {
-                assert_contains_files(&directory, i + 1)?;
-                file.rotate().unwrap();
-                file.write_all(&data).unwrap();
+                NonZeroUsize::new(1).unwrap(),
+                crate::Compression::None,
+            )
+            .with_timestamps(crate::TimestampFormat::UnixSeconds);
+            let before = crate::timestamp::unix_secs(std::time::SystemTime::now());
+            let data = lines.concat();
+            for chunk in data.as_bytes().chunks(3) {
+                file.write_all(chunk).unwrap();
             }
-            assert_contains_files(&directory, n + 1)?;
-            drop(file);
+            file.flush().unwrap();
+            let after = crate::timestamp::unix_secs(std::time::SystemTime::now());
 
-            for entry in fs::read_dir(&directory).unwrap().map(Result::unwrap) {
-                let path = entry.path();
-                let read = fs::read(&path).unwrap();
-                if path.file_stem().unwrap().to_string_lossy().ends_with(".0") {
-                    prop_assert_eq!(path.extension().unwrap().to_string_lossy(), "log");
-                    prop_assert_eq!(&read, &data);
+            let written = fs::read_dir(directory.path())
+                .unwrap()
+                .map(|entry| fs::read_to_string(entry.unwrap().path()).unwrap())
+                .collect::<String>();
+            let written = written.lines().collect::<Vec<_>>();
+            prop_assert_eq!(written.len(), lines.len());
+            for (written, line) in written.iter().zip(&lines) {
+                let (timestamp, rest) = written.split_at(written.find(' ').unwrap());
+                let timestamp = timestamp.parse::<u64>().unwrap();
+                prop_assert!(before <= timestamp && timestamp <= after);
+                prop_assert_eq!(format!("{}\n", &rest[1..]), line.clone());
+            }
+        }
+
+        #[test]
+        fn test_write_line(name in "[a-zA-Z_-]+", period in 1..8usize, lines in proptest::collection::vec("[a-z]{0,8}\n?", 0..32)) {
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Lines(period),
+                NonZeroUsize::new(lines.len() + 1).unwrap(),
+                crate::Compression::None,
+            )
+            .with_naming(Naming::Sequence);
+            for line in &lines {
+                file.write_line(line).unwrap();
+            }
+            prop_assert_eq!(file.write_line("a\nb").unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+
+            let files = file.files_matching("*").unwrap();
+            let mut written = Vec::new();
+            for path in files.iter().rev() {
+                let contents = fs::read_to_string(path).unwrap();
+                prop_assert!(contents.lines().count() <= period);
+                written.extend(contents.lines().map(String::from));
+            }
+            let expected = lines.iter().map(|line| line.trim_end_matches('\n').to_owned()).collect::<Vec<_>>();
+            prop_assert_eq!(written, expected);
+        }
+
+        #[test]
+        fn test_max_line_length(name in "[a-zA-Z_-]+", max in 1..16usize, split: bool, lines in proptest::collection::vec("[a-z]{0,48}\n", 0..16)) {
+            use std::io::prelude::*;
+            use crate::LongLines;
+
+            let directory = tempfile::tempdir().unwrap();
+            let long_lines = if split { LongLines::Split } else { LongLines::Truncate };
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(1).unwrap(),
+                crate::Compression::None,
+            )
+            .with_max_line_length(NonZeroUsize::new(max).unwrap(), long_lines);
+            for (i, line) in lines.iter().enumerate() {
+                if i % 2 == 0 {
+                    for chunk in line.as_bytes().chunks(5) {
+                        file.write_all(chunk).unwrap();
+                    }
                 } else {
-                    prop_assert_eq!(path.extension().unwrap().to_string_lossy(), "zstd");
-                    let read = zstd::decode_all(std::io::Cursor::new(read)).unwrap();
-                    prop_assert_eq!(&read, &data);
+                    file.write_record(line.as_bytes()).unwrap();
+                }
+            }
+
+            let mut expected = String::new();
+            for line in &lines {
+                let line = line.trim_end_matches('\n');
+                if split {
+                    for chunk in line.as_bytes().chunks(max) {
+                        expected.push_str(std::str::from_utf8(chunk).unwrap());
+                        expected.push('\n');
+                    }
+                    if line.is_empty() {
+                        expected.push('\n');
+                    }
+                } else if line.len() > max {
+                    expected.push_str(&line[..max]);
+                    expected.push_str("...\n");
+                } else {
+                    expected.push_str(line);
+                    expected.push('\n');
+                }
+            }
+            let written = fs::read_dir(directory.path())
+                .unwrap()
+                .map(|entry| fs::read_to_string(entry.unwrap().path()).unwrap())
+                .collect::<String>();
+            prop_assert_eq!(written, expected);
+        }
+
+        #[test]
+        fn test_exact_size(name in "[a-zA-Z_-]+", period in 1..64usize, writes in proptest::collection::vec(proptest::collection::vec(any::<u8>(), 0..256), 0..8)) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let data = writes.concat();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Bytes(period),
+                NonZeroUsize::new(data.len() / period + 2).unwrap(),
+                crate::Compression::None,
+            )
+            .with_naming(Naming::Sequence)
+            .with_exact_size(true);
+            for buf in &writes {
+                file.write_all(buf).unwrap();
+            }
+
+            let files = file.files_matching("*").unwrap();
+            let mut written = Vec::new();
+            for path in files.iter().rev() {
+                let contents = fs::read(path).unwrap();
+                prop_assert!(contents.len() <= period);
+                written.extend(contents);
+            }
+            prop_assert_eq!(written, data);
+        }
+
+        #[test]
+        fn test_shared(name in "[a-zA-Z_-]+", threads in 1..4usize, lines in proptest::collection::vec("[a-z]{0,32}\n", 0..16)) {
+            use std::io::prelude::*;
+
+            fn assert_send_sync<T: Send + Sync>() {}
+            assert_send_sync::<crate::SharedRotatingFile>();
+
+            let directory = tempfile::tempdir().unwrap();
+            let file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Lines(3),
+                NonZeroUsize::new(threads * lines.len() + 1).unwrap(),
+                crate::Compression::None,
+            )
+            .into_shared();
+            let handles = (0..threads)
+                .map(|_| {
+                    let file = file.try_clone().unwrap();
+                    let lines = lines.clone();
+                    std::thread::spawn(move || {
+                        for line in lines {
+                            (&file).write_all(line.as_bytes()).unwrap();
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+            (&file).flush().unwrap();
+
+            let mut written = Vec::new();
+            for path in file.lock().files_matching("*").unwrap() {
+                let contents = fs::read_to_string(path).unwrap();
+                written.extend(contents.split_inclusive('\n').map(String::from));
+            }
+            written.sort();
+            let mut expected = (0..threads).flat_map(|_| lines.iter().cloned()).collect::<Vec<_>>();
+            expected.sort();
+            prop_assert_eq!(written, expected);
+        }
+
+        #[test]
+        fn test_locking(name in "[a-zA-Z_-]+") {
+            use std::io::prelude::*;
+
+            // Two processes sharing the same files, as far as they can tell
+            let directory = tempfile::tempdir().unwrap();
+            let open = || {
+                RotatingFile::new(
+                    name.clone(),
+                    directory.path().to_owned(),
+                    RotationPeriod::Lines(2),
+                    NonZeroUsize::new(4).unwrap(),
+                    crate::Compression::None,
+                )
+                .with_resume(true)
+                .with_locking(true)
+            };
+            let mut a = open();
+            let mut b = open();
+            a.write_all(b"a0\n").unwrap();
+            b.write_all(b"b0\n").unwrap();
+            a.write_all(b"a1\n").unwrap();
+            a.write_all(b"a2\n").unwrap();
+            // `b` is due to rotate too, but `a` already did so it only needs to catch up
+            b.write_all(b"b1\n").unwrap();
+            b.write_all(b"b2\n").unwrap();
+
+            let files = a.files_matching("*").unwrap();
+            prop_assert_eq!(files.len(), 2);
+            prop_assert_eq!(fs::read_to_string(&files[0]).unwrap(), "a2\nb1\nb2\n");
+            prop_assert_eq!(fs::read_to_string(&files[1]).unwrap(), "a0\nb0\na1\n");
+        }
+
+        #[test]
+        fn test_lock_exclusively(name in "[a-zA-Z_-]+") {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let open = || {
+                RotatingFile::new(
+                    name.clone(),
+                    directory.path().to_owned(),
+                    RotationPeriod::Manual,
+                    NonZeroUsize::new(1).unwrap(),
+                    crate::Compression::None,
+                )
+                .lock_exclusively()
+            };
+            let mut first = open().unwrap();
+            let e = open().unwrap_err();
+            prop_assert_eq!(e.kind(), std::io::ErrorKind::WouldBlock);
+
+            // The lock file isn't one of the log files
+            first.write_all(b"hello\n").unwrap();
+            prop_assert_eq!(first.files_matching("*").unwrap().len(), 1);
+            drop(first);
+            prop_assert!(open().is_ok());
+        }
+
+        #[test]
+        fn test_copy_truncate(name in "[a-zA-Z_-]+", rotations in 1..4usize) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(4).unwrap(),
+                crate::Compression::None,
+            )
+            .with_copy_truncate(true);
+            file.write_all(b"0\n").unwrap();
+            file.flush().unwrap();
+
+            // Somebody following the current file, who should keep seeing what's written to it
+            let path = file.files_matching("*").unwrap().remove(0);
+            let mut reader = fs::File::open(&path).unwrap();
+            for rotation in 1..=rotations {
+                file.rotate().unwrap();
+                file.write_all(format!("{}\n", rotation).as_bytes()).unwrap();
+            }
+            file.flush().unwrap();
+
+            let mut current = String::new();
+            reader.seek(std::io::SeekFrom::Start(0)).unwrap();
+            reader.read_to_string(&mut current).unwrap();
+            prop_assert_eq!(current, format!("{}\n", rotations));
+
+            let files = file.files_matching("*").unwrap();
+            prop_assert_eq!(&files[0], &path);
+            let contents = files
+                .iter()
+                .map(|path| fs::read_to_string(path).unwrap())
+                .collect::<Vec<_>>();
+            let expected = (0..=rotations).rev().map(|i| format!("{}\n", i)).collect::<Vec<_>>();
+            prop_assert_eq!(contents, expected);
+        }
+
+        #[test]
+        fn test_flush_all(name in "[a-zA-Z_-]+", line in "[a-z]+") {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(1).unwrap(),
+                crate::Compression::None,
+            )
+            // Holds on to the line until it's flushed
+            .with_transform(|line| std::borrow::Cow::Borrowed(line))
+            .into_shared();
+            file.register();
+            (&file).write_all(line.as_bytes()).unwrap();
+
+            let contents = || {
+                file.lock()
+                    .files_matching("*")
+                    .unwrap()
+                    .iter()
+                    .map(|path| fs::read_to_string(path).unwrap())
+                    .collect::<String>()
+            };
+            prop_assert_eq!(contents(), "");
+            crate::flush_all().unwrap();
+            prop_assert_eq!(contents(), line);
+        }
+
+        #[test]
+        fn test_thread_buffered(name in "[a-zA-Z_-]+", threads in 1..8usize, lines in 1..64usize) {
+            use std::io::prelude::*;
+            use std::sync::{Arc, Mutex};
+
+            let directory = tempfile::tempdir().unwrap();
+            let file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(1).unwrap(),
+                crate::Compression::None,
+            );
+            let (writer, guard) = file.thread_buffered(std::time::Duration::from_millis(1)).unwrap();
+
+            // Writes are numbered in the order they're made, with threads taking turns
+            let next = Arc::new(Mutex::new(0));
+            let handles = (0..threads)
+                .map(|_| {
+                    let mut writer = writer.clone();
+                    let next = Arc::clone(&next);
+                    std::thread::spawn(move || {
+                        for _ in 0..lines {
+                            let mut next = next.lock().unwrap();
+                            writer.write_all(format!("{}\n", *next).as_bytes()).unwrap();
+                            *next += 1;
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles.into_iter().for_each(|handle| handle.join().unwrap());
+            drop(writer);
+            drop(guard);
+
+            let path = directory.path().read_dir().unwrap().next().unwrap().unwrap().path();
+            let expected = (0..threads * lines).map(|i| format!("{}\n", i)).collect::<String>();
+            prop_assert_eq!(fs::read_to_string(path).unwrap(), expected);
+        }
+
+        #[test]
+        fn test_thread_buffered_flush(name in "[a-zA-Z_-]+", line in "[a-z]+\n") {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(1).unwrap(),
+                crate::Compression::None,
+            );
+            let (mut writer, _guard) = file.thread_buffered(std::time::Duration::from_secs(3600)).unwrap();
+            writer.write_all(line.as_bytes()).unwrap();
+            writer.flush().unwrap();
+
+            let path = directory.path().read_dir().unwrap().next().unwrap().unwrap().path();
+            prop_assert_eq!(fs::read_to_string(path).unwrap(), line);
+        }
+
+        #[test]
+        fn test_reopen_on_move(name in "[a-zA-Z_-]+", recreate: bool) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let moved = directory.path().join("moved");
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(2).unwrap(),
+                crate::Compression::None,
+            )
+            .with_header(|_| b"header\n".to_vec())
+            .with_reopen_on_move(true);
+            file.write_all(b"before\n").unwrap();
+
+            // Like logrotate would, with or without `create`
+            let path = file.files_matching("*").unwrap().remove(0);
+            fs::rename(&path, &moved).unwrap();
+            if recreate {
+                fs::write(&path, b"").unwrap();
+            }
+            file.write_all(b"after\n").unwrap();
+
+            prop_assert_eq!(fs::read_to_string(&moved).unwrap(), "header\nbefore\n");
+            let expected = if recreate { "after\n" } else { "header\nafter\n" };
+            prop_assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+            prop_assert_eq!(file.files_matching("*").unwrap(), vec![path]);
+            prop_assert_eq!(file.stats().rotations, 1);
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn test_hardened_reopen_on_move(name in "[a-zA-Z_-]+", hardened: bool) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let outside = directory.path().join("outside");
+            fs::write(&outside, b"not a log\n").unwrap();
+            let logs = directory.path().join("logs");
+            fs::create_dir(&logs).unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                &logs,
+                RotationPeriod::Manual,
+                NonZeroUsize::new(2).unwrap(),
+                crate::Compression::None,
+            )
+            .with_hardening(hardened)
+            .with_reopen_on_move(true);
+            file.write_all(b"before\n").unwrap();
+
+            // A symlink put where the current file was moved away from must not be written through
+            let path = file.files_matching("*").unwrap().remove(0);
+            fs::rename(&path, logs.join("moved")).unwrap();
+            std::os::unix::fs::symlink(&outside, &path).unwrap();
+            let written = file.write_all(b"after\n");
+            prop_assert_eq!(written.is_err(), hardened);
+            let expected = if hardened { "not a log\n" } else { "not a log\nafter\n" };
+            prop_assert_eq!(fs::read_to_string(&outside).unwrap(), expected);
+        }
+
+        #[test]
+        fn test_line_boundaries(name in "[a-zA-Z_-]+", period in 1..64usize, lines in proptest::collection::vec("[a-z]{0,32}\n", 1..32)) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Bytes(period),
+                NonZeroUsize::new(lines.len() + 1).unwrap(),
+                crate::Compression::None
+            )
+            .with_line_boundaries(true);
+            // Writes that don't line up with lines are what could split them
+            let data = lines.concat();
+            for chunk in data.as_bytes().chunks(7) {
+                file.write_all(chunk).unwrap();
+            }
+            file.flush().unwrap();
+
+            let mut files = file.files_matching("*").unwrap();
+            files.reverse();
+            let mut contents = Vec::new();
+            for (i, path) in files.iter().enumerate() {
+                let read = fs::read(path).unwrap();
+                // The current file may have just been created by rotating
+                prop_assert!(read.ends_with(b"\n") || (read.is_empty() && i + 1 == files.len()));
+                if i + 1 < files.len() {
+                    prop_assert!(read.len() >= period);
+                }
+                contents.extend(read);
+            }
+            prop_assert_eq!(contents, data.into_bytes());
+        }
+
+        #[test]
+        fn test_write_record(name in "[a-zA-Z_-]+", period in 1..64usize, records in proptest::collection::vec("[a-z]{0,32}\n", 1..32)) {
+            let directory = tempfile::tempdir().unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Bytes(period),
+                NonZeroUsize::new(records.len() + 1).unwrap(),
+                crate::Compression::None
+            );
+            for record in &records {
+                file.write_record(record.as_bytes()).unwrap();
+            }
+
+            let mut files = file.files_matching("*").unwrap();
+            files.reverse();
+            let mut contents = Vec::new();
+            for (i, path) in files.iter().enumerate() {
+                let read = fs::read(path).unwrap();
+                prop_assert!(read.ends_with(b"\n"));
+                if i + 1 < files.len() {
+                    prop_assert!(read.len() >= period);
+                }
+                contents.extend(read);
+            }
+            prop_assert_eq!(contents, records.concat().into_bytes());
+        }
+
+        #[test]
+        fn test_fallback(name in "[a-zA-Z_-]+", before in "[a-z]+", during in "[a-z]+", after in "[a-z]+") {
+            use std::io::prelude::*;
+            use std::sync::{mpsc, Arc, Mutex};
+
+            #[derive(Clone, Default)]
+            struct Fallback(Arc<Mutex<Vec<u8>>>);
+
+            impl Write for Fallback {
+                fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                    self.0.lock().unwrap().write(buf)
+                }
+
+                fn flush(&mut self) -> std::io::Result<()> {
+                    Ok(())
+                }
+            }
+
+            let parent = tempfile::tempdir().unwrap();
+            let directory = parent.path().join("logs");
+            fs::create_dir(&directory).unwrap();
+            let fallback = Fallback::default();
+            let (sender, events) = mpsc::channel();
+            let mut file = RotatingFile::new(
+                name,
+                directory.clone(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(2).unwrap(),
+                crate::Compression::None,
+            )
+            .with_fallback(fallback.clone())
+            .with_spool_events(move |event| sender.send(event.clone()).unwrap());
+
+            file.write_all(before.as_bytes()).unwrap();
+            fs::remove_dir_all(&directory).unwrap();
+            file.rotate().unwrap();
+            file.write_all(during.as_bytes()).unwrap();
+            prop_assert_eq!(&*fallback.0.lock().unwrap(), during.as_bytes());
+            prop_assert_eq!(events.try_recv(), Ok(SpoolEvent::Started { directory: directory.clone() }));
+
+            // Nothing that went to the fallback gets written out again
+            fs::create_dir(&directory).unwrap();
+            file.write_all(after.as_bytes()).unwrap();
+            file.flush().unwrap();
+            let contents = file.files_matching("*").unwrap().into_iter().map(|path| fs::read_to_string(path).unwrap()).collect::<Vec<_>>();
+            prop_assert_eq!(contents, vec![after]);
+            let replayed = matches!(events.try_recv(), Ok(SpoolEvent::Replayed { bytes, .. }) if bytes == during.len());
+            prop_assert!(replayed);
+        }
+
+        #[test]
+        fn test_spool_record(name in "[a-zA-Z_-]+", capacity in 1..64usize, record in proptest::collection::vec(any::<u8>(), 1..64)) {
+            let directory = tempfile::tempdir().unwrap();
+            let logs = directory.path().join("logs");
+            fs::create_dir(&logs).unwrap();
+            let mut file = RotatingFile::new(
+                name,
+                &logs,
+                RotationPeriod::Manual,
+                NonZeroUsize::new(2).unwrap(),
+                crate::Compression::None
+            )
+            .with_spool(capacity);
+            file.rotate().unwrap();
+            fs::remove_dir_all(&logs).unwrap();
+            file.rotate().unwrap();
+
+            prop_assert_eq!(file.write_record(&record).is_ok(), record.len() <= capacity);
+            fs::create_dir(&logs).unwrap();
+            file.write_record(b"").unwrap();
+            let written = fs::read(&file.files_matching("*").unwrap()[0]).unwrap();
+            if record.len() <= capacity {
+                prop_assert_eq!(written, record);
+            } else {
+                prop_assert!(written.is_empty());
+            }
+        }
+
+        #[cfg(all(feature = "zstd", feature = "lz4", feature = "xz"))]
+        #[test]
+        fn test_switch_compression(name in "[a-zA-Z_-]+", shifting: bool, data: Vec<u8>) {
+            use std::io::prelude::*;
+
+            let directory = tempfile::tempdir().unwrap();
+            let compressions = [
+                crate::Compression::Xz { preset: 0 },
+                crate::Compression::zstd(0),
+                crate::Compression::Lz4,
+                crate::Compression::None,
+            ];
+            for compression in compressions {
+                let mut file = RotatingFile::new(
+                    name.clone(),
+                    directory.path().to_owned(),
+                    RotationPeriod::Manual,
+                    NonZeroUsize::new(10).unwrap(),
+                    compression
+                ).with_naming(if shifting { Naming::Index } else { Naming::Timestamp });
+                for _ in 0..2 {
+                    file.rotate().unwrap();
+                    file.write_all(&data).unwrap();
+                    file.flush().unwrap();
                 }
             }
+
+            // Archives get recompressed as configured, but are left as they are once compression is
+            // turned off, and can still be read
+            let file = RotatingFile::new(
+                name,
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(10).unwrap(),
+                crate::Compression::None
+            ).with_naming(if shifting { Naming::Index } else { Naming::Timestamp });
+            let files = file.files_matching("*").unwrap();
+            prop_assert_eq!(files.len(), 8);
+            let suffixes = files
+                .iter()
+                .map(|path| path.extension().unwrap().to_string_lossy().into_owned())
+                .collect::<Vec<_>>();
+            prop_assert_eq!(suffixes, ["log", "log", "log", "lz4", "lz4", "lz4", "lz4", "lz4"]);
+            for mut reader in file.read_matching("*").unwrap() {
+                let mut read = Vec::new();
+                reader.read_to_end(&mut read).unwrap();
+                prop_assert_eq!(&read, &data);
+            }
         }
     }
 }
@@ -0,0 +1,107 @@
+use std::borrow::Cow;
+use std::num::NonZeroUsize;
+
+/// What to do with lines that are too long, see
+/// [`RotatingFile::with_max_line_length`](struct.RotatingFile.html#method.with_max_line_length)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum LongLines {
+    /// Throw away the rest of the line, writing `...` in its place
+    Truncate,
+    /// Carry on with the rest of the line on a new one
+    Split,
+}
+
+// Keeps lines to a maximum length, not counting their line terminator
+#[derive(Debug, Default)]
+pub(super) struct LineLength {
+    pub(super) max: Option<(NonZeroUsize, LongLines)>,
+    // How long the line we're in the middle of is so far, and whether it's been truncated
+    column: usize,
+    truncated: bool,
+}
+
+impl LineLength {
+    pub(super) fn is_enabled(&self) -> bool {
+        self.max.is_some()
+    }
+
+    pub(super) fn apply<'a>(&mut self, buf: &'a [u8]) -> Cow<'a, [u8]> {
+        let (max, long_lines) = match self.max {
+            Some((max, long_lines)) => (max.get(), long_lines),
+            None => return Cow::Borrowed(buf),
+        };
+
+        let mut out = Vec::with_capacity(buf.len());
+        for piece in buf.split_inclusive(|&b| b == b'\n') {
+            let ends_line = piece.ends_with(b"\n");
+            let mut content = if ends_line {
+                &piece[..piece.len() - 1]
+            } else {
+                piece
+            };
+
+            match long_lines {
+                LongLines::Truncate => {
+                    let kept = max.saturating_sub(self.column).min(content.len());
+                    out.extend_from_slice(&content[..kept]);
+                    self.column += kept;
+                    if kept < content.len() && !self.truncated {
+                        out.extend_from_slice(b"...");
+                        self.truncated = true;
+                    }
+                }
+
+                LongLines::Split => loop {
+                    let room = max - self.column;
+                    if content.len() <= room {
+                        out.extend_from_slice(content);
+                        self.column += content.len();
+                        break;
+                    }
+                    out.extend_from_slice(&content[..room]);
+                    out.push(b'\n');
+                    self.column = 0;
+                    content = &content[room..];
+                },
+            }
+
+            if ends_line {
+                out.push(b'\n');
+                self.column = 0;
+                self.truncated = false;
+            }
+        }
+        Cow::Owned(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::{LineLength, LongLines};
+
+    fn line_length(max: usize, long_lines: LongLines) -> LineLength {
+        LineLength {
+            max: Some((NonZeroUsize::new(max).unwrap(), long_lines)),
+            ..LineLength::default()
+        }
+    }
+
+    #[test]
+    fn test_truncate() {
+        let mut line_length = line_length(3, LongLines::Truncate);
+        assert_eq!(&line_length.apply(b"ab\nabcd\nab")[..], b"ab\nabc...\nab");
+        assert_eq!(&line_length.apply(b"cd")[..], b"c...");
+        assert_eq!(&line_length.apply(b"ef\nabc\n")[..], b"\nabc\n");
+    }
+
+    #[test]
+    fn test_split() {
+        let mut line_length = line_length(3, LongLines::Split);
+        assert_eq!(&line_length.apply(b"abcdefg\nab")[..], b"abc\ndef\ng\nab");
+        assert_eq!(&line_length.apply(b"c")[..], b"c");
+        assert_eq!(&line_length.apply(b"d\n")[..], b"\nd\n");
+    }
+}
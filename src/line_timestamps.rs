@@ -0,0 +1,101 @@
+use std::borrow::Cow;
+use std::time::SystemTime;
+
+use super::timestamp;
+
+/// How to write the time at the start of every line, see
+/// [`RotatingFile::with_timestamps`](struct.RotatingFile.html#method.with_timestamps)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum TimestampFormat {
+    /// UTC, to the millisecond, like `2024-05-01T12:34:56.789Z`
+    Rfc3339,
+    /// Seconds since the unix epoch, like `1714566896`
+    UnixSeconds,
+    /// Milliseconds since the unix epoch, like `1714566896789`
+    UnixMillis,
+}
+
+impl TimestampFormat {
+    fn format(self, time: SystemTime) -> String {
+        match self {
+            TimestampFormat::Rfc3339 => timestamp::rfc3339(time),
+            TimestampFormat::UnixSeconds => timestamp::unix_secs(time).to_string(),
+            TimestampFormat::UnixMillis => timestamp::unix_millis(time).to_string(),
+        }
+    }
+}
+
+// Puts the time a line started being written, and a space, at the start of it
+#[derive(Debug)]
+pub(super) struct Timestamps {
+    pub(super) format: Option<TimestampFormat>,
+    at_line_start: bool,
+}
+
+impl Default for Timestamps {
+    fn default() -> Self {
+        Self {
+            format: None,
+            at_line_start: true,
+        }
+    }
+}
+
+impl Timestamps {
+    pub(super) fn is_enabled(&self) -> bool {
+        self.format.is_some()
+    }
+
+    pub(super) fn apply<'a>(&mut self, buf: &'a [u8]) -> Cow<'a, [u8]> {
+        self.apply_at(buf, SystemTime::now())
+    }
+
+    fn apply_at<'a>(&mut self, buf: &'a [u8], now: SystemTime) -> Cow<'a, [u8]> {
+        let format = match self.format {
+            Some(format) if !buf.is_empty() => format,
+            _ => return Cow::Borrowed(buf),
+        };
+
+        let timestamp = format.format(now);
+        let mut out = Vec::with_capacity(buf.len() + timestamp.len() + 1);
+        for line in buf.split_inclusive(|&b| b == b'\n') {
+            if self.at_line_start {
+                out.extend_from_slice(timestamp.as_bytes());
+                out.push(b' ');
+            }
+            out.extend_from_slice(line);
+            self.at_line_start = line.ends_with(b"\n");
+        }
+        Cow::Owned(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{TimestampFormat, Timestamps};
+
+    #[test]
+    fn test_timestamps() {
+        let mut timestamps = Timestamps::default();
+        assert_eq!(&timestamps.apply(b"a\n")[..], b"a\n");
+
+        let now = UNIX_EPOCH + Duration::from_millis(1_714_566_896_789);
+        timestamps.format = Some(TimestampFormat::UnixSeconds);
+        assert_eq!(
+            &timestamps.apply_at(b"a\nb", now)[..],
+            b"1714566896 a\n1714566896 b"
+        );
+        assert_eq!(&timestamps.apply_at(b"c\n\n", now)[..], b"c\n1714566896 \n");
+
+        timestamps.format = Some(TimestampFormat::Rfc3339);
+        assert_eq!(
+            &timestamps.apply_at(b"d\n", now)[..],
+            &b"2024-05-01T12:34:56.789Z d\n"[..]
+        );
+        timestamps.format = Some(TimestampFormat::UnixMillis);
+        assert_eq!(&timestamps.apply_at(b"e\n", now)[..], b"1714566896789 e\n");
+    }
+}
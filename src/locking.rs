@@ -0,0 +1,65 @@
+use std::fs::{self, TryLockError};
+use std::io;
+use std::path::Path;
+
+use super::current_file::CurrentFile;
+use super::permissions;
+
+// Lock the file at `path`, creating it if needed, for as long as the returned file is open. This
+// waits for whoever has it locked to be done.
+pub(super) fn lock(path: &Path) -> io::Result<fs::File> {
+    let file = open(path)?;
+    file.lock()?;
+    Ok(file)
+}
+
+// Like `lock`, but fail with `io::ErrorKind::WouldBlock` instead of waiting
+pub(super) fn try_lock(path: &Path) -> io::Result<fs::File> {
+    let file = open(path)?;
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(TryLockError::WouldBlock) => Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            format!("{} is locked by someone else", path.display()),
+        )),
+        Err(TryLockError::Error(e)) => Err(e),
+    }
+}
+
+fn open(path: &Path) -> io::Result<fs::File> {
+    permissions::no_follow(
+        fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false),
+    )
+    .open(path)
+}
+
+// Run `f` on `file` while holding a lock on it
+pub(super) fn locked<T>(
+    file: &mut CurrentFile,
+    f: impl FnOnce(&mut CurrentFile) -> io::Result<T>,
+) -> io::Result<T> {
+    file.handle().lock()?;
+    let result = f(file);
+    let unlocked = file.handle().unlock();
+    let result = result?;
+    unlocked?;
+    Ok(result)
+}
+
+// Whether both are about the same file, as far as we can tell
+pub(super) fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        a.dev() == b.dev() && a.ino() == b.ino()
+    }
+    // There's no stable way to tell, so assume the best
+    #[cfg(not(unix))]
+    {
+        let _ = (a, b);
+        true
+    }
+}
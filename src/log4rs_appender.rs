@@ -0,0 +1,113 @@
+use std::io::Write;
+
+use log::Record;
+use log4rs::append::Append;
+use log4rs::encode::pattern::PatternEncoder;
+use log4rs::encode::writer::simple::SimpleWriter;
+use log4rs::encode::Encode;
+
+use super::SharedRotatingFile;
+
+/// A log4rs appender writing to a rotating file, to use in place of log4rs' own
+/// `RollingFileAppender`
+///
+/// Every record is encoded with the encoder, log4rs' default [`PatternEncoder`] unless
+/// [`with_encoder`](#method.with_encoder) says otherwise, and then written with
+/// [`RotatingFile::write_record`](struct.RotatingFile.html#method.write_record) so that it's
+/// never split across files.
+///
+/// ```no_run
+/// # use std::num::NonZeroUsize;
+/// # use file_rotator::{Compression, Log4rsAppender, RotatingFile, RotationPeriod};
+/// use log4rs::config::{Appender, Config, Root};
+///
+/// let file = RotatingFile::new(
+///     "app",
+///     "logs",
+///     RotationPeriod::Interval(std::time::Duration::from_secs(60 * 60)),
+///     NonZeroUsize::new(24).unwrap(),
+///     Compression::None,
+/// );
+/// let config = Config::builder()
+///     .appender(Appender::builder().build("file", Box::new(Log4rsAppender::new(file))))
+///     .build(Root::builder().appender("file").build(log::LevelFilter::Info))
+///     .unwrap();
+/// log4rs::init_config(config).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Log4rsAppender {
+    file: SharedRotatingFile,
+    encoder: Box<dyn Encode>,
+}
+
+impl Log4rsAppender {
+    /// Append to the given rotating file, which can be shared with other writers
+    pub fn new<File>(file: File) -> Self
+    where
+        File: Into<SharedRotatingFile>,
+    {
+        Self {
+            file: file.into(),
+            encoder: Box::new(PatternEncoder::default()),
+        }
+    }
+
+    /// Encode records with the given encoder
+    pub fn with_encoder(mut self, encoder: Box<dyn Encode>) -> Self {
+        self.encoder = encoder;
+        self
+    }
+}
+
+impl Append for Log4rsAppender {
+    fn append(&self, record: &Record) -> anyhow::Result<()> {
+        let mut buf = SimpleWriter(Vec::new());
+        self.encoder.encode(&mut buf, record)?;
+        self.file.lock().write_record(&buf.0)?;
+        Ok(())
+    }
+
+    fn flush(&self) {
+        // There's nowhere to report it
+        let _ = self.file.lock().flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::num::NonZeroUsize;
+
+    use log4rs::append::Append;
+    use log4rs::encode::pattern::PatternEncoder;
+
+    use super::Log4rsAppender;
+    use crate::{Compression, RotatingFile, RotationPeriod};
+
+    #[test]
+    fn test_append() {
+        let directory = tempfile::tempdir().unwrap();
+        let file = RotatingFile::new(
+            "log4rs",
+            directory.path().to_owned(),
+            RotationPeriod::Manual,
+            NonZeroUsize::new(1).unwrap(),
+            Compression::None,
+        )
+        .into_shared();
+        let appender = Log4rsAppender::new(file.clone())
+            .with_encoder(Box::new(PatternEncoder::new("{l} {m}{n}")));
+        appender
+            .append(
+                &log::Record::builder()
+                    .level(log::Level::Warn)
+                    .args(format_args!("hello {}", 42))
+                    .build(),
+            )
+            .unwrap();
+        appender.flush();
+
+        let path = file.lock().files_matching("*").unwrap().remove(0);
+        assert_eq!(fs::read_to_string(path).unwrap(), "WARN hello 42\n");
+    }
+}
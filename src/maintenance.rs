@@ -0,0 +1,96 @@
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+use super::snapshot::Pending;
+use super::stats::Counters;
+#[cfg(feature = "zstd")]
+use super::ZstdOptions;
+use super::{ArchivePermissions, Compression};
+
+// (Re)compressing a file that has already been rotated out, which can happen away from the write
+// path
+#[derive(Debug)]
+pub(super) struct Job {
+    pub(super) src: PathBuf,
+    pub(super) src_compression: Compression,
+    pub(super) dst: PathBuf,
+    pub(super) level: i32,
+}
+
+// How archives are finished off once they've been compressed
+#[derive(Clone, Copy, Debug, Default)]
+pub(super) struct ArchiveSettings {
+    pub(super) permissions: ArchivePermissions,
+    #[cfg(feature = "checksum")]
+    pub(super) checksums: bool,
+    #[cfg(feature = "checksum")]
+    pub(super) verify: bool,
+    pub(super) durable: bool,
+    #[cfg(feature = "zstd")]
+    pub(super) zstd: ZstdOptions,
+}
+
+// A background thread working through the jobs generated by the last rotation
+//
+// Rotation renames and deletes files, so it must wait for the previous batch of jobs to finish
+// before it starts.
+#[derive(Debug, Default)]
+pub(super) struct Maintenance {
+    worker: Option<thread::JoinHandle<io::Result<()>>>,
+}
+
+impl Maintenance {
+    pub(super) fn spawn(
+        &mut self,
+        jobs: Vec<Job>,
+        compression: Compression,
+        settings: ArchiveSettings,
+        stats: Arc<Counters>,
+        archived: Option<Pending>,
+    ) -> io::Result<()> {
+        self.wait()?;
+        // The file that was rotated out gets handed out once it's done with. Errors end up in the
+        // statistics, there's nobody else to give them to.
+        if jobs.is_empty() {
+            if let Some(archived) = archived {
+                let _ = stats.record(archived.send());
+            }
+            return Ok(());
+        }
+
+        let worker = thread::Builder::new()
+            .name("file-rotator maintenance".into())
+            .spawn(move || {
+                let done = jobs.iter().try_for_each(|job| {
+                    let started = Instant::now();
+                    let compressed = compression.compress_file(
+                        &job.src,
+                        job.src_compression.clone(),
+                        &job.dst,
+                        job.level,
+                        &settings,
+                    );
+                    stats.compressed(started.elapsed());
+                    compressed
+                });
+                if let Some(archived) = archived {
+                    let _ = stats.record(archived.send());
+                }
+                done
+            })?;
+        self.worker = Some(worker);
+        Ok(())
+    }
+
+    pub(super) fn wait(&mut self) -> io::Result<()> {
+        match self.worker.take() {
+            Some(worker) => worker.join().unwrap_or_else(|_| {
+                Err(io::Error::other("file-rotator maintenance thread panicked"))
+            }),
+            None => Ok(()),
+        }
+    }
+}
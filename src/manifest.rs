@@ -0,0 +1,298 @@
+use std::fs;
+use std::io::{self, prelude::*};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use super::{checksum, permissions, timestamp};
+
+// What's known about one file that was rotated out
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct Entry {
+    // Where it is, relative to the directory, with `/` between components
+    pub(super) file: String,
+    // When it was created, if that's known
+    pub(super) created: Option<SystemTime>,
+    pub(super) rotated: SystemTime,
+    // Its size and SHA-256 as it was written, before any compression
+    pub(super) original_size: u64,
+    pub(super) sha256: [u8; 32],
+    // Its size on disk, if it's compressed
+    pub(super) compressed_size: Option<u64>,
+}
+
+// The history of the files that were rotated out, oldest first, kept as JSON in
+// `NAME.manifest.json` so that it outlives the process, see `RotatingFile::with_manifest`
+#[derive(Debug, Default)]
+pub(super) struct Manifest {
+    pub(super) entries: Vec<Entry>,
+    // When the current file was created, if that's known
+    pub(super) current_created: Option<SystemTime>,
+    loaded: bool,
+}
+
+impl Manifest {
+    // Read the manifest at `path`, one that isn't there being empty. One that can't be made
+    // sense of is an error of kind `InvalidData`.
+    pub(super) fn read(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(json) => parse(&json).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} can't be parsed: {}", path.display(), e),
+                )
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self {
+                loaded: true,
+                ..Self::default()
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Read the manifest at `path` if that hasn't been done yet. One that can't be made sense of
+    // is moved aside to `NAME.manifest.json.corrupt`, for someone to have a look at, and started
+    // over.
+    pub(super) fn load(&mut self, path: &Path) -> io::Result<()> {
+        if self.loaded {
+            return Ok(());
+        }
+        *self = match Self::read(path) {
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                let mut corrupt = path.to_owned().into_os_string();
+                corrupt.push(".corrupt");
+                fs::rename(path, corrupt)?;
+                Self::read(path)?
+            }
+            read => read?,
+        };
+        Ok(())
+    }
+
+    pub(super) fn is_loaded(&self) -> bool {
+        self.loaded
+    }
+
+    // Forget about everything, as if the manifest had just been read and was empty
+    pub(super) fn clear(&mut self) {
+        *self = Self {
+            loaded: true,
+            ..Self::default()
+        };
+    }
+
+    pub(super) fn entry(&self, file: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.file == file)
+    }
+
+    // Follow files that were moved, all at once since each move is from where things were before
+    // any of them. Files that something else was moved onto without being moved themselves are
+    // gone.
+    pub(super) fn moved(&mut self, moves: &[(String, String)]) {
+        self.entries.retain(|entry| {
+            moves.iter().any(|(from, _)| *from == entry.file)
+                || !moves.iter().any(|(_, to)| *to == entry.file)
+        });
+        for entry in &mut self.entries {
+            if let Some((_, to)) = moves.iter().find(|(from, _)| *from == entry.file) {
+                entry.file = to.clone();
+            }
+        }
+    }
+
+    // Write the manifest to a temporary file first, so that it's never seen half-written
+    pub(super) fn save(&self, path: &Path, name: &str) -> io::Result<()> {
+        let mut tmp = path.to_owned().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let mut file = permissions::create_replacing(&tmp)?;
+        file.write_all(self.to_json(name).as_bytes())?;
+        file.sync_all()?;
+        fs::rename(tmp, path)
+    }
+
+    fn to_json(&self, name: &str) -> String {
+        let json = Json {
+            name: name.to_owned(),
+            current_created: self.current_created.map(timestamp::rfc3339),
+            archives: self
+                .entries
+                .iter()
+                .map(|entry| JsonEntry {
+                    file: entry.file.clone(),
+                    created: entry.created.map(timestamp::rfc3339),
+                    rotated: timestamp::rfc3339(entry.rotated),
+                    original_size: entry.original_size,
+                    compressed_size: entry.compressed_size,
+                    sha256: checksum::hex(&entry.sha256),
+                })
+                .collect(),
+        };
+        let mut json =
+            serde_json::to_string_pretty(&json).expect("strings and numbers always serialize");
+        json.push('\n');
+        json
+    }
+}
+
+// The size and SHA-256 of everything in `reader`
+pub(super) fn digest<R: Read>(reader: R) -> io::Result<(u64, [u8; 32])> {
+    let mut reader = checksum::HashingReader::new(reader, true);
+    let size = io::copy(&mut reader, &mut io::sink())?;
+    Ok((size, reader.finish().expect("hashing is enabled")))
+}
+
+// How the manifest is laid out on disk, fields in the order they're written in
+#[derive(Deserialize, Serialize)]
+struct Json {
+    name: String,
+    current_created: Option<String>,
+    archives: Vec<JsonEntry>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct JsonEntry {
+    file: String,
+    created: Option<String>,
+    rotated: String,
+    original_size: u64,
+    compressed_size: Option<u64>,
+    sha256: String,
+}
+
+fn parse(json: &str) -> io::Result<Manifest> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let time = |time: &str| {
+        timestamp::parse_rfc3339(time).ok_or_else(|| invalid(format!("bad time {:?}", time)))
+    };
+    let json = serde_json::from_str::<Json>(json).map_err(|e| invalid(e.to_string()))?;
+    let entries = json
+        .archives
+        .into_iter()
+        .map(|archive| {
+            Ok(Entry {
+                created: archive.created.as_deref().map(time).transpose()?,
+                rotated: time(&archive.rotated)?,
+                original_size: archive.original_size,
+                sha256: checksum::parse_hex(&archive.sha256)
+                    .ok_or_else(|| invalid(format!("bad SHA-256 {:?}", archive.sha256)))?,
+                compressed_size: archive.compressed_size,
+                file: archive.file,
+            })
+        })
+        .collect::<io::Result<_>>()?;
+    Ok(Manifest {
+        entries,
+        current_created: json.current_created.as_deref().map(time).transpose()?,
+        loaded: true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{parse, Entry, Manifest};
+
+    #[test]
+    fn test_roundtrip() {
+        let at = |millis| UNIX_EPOCH + Duration::from_millis(millis);
+        let manifest = Manifest {
+            entries: vec![
+                Entry {
+                    file: "2024/app \"1\".log".to_owned(),
+                    created: None,
+                    rotated: at(1_714_566_896_789),
+                    original_size: 12,
+                    sha256: [0xab; 32],
+                    compressed_size: Some(5),
+                },
+                Entry {
+                    file: "app.0.log".to_owned(),
+                    created: Some(at(1_714_566_896_789)),
+                    rotated: at(1_714_566_900_000),
+                    original_size: 0,
+                    sha256: [0; 32],
+                    compressed_size: None,
+                },
+            ],
+            current_created: Some(at(1_714_566_900_000)),
+            loaded: true,
+        };
+        let parsed = parse(&manifest.to_json("app")).unwrap();
+        assert_eq!(parsed.entries, manifest.entries);
+        assert_eq!(parsed.current_created, manifest.current_created);
+
+        let empty = parse(&Manifest::default().to_json("app")).unwrap();
+        assert!(empty.entries.is_empty());
+        assert_eq!(empty.current_created, None);
+    }
+
+    #[test]
+    fn test_layout() {
+        // Just like in `RotatingFile::with_manifest`'s documentation
+        let at = |millis| UNIX_EPOCH + Duration::from_millis(millis);
+        let manifest = Manifest {
+            entries: vec![Entry {
+                file: "app.1.log.gz".to_owned(),
+                created: Some(at(1_714_521_600_000)),
+                rotated: at(1_714_566_896_789),
+                original_size: 1024,
+                sha256: [0xab; 32],
+                compressed_size: Some(256),
+            }],
+            current_created: Some(at(1_714_566_896_789)),
+            loaded: true,
+        };
+        let expected = format!(
+            r#"{{
+  "name": "app",
+  "current_created": "2024-05-01T12:34:56.789Z",
+  "archives": [
+    {{
+      "file": "app.1.log.gz",
+      "created": "2024-05-01T00:00:00.000Z",
+      "rotated": "2024-05-01T12:34:56.789Z",
+      "original_size": 1024,
+      "compressed_size": 256,
+      "sha256": "{}"
+    }}
+  ]
+}}
+"#,
+            "ab".repeat(32)
+        );
+        assert_eq!(manifest.to_json("app"), expected);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for json in [
+            "{",
+            r#"{"archives": [{}]}"#,
+            r#"{"name": "app", "current_created": "yesterday", "archives": []}"#,
+        ] {
+            let kind = parse(json).unwrap_err().kind();
+            assert_eq!(kind, std::io::ErrorKind::InvalidData, "{}", json);
+        }
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("app.manifest.json");
+        fs::write(&path, "{").unwrap();
+        let kind = Manifest::read(&path).unwrap_err().kind();
+        assert_eq!(kind, std::io::ErrorKind::InvalidData);
+
+        // Rotation carries on without losing what was there
+        let mut manifest = Manifest::default();
+        manifest.load(&path).unwrap();
+        assert!(manifest.is_loaded() && manifest.entries.is_empty());
+        let corrupt = directory.path().join("app.manifest.json.corrupt");
+        assert_eq!(fs::read_to_string(corrupt).unwrap(), "{");
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,337 @@
+use std::fmt;
+use std::time::SystemTime;
+
+use super::template::Template;
+use super::timestamp::{self, DateTime};
+
+/// Where a file sits in the rotation set, as understood by its [`NamingScheme`]
+///
+/// Keys are ordered by `major` first and `minor` second. Index-based schemes use the index as
+/// `major` and leave `minor` at zero, while time-based schemes use seconds since the unix epoch
+/// as `major` and a sequence number as `minor`.
+#[derive(Clone, Copy, Debug, Default, Hash, Ord, PartialOrd, Eq, PartialEq)]
+pub struct FileKey {
+    /// The main ordering component
+    pub major: u64,
+    /// The tie-breaking ordering component
+    pub minor: u64,
+}
+
+impl FileKey {
+    /// The key of the file with the given index, for index-based schemes
+    pub fn index(index: usize) -> Self {
+        Self {
+            major: index as u64,
+            minor: 0,
+        }
+    }
+}
+
+/// A way of naming log files on disk, and of recognizing them when scanning the directory
+///
+/// There are two kinds of schemes:
+///
+/// - Shifting schemes (like [`Naming::Index`]) use indices as keys, the current file having index
+///   zero and older files having bigger indices. On rotation, every file's index goes up by one,
+///   renaming it, to make room for the new current file.
+/// - Other schemes (like [`Naming::Timestamp`]) never rename files, and give newer files bigger
+///   keys via [`NamingScheme::next_key`]. The newest file is the current one.
+///
+/// Compression suffixes are handled by the rotating file itself: schemes only ever see and
+/// produce names without them.
+pub trait NamingScheme: fmt::Debug + Send {
+    /// Whether this is a shifting scheme, see the trait's documentation
+    fn shifts(&self) -> bool;
+
+    /// Build the file name of the file with the given key
+    ///
+    /// `name` is the rotating file's name and `extension` its extension (without a leading dot),
+    /// which schemes are free to ignore.
+    fn file_name(&self, name: &str, extension: &str, key: FileKey) -> String;
+
+    /// Recognize a name produced by [`NamingScheme::file_name`], returning the file's key
+    ///
+    /// This must return `None` for anything that doesn't belong to us, since the directory might
+    /// be shared with other files.
+    fn parse(&self, name: &str, extension: &str, file_name: &str) -> Option<FileKey>;
+
+    /// The key a brand new file created at `now` should get, given the newest existing file's key
+    ///
+    /// This is only used by non-shifting schemes and must return a key bigger than `newest`.
+    /// The default implementation uses the time in seconds, adding a sequence number if the
+    /// newest file is from the same second (or, if the clock went backwards, the future).
+    fn next_key(&self, newest: Option<FileKey>, now: SystemTime) -> FileKey {
+        next_key_from_secs(newest, timestamp::unix_secs(now))
+    }
+}
+
+pub(super) fn next_key_from_secs(newest: Option<FileKey>, now: u64) -> FileKey {
+    match newest {
+        Some(newest) if newest.major >= now => FileKey {
+            major: newest.major,
+            minor: newest.minor + 1,
+        },
+        _ => FileKey {
+            major: now,
+            minor: 0,
+        },
+    }
+}
+
+impl<T: NamingScheme + ?Sized> NamingScheme for Box<T> {
+    fn shifts(&self) -> bool {
+        (**self).shifts()
+    }
+
+    fn file_name(&self, name: &str, extension: &str, key: FileKey) -> String {
+        (**self).file_name(name, extension, key)
+    }
+
+    fn parse(&self, name: &str, extension: &str, file_name: &str) -> Option<FileKey> {
+        (**self).parse(name, extension, file_name)
+    }
+
+    fn next_key(&self, newest: Option<FileKey>, now: SystemTime) -> FileKey {
+        (**self).next_key(newest, now)
+    }
+}
+
+/// Which end of the rotation set index zero is at, for shifting schemes, see
+/// [`RotatingFile::with_index_direction`](struct.RotatingFile.html#method.with_index_direction)
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum IndexDirection {
+    /// The current file has index zero and older files have bigger indices
+    #[default]
+    NewestFirst,
+
+    /// The oldest file has index zero and newer files have bigger indices, the current file having
+    /// the biggest one
+    OldestFirst,
+}
+
+/// The built-in naming schemes
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Naming {
+    /// `NAME.INDEX.log`, where the current file always has index 0 and every rotation shifts all
+    /// the other files' indices up by one
+    #[default]
+    Index,
+
+    /// Like [`Naming::Index`], except that indices are zero-padded to the given width, e.g.
+    /// `NAME.007.log` with a width of 3
+    ///
+    /// This makes `ls`, shell globs and log shippers see files in the right order. Unpadded (or
+    /// differently padded) names are recognized too, and get renamed to the padded form on the
+    /// next rotation, so switching an existing directory over is seamless.
+    PaddedIndex(usize),
+
+    /// `NAME.SEQUENCE.log`, where every new file gets the next sequence number, starting from zero
+    ///
+    /// Unlike with [`Naming::Index`] files are never renamed, so rotating doesn't need to rename
+    /// every file and external indexers see stable names. The newest file, which has the biggest
+    /// number, is the current one, and retention deletes the files with the smallest numbers.
+    /// Numbers are never reused, as long as at least one file is left in the directory.
+    Sequence,
+
+    /// `NAME.TIMESTAMP.log`, where `TIMESTAMP` is the UTC time at which the file was created, e.g.
+    /// `NAME.2024-06-01T12-00-00.log`
+    ///
+    /// Files are never renamed and the newest one is the current one, so they sort naturally. If
+    /// more than one file gets created in the same second, a sequence number is added after the
+    /// timestamp (`NAME.2024-06-01T12-00-00.1.log`)
+    Timestamp,
+
+    /// Like [`Naming::Index`], except that the current file is always `NAME.log`
+    ///
+    /// This gives tools like `tail -F` and dashboards a fixed path to look at: rotating renames
+    /// `NAME.log` to `NAME.1.log`, `NAME.1.log` to `NAME.2.log` and so on before recreating
+    /// `NAME.log`.
+    Stable,
+
+    /// Names generated by a user-provided [`Template`], which is also used to recognize existing
+    /// files when scanning the directory
+    ///
+    /// Like with [`Naming::Timestamp`], files are never renamed and the newest one is the current
+    /// one. The template controls the whole file name, extension included.
+    Template(Template),
+}
+
+// Split NAME.KEY.EXTENSION into KEY, making sure that NAME and EXTENSION match. KEY may also be
+// missing altogether (NAME.EXTENSION), in which case it's returned as empty.
+fn split_file_name<'a>(name: &str, extension: &str, file_name: &'a str) -> Option<&'a str> {
+    let key = file_name
+        .strip_suffix(extension)?
+        .strip_suffix('.')?
+        .strip_prefix(name)?;
+    if key.is_empty() {
+        Some(key)
+    } else {
+        key.strip_prefix('.')
+    }
+}
+
+impl NamingScheme for Naming {
+    fn shifts(&self) -> bool {
+        match self {
+            Naming::Index | Naming::PaddedIndex(..) | Naming::Stable => true,
+            Naming::Sequence | Naming::Timestamp => false,
+            Naming::Template(template) => template.shifts(),
+        }
+    }
+
+    fn file_name(&self, name: &str, extension: &str, key: FileKey) -> String {
+        match self {
+            Naming::Stable if key.major == 0 => format!("{}.{}", name, extension),
+            Naming::Index | Naming::Stable | Naming::Sequence => {
+                format!("{}.{}.{}", name, key.major, extension)
+            }
+            Naming::PaddedIndex(width) => {
+                format!(
+                    "{}.{:0width$}.{}",
+                    name,
+                    key.major,
+                    extension,
+                    width = width
+                )
+            }
+            Naming::Timestamp if key.minor == 0 => {
+                format!("{}.{}.{}", name, DateTime::from_unix(key.major), extension)
+            }
+            Naming::Timestamp => format!(
+                "{}.{}.{}.{}",
+                name,
+                DateTime::from_unix(key.major),
+                key.minor,
+                extension
+            ),
+            Naming::Template(template) => template.file_name(name, extension, key),
+        }
+    }
+
+    fn parse(&self, name: &str, extension: &str, file_name: &str) -> Option<FileKey> {
+        match self {
+            // Leading zeros are fine with `parse`, so both recognize padded and unpadded indices
+            Naming::Index | Naming::PaddedIndex(..) | Naming::Sequence => {
+                split_file_name(name, extension, file_name)?
+                    .parse()
+                    .ok()
+                    .map(FileKey::index)
+            }
+
+            // The current file has no index in its name, so the others start from one
+            Naming::Stable => match split_file_name(name, extension, file_name)? {
+                "" => Some(FileKey::index(0)),
+                key => key
+                    .parse()
+                    .ok()
+                    .filter(|&index| index != 0)
+                    .map(FileKey::index),
+            },
+
+            // Timestamped files' KEY is TIMESTAMP or TIMESTAMP.SEQUENCE
+            Naming::Timestamp => {
+                let key = split_file_name(name, extension, file_name)?;
+                let (timestamp, sequence) = match key.split_once('.') {
+                    Some((timestamp, sequence)) => (timestamp, sequence.parse().ok()?),
+                    None => (key, 0),
+                };
+                Some(FileKey {
+                    major: DateTime::parse(timestamp)?.to_unix()?,
+                    minor: sequence,
+                })
+            }
+
+            Naming::Template(template) => template.parse(name, extension, file_name),
+        }
+    }
+
+    fn next_key(&self, newest: Option<FileKey>, now: SystemTime) -> FileKey {
+        match self {
+            Naming::Template(template) => template.next_key(newest, now),
+            Naming::Sequence => newest.map_or(FileKey::index(0), |newest| FileKey {
+                major: newest.major + 1,
+                minor: 0,
+            }),
+            Naming::Index | Naming::PaddedIndex(..) | Naming::Stable | Naming::Timestamp => {
+                next_key_from_secs(newest, timestamp::unix_secs(now))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::{FileKey, Naming, NamingScheme};
+
+    #[test]
+    fn test_next_key() {
+        let naming = Naming::Timestamp;
+        let now = std::time::UNIX_EPOCH + std::time::Duration::from_secs(100);
+        let key = |major, minor| FileKey { major, minor };
+
+        assert_eq!(naming.next_key(None, now), key(100, 0));
+        assert_eq!(naming.next_key(Some(key(99, 5)), now), key(100, 0));
+        assert_eq!(naming.next_key(Some(key(100, 0)), now), key(100, 1));
+        assert_eq!(naming.next_key(Some(key(101, 2)), now), key(101, 3));
+    }
+
+    #[test]
+    fn test_padded_index() {
+        let naming = Naming::PaddedIndex(3);
+        assert_eq!(
+            naming.file_name("app", "log", FileKey::index(7)),
+            "app.007.log"
+        );
+        assert_eq!(
+            naming.file_name("app", "log", FileKey::index(1234)),
+            "app.1234.log"
+        );
+
+        for file_name in ["app.007.log", "app.7.log", "app.0007.log"] {
+            assert_eq!(
+                naming.parse("app", "log", file_name),
+                Some(FileKey::index(7)),
+                "{:?}",
+                file_name
+            );
+            assert_eq!(
+                Naming::Index.parse("app", "log", file_name),
+                Some(FileKey::index(7)),
+                "{:?}",
+                file_name
+            );
+        }
+    }
+
+    #[test]
+    fn test_next_sequence() {
+        let naming = Naming::Sequence;
+        let now = std::time::SystemTime::now();
+
+        assert_eq!(naming.next_key(None, now), FileKey::index(0));
+        assert_eq!(
+            naming.next_key(Some(FileKey::index(41)), now),
+            FileKey::index(42)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_roundtrip(name in "[a-zA-Z_.-]+", extension in "[a-z]{1,5}", major in 0..=253_402_300_799_u64, minor: u64) {
+            for naming in [Naming::Index, Naming::PaddedIndex(4), Naming::Stable, Naming::Sequence] {
+                let key = FileKey { major, minor: 0 };
+                let file_name = naming.file_name(&name, &extension, key);
+                prop_assert_eq!(naming.parse(&name, &extension, &file_name), Some(key));
+                prop_assert_eq!(naming.parse(&name, "other", &file_name), None);
+            }
+
+            let key = FileKey { major, minor };
+            let file_name = Naming::Timestamp.file_name(&name, &extension, key);
+            prop_assert_eq!(Naming::Timestamp.parse(&name, &extension, &file_name), Some(key));
+            prop_assert_eq!(Naming::Index.parse(&name, &extension, &file_name), None);
+        }
+    }
+}
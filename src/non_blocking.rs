@@ -0,0 +1,307 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+
+use super::stats::Counters;
+use super::{RotatingFile, Stats};
+
+/// What a [`NonBlocking`] does with a write once the queue for the background thread is full
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Backpressure {
+    /// Wait for the background thread to make room, which means waiting on the disk
+    Block,
+
+    /// Drop the write
+    #[default]
+    DropNewest,
+
+    /// Drop the oldest writes still in the queue until there's room
+    DropOldest,
+
+    /// Fail the write with [`io::ErrorKind::WouldBlock`]
+    Error,
+}
+
+/// How a [`NonBlocking`] goes about things, see [`RotatingFile::non_blocking_with`]
+#[derive(Clone, Copy, Debug)]
+pub struct NonBlockingOptions {
+    capacity: usize,
+    byte_capacity: usize,
+    backpressure: Backpressure,
+    report_dropped: bool,
+}
+
+impl Default for NonBlockingOptions {
+    fn default() -> Self {
+        Self {
+            capacity: 128_000,
+            byte_capacity: usize::MAX,
+            backpressure: Backpressure::default(),
+            report_dropped: false,
+        }
+    }
+}
+
+impl NonBlockingOptions {
+    /// Queue up at most `capacity` writes for the background thread, 128,000 by default
+    ///
+    /// A write is always let into an empty queue, however big it is.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Queue up at most `byte_capacity` bytes for the background thread, unlimited by default
+    pub fn with_byte_capacity(mut self, byte_capacity: usize) -> Self {
+        self.byte_capacity = byte_capacity;
+        self
+    }
+
+    /// What to do with writes once the queue is full, [`Backpressure::DropNewest`] by default
+    pub fn with_backpressure(mut self, backpressure: Backpressure) -> Self {
+        self.backpressure = backpressure;
+        self
+    }
+
+    /// Whether to write a line like `3 messages dropped` to the file once the background thread
+    /// has caught up after writes were dropped, off by default
+    pub fn with_dropped_report(mut self, report_dropped: bool) -> Self {
+        self.report_dropped = report_dropped;
+        self
+    }
+}
+
+#[derive(Default)]
+struct State {
+    writes: VecDeque<Vec<u8>>,
+    bytes: usize,
+    flush: bool,
+    shutdown: bool,
+}
+
+impl State {
+    fn is_idle(&self) -> bool {
+        self.writes.is_empty() && !self.flush && !self.shutdown
+    }
+}
+
+// What's shared between the handles, the guard and the background thread. The background thread
+// never touches the disk while holding the lock, so that writers never wait on it.
+struct Queue {
+    options: NonBlockingOptions,
+    state: Mutex<State>,
+    // Notified when there's something for the background thread to do
+    pushed: Condvar,
+    // Notified when the background thread takes writes out of the queue
+    popped: Condvar,
+    dropped: AtomicU64,
+}
+
+impl std::fmt::Debug for Queue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Queue")
+            .field("options", &self.options)
+            .field("dropped", &self.dropped)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Queue {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn has_room(&self, state: &State, len: usize) -> bool {
+        state.writes.is_empty()
+            || (state.writes.len() < self.options.capacity
+                && state.bytes.saturating_add(len) <= self.options.byte_capacity)
+    }
+
+    fn push(&self, buf: &[u8]) -> io::Result<()> {
+        let mut state = self.lock();
+        loop {
+            if state.shutdown {
+                return Err(io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "the background thread writing to the file has stopped",
+                ));
+            }
+            if self.has_room(&state, buf.len()) {
+                break;
+            }
+            match self.options.backpressure {
+                Backpressure::Block => {
+                    state = self.popped.wait(state).unwrap_or_else(|e| e.into_inner());
+                }
+                Backpressure::DropNewest => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    return Ok(());
+                }
+                Backpressure::DropOldest => {
+                    if let Some(oldest) = state.writes.pop_front() {
+                        state.bytes -= oldest.len();
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                Backpressure::Error => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WouldBlock,
+                        "the queue for the background thread is full",
+                    ))
+                }
+            }
+        }
+
+        state.bytes += buf.len();
+        state.writes.push_back(buf.to_vec());
+        self.pushed.notify_one();
+        Ok(())
+    }
+
+    fn request(&self, set: impl FnOnce(&mut State)) {
+        set(&mut self.lock());
+        self.pushed.notify_one();
+        self.popped.notify_all();
+    }
+}
+
+/// A cheap handle for writing to a rotating file from any thread without waiting on the disk,
+/// see [`RotatingFile::non_blocking`]
+///
+/// Writes are handed over to a background thread which owns the rotating file. If it falls so far
+/// behind that the queue filled up, what happens depends on the [`Backpressure`]: by default
+/// writes are dropped rather than waited on and counted in [`dropped`](#method.dropped). Flushing
+/// only asks the background thread to flush, without waiting for it to.
+#[derive(Clone, Debug)]
+pub struct NonBlocking {
+    queue: Arc<Queue>,
+    stats: Arc<Counters>,
+}
+
+impl NonBlocking {
+    /// The statistics of the rotating file being written to, see [`RotatingFile::stats`]
+    pub fn stats(&self) -> Stats {
+        self.stats.snapshot()
+    }
+
+    /// How many writes have been dropped because the background thread couldn't keep up, across
+    /// every clone of this handle
+    pub fn dropped(&self) -> u64 {
+        self.queue.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Write for NonBlocking {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.queue.push(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.queue.request(|state| state.flush = true);
+        Ok(())
+    }
+}
+
+/// Keeps the background thread of a [`NonBlocking`] going, writing out everything that's been
+/// sent and flushing the file once it's dropped
+///
+/// This must be held onto for as long as things are being written, e.g. in `main`: dropping it
+/// right away stops the background thread.
+#[must_use = "dropping the guard stops the background thread"]
+#[derive(Debug)]
+pub struct WorkerGuard {
+    queue: Arc<Queue>,
+    worker: Option<thread::JoinHandle<()>>,
+}
+
+impl Drop for WorkerGuard {
+    fn drop(&mut self) {
+        self.queue.request(|state| state.shutdown = true);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+// Write a line about writes dropped since the last one, if there were any
+fn report_dropped(file: &mut RotatingFile, dropped: &AtomicU64, reported: &mut u64) {
+    let now = dropped.load(Ordering::Relaxed);
+    if now > *reported {
+        let _ = file.write_record(format!("{} messages dropped\n", now - *reported).as_bytes());
+        *reported = now;
+    }
+}
+
+fn work(mut file: RotatingFile, queue: &Queue) {
+    let mut reported = 0;
+    // Errors end up in the statistics, there's nobody else to give them to
+    loop {
+        let mut state = queue.lock();
+        if state.is_idle() {
+            // Caught up, so it's a good time to let the file know what was lost
+            if queue.options.report_dropped {
+                drop(state);
+                report_dropped(&mut file, &queue.dropped, &mut reported);
+                state = queue.lock();
+            }
+            while state.is_idle() {
+                state = queue.pushed.wait(state).unwrap_or_else(|e| e.into_inner());
+            }
+        }
+        let writes = mem::take(&mut state.writes);
+        state.bytes = 0;
+        let flush = mem::take(&mut state.flush);
+        let shutdown = state.shutdown;
+        drop(state);
+        queue.popped.notify_all();
+
+        for buf in writes {
+            let _ = file.write_all(&buf);
+        }
+        if shutdown {
+            break;
+        }
+        if flush {
+            let _ = file.flush();
+        }
+    }
+
+    if queue.options.report_dropped {
+        report_dropped(&mut file, &queue.dropped, &mut reported);
+    }
+    let _ = file.flush();
+}
+
+pub(super) fn spawn(
+    file: RotatingFile,
+    options: NonBlockingOptions,
+) -> io::Result<(NonBlocking, WorkerGuard)> {
+    let queue = Arc::new(Queue {
+        options,
+        state: Mutex::default(),
+        pushed: Condvar::new(),
+        popped: Condvar::new(),
+        dropped: AtomicU64::new(0),
+    });
+    let stats = Arc::clone(&file.stats);
+    let worker_queue = Arc::clone(&queue);
+    let worker = thread::Builder::new()
+        .name("file-rotator writer".into())
+        .spawn(move || work(file, &worker_queue))?;
+
+    Ok((
+        NonBlocking {
+            queue: Arc::clone(&queue),
+            stats,
+        },
+        WorkerGuard {
+            queue,
+            worker: Some(worker),
+        },
+    ))
+}
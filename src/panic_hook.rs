@@ -0,0 +1,60 @@
+use std::backtrace::Backtrace;
+use std::io::Write;
+use std::panic::{self, PanicHookInfo};
+use std::sync::{MutexGuard, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{RotatingFile, SharedRotatingFile};
+
+// How long to wait for whoever's using the file before giving up on writing the panic to it,
+// since the thread that panicked might be the one holding it
+const LOCK_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub(super) fn install(file: SharedRotatingFile, rotate: bool) {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if let Some(mut file) = lock(&file) {
+            write_panic(&mut file, info, rotate);
+        }
+        previous(info);
+    }));
+}
+
+fn lock(file: &SharedRotatingFile) -> Option<MutexGuard<'_, RotatingFile>> {
+    let deadline = Instant::now() + LOCK_TIMEOUT;
+    loop {
+        match file.0.try_lock() {
+            Ok(file) => return Some(file),
+            Err(TryLockError::Poisoned(e)) => return Some(e.into_inner()),
+            Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+                thread::sleep(Duration::from_millis(1))
+            }
+            Err(TryLockError::WouldBlock) => return None,
+        }
+    }
+}
+
+// Write down the panic and make sure it's on disk, ignoring errors since there's nowhere left to
+// report them
+fn write_panic(file: &mut RotatingFile, info: &PanicHookInfo, rotate: bool) {
+    let thread = thread::current();
+    let report = format!(
+        "thread '{}' {}\nstack backtrace:\n{}\n",
+        thread.name().unwrap_or("<unnamed>"),
+        info,
+        Backtrace::force_capture()
+    );
+
+    // Everything that was written before the panic goes first, in a file of its own
+    let _ = file.flush();
+    if rotate {
+        let _ = file.rotate();
+    }
+    let _ = file.write_record(report.as_bytes());
+    let _ = file.flush();
+    let _ = file.flush_with_info();
+    if rotate {
+        let _ = file.rotate();
+    }
+}
@@ -0,0 +1,244 @@
+//! Parsing of human-friendly durations and sizes, for configuration files and command lines
+
+use std::convert::TryFrom;
+use std::io;
+use std::str::FromStr;
+use std::time::Duration;
+
+use super::RotationPeriod;
+
+fn invalid(what: &str, s: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid {} {:?}", what, s),
+    )
+}
+
+// Split a string like "15m30s" into ("15", "m") and "30s", skipping whitespace
+fn next_quantity(s: &str) -> Option<((&str, &str), &str)> {
+    let s = s.trim_start();
+    let digits = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, rest) = s.split_at(digits);
+    let rest = rest.trim_start();
+    let unit = rest
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(rest.len());
+    let (unit, rest) = rest.split_at(unit);
+    if number.is_empty() {
+        None
+    } else {
+        Some(((number, unit), rest))
+    }
+}
+
+/// Parse a duration like `"15m"`, `"2h"` or `"1h 30m"`
+///
+/// A duration is one or more numbers, each followed by a unit: `ms`, `s`, `m`, `h`, `d` (24
+/// hours) or `w` (7 days), longer names like `min`, `hours` or `days` being accepted too. Numbers
+/// may have a fractional part, as in `"1.5h"`.
+///
+/// # Errors
+///
+/// Returns an error of kind [`io::ErrorKind::InvalidInput`] if the string isn't a valid duration.
+pub fn parse_duration(s: &str) -> io::Result<Duration> {
+    let mut rest = s;
+    let mut total = Duration::ZERO;
+    while !rest.trim().is_empty() {
+        let ((number, unit), after) = next_quantity(rest).ok_or_else(|| invalid("duration", s))?;
+        let number = number.parse::<f64>().map_err(|_| invalid("duration", s))?;
+        let unit = match unit.to_ascii_lowercase().as_str() {
+            "ms" | "msec" | "millis" => 0.001,
+            "s" | "sec" | "secs" | "second" | "seconds" => 1.0,
+            "m" | "min" | "mins" | "minute" | "minutes" => 60.0,
+            "h" | "hr" | "hrs" | "hour" | "hours" => 60.0 * 60.0,
+            "d" | "day" | "days" => 24.0 * 60.0 * 60.0,
+            "w" | "week" | "weeks" => 7.0 * 24.0 * 60.0 * 60.0,
+            _ => return Err(invalid("duration", s)),
+        };
+        let quantity =
+            Duration::try_from_secs_f64(number * unit).map_err(|_| invalid("duration", s))?;
+        total = total
+            .checked_add(quantity)
+            .ok_or_else(|| invalid("duration", s))?;
+        rest = after;
+    }
+
+    if rest.len() == s.len() {
+        // Nothing at all
+        return Err(invalid("duration", s));
+    }
+    Ok(total)
+}
+
+/// Parse a size like `"500MB"`, `"64 KiB"` or `"1024"`
+///
+/// A size is a number optionally followed by a unit: `B`, decimal units (`KB`, `MB`, `GB` and
+/// `TB`, powers of 1000) or binary units (`KiB`, `MiB`, `GiB` and `TiB`, powers of 1024). Units
+/// are case-insensitive and `K`, `M`, `G` and `T` on their own are the same as their decimal
+/// counterparts. Numbers may have a fractional part, as in `"1.5GB"`.
+///
+/// # Errors
+///
+/// Returns an error of kind [`io::ErrorKind::InvalidInput`] if the string isn't a valid size.
+pub fn parse_size(s: &str) -> io::Result<u64> {
+    let ((number, unit), rest) = next_quantity(s).ok_or_else(|| invalid("size", s))?;
+    if !rest.trim().is_empty() {
+        return Err(invalid("size", s));
+    }
+
+    let multiplier: u64 = match unit.to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1000,
+        "m" | "mb" => 1000_u64.pow(2),
+        "g" | "gb" => 1000_u64.pow(3),
+        "t" | "tb" => 1000_u64.pow(4),
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        _ => return Err(invalid("size", s)),
+    };
+
+    // Avoid going through floats for plain integers, so that big sizes stay exact
+    match number.parse::<u64>() {
+        Ok(number) => number
+            .checked_mul(multiplier)
+            .ok_or_else(|| invalid("size", s)),
+        Err(_) => {
+            let size = number.parse::<f64>().map_err(|_| invalid("size", s))? * multiplier as f64;
+            if size.is_finite() && size < u64::MAX as f64 {
+                Ok(size as u64)
+            } else {
+                Err(invalid("size", s))
+            }
+        }
+    }
+}
+
+/// Parse a rotation period from a string, for configuration files and command lines
+///
+/// Accepted formats are:
+///
+/// - `manual`, for [`RotationPeriod::Manual`]
+/// - a number followed by `lines`, such as `"10000 lines"`, for [`RotationPeriod::Lines`]
+/// - a duration, as accepted by [`parse_duration`], such as `"15m"`, for
+///   [`RotationPeriod::Interval`]
+/// - a size, as accepted by [`parse_size`], such as `"500MB"`, for [`RotationPeriod::Bytes`]
+///
+/// A lone `m` is taken to mean minutes, so use `MB` for megabytes.
+impl FromStr for RotationPeriod {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Self> {
+        let trimmed = s.trim();
+        if trimmed.eq_ignore_ascii_case("manual") {
+            return Ok(RotationPeriod::Manual);
+        }
+
+        if let Some(lines) = trimmed
+            .strip_suffix("lines")
+            .or_else(|| trimmed.strip_suffix("line"))
+        {
+            return lines
+                .trim_end()
+                .parse()
+                .map(RotationPeriod::Lines)
+                .map_err(|_| invalid("rotation period", s));
+        }
+
+        // Durations come first so that `m` means minutes rather than megabytes
+        if let Ok(interval) = parse_duration(trimmed) {
+            return Ok(RotationPeriod::Interval(interval));
+        }
+
+        parse_size(trimmed)
+            .ok()
+            .and_then(|bytes| usize::try_from(bytes).ok())
+            .map(RotationPeriod::Bytes)
+            .ok_or_else(|| invalid("rotation period", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{parse_duration, parse_size};
+    use crate::RotationPeriod;
+
+    #[test]
+    fn test_parse_duration() {
+        let cases = [
+            ("15m", 15 * 60),
+            ("2h", 2 * 60 * 60),
+            ("1h30m", 90 * 60),
+            ("1h 30m", 90 * 60),
+            ("1.5 hours", 90 * 60),
+            ("7d", 7 * 24 * 60 * 60),
+            ("1w", 7 * 24 * 60 * 60),
+            ("45 s", 45),
+        ];
+        for &(s, secs) in &cases {
+            assert_eq!(
+                parse_duration(s).unwrap(),
+                Duration::from_secs(secs),
+                "{:?}",
+                s
+            );
+        }
+        assert_eq!(parse_duration("250ms").unwrap(), Duration::from_millis(250));
+
+        for s in [
+            "",
+            "15",
+            "m",
+            "15x",
+            "-1s",
+            "1h 30",
+            "18000000000000000000s 18000000000000000000s",
+        ] {
+            assert!(parse_duration(s).is_err(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn test_parse_size() {
+        let cases = [
+            ("1024", 1024),
+            ("500MB", 500_000_000),
+            ("500 mb", 500_000_000),
+            ("64KiB", 64 * 1024),
+            ("1.5GB", 1_500_000_000),
+            ("2 GiB", 2 << 30),
+            ("10k", 10_000),
+        ];
+        for &(s, size) in &cases {
+            assert_eq!(parse_size(s).unwrap(), size, "{:?}", s);
+        }
+
+        for s in ["", "MB", "5 parsecs", "1MB 2MB", "99999999999TB"] {
+            assert!(parse_size(s).is_err(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn test_parse_rotation_period() {
+        let cases = [
+            ("manual", RotationPeriod::Manual),
+            ("10000 lines", RotationPeriod::Lines(10000)),
+            ("500MB", RotationPeriod::Bytes(500_000_000)),
+            ("4096", RotationPeriod::Bytes(4096)),
+            ("10k", RotationPeriod::Bytes(10_000)),
+            (
+                "15m",
+                RotationPeriod::Interval(Duration::from_secs(15 * 60)),
+            ),
+        ];
+        for &(s, period) in &cases {
+            assert_eq!(s.parse::<RotationPeriod>().unwrap(), period, "{:?}", s);
+        }
+        assert!("sometimes".parse::<RotationPeriod>().is_err());
+    }
+}
@@ -0,0 +1,155 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Permissions and ownership to give files once they're rotated out
+///
+/// This allows e.g. making archives group-readable by a log shipper while the current file stays
+/// private. Fields left as `None` are not touched. Everything is applied before the archive shows
+/// up under its final name.
+///
+/// This is only supported on Unix, and does nothing elsewhere.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ArchivePermissions {
+    /// Permission bits, e.g. `0o640`
+    pub mode: Option<u32>,
+    /// Owning user ID
+    pub uid: Option<u32>,
+    /// Owning group ID
+    pub gid: Option<u32>,
+}
+
+impl ArchivePermissions {
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    pub(super) fn apply(&self, path: &Path) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            if *self == Self::default() {
+                return Ok(());
+            }
+            // Going through a handle rather than the path, so that a symlink swapped in for the
+            // file can't get something else changed instead
+            let file = no_follow(fs::OpenOptions::new().read(true)).open(path)?;
+
+            // Changing ownership can clear the setuid and setgid bits, so do it first
+            if self.uid.is_some() || self.gid.is_some() {
+                std::os::unix::fs::fchown(&file, self.uid, self.gid)?;
+            }
+
+            if let Some(mode) = self.mode {
+                file.set_permissions(fs::Permissions::from_mode(mode))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Create a file that doesn't exist yet for writing, with exactly the given permission bits
+// (regardless of the umask) and ownership, leaving out whatever isn't set. On Windows, it's opened
+// with the given share mode if there's one.
+#[cfg_attr(not(unix), allow(unused_variables))]
+#[cfg_attr(not(windows), allow(unused_variables))]
+pub(super) fn create_new_file(
+    path: &Path,
+    permissions: &ArchivePermissions,
+    share_mode: Option<u32>,
+) -> io::Result<fs::File> {
+    let mut options = fs::OpenOptions::new();
+    // Appending, so that writes from other processes sharing the file never trample each other
+    options.create_new(true).append(true);
+    #[cfg(windows)]
+    if let Some(share_mode) = share_mode {
+        use std::os::windows::fs::OpenOptionsExt;
+
+        options.share_mode(share_mode);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+        // Also passing the mode to `open` means the file is never more accessible than it should
+        // be, even before the umask is overridden
+        if let Some(mode) = permissions.mode {
+            options.mode(mode);
+        }
+        let file = options.open(path)?;
+        if permissions.uid.is_some() || permissions.gid.is_some() {
+            std::os::unix::fs::fchown(&file, permissions.uid, permissions.gid)?;
+        }
+        if let Some(mode) = permissions.mode {
+            file.set_permissions(fs::Permissions::from_mode(mode))?;
+        }
+        Ok(file)
+    }
+    #[cfg(not(unix))]
+    options.open(path)
+}
+
+// Create a directory and any missing parents, giving the ones created here the given permissions
+// and ownership
+pub(super) fn create_dir_all(path: &Path, permissions: &ArchivePermissions) -> io::Result<()> {
+    if *permissions == ArchivePermissions::default() || path.is_dir() {
+        return fs::create_dir_all(path);
+    }
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent, permissions)?;
+    }
+
+    #[cfg_attr(not(unix), allow(unused_mut))]
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    if let Some(mode) = permissions.mode {
+        use std::os::unix::fs::DirBuilderExt;
+
+        builder.mode(mode);
+    }
+    match builder.create(path) {
+        Ok(()) => permissions.apply(path),
+        // Somebody else might have beaten us to it
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists && path.is_dir() => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+// Create a file for writing at `path`, replacing whatever is there. Anything in the way is removed
+// first rather than written through, so that a symlink planted where a temporary file goes can't
+// redirect our writes somewhere else.
+pub(super) fn create_replacing(path: &Path) -> io::Result<fs::File> {
+    remove_if_exists(path)?;
+    fs::OpenOptions::new()
+        .create_new(true)
+        .write(true)
+        .open(path)
+}
+
+pub(super) fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+// Make `options` refuse to open symlinks, where that's supported
+pub(super) fn no_follow(options: &mut fs::OpenOptions) -> &mut fs::OpenOptions {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        options.custom_flags(libc::O_NOFOLLOW);
+    }
+    options
+}
+
+// Fail if `path` is a symlink, for directories we're told not to trust
+pub(super) fn check_not_symlink(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.file_type().is_symlink() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is a symlink", path.display()),
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,187 @@
+use std::ffi::OsString;
+use std::io;
+use std::path::Path;
+use std::process;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::snapshot::Archived;
+use super::stats::Counters;
+use super::RotatingFile;
+
+// How often to check whether the command has exited yet
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Runs a command after every rotation with the file that was rotated out as its last argument,
+/// like logrotate's `postrotate`, from a background thread, see [`spawn`](#method.spawn)
+///
+/// The command gets the file once it's done being compressed, through a hard link to it in the
+/// hidden `.NAME.snapshots` directory next to it whose name ends with the archive's. Later
+/// rotations would otherwise move it on or delete it from under the command, e.g. with
+/// [`Naming::Index`](enum.Naming.html#variant.Index). The link is removed once the command exits.
+///
+/// Commands run one at a time, in the order of the rotations, without a shell: to use one, run
+/// e.g. `sh` with `-c`, the script and a name for `$0`, so that the path ends up in `$1`. Commands
+/// that exit unsuccessfully or run past their timeout, at which point they're killed, show up in
+/// the rotating file's [`Stats`](struct.Stats.html).
+#[derive(Clone, Debug)]
+pub struct PostRotate {
+    command: OsString,
+    args: Vec<OsString>,
+    timeout: Duration,
+}
+
+impl PostRotate {
+    /// Run `command`, looked up in `PATH` if it's not a path itself
+    pub fn new<S>(command: S) -> Self
+    where
+        S: Into<OsString>,
+    {
+        Self {
+            command: command.into(),
+            args: Vec::new(),
+            timeout: Duration::from_secs(60),
+        }
+    }
+
+    /// Pass these arguments to the command, before the path of the file
+    pub fn with_args<Args>(mut self, args: Args) -> Self
+    where
+        Args: IntoIterator,
+        Args::Item: Into<OsString>,
+    {
+        self.args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Kill the command if it's still running after `timeout`, a minute by default
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Start running the command for whatever `file` rotates out from now on, from a background
+    /// thread
+    ///
+    /// The thread stops once `file` is dropped and the command has been run for everything it
+    /// rotated out.
+    pub fn spawn(self, file: &mut RotatingFile) -> io::Result<thread::JoinHandle<()>> {
+        let archives = file.subscribe_archived();
+        let stats = Arc::clone(&file.stats);
+        thread::Builder::new()
+            .name("file-rotator postrotate".into())
+            .spawn(move || self.work(&archives, &stats))
+    }
+
+    fn work(&self, archives: &mpsc::Receiver<Arc<Archived>>, stats: &Counters) {
+        for archived in archives {
+            // Errors end up in the statistics, there's nobody else to give them to
+            let _ = stats.record(self.run(&archived.snapshot));
+        }
+    }
+
+    fn run(&self, path: &Path) -> io::Result<()> {
+        let mut child = process::Command::new(&self.command)
+            .args(&self.args)
+            .arg(path)
+            .stdin(process::Stdio::null())
+            .spawn()?;
+        let deadline = Instant::now() + self.timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!(
+                        "{} was still running after {:?}",
+                        self.command.to_string_lossy(),
+                        self.timeout
+                    ),
+                ));
+            }
+            thread::sleep(POLL_INTERVAL);
+        };
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "{} exited with {}",
+                self.command.to_string_lossy(),
+                status
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+    use std::num::NonZeroUsize;
+    use std::time::Duration;
+
+    use super::PostRotate;
+    use crate::{Compression, RotatingFile, RotationPeriod};
+
+    fn rotating_file(directory: &tempfile::TempDir) -> RotatingFile {
+        RotatingFile::new(
+            "post",
+            directory.path().to_owned(),
+            RotationPeriod::Manual,
+            NonZeroUsize::new(3).unwrap(),
+            Compression::None,
+        )
+    }
+
+    #[test]
+    fn test_post_rotate() {
+        let directory = tempfile::tempdir().unwrap();
+        let out = directory.path().join("rotated");
+        let mut file = rotating_file(&directory);
+        let post_rotate = PostRotate::new("sh")
+            .with_args(vec![
+                "-c".into(),
+                format!("cat \"$1\" >> '{}'", out.display()),
+                "sh".into(),
+            ])
+            .spawn(&mut file)
+            .unwrap();
+        file.write_all(b"a\n").unwrap();
+        file.rotate().unwrap();
+        file.write_all(b"b\n").unwrap();
+        file.rotate().unwrap();
+        let stats = file.stats.clone();
+        drop(file);
+        post_rotate.join().unwrap();
+
+        // Both were rotated out to the same name, the first having been shifted along since, but
+        // the command saw each as it was
+        assert_eq!(fs::read_to_string(&out).unwrap(), "a\nb\n");
+        assert_eq!(stats.snapshot().last_error, None);
+        let snapshots = directory.path().join(".post.snapshots");
+        assert_eq!(fs::read_dir(snapshots).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_post_rotate_timeout() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut file = rotating_file(&directory);
+        let post_rotate = PostRotate::new("sh")
+            .with_args(vec!["-c", "exec sleep 10", "sh"])
+            .with_timeout(Duration::from_millis(50))
+            .spawn(&mut file)
+            .unwrap();
+        file.write_all(b"a\n").unwrap();
+        file.rotate().unwrap();
+        let stats = file.stats.clone();
+        drop(file);
+        post_rotate.join().unwrap();
+
+        let last_error = stats.snapshot().last_error.unwrap();
+        assert!(last_error.contains("still running"), "{}", last_error);
+    }
+}
@@ -0,0 +1,216 @@
+use std::time::{Duration, Instant};
+
+/// How much may be written in a given amount of time, see
+/// [`RotatingFile::with_rate_limit`](struct.RotatingFile.html#method.with_rate_limit)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum RateLimit {
+    /// At most `limit` bytes every `per`
+    Bytes {
+        /// How many bytes
+        limit: usize,
+        /// For how long
+        per: Duration,
+    },
+
+    /// At most `limit` line terminator bytes (0x0a, b'\n') every `per`
+    Lines {
+        /// How many lines
+        limit: usize,
+        /// For how long
+        per: Duration,
+    },
+}
+
+impl RateLimit {
+    fn per(self) -> Duration {
+        match self {
+            RateLimit::Bytes { per, .. } | RateLimit::Lines { per, .. } => per,
+        }
+    }
+
+    fn limit(self) -> usize {
+        match self {
+            RateLimit::Bytes { limit, .. } | RateLimit::Lines { limit, .. } => limit,
+        }
+    }
+
+    // How much of the limit `buf` takes up
+    fn cost(self, buf: &[u8]) -> usize {
+        match self {
+            RateLimit::Bytes { .. } => buf.len(),
+            RateLimit::Lines { .. } => bytecount::count(buf, b'\n'),
+        }
+    }
+
+    fn unit(self) -> &'static str {
+        match self {
+            RateLimit::Bytes { .. } => "bytes",
+            RateLimit::Lines { .. } => "lines",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(super) struct RateLimiter {
+    limit: Option<RateLimit>,
+    pub(super) summarize: bool,
+    window_start: Instant,
+    used: usize,
+    dropped: u64,
+    dropped_in_window: u64,
+    summary: Option<Vec<u8>>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self {
+            limit: None,
+            summarize: false,
+            window_start: Instant::now(),
+            used: 0,
+            dropped: 0,
+            dropped_in_window: 0,
+            summary: None,
+        }
+    }
+}
+
+impl RateLimiter {
+    pub(super) fn set_limit(&mut self, limit: RateLimit) {
+        self.limit = Some(limit);
+        self.window_start = Instant::now();
+        self.used = 0;
+    }
+
+    pub(super) fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    // How long a prefix of `buf` may be written now
+    pub(super) fn allowed(&mut self, buf: &[u8]) -> usize {
+        self.allowed_at(buf, Instant::now())
+    }
+
+    fn allowed_at(&mut self, buf: &[u8], now: Instant) -> usize {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return buf.len(),
+        };
+
+        if now.duration_since(self.window_start) >= limit.per() {
+            self.window_start = now;
+            self.used = 0;
+            if self.summarize && self.dropped_in_window > 0 {
+                self.summary = Some(
+                    format!(
+                        "rate limit exceeded, {} {} dropped\n",
+                        self.dropped_in_window,
+                        limit.unit()
+                    )
+                    .into_bytes(),
+                );
+            }
+            self.dropped_in_window = 0;
+        }
+
+        let left = limit.limit().saturating_sub(self.used);
+        match limit {
+            RateLimit::Bytes { .. } => left.min(buf.len()),
+            // Up to and including the last line terminator that fits, unless there's still room
+            // for all of them
+            RateLimit::Lines { .. } if left == 0 => 0,
+            RateLimit::Lines { .. } => buf
+                .iter()
+                .enumerate()
+                .filter(|&(_, &b)| b == b'\n')
+                .nth(left - 1)
+                .map_or(buf.len(), |(i, _)| i + 1),
+        }
+    }
+
+    // The line summarizing what the last window dropped, to be written before anything else
+    pub(super) fn take_summary(&mut self) -> Option<Vec<u8>> {
+        self.summary.take()
+    }
+
+    pub(super) fn wrote(&mut self, buf: &[u8]) {
+        if let Some(limit) = self.limit {
+            self.used += limit.cost(buf);
+        }
+    }
+
+    pub(super) fn discard(&mut self, buf: &[u8]) {
+        if let Some(limit) = self.limit {
+            let cost = limit.cost(buf) as u64;
+            self.dropped += cost;
+            self.dropped_in_window += cost;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{RateLimit, RateLimiter};
+
+    fn limiter(limit: RateLimit) -> RateLimiter {
+        let mut limiter = RateLimiter {
+            summarize: true,
+            ..RateLimiter::default()
+        };
+        limiter.set_limit(limit);
+        limiter
+    }
+
+    // Write what the limiter allows of `buf` at `now`, returning it
+    fn write<'a>(limiter: &mut RateLimiter, buf: &'a [u8], now: Instant) -> &'a [u8] {
+        let allowed = limiter.allowed_at(buf, now);
+        limiter.wrote(&buf[..allowed]);
+        limiter.discard(&buf[allowed..]);
+        &buf[..allowed]
+    }
+
+    #[test]
+    fn test_bytes() {
+        let mut limiter = limiter(RateLimit::Bytes {
+            limit: 5,
+            per: Duration::from_secs(1),
+        });
+        let start = limiter.window_start;
+        assert_eq!(write(&mut limiter, b"abc", start), b"abc");
+        assert_eq!(write(&mut limiter, b"defg", start), b"de");
+        assert_eq!(write(&mut limiter, b"h", start), b"");
+        assert_eq!(limiter.dropped(), 3);
+        assert_eq!(limiter.take_summary(), None);
+
+        let later = start + Duration::from_secs(1);
+        assert_eq!(write(&mut limiter, b"ijklmn", later), b"ijklm");
+        assert_eq!(
+            limiter.take_summary().unwrap(),
+            b"rate limit exceeded, 3 bytes dropped\n"
+        );
+        assert_eq!(limiter.dropped(), 4);
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut limiter = limiter(RateLimit::Lines {
+            limit: 2,
+            per: Duration::from_secs(1),
+        });
+        let start = limiter.window_start;
+        assert_eq!(write(&mut limiter, b"a\nb", start), b"a\nb");
+        assert_eq!(write(&mut limiter, b"c\nd\ne\n", start), b"c\n");
+        assert_eq!(write(&mut limiter, b"f\n", start), b"");
+        assert_eq!(limiter.dropped(), 3);
+
+        let later = start + Duration::from_secs(2);
+        assert_eq!(write(&mut limiter, b"g\n", later), b"g\n");
+        assert_eq!(
+            limiter.take_summary().unwrap(),
+            b"rate limit exceeded, 3 lines dropped\n"
+        );
+    }
+}
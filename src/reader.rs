@@ -0,0 +1,62 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read};
+
+/// Reads every file of a rotating file one after the other, oldest first, as if they were one,
+/// see [`RotatingFile::reader`](struct.RotatingFile.html#method.reader)
+///
+/// Files are decompressed transparently and joined as they are, like `cat` would: nothing is put
+/// between a file that doesn't end with a line terminator and the next one.
+pub struct LogReader {
+    files: VecDeque<BufReader<Box<dyn Read + Send>>>,
+}
+
+impl LogReader {
+    // Read `files` in order
+    pub(super) fn new(files: Vec<Box<dyn Read + Send>>) -> Self {
+        Self {
+            files: files.into_iter().map(BufReader::new).collect(),
+        }
+    }
+}
+
+impl fmt::Debug for LogReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LogReader")
+            .field("files_left", &self.files.len())
+            .finish()
+    }
+}
+
+impl Read for LogReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let read = available.len().min(buf.len());
+        buf[..read].copy_from_slice(&available[..read]);
+        self.consume(read);
+        Ok(read)
+    }
+}
+
+impl BufRead for LogReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // Get past the files that are done with first, so that what's returned is borrowed from
+        // a file that isn't going anywhere
+        while let Some(file) = self.files.front_mut() {
+            if !file.fill_buf()?.is_empty() {
+                break;
+            }
+            self.files.pop_front();
+        }
+        match self.files.front_mut() {
+            Some(file) => file.fill_buf(),
+            None => Ok(&[]),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if let Some(file) = self.files.front_mut() {
+            file.consume(amt);
+        }
+    }
+}
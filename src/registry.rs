@@ -0,0 +1,68 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, Weak};
+
+use super::RotatingFile;
+
+// Every registered rotating file that hasn't been dropped yet, see `SharedRotatingFile::register`
+static REGISTRY: Mutex<Vec<Weak<Mutex<RotatingFile>>>> = Mutex::new(Vec::new());
+
+pub(super) fn register(file: &Arc<Mutex<RotatingFile>>) {
+    let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    registry.retain(|file| file.strong_count() > 0);
+    if !registry
+        .iter()
+        .any(|registered| registered.ptr_eq(&Arc::downgrade(file)))
+    {
+        registry.push(Arc::downgrade(file));
+    }
+}
+
+// Run `f` on every registered rotating file that's still around, even if it fails on some of
+// them, returning the first error
+pub(super) fn for_each<F>(mut f: F) -> io::Result<()>
+where
+    F: FnMut(&mut RotatingFile) -> io::Result<()>,
+{
+    // Not holding on to the registry while waiting for the files, so that they can be registered
+    // and dropped in the meantime
+    let files = {
+        let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+        registry.retain(|file| file.strong_count() > 0);
+        registry
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect::<Vec<_>>()
+    };
+
+    let mut result = Ok(());
+    for file in files {
+        let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = f(&mut file) {
+            result = result.and(Err(e));
+        }
+    }
+    result
+}
+
+// Flush every registered rotating file and sync it to disk, for when the process is about to end
+#[cfg(all(unix, feature = "signals"))]
+pub(super) fn sync_all() -> io::Result<()> {
+    for_each(|file| {
+        file.flush()?;
+        file.flush_with_info().map(drop)
+    })
+}
+
+/// Flush every rotating file that's been registered with [`SharedRotatingFile::register`] and
+/// is still around, for making sure nothing is lost when shutting down
+///
+/// Files that fail to flush don't keep the others from being flushed.
+///
+/// # Errors
+///
+/// Returns the first error any of the files gave.
+///
+/// [`SharedRotatingFile::register`]: struct.SharedRotatingFile.html#method.register
+pub fn flush_all() -> io::Result<()> {
+    for_each(|file| file.flush())
+}
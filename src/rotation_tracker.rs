@@ -52,6 +52,18 @@ impl RotationTracker {
         }
     }
 
+    /// How many bytes can be written before rotating is due, starting after the rotation if it's
+    /// due now, for byte-based periods
+    pub(super) fn bytes_left(&self) -> Option<usize> {
+        match *self {
+            RotationTracker::Bytes { period, written } if written < period => {
+                Some(period - written)
+            }
+            RotationTracker::Bytes { period, .. } => Some(period.max(1)),
+            _ => None,
+        }
+    }
+
     /// Notify the tracker that we have rotated and so internal counters should be reset
     pub(super) fn reset(&mut self) {
         match self {
@@ -83,6 +95,20 @@ impl From<super::RotationPeriod> for RotationTracker {
     }
 }
 
+// Flushing is tracked just like rotating, flushing being due when rotating would be
+impl From<super::FlushPolicy> for RotationTracker {
+    fn from(policy: super::FlushPolicy) -> Self {
+        match policy {
+            super::FlushPolicy::Never => Self::Manual,
+            super::FlushPolicy::Bytes(period) => Self::from(super::RotationPeriod::Bytes(period)),
+            super::FlushPolicy::Lines(period) => Self::from(super::RotationPeriod::Lines(period)),
+            super::FlushPolicy::Interval(period) => {
+                Self::from(super::RotationPeriod::Interval(period))
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use proptest::prelude::*;
@@ -0,0 +1,96 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::PathBuf;
+
+/// A line found by [`RotatingFile::search`](struct.RotatingFile.html#method.search)
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct Match {
+    /// The file the line is in
+    pub path: PathBuf,
+    /// Where the line starts in the file, once decompressed
+    pub offset: u64,
+    /// The line itself, without its line terminator
+    pub line: Vec<u8>,
+}
+
+/// The lines containing a pattern in every file of a rotating file, oldest first, see
+/// [`RotatingFile::search`](struct.RotatingFile.html#method.search)
+///
+/// Files are read as the search goes, so lines come one at a time. An error reading a file ends
+/// the search of that file, and the search carries on with the next one.
+pub struct Search {
+    pattern: Vec<u8>,
+    files: VecDeque<(PathBuf, BufReader<Box<dyn Read + Send>>)>,
+    // Where the next line starts in the first file
+    offset: u64,
+    line: Vec<u8>,
+}
+
+impl Search {
+    // Search `files` in order
+    pub(super) fn new(pattern: Vec<u8>, files: Vec<(PathBuf, Box<dyn Read + Send>)>) -> Self {
+        Self {
+            pattern,
+            files: files
+                .into_iter()
+                .map(|(path, file)| (path, BufReader::new(file)))
+                .collect(),
+            offset: 0,
+            line: Vec::new(),
+        }
+    }
+
+    fn next_file(&mut self) {
+        self.files.pop_front();
+        self.offset = 0;
+    }
+}
+
+impl fmt::Debug for Search {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Search")
+            .field("pattern", &String::from_utf8_lossy(&self.pattern))
+            .field("files_left", &self.files.len())
+            .field("offset", &self.offset)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Iterator for Search {
+    type Item = io::Result<Match>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (path, file) = self.files.front_mut()?;
+            self.line.clear();
+            match file.read_until(b'\n', &mut self.line) {
+                Ok(0) => self.next_file(),
+                Ok(read) => {
+                    let offset = self.offset;
+                    self.offset += read as u64;
+                    let line = self.line.strip_suffix(b"\n").unwrap_or(&self.line);
+                    if contains(line, &self.pattern) {
+                        return Some(Ok(Match {
+                            path: path.clone(),
+                            offset,
+                            line: line.to_vec(),
+                        }));
+                    }
+                }
+                Err(e) => {
+                    self.next_file();
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    needle.is_empty()
+        || haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+}
@@ -0,0 +1,170 @@
+use std::convert::TryFrom;
+use std::io::{self, Write};
+
+use super::ZstdOptions;
+
+// See https://github.com/facebook/zstd/blob/dev/contrib/seekable_format/zstd_seekable_compression_format.md
+const SKIPPABLE_MAGIC: u32 = 0x184D_2A5E;
+const SEEKABLE_MAGIC: u32 = 0x8F92_EAB1;
+
+// The biggest frame the format allows
+const MAX_FRAME_SIZE: u32 = 1 << 30;
+
+// Counts the bytes going through it, to know how big each compressed frame is
+struct Counting<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for Counting<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Writes the zstd seekable format: independent frames of `frame_size` uncompressed bytes each,
+// followed by a seek table (which regular decoders skip) telling where each of them is
+pub(super) struct SeekableEncoder<W: Write> {
+    options: ZstdOptions,
+    level: i32,
+    frame_size: usize,
+    writer: Option<Counting<W>>,
+    frame: Option<zstd::Encoder<'static, Counting<W>>>,
+    // Uncompressed bytes in the current frame, and where it started in the output
+    frame_written: usize,
+    frame_start: u64,
+    // (compressed size, decompressed size) of every finished frame
+    entries: Vec<(u32, u32)>,
+}
+
+impl<W: Write> SeekableEncoder<W> {
+    pub(super) fn new(writer: W, options: ZstdOptions, level: i32, frame_size: u32) -> Self {
+        Self {
+            options,
+            level,
+            frame_size: frame_size.clamp(1, MAX_FRAME_SIZE) as usize,
+            writer: Some(Counting {
+                inner: writer,
+                count: 0,
+            }),
+            frame: None,
+            frame_written: 0,
+            frame_start: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    fn end_frame(&mut self) -> io::Result<()> {
+        if let Some(frame) = self.frame.take() {
+            let writer = frame.finish()?;
+            let compressed = u32::try_from(writer.count - self.frame_start)
+                .map_err(|_| io::Error::other("zstd frame too big for the seek table"))?;
+            self.entries.push((compressed, self.frame_written as u32));
+            self.frame_start = writer.count;
+            self.frame_written = 0;
+            self.writer = Some(writer);
+        }
+        Ok(())
+    }
+
+    // End the last frame and write the seek table, returning the underlying writer
+    pub(super) fn finish(mut self) -> io::Result<W> {
+        self.end_frame()?;
+        let mut writer = self.writer.take().expect("not in a frame").inner;
+
+        let entries = u32::try_from(self.entries.len())
+            .map_err(|_| io::Error::other("too many zstd frames for the seek table"))?;
+        let mut table = Vec::with_capacity(8 + self.entries.len() * 8 + 9);
+        table.extend_from_slice(&SKIPPABLE_MAGIC.to_le_bytes());
+        table.extend_from_slice(&(entries * 8 + 9).to_le_bytes());
+        for &(compressed, decompressed) in &self.entries {
+            table.extend_from_slice(&compressed.to_le_bytes());
+            table.extend_from_slice(&decompressed.to_le_bytes());
+        }
+        table.extend_from_slice(&entries.to_le_bytes());
+        // No per-frame checksums
+        table.push(0);
+        table.extend_from_slice(&SEEKABLE_MAGIC.to_le_bytes());
+        writer.write_all(&table)?;
+        Ok(writer)
+    }
+}
+
+impl<W: Write> Write for SeekableEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let frame = match &mut self.frame {
+            Some(frame) => frame,
+            None => {
+                let writer = self.writer.take().expect("not in a frame");
+                let encoder = self.options.encoder(writer, self.level)?;
+                self.frame.insert(encoder)
+            }
+        };
+
+        let room = self.frame_size - self.frame_written;
+        let written = frame.write(&buf[..buf.len().min(room)])?;
+        self.frame_written += written;
+        if self.frame_written == self.frame_size {
+            self.end_frame()?;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.frame {
+            Some(frame) => frame.flush(),
+            None => self.writer.as_mut().expect("not in a frame").flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+    use std::io::Write;
+
+    use proptest::prelude::*;
+
+    use super::{SeekableEncoder, SEEKABLE_MAGIC, SKIPPABLE_MAGIC};
+    use crate::ZstdOptions;
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    proptest! {
+        #[test]
+        fn test_seek_table(frame_size in 1..1000u32, data: Vec<u8>) {
+            let mut encoder = SeekableEncoder::new(Vec::new(), ZstdOptions::default(), 3, frame_size);
+            encoder.write_all(&data).unwrap();
+            let compressed = encoder.finish().unwrap();
+
+            // Regular decoders just skip the seek table
+            prop_assert_eq!(&zstd::decode_all(&compressed[..]).unwrap(), &data);
+
+            let footer = compressed.len() - 9;
+            prop_assert_eq!(u32_at(&compressed, footer + 5), SEEKABLE_MAGIC);
+            let frames = u32_at(&compressed, footer) as usize;
+            prop_assert_eq!(frames, data.len().div_ceil(frame_size as usize));
+            let table = footer - frames * 8;
+            prop_assert_eq!(u32_at(&compressed, table - 8), SKIPPABLE_MAGIC);
+
+            // Every frame can be decompressed on its own
+            let mut offset = 0;
+            for (i, chunk) in data.chunks(frame_size as usize).enumerate() {
+                let size = u32_at(&compressed, table + i * 8) as usize;
+                prop_assert_eq!(u32_at(&compressed, table + i * 8 + 4) as usize, chunk.len());
+                let frame = zstd::decode_all(&compressed[offset..offset + size]).unwrap();
+                prop_assert_eq!(&frame, &chunk);
+                offset += size;
+            }
+            prop_assert_eq!(offset, table - 8);
+        }
+    }
+}
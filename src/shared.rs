@@ -0,0 +1,89 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::{panic_hook, registry, RotatingFile};
+
+/// A rotating file that can be shared between threads, writing through `&SharedRotatingFile`
+///
+/// Clones refer to the same rotating file, and every write or flush locks it for as long as it
+/// takes. Other methods of [`RotatingFile`] are available through [`lock`](#method.lock).
+#[derive(Clone, Debug)]
+pub struct SharedRotatingFile(pub(super) Arc<Mutex<RotatingFile>>);
+
+impl SharedRotatingFile {
+    /// Share the given rotating file
+    pub fn new(file: RotatingFile) -> Self {
+        Self(Arc::new(Mutex::new(file)))
+    }
+
+    /// Get another handle to the same rotating file, like [`File::try_clone`](std::fs::File::try_clone)
+    ///
+    /// This is the same as cloning and never fails, it's only here for code written against files.
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(self.clone())
+    }
+
+    /// Add the rotating file to the global registry, so that [`flush_all`] flushes it until
+    /// every handle to it has been dropped
+    ///
+    /// Registering it more than once does nothing.
+    ///
+    /// [`flush_all`]: fn.flush_all.html
+    pub fn register(&self) {
+        registry::register(&self.0);
+    }
+
+    /// Write every panic to the rotating file, with its backtrace, before handing it to the panic
+    /// hook that was installed before
+    ///
+    /// The file is flushed and synced to disk right after, so the panic is there even if the
+    /// process doesn't survive it. With `rotate`, the file is rotated both before and after, so
+    /// that the panic ends up in a file of its own. If the file is still locked a second after the
+    /// panic, which is the case if the panicking thread was using it, the panic isn't written.
+    /// The file is kept around for as long as the process lives.
+    pub fn log_panics(&self, rotate: bool) {
+        panic_hook::install(self.clone(), rotate);
+    }
+
+    /// Lock the rotating file, waiting for whoever's using it to be done
+    ///
+    /// A panic while it was locked doesn't keep it from being used.
+    pub fn lock(&self) -> MutexGuard<'_, RotatingFile> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl From<RotatingFile> for SharedRotatingFile {
+    fn from(file: RotatingFile) -> Self {
+        Self::new(file)
+    }
+}
+
+impl Write for &SharedRotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock().write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        // Holding on to the lock, so that the whole of `buf` stays together
+        self.lock().write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lock().flush()
+    }
+}
+
+impl Write for SharedRotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        (&*self).write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&*self).flush()
+    }
+}
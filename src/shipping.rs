@@ -0,0 +1,30 @@
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+// How long to give a single upload before giving up on it
+pub(super) const TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+// Try `upload` again up to `retries` times, waiting `delay` before the first retry and twice as
+// long before every one after that
+pub(super) fn retrying<F>(retries: usize, mut delay: Duration, mut upload: F) -> io::Result<()>
+where
+    F: FnMut() -> io::Result<()>,
+{
+    let mut retries = retries;
+    loop {
+        match upload() {
+            // Files that are gone aren't coming back
+            Err(e) if retries > 0 && e.kind() != io::ErrorKind::NotFound => {
+                thread::sleep(delay);
+                delay *= 2;
+                retries -= 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+pub(super) fn to_io_error(what: &str, e: ureq::Error) -> io::Error {
+    io::Error::other(format!("{}: {}", what, e))
+}
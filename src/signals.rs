@@ -0,0 +1,108 @@
+use std::io;
+use std::sync::Mutex;
+
+/// Flush and sync every registered rotating file when the process is asked to stop with
+/// `SIGINT` (Ctrl-C) or `SIGTERM`, then let the signal end it as it would have
+///
+/// Only files registered with [`SharedRotatingFile::register`] are flushed, just like with
+/// [`flush_all`]. Any handlers already installed for these signals are replaced. Calling this
+/// more than once does nothing.
+///
+/// # Errors
+///
+/// Returns an error if the handlers can't be installed, or one of kind
+/// [`io::ErrorKind::Unsupported`] on platforms other than unix.
+///
+/// [`SharedRotatingFile::register`]: struct.SharedRotatingFile.html#method.register
+/// [`flush_all`]: fn.flush_all.html
+pub fn flush_on_signal() -> io::Result<()> {
+    static INSTALLED: Mutex<bool> = Mutex::new(false);
+
+    let mut installed = INSTALLED.lock().unwrap_or_else(|e| e.into_inner());
+    if !*installed {
+        imp::install()?;
+        *installed = true;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::io::{self, Read};
+    use std::os::unix::io::IntoRawFd;
+    use std::os::unix::net::UnixStream;
+    use std::ptr;
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::thread;
+
+    use super::super::registry;
+
+    const SIGNALS: [libc::c_int; 2] = [libc::SIGINT, libc::SIGTERM];
+
+    // Where the handler tells our thread which signal came in, since there's next to nothing it
+    // can safely do itself
+    static SENDER: AtomicI32 = AtomicI32::new(-1);
+
+    extern "C" fn handle(signal: libc::c_int) {
+        let signal = signal as u8;
+        // SAFETY: `write` is async-signal-safe, and the byte is valid for reads while it runs
+        unsafe {
+            libc::write(
+                SENDER.load(Ordering::Relaxed),
+                (&signal as *const u8).cast(),
+                1,
+            )
+        };
+    }
+
+    pub(super) fn install() -> io::Result<()> {
+        let (sender, mut receiver) = UnixStream::pair()?;
+        // A signal that comes in while we're flushing mustn't leave the handler stuck
+        sender.set_nonblocking(true)?;
+        thread::Builder::new()
+            .name("file-rotator signals".to_owned())
+            .spawn(move || {
+                let mut signal = [0];
+                if receiver.read_exact(&mut signal).is_ok() {
+                    let signal = libc::c_int::from(signal[0]);
+                    let _ = registry::sync_all();
+                    // Ending the process just like the signal would've if we weren't here
+                    if set_handler(signal, libc::SIG_DFL).is_ok() {
+                        // SAFETY: raising a signal has no preconditions
+                        unsafe { libc::raise(signal) };
+                    }
+                }
+            })?;
+
+        // The handler gets to use it for as long as the process lives
+        SENDER.store(sender.into_raw_fd(), Ordering::Relaxed);
+        let handle = handle as extern "C" fn(libc::c_int);
+        SIGNALS
+            .iter()
+            .try_for_each(|&signal| set_handler(signal, handle as libc::sighandler_t))
+    }
+
+    fn set_handler(signal: libc::c_int, handler: libc::sighandler_t) -> io::Result<()> {
+        // SAFETY: all zeroes is a valid `sigaction`, with no flags and an empty mask
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = handler;
+        action.sa_flags = libc::SA_RESTART;
+        // SAFETY: the action is fully initialized, and we don't need the old one
+        if unsafe { libc::sigaction(signal, &action, ptr::null_mut()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use std::io;
+
+    pub(super) fn install() -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "flushing on signals is only supported on unix",
+        ))
+    }
+}
@@ -0,0 +1,145 @@
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+
+use super::{locking, permissions};
+
+// The background threads that want every file rotated out, see `RotatingFile::subscribe_archived`
+pub(super) type Subscribers = Arc<Mutex<Vec<mpsc::Sender<Arc<Archived>>>>>;
+
+// A file that was rotated out, as it ended up once it was done being compressed
+//
+// Rotations keep renaming, recompressing and deleting archives, so those who get one of these go
+// through a hard link to it which stays put, and which is removed once they're all done with it.
+#[derive(Debug)]
+pub(super) struct Archived {
+    // Where the archive was, which it might have been moved on from since
+    pub(super) path: PathBuf,
+    pub(super) snapshot: PathBuf,
+}
+
+impl Archived {
+    // What the archive was called
+    #[cfg_attr(not(feature = "http"), allow(dead_code))]
+    pub(super) fn name(&self) -> Cow<'_, str> {
+        self.path.file_name().unwrap_or_default().to_string_lossy()
+    }
+
+    // A name that no other file rotated out of the same rotating file gets
+    #[cfg_attr(not(feature = "s3"), allow(dead_code))]
+    pub(super) fn unique_name(&self) -> Cow<'_, str> {
+        self.snapshot
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+    }
+
+    // Delete the archive itself unless it's been moved on from where it was, returning whether it
+    // was there to be deleted
+    #[cfg_attr(not(feature = "s3"), allow(dead_code))]
+    pub(super) fn delete(&self) -> io::Result<bool> {
+        let snapshot = fs::metadata(&self.snapshot)?;
+        let archive = match fs::symlink_metadata(&self.path) {
+            Ok(archive) => archive,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        // Telling files apart isn't possible everywhere, but something else being put in its
+        // place with the same size and modification time is unlikely
+        if !locking::same_file(&archive, &snapshot)
+            || archive.len() != snapshot.len()
+            || archive.modified().ok() != snapshot.modified().ok()
+        {
+            return Ok(false);
+        }
+        fs::remove_file(&self.path)?;
+        Ok(true)
+    }
+}
+
+impl Drop for Archived {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.snapshot);
+    }
+}
+
+// An archive to hand out once it's done, which may be after the background maintenance is
+#[derive(Debug)]
+pub(super) struct Pending {
+    pub(super) path: PathBuf,
+    // Where it would be if compressing it didn't work out
+    pub(super) fallback: Option<PathBuf>,
+    pub(super) snapshot: PathBuf,
+    pub(super) subscribers: Subscribers,
+}
+
+impl Pending {
+    pub(super) fn send(self) -> io::Result<()> {
+        let directory = self.snapshot.parent().unwrap_or_else(|| Path::new("."));
+        fs::create_dir_all(directory)?;
+        // Nothing but us should ever be putting anything there
+        permissions::check_not_symlink(directory)?;
+        let path = match (fs::hard_link(&self.path, &self.snapshot), self.fallback) {
+            (Ok(()), _) => self.path,
+            (Err(e), Some(fallback)) if e.kind() == io::ErrorKind::NotFound => {
+                fs::hard_link(&fallback, &self.snapshot)?;
+                fallback
+            }
+            (Err(e), _) => return Err(e),
+        };
+
+        let archived = Arc::new(Archived {
+            path,
+            snapshot: self.snapshot,
+        });
+        // Receivers that have been dropped don't need to hear about it anymore
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|subscriber| subscriber.send(Arc::clone(&archived)).is_ok());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::{mpsc, Arc, Mutex};
+
+    use super::Pending;
+
+    #[test]
+    fn test_snapshot() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("app.1.log");
+        let snapshot = directory.path().join(".app.snapshots").join("0.app.1.log");
+        fs::write(&path, "a\n").unwrap();
+        let (sender, receiver) = mpsc::channel();
+        Pending {
+            path: directory.path().join("app.1.log.gz"),
+            fallback: Some(path.clone()),
+            snapshot: snapshot.clone(),
+            subscribers: Arc::new(Mutex::new(vec![sender])),
+        }
+        .send()
+        .unwrap();
+        let archived = receiver.recv().unwrap();
+        assert_eq!(archived.path, path);
+        assert_eq!(archived.unique_name(), "0.app.1.log");
+
+        // Whatever's put in the archive's place is left alone
+        fs::rename(&path, directory.path().join("app.2.log")).unwrap();
+        fs::write(&path, "b\n").unwrap();
+        assert!(!archived.delete().unwrap());
+        assert_eq!(fs::read_to_string(&archived.snapshot).unwrap(), "a\n");
+        fs::remove_file(&path).unwrap();
+        fs::rename(directory.path().join("app.2.log"), &path).unwrap();
+        assert!(archived.delete().unwrap());
+        assert!(!path.exists());
+
+        drop(archived);
+        assert!(!snapshot.exists());
+    }
+}
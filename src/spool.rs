@@ -0,0 +1,152 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Something that happened to the in-memory spool, see [`RotatingFile::with_spool`]
+///
+/// [`RotatingFile::with_spool`]: struct.RotatingFile.html#method.with_spool
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum SpoolEvent {
+    /// The directory went missing, so writes are being kept in memory from now on, or sent to the
+    /// fallback if there's one (see
+    /// [`RotatingFile::with_fallback`](struct.RotatingFile.html#method.with_fallback))
+    Started {
+        /// The directory that went missing
+        directory: PathBuf,
+    },
+
+    /// The directory came back, and everything that was kept in memory has been written to a new
+    /// current file. Whatever was sent to the fallback stays there.
+    Replayed {
+        /// How many bytes were written while the directory was missing, fallback included
+        bytes: usize,
+        /// How long the directory was missing for
+        gap: Duration,
+    },
+}
+
+type Callback = Box<dyn FnMut(&SpoolEvent) + Send>;
+
+// How often to check whether the directory is writable again while writing to the fallback
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+// Writes kept in memory while the directory is missing, or sent to the fallback instead if
+// there's one
+#[derive(Default)]
+pub(super) struct Spool {
+    pub(super) capacity: usize,
+    pub(super) on_event: Option<Callback>,
+    pub(super) fallback: Option<Box<dyn Write + Send>>,
+    buffer: Vec<u8>,
+    redirected: usize,
+    since: Option<Instant>,
+    refused: Option<Instant>,
+}
+
+impl fmt::Debug for Spool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Spool")
+            .field("capacity", &self.capacity)
+            .field("buffered", &self.buffer.len())
+            .field("fallback", &self.fallback.is_some())
+            .field("redirected", &self.redirected)
+            .field("since", &self.since)
+            .finish()
+    }
+}
+
+impl Spool {
+    pub(super) fn is_enabled(&self) -> bool {
+        self.capacity != 0 || self.fallback.is_some()
+    }
+
+    // Whether `e` means the directory is there but can't be written to, which only the fallback
+    // is for
+    pub(super) fn falls_back_on(&self, e: &io::Error) -> bool {
+        self.fallback.is_some()
+            && matches!(
+                e.kind(),
+                io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem
+            )
+    }
+
+    // Whether it's time to try writing to the directory again, which is on every write unless
+    // it refused to be written to just now
+    pub(super) fn may_retry(&self) -> bool {
+        self.refused
+            .is_none_or(|refused| refused.elapsed() >= RETRY_INTERVAL)
+    }
+
+    pub(super) fn refused(&mut self) {
+        self.refused = Some(Instant::now());
+    }
+
+    pub(super) fn is_active(&self) -> bool {
+        self.since.is_some()
+    }
+
+    fn emit(&mut self, event: SpoolEvent) {
+        if let Some(on_event) = &mut self.on_event {
+            on_event(&event);
+        }
+    }
+
+    pub(super) fn start(&mut self, directory: PathBuf) {
+        self.since = Some(Instant::now());
+        self.emit(SpoolEvent::Started { directory });
+    }
+
+    // Keep as much of `buf` as there's room for, returning how much that was
+    pub(super) fn push(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(fallback) = &mut self.fallback {
+            let written = fallback.write(buf)?;
+            self.redirected += written;
+            return Ok(written);
+        }
+        let room = self.capacity.saturating_sub(self.buffer.len());
+        let taken = room.min(buf.len());
+        self.buffer.extend_from_slice(&buf[..taken]);
+        Ok(taken)
+    }
+
+    // Keep all of `buf` if there's room for it, returning whether there was
+    pub(super) fn push_all(&mut self, buf: &[u8]) -> io::Result<bool> {
+        if let Some(fallback) = &mut self.fallback {
+            fallback.write_all(buf)?;
+            self.redirected += buf.len();
+            return Ok(true);
+        }
+        if self.capacity.saturating_sub(self.buffer.len()) < buf.len() {
+            return Ok(false);
+        }
+        self.buffer.extend_from_slice(buf);
+        Ok(true)
+    }
+
+    pub(super) fn flush(&mut self) -> io::Result<()> {
+        match &mut self.fallback {
+            Some(fallback) => fallback.flush(),
+            None => Ok(()),
+        }
+    }
+
+    pub(super) fn buffered(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    // Forget about everything once it's been written out
+    pub(super) fn finish(&mut self) {
+        let bytes = self.buffer.len() + self.redirected;
+        self.buffer = Vec::new();
+        self.redirected = 0;
+        self.refused = None;
+        if let Some(since) = self.since.take() {
+            self.emit(SpoolEvent::Replayed {
+                bytes,
+                gap: since.elapsed(),
+            });
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use std::convert::TryFrom;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// What a rotating file has been up to since it was created, see
+/// [`RotatingFile::stats`](struct.RotatingFile.html#method.stats)
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct Stats {
+    /// Bytes written to files, including headers and footers
+    pub bytes_written: u64,
+    /// Line terminator bytes (0x0a, b'\n') written to files
+    pub lines_written: u64,
+    /// Times a new current file was started
+    pub rotations: u64,
+    /// Files deleted to make room for new ones
+    pub files_deleted: u64,
+    /// Time spent compressing files, in the background or not
+    pub compression_time: Duration,
+    /// The last error that was returned, as a message
+    pub last_error: Option<String>,
+}
+
+/// How much room the files of a rotating file take up, see
+/// [`RotatingFile::disk_usage`](struct.RotatingFile.html#method.disk_usage)
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct DiskUsage {
+    /// Bytes taken up by all of the files together
+    pub total_bytes: u64,
+    /// Every file with its size in bytes, newest first, from the current file to the bundles
+    pub files: Vec<(PathBuf, u64)>,
+    /// How long it's been since the oldest file was last modified, if there are any files
+    pub oldest_age: Option<Duration>,
+}
+
+impl DiskUsage {
+    /// How many files there are
+    pub fn file_count(&self) -> usize {
+        self.files.len()
+    }
+}
+
+// The counters behind `Stats`, shared with the background maintenance
+//
+// With the `metrics` feature, they're also given to the `metrics` facade as they go, labelled
+// with the name of the file as it is at the time, see `RotatingFile::with_instance`.
+#[derive(Debug, Default)]
+pub(super) struct Counters {
+    bytes_written: AtomicU64,
+    lines_written: AtomicU64,
+    rotations: AtomicU64,
+    files_deleted: AtomicU64,
+    compression_nanos: AtomicU64,
+    last_error: Mutex<Option<String>>,
+    #[cfg(feature = "metrics")]
+    name: Mutex<String>,
+}
+
+impl Counters {
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    pub(super) fn new(name: &str) -> Self {
+        Self {
+            #[cfg(feature = "metrics")]
+            name: Mutex::new(name.to_owned()),
+            ..Self::default()
+        }
+    }
+
+    // The file was renamed, which the metrics reported from now on are labelled with
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    pub(super) fn renamed(&self, name: &str) {
+        #[cfg(feature = "metrics")]
+        {
+            *self.name.lock().unwrap_or_else(|e| e.into_inner()) = name.to_owned();
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    fn name(&self) -> String {
+        self.name.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub(super) fn wrote(&self, buf: &[u8]) {
+        self.bytes_written
+            .fetch_add(buf.len() as u64, Ordering::Relaxed);
+        self.lines_written
+            .fetch_add(bytecount::count(buf, b'\n') as u64, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        metrics::counter!("file_rotator_bytes_written", "file" => self.name())
+            .increment(buf.len() as u64);
+    }
+
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    pub(super) fn rotated(&self, time: Duration) {
+        self.rotations.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        metrics::histogram!("file_rotator_rotation_duration_seconds", "file" => self.name())
+            .record(time.as_secs_f64());
+    }
+
+    pub(super) fn deleted(&self, files: usize) {
+        self.files_deleted
+            .fetch_add(files as u64, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        metrics::counter!("file_rotator_files_deleted", "file" => self.name())
+            .increment(files as u64);
+    }
+
+    pub(super) fn compressed(&self, time: Duration) {
+        let nanos = u64::try_from(time.as_nanos()).unwrap_or(u64::MAX);
+        self.compression_nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    // Remember the error in `result`, if any
+    pub(super) fn record<T>(&self, result: io::Result<T>) -> io::Result<T> {
+        if let Err(e) = &result {
+            let mut last_error = self.last_error.lock().unwrap_or_else(|e| e.into_inner());
+            *last_error = Some(e.to_string());
+        }
+        result
+    }
+
+    pub(super) fn snapshot(&self) -> Stats {
+        Stats {
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            lines_written: self.lines_written.load(Ordering::Relaxed),
+            rotations: self.rotations.load(Ordering::Relaxed),
+            files_deleted: self.files_deleted.load(Ordering::Relaxed),
+            compression_time: Duration::from_nanos(self.compression_nanos.load(Ordering::Relaxed)),
+            last_error: self
+                .last_error
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use std::io::Write;
+    use std::num::NonZeroUsize;
+    use std::sync::{Arc, Mutex};
+
+    use metrics::{Counter, CounterFn, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata};
+    use metrics::{SharedString, Unit};
+
+    use crate::{Compression, Instance, RotatingFile, RotationPeriod};
+
+    type Recorded = Arc<Mutex<Vec<(String, f64)>>>;
+
+    // Remembers every value given to a metric, along with the metric's name and labels
+    #[derive(Default)]
+    struct Recorder(Recorded);
+
+    struct Metric(String, Recorded);
+
+    impl Metric {
+        fn push(&self, value: f64) {
+            self.1.lock().unwrap().push((self.0.clone(), value));
+        }
+    }
+
+    impl CounterFn for Metric {
+        fn increment(&self, value: u64) {
+            self.push(value as f64);
+        }
+
+        fn absolute(&self, value: u64) {
+            self.push(value as f64);
+        }
+    }
+
+    impl HistogramFn for Metric {
+        fn record(&self, value: f64) {
+            self.push(value);
+        }
+    }
+
+    impl Recorder {
+        fn metric(&self, key: &Key) -> Arc<Metric> {
+            let labels = key
+                .labels()
+                .map(|label| format!("{}={}", label.key(), label.value()))
+                .collect::<Vec<_>>();
+            let name = format!("{}{{{}}}", key.name(), labels.join(","));
+            Arc::new(Metric(name, Arc::clone(&self.0)))
+        }
+
+        fn total(&self, name: &str) -> (usize, f64) {
+            let recorded = self.0.lock().unwrap();
+            let values = recorded.iter().filter(|(n, _)| n == name);
+            (values.clone().count(), values.map(|(_, value)| value).sum())
+        }
+    }
+
+    impl metrics::Recorder for Recorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.metric(key))
+        }
+
+        fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+
+        fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::from_arc(self.metric(key))
+        }
+    }
+
+    #[test]
+    fn test_metrics() {
+        let directory = tempfile::tempdir().unwrap();
+        let recorder = Recorder::default();
+        let stats = metrics::with_local_recorder(&recorder, || {
+            let mut file = RotatingFile::new(
+                "metrics",
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(1).unwrap(),
+                Compression::None,
+            );
+            for _ in 0..3 {
+                file.write_all(b"hello\n").unwrap();
+                file.rotate().unwrap();
+            }
+            file.stats()
+        });
+
+        assert_eq!(
+            recorder.total("file_rotator_bytes_written{file=metrics}").1,
+            stats.bytes_written as f64
+        );
+        assert_eq!(
+            recorder.total("file_rotator_files_deleted{file=metrics}").1,
+            stats.files_deleted as f64
+        );
+        assert!(stats.files_deleted > 0);
+        assert_eq!(
+            recorder
+                .total("file_rotator_rotation_duration_seconds{file=metrics}")
+                .0,
+            stats.rotations as usize
+        );
+    }
+
+    #[test]
+    fn test_metrics_instance() {
+        let directory = tempfile::tempdir().unwrap();
+        let recorder = Recorder::default();
+        metrics::with_local_recorder(&recorder, || {
+            let mut file = RotatingFile::new(
+                "metrics",
+                directory.path().to_owned(),
+                RotationPeriod::Manual,
+                NonZeroUsize::new(1).unwrap(),
+                Compression::None,
+            )
+            .with_instance(Instance::Id("worker".into()))
+            .unwrap();
+            file.write_all(b"hello\n").unwrap();
+            file.flush().unwrap();
+        });
+
+        // Labelled with the name the files are actually called after
+        assert_eq!(
+            recorder
+                .total("file_rotator_bytes_written{file=metrics-worker}")
+                .1,
+            6.0
+        );
+        assert_eq!(
+            recorder.total("file_rotator_bytes_written{file=metrics}").0,
+            0
+        );
+    }
+}
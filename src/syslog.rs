@@ -0,0 +1,66 @@
+use std::ffi::CString;
+use std::io::{self, Write};
+
+/// Sends every line written to it to syslog, for using as a fallback with
+/// [`RotatingFile::with_fallback`](struct.RotatingFile.html#method.with_fallback)
+///
+/// Lines are sent as they're completed, and whatever is left of an incomplete line is sent when
+/// flushing or dropping this.
+#[derive(Debug)]
+pub struct Syslog {
+    priority: i32,
+    partial: Vec<u8>,
+}
+
+impl Default for Syslog {
+    fn default() -> Self {
+        Self {
+            priority: libc::LOG_USER | libc::LOG_INFO,
+            partial: Vec::new(),
+        }
+    }
+}
+
+impl Syslog {
+    /// Send lines with the given priority, a facility and a level such as `LOG_DAEMON |
+    /// LOG_WARNING`, instead of `LOG_USER | LOG_INFO`
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    fn send(&self, line: &[u8]) {
+        // Syslog can't take NULs, so they're left out rather than losing the line
+        let line = CString::new(line.iter().copied().filter(|&b| b != 0).collect::<Vec<_>>())
+            .expect("NULs were left out");
+        // SAFETY: the format is a NUL-terminated string taking the single string it's given
+        unsafe { libc::syslog(self.priority, b"%s\0".as_ptr().cast(), line.as_ptr()) };
+    }
+}
+
+impl Write for Syslog {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.partial.extend_from_slice(buf);
+        let mut start = 0;
+        while let Some(end) = self.partial[start..].iter().position(|&b| b == b'\n') {
+            self.send(&self.partial[start..start + end]);
+            start += end + 1;
+        }
+        self.partial.drain(..start);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.partial.is_empty() {
+            self.send(&self.partial);
+            self.partial.clear();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Syslog {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
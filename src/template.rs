@@ -0,0 +1,349 @@
+use std::io;
+use std::time::SystemTime;
+
+use super::hostname;
+use super::naming::{next_key_from_secs, FileKey, NamingScheme};
+use super::timestamp::{self, DateTime};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Piece {
+    Literal(String),
+    Name,
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    Sequence,
+}
+
+/// A file name template, to be used directly as a [`NamingScheme`] or with
+/// [`Naming::Template`](enum.Naming.html#variant.Template)
+///
+/// Templates are made of literal text and placeholders in braces:
+///
+/// - `{name}` is replaced by the rotating file's name
+/// - `{hostname}` is replaced by the machine's hostname, as found when the template is parsed
+/// - `{seq}` is replaced by a sequence number, which starts at zero and distinguishes files whose
+///   date components are the same
+/// - `{%...}` is replaced by the UTC time at which the file was created, formatted
+///   `strftime`-style: `%Y` (four digit year), `%m`, `%d`, `%H`, `%M` and `%S` (two digit month,
+///   day, hour, minute and second) and `%%` (a literal `%`) are supported
+///
+/// Use `{{` and `}}` for literal braces. For example, `"{name}.{%Y-%m-%d}.{seq}.log"` produces
+/// `NAME.2024-06-01.0.log`, `NAME.2024-06-01.1.log` and so on.
+///
+/// Files are ordered by their date components and then by their sequence number, so only the
+/// components that appear in the template count. The template must contain `{seq}` or at least
+/// one date component, otherwise there would be no way to tell files apart. Without `{seq}`, files
+/// created while one with the same date components already exists get `.1`, `.2` and so on added
+/// to the end of their name: `"{name}.{%Y-%m-%d}.log"` produces `NAME.2024-06-01.log`, then
+/// `NAME.2024-06-01.log.1` if it's rotated again the same day.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Template {
+    pieces: Vec<Piece>,
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg.into())
+}
+
+impl Template {
+    /// Parse a template
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`io::ErrorKind::InvalidInput`] if the template is malformed,
+    /// uses an unknown placeholder or has no way to tell files apart.
+    pub fn parse(template: &str) -> io::Result<Self> {
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+
+                '}' => return Err(invalid(format!("unmatched '}}' in {:?}", template))),
+
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest
+                        .find('}')
+                        .ok_or_else(|| invalid(format!("unmatched '{{' in {:?}", template)))?;
+                    let placeholder = &rest[..end];
+                    chars = rest[end + 1..].chars();
+
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                    }
+
+                    match placeholder {
+                        "name" => pieces.push(Piece::Name),
+                        "hostname" => literal.push_str(&hostname::hostname()),
+                        "seq" => pieces.push(Piece::Sequence),
+                        _ if placeholder.starts_with('%') => {
+                            Self::parse_time(placeholder, &mut pieces)?
+                        }
+                        _ => {
+                            return Err(invalid(format!(
+                                "unknown placeholder {{{}}} in {:?}",
+                                placeholder, template
+                            )))
+                        }
+                    }
+                }
+
+                _ => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+
+        if !pieces
+            .iter()
+            .any(|piece| !matches!(piece, Piece::Literal(..) | Piece::Name))
+        {
+            return Err(invalid(format!(
+                "{:?} has neither a date component nor {{seq}}",
+                template
+            )));
+        }
+
+        Ok(Self { pieces })
+    }
+
+    fn parse_time(format: &str, pieces: &mut Vec<Piece>) -> io::Result<()> {
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            let piece = match c {
+                '%' => match chars.next() {
+                    Some('Y') => Piece::Year,
+                    Some('m') => Piece::Month,
+                    Some('d') => Piece::Day,
+                    Some('H') => Piece::Hour,
+                    Some('M') => Piece::Minute,
+                    Some('S') => Piece::Second,
+                    Some('%') => Piece::Literal("%".into()),
+                    Some(c) => return Err(invalid(format!("unknown specifier %{}", c))),
+                    None => return Err(invalid("trailing '%' in date format")),
+                },
+                c => Piece::Literal(c.to_string()),
+            };
+
+            // Merge literals so that matching doesn't need to look ahead
+            match (pieces.last_mut(), piece) {
+                (Some(Piece::Literal(prev)), Piece::Literal(s)) => prev.push_str(&s),
+                (_, piece) => pieces.push(piece),
+            }
+        }
+        Ok(())
+    }
+
+    /// Render the file name for the given base name and (seconds since epoch, sequence) key
+    pub(super) fn render(&self, name: &str, (secs, sequence): (u64, u64)) -> String {
+        let datetime = DateTime::from_unix(secs);
+        let mut rendered = self
+            .pieces
+            .iter()
+            .map(|piece| match piece {
+                Piece::Literal(s) => s.clone(),
+                Piece::Name => name.to_owned(),
+                Piece::Year => format!("{:04}", datetime.year),
+                Piece::Month => format!("{:02}", datetime.month),
+                Piece::Day => format!("{:02}", datetime.day),
+                Piece::Hour => format!("{:02}", datetime.hour),
+                Piece::Minute => format!("{:02}", datetime.minute),
+                Piece::Second => format!("{:02}", datetime.second),
+                Piece::Sequence => sequence.to_string(),
+            })
+            .collect::<String>();
+        // Files that would otherwise get the same name need something to tell them apart
+        if sequence > 0 && !self.has_sequence() {
+            rendered.push_str(&format!(".{}", sequence));
+        }
+        rendered
+    }
+
+    fn has_sequence(&self) -> bool {
+        self.pieces.contains(&Piece::Sequence)
+    }
+
+    /// Round the given time down to the precision of the template's date components, so that
+    /// files created at different times within it compare as equal
+    pub(super) fn truncate(&self, secs: u64) -> u64 {
+        let name = self.render("", (secs, 0));
+        self.matches("", &name).map_or(secs, |(secs, _)| secs)
+    }
+
+    /// Check if the given file name was produced by this template, returning its key if so
+    pub(super) fn matches(&self, name: &str, file_name: &str) -> Option<(u64, u64)> {
+        // Components missing from the template get fixed values, 2000 being a leap year so that
+        // February 29th without a year still makes sense
+        let mut datetime = DateTime {
+            year: 2000,
+            month: 1,
+            day: 1,
+            hour: 0,
+            minute: 0,
+            second: 0,
+        };
+        let mut sequence = 0;
+
+        let mut rest = file_name;
+        for piece in &self.pieces {
+            let digits = |rest: &str, n| -> Option<u32> {
+                let digits = rest.get(..n)?;
+                if digits.bytes().all(|b| b.is_ascii_digit()) {
+                    digits.parse().ok()
+                } else {
+                    None
+                }
+            };
+
+            rest = match piece {
+                Piece::Literal(s) => rest.strip_prefix(s.as_str())?,
+                Piece::Name => rest.strip_prefix(name)?,
+                Piece::Year => {
+                    datetime.year = digits(rest, 4)?;
+                    &rest[4..]
+                }
+                Piece::Month | Piece::Day | Piece::Hour | Piece::Minute | Piece::Second => {
+                    let value = digits(rest, 2)?;
+                    match piece {
+                        Piece::Month => datetime.month = value,
+                        Piece::Day => datetime.day = value,
+                        Piece::Hour => datetime.hour = value,
+                        Piece::Minute => datetime.minute = value,
+                        _ => datetime.second = value,
+                    }
+                    &rest[2..]
+                }
+                Piece::Sequence => {
+                    let len = rest.bytes().take_while(u8::is_ascii_digit).count();
+                    sequence = rest[..len].parse().ok()?;
+                    &rest[len..]
+                }
+            };
+        }
+
+        // The suffix `render` adds in place of `{seq}`, which never has leading zeros
+        if !rest.is_empty() && !self.has_sequence() {
+            let digits = rest.strip_prefix('.')?;
+            if digits.starts_with('0') || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            sequence = digits.parse().ok()?;
+            rest = "";
+        }
+
+        if rest.is_empty() {
+            Some((datetime.to_unix()?, sequence))
+        } else {
+            None
+        }
+    }
+}
+
+impl NamingScheme for Template {
+    fn shifts(&self) -> bool {
+        false
+    }
+
+    fn file_name(&self, name: &str, _extension: &str, key: FileKey) -> String {
+        self.render(name, (key.major, key.minor))
+    }
+
+    fn parse(&self, name: &str, _extension: &str, file_name: &str) -> Option<FileKey> {
+        let (major, minor) = self.matches(name, file_name)?;
+        Some(FileKey { major, minor })
+    }
+
+    fn next_key(&self, newest: Option<FileKey>, now: SystemTime) -> FileKey {
+        next_key_from_secs(newest, self.truncate(timestamp::unix_secs(now)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::Template;
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Template::parse("{name}.log").is_err());
+        assert!(Template::parse("{name}.{seq.log").is_err());
+        assert!(Template::parse("{name}}.{seq}.log").is_err());
+        assert!(Template::parse("{name}.{foo}.log").is_err());
+        assert!(Template::parse("{name}.{%Y-%q}.log").is_err());
+        assert!(Template::parse("{name}.{%Y%}.log").is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let template = Template::parse("{name}.{%Y-%m-%d}.{seq}.log").unwrap();
+        assert_eq!(
+            template.render("app", (1_717_243_200, 3)),
+            "app.2024-06-01.3.log"
+        );
+
+        let template = Template::parse("{{{name}}}-{%H%%}").unwrap();
+        assert_eq!(template.render("app", (1_717_243_200, 0)), "{app}-12%");
+    }
+
+    #[test]
+    fn test_hostname() {
+        let template = Template::parse("{name}.{hostname}.{seq}.log").unwrap();
+        let expected = format!("app.{}.0.log", crate::hostname::hostname());
+        assert_eq!(template.render("app", (0, 0)), expected);
+        assert_eq!(template.matches("app", &expected), Some((946_684_800, 0)));
+    }
+
+    #[test]
+    fn test_without_sequence() {
+        let template = Template::parse("{name}.{%Y-%m-%d}.log").unwrap();
+        assert_eq!(
+            template.render("app", (1_717_200_000, 0)),
+            "app.2024-06-01.log"
+        );
+        assert_eq!(
+            template.render("app", (1_717_200_000, 2)),
+            "app.2024-06-01.log.2"
+        );
+        assert_eq!(
+            template.matches("app", "app.2024-06-01.log.2"),
+            Some((1_717_200_000, 2))
+        );
+        assert_eq!(template.matches("app", "app.2024-06-01.log.02"), None);
+        assert_eq!(template.matches("app", "app.2024-06-01.log."), None);
+        assert_eq!(template.matches("app", "app.2024-06-01.log.gz"), None);
+    }
+
+    #[test]
+    fn test_truncate() {
+        let template = Template::parse("{name}.{%Y-%m-%d}.{seq}.log").unwrap();
+        assert_eq!(template.truncate(1_717_243_200), 1_717_200_000);
+    }
+
+    proptest! {
+        #[test]
+        fn test_roundtrip(name in "[a-zA-Z_-]+", secs in 0..=253_402_300_799_u64, sequence: u64) {
+            let template = Template::parse("{name}.{%Y-%m-%dT%H-%M-%S}.{seq}.log").unwrap();
+            let file_name = template.render(&name, (secs, sequence));
+            prop_assert_eq!(template.matches(&name, &file_name), Some((secs, sequence)));
+            prop_assert_eq!(template.matches("other", &file_name), None);
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::io::{self, Write};
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::stats::Counters;
+use super::{RotatingFile, Stats};
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// A write, along with when it was made
+type Entry = (Instant, Vec<u8>);
+
+// One handle's writes that haven't been merged yet, oldest first
+type Buffer = Arc<Mutex<Vec<Entry>>>;
+
+// What's shared between the handles, the guard and the background thread
+struct Shared {
+    interval: Duration,
+    buffers: Mutex<Vec<Buffer>>,
+    merger: Mutex<Merger>,
+    shutdown: AtomicBool,
+}
+
+impl std::fmt::Debug for Shared {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Shared")
+            .field("interval", &self.interval)
+            .field("buffers", &lock(&self.buffers).len())
+            .finish_non_exhaustive()
+    }
+}
+
+struct Merger {
+    file: RotatingFile,
+    // Writes taken out of the buffers that have to wait for the next merge, since a write made
+    // before them might not have made it into its buffer yet
+    pending: Vec<Entry>,
+}
+
+impl Shared {
+    fn add_buffer(&self) -> Buffer {
+        let buffer = Buffer::default();
+        lock(&self.buffers).push(Arc::clone(&buffer));
+        buffer
+    }
+
+    // Write out everything that was written before now, in the order it was written in
+    fn merge(&self) -> io::Result<()> {
+        let mut merger = lock(&self.merger);
+        // Writes are timestamped while their buffer is locked, so anything that's not in its
+        // buffer by the time we get to it was made after this
+        let cutoff = Instant::now();
+        let buffers = {
+            let mut buffers = lock(&self.buffers);
+            // Only we have a hold of the buffers of handles that have been dropped
+            buffers.retain(|buffer| Arc::strong_count(buffer) > 1 || !lock(buffer).is_empty());
+            buffers.clone()
+        };
+        for buffer in buffers {
+            let entries = mem::take(&mut *lock(&buffer));
+            merger.pending.extend(entries);
+        }
+
+        // Stable, so that writes made at the same time by the same handle stay in order
+        merger.pending.sort_by_key(|&(at, _)| at);
+        let ready = merger.pending.partition_point(|&(at, _)| at <= cutoff);
+        let Merger { file, pending } = &mut *merger;
+        let result = pending
+            .drain(..ready)
+            .try_for_each(|(_, buf)| file.write_all(&buf));
+        result
+    }
+}
+
+/// A handle for writing to a rotating file from many threads without them all waiting on the
+/// same lock, see [`RotatingFile::thread_buffered`]
+///
+/// Every clone has a buffer of its own, so each thread should have its own clone. Writes are
+/// timestamped and kept in their buffer until the background thread merges all the buffers into
+/// the file, in the order the writes were made in. Each write is kept whole, so a line should be
+/// written all at once. Flushing merges everything written so far right away, and flushes the
+/// file.
+#[derive(Debug)]
+pub struct ThreadBuffered {
+    shared: Arc<Shared>,
+    buffer: Buffer,
+    stats: Arc<Counters>,
+}
+
+impl ThreadBuffered {
+    /// The statistics of the rotating file being written to, see [`RotatingFile::stats`]
+    pub fn stats(&self) -> Stats {
+        self.stats.snapshot()
+    }
+}
+
+impl Clone for ThreadBuffered {
+    fn clone(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
+            buffer: self.shared.add_buffer(),
+            stats: Arc::clone(&self.stats),
+        }
+    }
+}
+
+impl Write for ThreadBuffered {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut buffer = lock(&self.buffer);
+        // Checked with the buffer locked, so that the last merge can't miss the write
+        if self.shared.shutdown.load(Ordering::SeqCst) {
+            return Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "the background thread merging the buffers has stopped",
+            ));
+        }
+        buffer.push((Instant::now(), buf.to_vec()));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.shared.merge()?;
+        lock(&self.shared.merger).file.flush()
+    }
+}
+
+/// Keeps the background thread of a [`ThreadBuffered`] going, merging everything that's left
+/// and flushing the file once it's dropped
+///
+/// This must be held onto for as long as things are being written, e.g. in `main`: dropping it
+/// right away stops the background thread.
+#[must_use = "dropping the guard stops the background thread"]
+#[derive(Debug)]
+pub struct MergeGuard {
+    shared: Arc<Shared>,
+    worker: Option<thread::JoinHandle<()>>,
+}
+
+impl Drop for MergeGuard {
+    fn drop(&mut self) {
+        self.shared.shutdown.store(true, Ordering::SeqCst);
+        if let Some(worker) = self.worker.take() {
+            worker.thread().unpark();
+            let _ = worker.join();
+        }
+    }
+}
+
+fn work(shared: &Shared) {
+    // Errors end up in the statistics, there's nobody else to give them to
+    while !shared.shutdown.load(Ordering::SeqCst) {
+        thread::park_timeout(shared.interval);
+        let _ = shared.merge();
+    }
+
+    // Nothing can be written anymore, so everything is ready
+    let _ = shared.merge();
+    let mut merger = lock(&shared.merger);
+    let Merger { file, pending } = &mut *merger;
+    for (_, buf) in pending.drain(..) {
+        let _ = file.write_all(&buf);
+    }
+    let _ = file.flush();
+}
+
+pub(super) fn spawn(
+    file: RotatingFile,
+    interval: Duration,
+) -> io::Result<(ThreadBuffered, MergeGuard)> {
+    let stats = Arc::clone(&file.stats);
+    let shared = Arc::new(Shared {
+        interval,
+        buffers: Mutex::default(),
+        merger: Mutex::new(Merger {
+            file,
+            pending: Vec::new(),
+        }),
+        shutdown: AtomicBool::new(false),
+    });
+    let worker_shared = Arc::clone(&shared);
+    let worker = thread::Builder::new()
+        .name("file-rotator merger".into())
+        .spawn(move || work(&worker_shared))?;
+
+    Ok((
+        ThreadBuffered {
+            buffer: shared.add_buffer(),
+            shared: Arc::clone(&shared),
+            stats,
+        },
+        MergeGuard {
+            shared,
+            worker: Some(worker),
+        },
+    ))
+}
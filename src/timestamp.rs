@@ -0,0 +1,223 @@
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A broken-down UTC date and time, precise to the second
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+pub(super) struct DateTime {
+    pub(super) year: u32,
+    pub(super) month: u32,
+    pub(super) day: u32,
+    pub(super) hour: u32,
+    pub(super) minute: u32,
+    pub(super) second: u32,
+}
+
+// These two are Howard Hinnant's `civil_from_days` and `days_from_civil`, restricted to dates
+// after the unix epoch since that's all we care about
+fn civil_from_days(days: u64) -> (u32, u32, u32) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year as u32, month as u32, day as u32)
+}
+
+fn days_from_civil(year: u32, month: u32, day: u32) -> Option<u64> {
+    // Year 0 is before the epoch anyway, its January and February even more so
+    let year = u64::from(year).checked_sub(u64::from(month <= 2))?;
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let mp = u64::from(if month > 2 { month - 3 } else { month + 9 });
+    let doy = (153 * mp + 2) / 5 + u64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    (era * 146_097 + doe).checked_sub(719_468)
+}
+
+impl DateTime {
+    pub(super) fn from_unix(secs: u64) -> Self {
+        let (year, month, day) = civil_from_days(secs / 86400);
+        let secs_of_day = (secs % 86400) as u32;
+        Self {
+            year,
+            month,
+            day,
+            hour: secs_of_day / 3600,
+            minute: secs_of_day / 60 % 60,
+            second: secs_of_day % 60,
+        }
+    }
+
+    pub(super) fn to_unix(self) -> Option<u64> {
+        if !(1..=12).contains(&self.month)
+            || !(1..=31).contains(&self.day)
+            || self.hour > 23
+            || self.minute > 59
+            || self.second > 59
+        {
+            return None;
+        }
+
+        let secs = days_from_civil(self.year, self.month, self.day)? * 86400
+            + u64::from(self.hour) * 3600
+            + u64::from(self.minute) * 60
+            + u64::from(self.second);
+
+        // Make sure things like February 31st don't sneak through
+        if Self::from_unix(secs) == self {
+            Some(secs)
+        } else {
+            None
+        }
+    }
+
+    /// Parse the output of our `Display` implementation
+    pub(super) fn parse(s: &str) -> Option<Self> {
+        let b = s.as_bytes();
+        if b.len() != 19
+            || b[4] != b'-'
+            || b[7] != b'-'
+            || b[10] != b'T'
+            || b[13] != b'-'
+            || b[16] != b'-'
+        {
+            return None;
+        }
+
+        let num = |range: std::ops::Range<usize>| -> Option<u32> {
+            let digits = s.get(range)?;
+            if digits.bytes().all(|b| b.is_ascii_digit()) {
+                digits.parse().ok()
+            } else {
+                None
+            }
+        };
+
+        let datetime = Self {
+            year: num(0..4)?,
+            month: num(5..7)?,
+            day: num(8..10)?,
+            hour: num(11..13)?,
+            minute: num(14..16)?,
+            second: num(17..19)?,
+        };
+        datetime.to_unix().map(|_| datetime)
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}-{:02}-{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+pub(super) fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::from_secs(0))
+        .as_secs()
+}
+
+pub(super) fn unix_millis(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::from_secs(0))
+        .as_millis()
+}
+
+// Like `2024-05-01T12:34:56.789Z`
+pub(super) fn rfc3339(time: SystemTime) -> String {
+    let millis = unix_millis(time);
+    let t = DateTime::from_unix((millis / 1000) as u64);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        t.year,
+        t.month,
+        t.day,
+        t.hour,
+        t.minute,
+        t.second,
+        millis % 1000
+    )
+}
+
+// Parse the output of `rfc3339`
+#[cfg(feature = "manifest")]
+pub(super) fn parse_rfc3339(s: &str) -> Option<SystemTime> {
+    let (datetime, millis) = s.strip_suffix('Z')?.split_once('.')?;
+    if datetime.get(13..14)? != ":" || datetime.get(16..17)? != ":" {
+        return None;
+    }
+    let secs = DateTime::parse(&datetime.replace(':', "-"))?.to_unix()?;
+    if millis.len() != 3 || !millis.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(UNIX_EPOCH + Duration::from_secs(secs) + Duration::from_millis(millis.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{rfc3339, DateTime};
+
+    #[test]
+    fn test_rfc3339() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_714_566_896_789);
+        assert_eq!(rfc3339(time), "2024-05-01T12:34:56.789Z");
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+    }
+
+    #[cfg(feature = "manifest")]
+    #[test]
+    fn test_parse_rfc3339() {
+        use super::parse_rfc3339;
+
+        let time = UNIX_EPOCH + Duration::from_millis(1_714_566_896_789);
+        assert_eq!(parse_rfc3339("2024-05-01T12:34:56.789Z"), Some(time));
+        assert_eq!(parse_rfc3339("2024-05-01T12-34-56.789Z"), None);
+        assert_eq!(parse_rfc3339("2024-05-01T12:34:56Z"), None);
+    }
+
+    #[test]
+    fn test_known_dates() {
+        assert_eq!(DateTime::from_unix(0).to_string(), "1970-01-01T00-00-00");
+        assert_eq!(
+            DateTime::from_unix(1_717_243_200).to_string(),
+            "2024-06-01T12-00-00"
+        );
+        assert_eq!(
+            DateTime::from_unix(951_825_600).to_string(),
+            "2000-02-29T12-00-00"
+        );
+    }
+
+    #[test]
+    fn test_invalid_dates() {
+        assert!(DateTime::parse("2023-02-29T00-00-00").is_none());
+        assert!(DateTime::parse("2023-13-01T00-00-00").is_none());
+        assert!(DateTime::parse("2023-01-01T24-00-00").is_none());
+        assert!(DateTime::parse("2023-01-01 00-00-00").is_none());
+        assert!(DateTime::parse("2023-01-01T00-00-0").is_none());
+        assert!(DateTime::parse("+023-01-01T00-00-00").is_none());
+        assert!(DateTime::parse("0000-01-01T00-00-00").is_none());
+        assert!(DateTime::parse("0000-03-01T00-00-00").is_none());
+    }
+
+    proptest! {
+        #[test]
+        fn test_roundtrip(secs in 0..=253_402_300_799_u64) {
+            let datetime = DateTime::from_unix(secs);
+            prop_assert_eq!(datetime.to_unix(), Some(secs));
+            prop_assert_eq!(DateTime::parse(&datetime.to_string()), Some(datetime));
+        }
+    }
+}
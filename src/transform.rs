@@ -0,0 +1,90 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::mem;
+
+type LineHook = Box<dyn FnMut(&[u8]) -> Cow<'_, [u8]> + Send>;
+
+// Runs every line through a function before it's written, holding lines back until they're
+// complete
+#[derive(Default)]
+pub(super) struct Transform {
+    hook: Option<LineHook>,
+    partial: Vec<u8>,
+}
+
+impl Transform {
+    pub(super) fn set<F>(&mut self, hook: F)
+    where
+        F: FnMut(&[u8]) -> Cow<'_, [u8]> + Send + 'static,
+    {
+        self.hook = Some(Box::new(hook));
+    }
+
+    pub(super) fn is_enabled(&self) -> bool {
+        self.hook.is_some()
+    }
+
+    // Take in `buf`, returning the lines that are complete now, transformed
+    pub(super) fn apply<'a>(&mut self, buf: &'a [u8]) -> Cow<'a, [u8]> {
+        let hook = match &mut self.hook {
+            Some(hook) => hook,
+            None => return Cow::Borrowed(buf),
+        };
+
+        let mut out = Vec::with_capacity(buf.len());
+        let mut partial = mem::take(&mut self.partial);
+        partial.extend_from_slice(buf);
+        let mut start = 0;
+        while let Some(end) = partial[start..].iter().position(|&b| b == b'\n') {
+            out.extend_from_slice(&hook(&partial[start..start + end]));
+            out.push(b'\n');
+            start += end + 1;
+        }
+        partial.drain(..start);
+        self.partial = partial;
+        Cow::Owned(out)
+    }
+
+    // Give up on the line that's being held back, returning it transformed
+    pub(super) fn flush(&mut self) -> Vec<u8> {
+        match &mut self.hook {
+            Some(hook) if !self.partial.is_empty() => {
+                let out = hook(&self.partial).into_owned();
+                self.partial.clear();
+                out
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Debug for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Transform")
+            .field("enabled", &self.is_enabled())
+            .field("partial", &self.partial.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::Transform;
+
+    #[test]
+    fn test_transform() {
+        let mut transform = Transform::default();
+        assert!(matches!(transform.apply(b"a\nb"), Cow::Borrowed(b"a\nb")));
+
+        transform.set(|line| match line {
+            b"secret" => Cow::Borrowed(b"[redacted]"),
+            _ => Cow::Borrowed(line),
+        });
+        assert_eq!(&transform.apply(b"a\nsec")[..], b"a\n");
+        assert_eq!(&transform.apply(b"ret\nb\nsecret")[..], b"[redacted]\nb\n");
+        assert_eq!(transform.flush(), b"[redacted]");
+        assert_eq!(transform.flush(), b"");
+    }
+}
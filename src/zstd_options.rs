@@ -0,0 +1,72 @@
+use std::io::{self, Write};
+
+/// Extra zstd parameters, on top of the level that
+/// [`Compression::Zstd`](enum.Compression.html#variant.Zstd) has, see
+/// [`RotatingFile::with_zstd_options`](struct.RotatingFile.html#method.with_zstd_options)
+///
+/// The defaults are zstd's own, plus archives in the regular format.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct ZstdOptions {
+    /// Enable long-distance matching, which finds repetitions much further apart than usual and
+    /// helps a lot with big, highly repetitive logs.
+    pub long_distance_matching: bool,
+    /// Add a checksum of the content to each archive, so that corruption is detected when it's
+    /// decompressed.
+    pub checksum: bool,
+    /// Base-2 logarithm of the maximum back-reference distance, `None` meaning zstd's default.
+    ///
+    /// Archives compressed with a window log above 27 need the decompressor to allow it (e.g.
+    /// `zstd -d --long=31`).
+    pub window_log: Option<u32>,
+    /// Write archives in the zstd seekable format, which splits them into independent frames of
+    /// this many uncompressed bytes (at most 1 GiB) followed by a seek table, so that tools can
+    /// get to any part of a big archive without decompressing everything before it.
+    ///
+    /// Seekable archives are still regular zstd files. This doesn't apply to the current file
+    /// when it's compressed live, see
+    /// [`RotatingFile::with_live_compression`](struct.RotatingFile.html#method.with_live_compression).
+    pub seekable_frame_size: Option<u32>,
+}
+
+impl ZstdOptions {
+    /// See [`ZstdOptions::long_distance_matching`]
+    pub fn with_long_distance_matching(mut self, long_distance_matching: bool) -> Self {
+        self.long_distance_matching = long_distance_matching;
+        self
+    }
+
+    /// See [`ZstdOptions::checksum`]
+    pub fn with_checksum(mut self, checksum: bool) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// See [`ZstdOptions::window_log`]
+    pub fn with_window_log(mut self, window_log: Option<u32>) -> Self {
+        self.window_log = window_log;
+        self
+    }
+
+    /// See [`ZstdOptions::seekable_frame_size`]
+    pub fn with_seekable_frame_size(mut self, seekable_frame_size: Option<u32>) -> Self {
+        self.seekable_frame_size = seekable_frame_size;
+        self
+    }
+
+    // A zstd encoder with all of these parameters, at a level that comes from the compression
+    // and its tiers
+    pub(super) fn encoder<W: Write>(
+        &self,
+        writer: W,
+        level: i32,
+    ) -> io::Result<zstd::Encoder<'static, W>> {
+        let mut encoder = zstd::Encoder::new(writer, level)?;
+        encoder.long_distance_matching(self.long_distance_matching)?;
+        encoder.include_checksum(self.checksum)?;
+        if let Some(window_log) = self.window_log {
+            encoder.window_log(window_log)?;
+        }
+        Ok(encoder)
+    }
+}